@@ -63,7 +63,14 @@ async fn protected(jar: CookieJar) -> impl IntoResponse {
     let auth_hostname = env::var("AUTH_SERVICE_HOST_NAME").unwrap_or("0.0.0.0".to_owned());
     let url = format!("http://{}:3000/verify-token", auth_hostname);
 
-    let response = match api_client.post(&url).json(&verify_token_body).send().await {
+    // Exempts the check from the auth service's rate limits, which would otherwise count every
+    // user's page views against this service's address
+    let mut request = api_client.post(&url).json(&verify_token_body);
+    if let Ok(api_key) = env::var("AUTH_SERVICE_API_KEY") {
+        request = request.header("x-api-key", api_key);
+    }
+
+    let response = match request.send().await {
         Ok(response) => response,
         Err(_) => {
             return StatusCode::INTERNAL_SERVER_ERROR.into_response();
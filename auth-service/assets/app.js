@@ -132,18 +132,20 @@ TwoFAButton.addEventListener("click", (e) => {
     const email = TwoFAForm.email.value;
     const loginAttemptId = TwoFAForm.login_attempt_id.value;
     const TwoFACode = TwoFAForm.email_code.value;
+    const rememberDevice = TwoFAForm.rememberDevice.checked;
 
     fetch('/verify-2fa', {
         method: 'POST',
         headers: {
             'Content-Type': 'application/json',
         },
-        body: JSON.stringify({ email, loginAttemptId, "2FACode": TwoFACode }),
+        body: JSON.stringify({ email, loginAttemptId, "2FACode": TwoFACode, rememberDevice }),
     }).then(response => {
         if (response.ok) {
             TwoFAForm.email.value = "";
             TwoFAForm.email_code.value = "";
             TwoFAForm.login_attempt_id.value = "";
+            TwoFAForm.rememberDevice.checked = false;
             TwoFAErrAlter.style.display = "none";
             alert("You have successfully logged in.");
             loginSection.style.display = "block";
@@ -161,4 +163,53 @@ TwoFAButton.addEventListener("click", (e) => {
             });
         }
     });
+});
+
+// Password reset links from emails open this page with the token in the query string
+const resetPasswordSection = document.getElementById("reset-password-section");
+const resetPasswordForm = document.getElementById("reset-password-form");
+const resetPasswordButton = document.getElementById("reset-password-form-submit");
+const resetPasswordErrAlter = document.getElementById("reset-password-err-alert");
+
+const resetToken = new URLSearchParams(window.location.search).get("reset_token");
+if (resetToken) {
+    resetPasswordForm.token.value = resetToken;
+    window.history.replaceState(null, "", window.location.pathname);
+
+    loginSection.style.display = "none";
+    resetPasswordSection.style.display = "block";
+}
+
+resetPasswordButton.addEventListener("click", (e) => {
+    e.preventDefault();
+
+    const token = resetPasswordForm.token.value;
+    const newPassword = resetPasswordForm.password.value;
+
+    fetch('/reset-password', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ token, newPassword }),
+    }).then(response => {
+        if (response.ok) {
+            resetPasswordForm.token.value = "";
+            resetPasswordForm.password.value = "";
+            resetPasswordErrAlter.style.display = "none";
+            alert("Your password has been reset. Log in with your new password.");
+            loginSection.style.display = "block";
+            resetPasswordSection.style.display = "none";
+        } else {
+            response.json().then(data => {
+                let error_msg = data.error;
+                if (error_msg !== undefined && error_msg !== null && error_msg !== "") {
+                    resetPasswordErrAlter.innerHTML = `<span><strong>Error: </strong>${error_msg}</span>`;
+                    resetPasswordErrAlter.style.display = "block";
+                } else {
+                    resetPasswordErrAlter.style.display = "none";
+                }
+            });
+        }
+    });
 });
\ No newline at end of file
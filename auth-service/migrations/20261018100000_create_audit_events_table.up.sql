@@ -0,0 +1,26 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS audit_events(
+  id BIGSERIAL PRIMARY KEY,
+  occurred_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  event_type TEXT NOT NULL,
+  email TEXT,
+  ip TEXT,
+  user_agent TEXT,
+  request_id TEXT,
+  outcome TEXT NOT NULL,
+  status_code SMALLINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS audit_events_email_idx ON audit_events (email, id);
+CREATE INDEX IF NOT EXISTS audit_events_event_type_idx ON audit_events (event_type, id);
+
+-- The audit log is append-only
+CREATE OR REPLACE FUNCTION reject_audit_event_change() RETURNS TRIGGER AS $$
+BEGIN
+  RAISE EXCEPTION 'audit_events is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_events_append_only
+  BEFORE UPDATE OR DELETE ON audit_events
+  FOR EACH ROW EXECUTE FUNCTION reject_audit_event_change();
@@ -0,0 +1,13 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS group_members;
+DROP TABLE IF EXISTS groups;
+
+DROP INDEX IF EXISTS users_external_id_idx;
+
+ALTER TABLE users
+  DROP CONSTRAINT IF EXISTS users_id_key,
+  DROP COLUMN IF EXISTS updated_at,
+  DROP COLUMN IF EXISTS created_at,
+  DROP COLUMN IF EXISTS external_id,
+  DROP COLUMN IF EXISTS active,
+  DROP COLUMN IF EXISTS id;
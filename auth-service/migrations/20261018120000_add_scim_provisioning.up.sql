@@ -0,0 +1,24 @@
+-- Add up migration script here
+ALTER TABLE users
+  ADD COLUMN id UUID NOT NULL DEFAULT gen_random_uuid(),
+  ADD COLUMN active BOOLEAN NOT NULL DEFAULT TRUE,
+  ADD COLUMN external_id TEXT,
+  ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  ADD CONSTRAINT users_id_key UNIQUE (id);
+
+CREATE INDEX IF NOT EXISTS users_external_id_idx ON users (external_id);
+
+CREATE TABLE IF NOT EXISTS groups(
+  id UUID PRIMARY KEY,
+  display_name TEXT NOT NULL UNIQUE,
+  external_id TEXT,
+  created_at TIMESTAMPTZ NOT NULL,
+  updated_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS group_members(
+  group_id UUID NOT NULL REFERENCES groups (id) ON DELETE CASCADE,
+  user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  PRIMARY KEY (group_id, user_id)
+);
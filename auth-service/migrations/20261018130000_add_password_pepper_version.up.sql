@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- The version of the server-side pepper mixed into the password before hashing, if any
+ALTER TABLE users
+  ADD COLUMN password_pepper_version INTEGER;
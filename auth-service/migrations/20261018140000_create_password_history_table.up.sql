@@ -0,0 +1,11 @@
+-- Add up migration script here
+-- Hashes of the passwords users had before their current one, to prevent reuse
+CREATE TABLE IF NOT EXISTS password_history(
+  id BIGSERIAL PRIMARY KEY,
+  user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  password_hash TEXT NOT NULL,
+  password_pepper_version INTEGER,
+  replaced_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS password_history_user_id_idx ON password_history (user_id, id);
@@ -0,0 +1,11 @@
+-- Add up migration script here
+-- Devices users have successfully logged in from, so logins from new ones can be reported
+CREATE TABLE IF NOT EXISTS known_devices(
+  user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  fingerprint TEXT NOT NULL,
+  user_agent TEXT,
+  ip_prefix TEXT NOT NULL,
+  first_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  last_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+  PRIMARY KEY (user_id, fingerprint)
+);
@@ -0,0 +1,12 @@
+-- Add up migration script here
+-- Devices users chose to skip 2FA on for a while
+CREATE TABLE IF NOT EXISTS trusted_devices(
+  id UUID PRIMARY KEY,
+  user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  user_agent TEXT,
+  ip_prefix TEXT NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL,
+  expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS trusted_devices_user_id_idx ON trusted_devices (user_id);
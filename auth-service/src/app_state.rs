@@ -1,33 +1,83 @@
 use std::sync::Arc;
-use tokio::sync::RwLock;
 
-use crate::domain::{BannedTokenStore, EmailClient, TwoFACodeStore, UserStore};
+use crate::{
+    domain::{
+        AuditLogStore, BannedTokenStore, BreachedPasswordCheck, EmailClient, GroupStore,
+        HealthCheck, KnownDeviceStore, PasswordPolicy, RateLimitStore, SmsClient, TwoFACodeStore,
+        UserStore, WebhookStore,
+    },
+    settings::Settings,
+    utils::rate_limit::RateLimiter,
+};
 
-pub type UserStoreType = Arc<RwLock<dyn UserStore + Send + Sync>>;
-pub type BannedTokenStoreType = Arc<RwLock<dyn BannedTokenStore + Send + Sync>>;
-pub type TwoFACodeStoreType = Arc<RwLock<dyn TwoFACodeStore + Send + Sync>>;
+pub type UserStoreType = Arc<dyn UserStore + Send + Sync>;
+pub type BannedTokenStoreType = Arc<dyn BannedTokenStore + Send + Sync>;
+pub type TwoFACodeStoreType = Arc<dyn TwoFACodeStore + Send + Sync>;
+pub type AuditLogStoreType = Arc<dyn AuditLogStore + Send + Sync>;
+pub type GroupStoreType = Arc<dyn GroupStore + Send + Sync>;
+pub type WebhookStoreType = Arc<dyn WebhookStore + Send + Sync>;
+pub type KnownDeviceStoreType = Arc<dyn KnownDeviceStore + Send + Sync>;
+pub type RateLimitStoreType = Arc<dyn RateLimitStore + Send + Sync>;
 pub type EmailClientType = Arc<dyn EmailClient + Send + Sync>;
+pub type SmsClientType = Arc<dyn SmsClient + Send + Sync>;
+pub type HealthCheckType = Arc<dyn HealthCheck + Send + Sync>;
 
 #[derive(Clone)]
 pub struct AppState {
+    pub settings: Arc<Settings>,
     pub user_store: UserStoreType,
     pub banned_token_store: BannedTokenStoreType,
     pub two_fa_code_store: TwoFACodeStoreType,
+    // Kept apart from login codes so a phone verification can't complete a login
+    pub phone_verification_store: TwoFACodeStoreType,
     pub email_client: EmailClientType,
+    pub sms_client: SmsClientType,
+    pub rate_limiter: RateLimiter,
+    pub audit_log_store: AuditLogStoreType,
+    pub webhook_store: WebhookStoreType,
+    pub group_store: GroupStoreType,
+    pub password_policy: Arc<PasswordPolicy>,
+    pub breached_password_check: Option<BreachedPasswordCheck>,
+    pub known_device_store: KnownDeviceStoreType,
+    /// The dependencies `/health/ready` checks
+    pub health_checks: Vec<HealthCheckType>,
 }
 
 impl AppState {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
+        settings: Arc<Settings>,
         user_store: UserStoreType,
         banned_token_store: BannedTokenStoreType,
         two_fa_code_store: TwoFACodeStoreType,
+        phone_verification_store: TwoFACodeStoreType,
         email_client: EmailClientType,
+        sms_client: SmsClientType,
+        rate_limiter: RateLimiter,
+        audit_log_store: AuditLogStoreType,
+        webhook_store: WebhookStoreType,
+        group_store: GroupStoreType,
+        password_policy: Arc<PasswordPolicy>,
+        breached_password_check: Option<BreachedPasswordCheck>,
+        known_device_store: KnownDeviceStoreType,
+        health_checks: Vec<HealthCheckType>,
     ) -> Self {
         Self {
+            settings,
             user_store,
             banned_token_store,
             two_fa_code_store,
+            phone_verification_store,
             email_client,
+            sms_client,
+            rate_limiter,
+            audit_log_store,
+            webhook_store,
+            group_store,
+            password_policy,
+            breached_password_check,
+            known_device_store,
+            health_checks,
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,67 @@
+//! Builds the compact Bloom filter the auth service loads from `BREACHED_PASSWORDS_PATH` out of
+//! a Have I Been Pwned corpus, either a `HASH:COUNT` text file or a directory of range files.
+//!
+//! Usage: build_breach_filter <input> <output> [--false-positive-rate 0.001] [--min-count 1]
+
+use std::{fs::File, io::BufWriter, path::PathBuf};
+
+use auth_service::services::hibp_breached_passwords::{read_hibp_entries, BloomFilter};
+use color_eyre::eyre::{eyre, Context, Result};
+
+const USAGE: &str =
+    "Usage: build_breach_filter <input> <output> [--false-positive-rate 0.001] [--min-count 1]";
+
+fn main() -> Result<()> {
+    color_eyre::install()?;
+
+    let mut args = std::env::args().skip(1);
+    let mut paths = vec![];
+    let mut false_positive_rate = 0.001;
+    let mut min_count = 1;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--false-positive-rate" => {
+                false_positive_rate = args
+                    .next()
+                    .ok_or_else(|| eyre!(USAGE))?
+                    .parse()
+                    .wrap_err("invalid false positive rate")?;
+            }
+            "--min-count" => {
+                min_count = args
+                    .next()
+                    .ok_or_else(|| eyre!(USAGE))?
+                    .parse()
+                    .wrap_err("invalid minimum count")?;
+            }
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    let [input, output] = <[PathBuf; 2]>::try_from(paths).map_err(|_| eyre!(USAGE))?;
+    if !(0.0..1.0).contains(&false_positive_rate) || false_positive_rate == 0.0 {
+        return Err(eyre!("the false positive rate must be between 0 and 1"));
+    }
+
+    // The corpus is read twice so the filter can be sized without holding it in memory
+    let mut items = 0;
+    read_hibp_entries(&input, |_, count| {
+        if count >= min_count {
+            items += 1;
+        }
+    })?;
+
+    let mut filter = BloomFilter::new(items, false_positive_rate);
+    read_hibp_entries(&input, |hash, count| {
+        if count >= min_count {
+            filter.insert(&hash);
+        }
+    })?;
+
+    let file = File::create(&output).wrap_err("failed to create output file")?;
+    filter.write_to(BufWriter::new(file))?;
+
+    println!("Wrote {} passwords to {}", items, output.display());
+    Ok(())
+}
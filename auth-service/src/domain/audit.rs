@@ -0,0 +1,253 @@
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{eyre, Result};
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+
+use super::Email;
+
+/// The kind of request an audit event records, one per route
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEventType {
+    Signup,
+    Login,
+    #[serde(rename = "verify_2fa")]
+    Verify2fa,
+    #[serde(rename = "resend_2fa_code")]
+    Resend2faCode,
+    Logout,
+    ChangePassword,
+    VerifyToken,
+    SetPhoneNumber,
+    VerifyPhoneNumber,
+    #[serde(rename = "set_2fa_channel")]
+    Set2faChannel,
+    ReportLogin,
+    ResetPassword,
+    ManageTrustedDevices,
+    QueryAuditLog,
+    ImportUsers,
+    ManageWebhooks,
+    ScimProvisioning,
+}
+
+impl AuditEventType {
+    const ALL: [Self; 17] = [
+        Self::Signup,
+        Self::Login,
+        Self::Verify2fa,
+        Self::Resend2faCode,
+        Self::Logout,
+        Self::ChangePassword,
+        Self::VerifyToken,
+        Self::SetPhoneNumber,
+        Self::VerifyPhoneNumber,
+        Self::Set2faChannel,
+        Self::ReportLogin,
+        Self::ResetPassword,
+        Self::ManageTrustedDevices,
+        Self::QueryAuditLog,
+        Self::ImportUsers,
+        Self::ManageWebhooks,
+        Self::ScimProvisioning,
+    ];
+
+    /// The event recorded for requests to `path`, if it is audited
+    pub fn from_path(path: &str) -> Option<Self> {
+        match path {
+            "/signup" => Some(Self::Signup),
+            "/login" => Some(Self::Login),
+            "/verify-2fa" => Some(Self::Verify2fa),
+            "/verify-2fa/resend" => Some(Self::Resend2faCode),
+            "/logout" => Some(Self::Logout),
+            "/change-password" => Some(Self::ChangePassword),
+            "/verify-token" => Some(Self::VerifyToken),
+            "/phone-number" => Some(Self::SetPhoneNumber),
+            "/phone-number/verify" => Some(Self::VerifyPhoneNumber),
+            "/2fa-channel" => Some(Self::Set2faChannel),
+            "/report-login" => Some(Self::ReportLogin),
+            "/reset-password" => Some(Self::ResetPassword),
+            path if path.starts_with("/trusted-devices") => Some(Self::ManageTrustedDevices),
+            "/admin/audit" => Some(Self::QueryAuditLog),
+            "/admin/users/import" => Some(Self::ImportUsers),
+            path if path.starts_with("/admin/webhooks") => Some(Self::ManageWebhooks),
+            path if path.starts_with("/scim/v2/") => Some(Self::ScimProvisioning),
+            _ => None,
+        }
+    }
+
+    pub fn parse(event_type: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|candidate| candidate.as_str() == event_type)
+            .ok_or(eyre!("{} is not a valid audit event type", event_type))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Signup => "signup",
+            Self::Login => "login",
+            Self::Verify2fa => "verify_2fa",
+            Self::Resend2faCode => "resend_2fa_code",
+            Self::Logout => "logout",
+            Self::ChangePassword => "change_password",
+            Self::VerifyToken => "verify_token",
+            Self::SetPhoneNumber => "set_phone_number",
+            Self::VerifyPhoneNumber => "verify_phone_number",
+            Self::Set2faChannel => "set_2fa_channel",
+            Self::ReportLogin => "report_login",
+            Self::ResetPassword => "reset_password",
+            Self::ManageTrustedDevices => "manage_trusted_devices",
+            Self::QueryAuditLog => "query_audit_log",
+            Self::ImportUsers => "import_users",
+            Self::ManageWebhooks => "manage_webhooks",
+            Self::ScimProvisioning => "scim_provisioning",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+    Success,
+    /// The request succeeded but needs a second factor to complete, i.e. a 206 from `/login`
+    Challenged,
+    /// Rejected because of the client, e.g. bad credentials or rate limiting
+    Failure,
+    /// Rejected because of the server
+    Error,
+}
+
+impl AuditOutcome {
+    pub fn from_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::PARTIAL_CONTENT => Self::Challenged,
+            status if status.is_success() => Self::Success,
+            status if status.is_server_error() => Self::Error,
+            _ => Self::Failure,
+        }
+    }
+
+    pub fn parse(outcome: &str) -> Result<Self> {
+        [Self::Success, Self::Challenged, Self::Failure, Self::Error]
+            .into_iter()
+            .find(|candidate| candidate.as_str() == outcome)
+            .ok_or(eyre!("{} is not a valid audit outcome", outcome))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Success => "success",
+            Self::Challenged => "challenged",
+            Self::Failure => "failure",
+            Self::Error => "error",
+        }
+    }
+}
+
+/// An audit event that hasn't been recorded yet
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewAuditEvent {
+    pub event_type: AuditEventType,
+    /// The user the request acted on, which for failed logins is whoever was claimed
+    pub email: Option<Email>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_id: Option<String>,
+    pub outcome: AuditOutcome,
+    pub status_code: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEvent {
+    pub id: i64,
+    #[serde(rename = "occurredAt")]
+    pub occurred_at: DateTime<Utc>,
+    #[serde(rename = "eventType")]
+    pub event_type: AuditEventType,
+    pub email: Option<String>,
+    pub ip: Option<String>,
+    #[serde(rename = "userAgent")]
+    pub user_agent: Option<String>,
+    #[serde(rename = "requestId")]
+    pub request_id: Option<String>,
+    pub outcome: AuditOutcome,
+    #[serde(rename = "statusCode")]
+    pub status_code: u16,
+}
+
+/// Only events matching every field that is set are returned
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuditLogFilter {
+    pub event_type: Option<AuditEventType>,
+    pub email: Option<Email>,
+    pub ip: Option<String>,
+    pub outcome: Option<AuditOutcome>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl AuditLogFilter {
+    pub fn matches(&self, event: &AuditEvent) -> bool {
+        self.event_type
+            .is_none_or(|event_type| event.event_type == event_type)
+            && self.email.as_ref().is_none_or(|email| {
+                event.email.as_deref() == Some(email.as_ref().expose_secret().as_str())
+            })
+            && self
+                .ip
+                .as_ref()
+                .is_none_or(|ip| event.ip.as_ref() == Some(ip))
+            && self.outcome.is_none_or(|outcome| event.outcome == outcome)
+            && self.since.is_none_or(|since| event.occurred_at >= since)
+            && self.until.is_none_or(|until| event.occurred_at < until)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_type_round_trips_through_str() {
+        for event_type in AuditEventType::ALL {
+            assert_eq!(
+                AuditEventType::parse(event_type.as_str()).unwrap(),
+                event_type
+            );
+            assert_eq!(
+                serde_json::to_string(&event_type).unwrap(),
+                format!("\"{}\"", event_type.as_str())
+            );
+        }
+    }
+
+    #[test]
+    fn outcome_is_derived_from_status() {
+        assert_eq!(
+            AuditOutcome::from_status(StatusCode::OK),
+            AuditOutcome::Success
+        );
+        assert_eq!(
+            AuditOutcome::from_status(StatusCode::CREATED),
+            AuditOutcome::Success
+        );
+        assert_eq!(
+            AuditOutcome::from_status(StatusCode::PARTIAL_CONTENT),
+            AuditOutcome::Challenged
+        );
+        assert_eq!(
+            AuditOutcome::from_status(StatusCode::UNAUTHORIZED),
+            AuditOutcome::Failure
+        );
+        assert_eq!(
+            AuditOutcome::from_status(StatusCode::TOO_MANY_REQUESTS),
+            AuditOutcome::Failure
+        );
+        assert_eq!(
+            AuditOutcome::from_status(StatusCode::INTERNAL_SERVER_ERROR),
+            AuditOutcome::Error
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use color_eyre::eyre::{eyre, Result};
+use secrecy::ExposeSecret;
+use sha1::{Digest, Sha1};
+
+use super::{Password, PasswordPolicyViolation, PasswordRule};
+
+/// A set of breached passwords, identified by their SHA-1 hash as in Have I Been Pwned
+pub trait BreachedPasswordList {
+    /// Whether the password with this hash appears in the list. Implementations may return
+    /// false positives, but never false negatives.
+    fn contains(&self, sha1: &[u8; 20]) -> bool;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreachedPasswordMode {
+    /// Breached passwords are rejected like any other policy violation
+    Reject,
+    /// Breached passwords are accepted, but logged
+    Warn,
+}
+
+impl BreachedPasswordMode {
+    pub fn parse(mode: &str) -> Result<Self> {
+        match mode {
+            "reject" => Ok(Self::Reject),
+            "warn" => Ok(Self::Warn),
+            other => Err(eyre!("Unknown breached password mode: {}", other)),
+        }
+    }
+}
+
+/// Checks new passwords against a local breach corpus
+#[derive(Clone)]
+pub struct BreachedPasswordCheck {
+    list: Arc<dyn BreachedPasswordList + Send + Sync>,
+    mode: BreachedPasswordMode,
+}
+
+impl BreachedPasswordCheck {
+    pub fn new(
+        list: Arc<dyn BreachedPasswordList + Send + Sync>,
+        mode: BreachedPasswordMode,
+    ) -> Self {
+        Self { list, mode }
+    }
+
+    pub fn check(&self, password: &Password) -> Result<(), PasswordPolicyViolation> {
+        let hash: [u8; 20] = Sha1::digest(password.as_ref().expose_secret().as_bytes()).into();
+
+        if !self.list.contains(&hash) {
+            return Ok(());
+        }
+
+        match self.mode {
+            BreachedPasswordMode::Reject => Err(PasswordPolicyViolation {
+                rule: PasswordRule::Breached,
+                message: "Password has appeared in a data breach".to_owned(),
+            }),
+            BreachedPasswordMode::Warn => {
+                tracing::warn!("Accepted a password that has appeared in a data breach");
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use secrecy::Secret;
+
+    use super::*;
+
+    struct HashSetList(HashSet<[u8; 20]>);
+
+    impl BreachedPasswordList for HashSetList {
+        fn contains(&self, sha1: &[u8; 20]) -> bool {
+            self.0.contains(sha1)
+        }
+    }
+
+    fn check(mode: BreachedPasswordMode, password: &str) -> Result<(), PasswordPolicyViolation> {
+        let breached: [u8; 20] = Sha1::digest(b"password123").into();
+        let list = Arc::new(HashSetList(HashSet::from([breached])));
+        let password = Password::parse(Secret::new(password.to_owned())).unwrap();
+        BreachedPasswordCheck::new(list, mode).check(&password)
+    }
+
+    #[test]
+    fn breached_passwords_are_rejected() {
+        let violation = check(BreachedPasswordMode::Reject, "password123").unwrap_err();
+        assert_eq!(violation.rule, PasswordRule::Breached);
+        assert!(check(BreachedPasswordMode::Reject, "password1234").is_ok());
+    }
+
+    #[test]
+    fn breached_passwords_are_allowed_in_warn_mode() {
+        assert!(check(BreachedPasswordMode::Warn, "password123").is_ok());
+    }
+}
@@ -1,16 +1,77 @@
+use std::hash::Hash;
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
 use rand::Rng;
 use secrecy::{ExposeSecret, Secret};
+use subtle::ConstantTimeEq;
 use thiserror::Error;
 use color_eyre::eyre::{eyre, Context, Report, Result};
 
-use super::{Email, Password, User};
+use super::{
+    AuditEvent, AuditLogFilter, DeviceSighting, DueWebhookDelivery, Email, Group, LoginDevice,
+    NewAuditEvent, Password, TrustedDevice, PhoneNumber, RateLimitDecision, RateLimitPolicy,
+    TwoFAChannel, User, UserQuery, WebhookDelivery, WebhookDeliveryAttempt, WebhookEvent,
+    WebhookSubscription,
+};
 
+/// Shared by every request as is, so implementations synchronize access themselves
 #[async_trait::async_trait]
 pub trait UserStore {
-    async fn add_user(&mut self, user: User) -> Result<(), UserStoreError>;
+    async fn add_user(&self, user: User) -> Result<(), UserStoreError>;
+    /// Adds a user migrated from another system along with their existing password hash,
+    /// which replaces `user.password`. The hash is upgraded when the user next logs in.
+    async fn import_user(
+        &self,
+        user: User,
+        password_hash: Secret<String>,
+    ) -> Result<(), UserStoreError>;
     async fn get_user(&self, email: &Email) -> Result<User, UserStoreError>;
     async fn validate_user(&self, email: &Email, password: &Password)
         -> Result<(), UserStoreError>;
+    /// Replaces the user's password unless it is one of their last `history_size` passwords,
+    /// the current one included, and remembers the replaced one. Older history is pruned.
+    async fn update_password(
+        &self,
+        email: &Email,
+        password: Password,
+        history_size: usize,
+    ) -> Result<(), UserStoreError>;
+    /// Stores an unverified phone number for the user, switching them back to email 2FA until
+    /// the new number is verified.
+    async fn set_phone_number(
+        &self,
+        email: &Email,
+        phone_number: PhoneNumber,
+    ) -> Result<(), UserStoreError>;
+    /// Marks the user's phone number as verified, provided it is still `phone_number`
+    async fn verify_phone_number(
+        &self,
+        email: &Email,
+        phone_number: &PhoneNumber,
+    ) -> Result<(), UserStoreError>;
+    async fn set_two_fa_channel(
+        &self,
+        email: &Email,
+        channel: TwoFAChannel,
+    ) -> Result<(), UserStoreError>;
+    async fn get_user_by_id(&self, id: Uuid) -> Result<User, UserStoreError>;
+    /// The page of users matching `query` that starts `offset` users in, oldest first, along
+    /// with how many users match in total.
+    async fn list_users(
+        &self,
+        query: &UserQuery,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<User>, usize), UserStoreError>;
+    async fn set_active(&self, email: &Email, active: bool) -> Result<(), UserStoreError>;
+    async fn set_external_id(
+        &self,
+        email: &Email,
+        external_id: Option<String>,
+    ) -> Result<(), UserStoreError>;
+    async fn delete_user(&self, email: &Email) -> Result<(), UserStoreError>;
 }
 
 #[derive(Debug, Error)]
@@ -21,6 +82,10 @@ pub enum UserStoreError {
     UserNotFound,
     #[error("Invalid credentials")]
     InvalidCredentials,
+    #[error("Password was used recently")]
+    PasswordReused,
+    #[error("Phone number has changed")]
+    PhoneNumberChanged,
     #[error("Unexpected error")]
     UnexpectedError(#[source] Report),
 }
@@ -32,16 +97,71 @@ impl PartialEq for UserStoreError {
             (Self::UserAlreadyExists, Self::UserAlreadyExists)
                 | (Self::UserNotFound, Self::UserNotFound)
                 | (Self::InvalidCredentials, Self::InvalidCredentials)
+                | (Self::PasswordReused, Self::PasswordReused)
+                | (Self::PhoneNumberChanged, Self::PhoneNumberChanged)
                 | (Self::UnexpectedError(_), Self::UnexpectedError(_))
         )
     }
 }
 
+#[async_trait::async_trait]
+pub trait GroupStore {
+    async fn add_group(&self, group: Group) -> Result<(), GroupStoreError>;
+    async fn get_group(&self, id: Uuid) -> Result<Group, GroupStoreError>;
+    /// Like `UserStore::list_users`, optionally narrowed down to the group with `display_name`
+    async fn list_groups(
+        &self,
+        display_name: Option<&str>,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<Group>, usize), GroupStoreError>;
+    /// Overwrites the stored group with the same ID, members included
+    async fn update_group(&self, group: &Group) -> Result<(), GroupStoreError>;
+    async fn delete_group(&self, id: Uuid) -> Result<(), GroupStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum GroupStoreError {
+    /// Display names are unique
+    #[error("Group already exists")]
+    GroupAlreadyExists,
+    #[error("Group not found")]
+    GroupNotFound,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for GroupStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::GroupAlreadyExists, Self::GroupAlreadyExists)
+                | (Self::GroupNotFound, Self::GroupNotFound)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
 
 #[async_trait::async_trait]
 pub trait BannedTokenStore {
-    async fn add_token(&mut self, token: Secret<String>) -> Result<(), BannedTokenStoreError>;
+    /// Bans `token` for `ttl_seconds`, which has to outlast the token for the ban to hold
+    async fn add_token(
+        &self,
+        token: Secret<String>,
+        ttl_seconds: u64,
+    ) -> Result<(), BannedTokenStoreError>;
     async fn contains_token(&self, token: &Secret<String>) -> Result<bool, BannedTokenStoreError>;
+    /// Revokes every token issued to the user before `timestamp`, in seconds since the epoch
+    async fn revoke_tokens_issued_before(
+        &self,
+        email: &Email,
+        timestamp: i64,
+    ) -> Result<(), BannedTokenStoreError>;
+    /// When the user's tokens were last revoked, if that's recent enough for any to be unexpired
+    async fn tokens_revoked_before(
+        &self,
+        email: &Email,
+    ) -> Result<Option<i64>, BannedTokenStoreError>;
 }
 
 #[derive(Debug, Error)]
@@ -50,19 +170,204 @@ pub enum BannedTokenStoreError {
     UnexpectedError(#[source] Report),
 }
 
+/// The devices each user has successfully logged in from
+#[async_trait::async_trait]
+pub trait KnownDeviceStore {
+    /// Remembers that the user logged in from `device`, reporting whether they had before
+    async fn record_device(
+        &self,
+        email: &Email,
+        device: &LoginDevice,
+    ) -> Result<DeviceSighting, KnownDeviceStoreError>;
+    /// Forgets every device of the user, so that each one is reported as new again and none
+    /// of them stays trusted
+    async fn forget_devices(&self, email: &Email) -> Result<(), KnownDeviceStoreError>;
+    async fn trust_device(
+        &self,
+        email: &Email,
+        device: TrustedDevice,
+    ) -> Result<(), KnownDeviceStoreError>;
+    /// The user's trusted device with `id`, unless it has expired
+    async fn get_trusted_device(
+        &self,
+        email: &Email,
+        id: Uuid,
+    ) -> Result<TrustedDevice, KnownDeviceStoreError>;
+    /// The user's unexpired trusted devices, most recently trusted first
+    async fn list_trusted_devices(
+        &self,
+        email: &Email,
+    ) -> Result<Vec<TrustedDevice>, KnownDeviceStoreError>;
+    async fn revoke_trusted_device(
+        &self,
+        email: &Email,
+        id: Uuid,
+    ) -> Result<(), KnownDeviceStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum KnownDeviceStoreError {
+    #[error("User not found")]
+    UserNotFound,
+    #[error("Trusted device not found")]
+    TrustedDeviceNotFound,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+#[async_trait::async_trait]
+pub trait RateLimitStore {
+    /// Takes a token from the bucket at `key`, creating a full bucket if there isn't one yet
+    async fn take_token(
+        &self,
+        key: &str,
+        policy: &RateLimitPolicy,
+    ) -> Result<RateLimitDecision, RateLimitStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum RateLimitStoreError {
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+/// An append-only log of audit events. Events are returned newest first and paginated by ID:
+/// pass the ID of the last event of one page as `before` to get the next.
+#[async_trait::async_trait]
+pub trait AuditLogStore {
+    async fn append(&self, event: NewAuditEvent) -> Result<(), AuditLogStoreError>;
+    async fn query(
+        &self,
+        filter: &AuditLogFilter,
+        before: Option<i64>,
+        limit: usize,
+    ) -> Result<Vec<AuditEvent>, AuditLogStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum AuditLogStoreError {
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+/// Webhook subscriptions along with the outbox of deliveries still to be made to them
+#[async_trait::async_trait]
+pub trait WebhookStore {
+    async fn add_subscription(
+        &self,
+        subscription: WebhookSubscription,
+    ) -> Result<(), WebhookStoreError>;
+    async fn get_subscriptions(&self) -> Result<Vec<WebhookSubscription>, WebhookStoreError>;
+    async fn get_subscription(&self, id: Uuid) -> Result<WebhookSubscription, WebhookStoreError>;
+    /// Also drops the subscription's deliveries, including any still pending
+    async fn delete_subscription(&self, id: Uuid) -> Result<(), WebhookStoreError>;
+    /// Queues a delivery of `event` to every subscription to its type, due immediately
+    async fn enqueue_event(&self, event: &WebhookEvent) -> Result<(), WebhookStoreError>;
+    /// Hands out up to `limit` pending deliveries due at `now`, deferring them to `lease_until`
+    /// so that other dispatchers don't pick them up while they are being sent.
+    async fn claim_due_deliveries(
+        &self,
+        now: DateTime<Utc>,
+        lease_until: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<DueWebhookDelivery>, WebhookStoreError>;
+    /// Logs an attempt at a delivery. A failed attempt is retried at `retry_at`, or gives the
+    /// delivery up for failed when there is none.
+    async fn record_attempt(
+        &self,
+        delivery_id: Uuid,
+        attempt: WebhookDeliveryAttempt,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<(), WebhookStoreError>;
+    /// The subscription's most recent deliveries, newest first
+    async fn get_deliveries(
+        &self,
+        subscription_id: Uuid,
+        limit: usize,
+    ) -> Result<Vec<WebhookDelivery>, WebhookStoreError>;
+}
+
+#[derive(Debug, Error)]
+pub enum WebhookStoreError {
+    #[error("Webhook subscription not found")]
+    SubscriptionNotFound,
+    #[error("Webhook delivery not found")]
+    DeliveryNotFound,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl PartialEq for WebhookStoreError {
+    fn eq(&self, other: &Self) -> bool {
+        matches!(
+            (self, other),
+            (Self::SubscriptionNotFound, Self::SubscriptionNotFound)
+                | (Self::DeliveryNotFound, Self::DeliveryNotFound)
+                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+        )
+    }
+}
+
+/// Pending 2FA challenges are keyed by login attempt ID, so a user can have several in flight at
+/// once (e.g. logging in on two devices). Adding a code beyond
+/// `MAX_PENDING_2FA_ATTEMPTS_PER_USER` evicts that user's oldest pending challenge.
 #[async_trait::async_trait]
 pub trait TwoFACodeStore {
     async fn add_code(
-        &mut self,
+        &self,
+        email: Email,
+        login_attempt_id: LoginAttemptId,
+        code: TwoFACode,
+    ) -> Result<(), TwoFACodeStoreError>;
+    /// Like `add_code`, but binds the code to the phone number it was sent to
+    async fn add_phone_number_code(
+        &self,
         email: Email,
+        phone_number: PhoneNumber,
         login_attempt_id: LoginAttemptId,
         code: TwoFACode,
     ) -> Result<(), TwoFACodeStoreError>;
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError>;
+    async fn remove_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<(), TwoFACodeStoreError>;
     async fn get_code(
         &self,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<(Email, TwoFACode), TwoFACodeStoreError>;
+    /// Like `get_code`, for codes added with `add_phone_number_code`. Codes that aren't bound to
+    /// a phone number are treated as missing.
+    async fn get_phone_number_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<(Email, PhoneNumber, TwoFACode), TwoFACodeStoreError>;
+    /// Removes the pending code if it belongs to `email` and matches `code`, so it can only be
+    /// redeemed once. Otherwise the attempt counts as failed, and once `MAX_FAILED_2FA_ATTEMPTS`
+    /// is reached the pending code is removed. Checking, removing and counting happen in one
+    /// step, so concurrent requests can't redeem a code twice or make more guesses than allowed.
+    async fn consume_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+        email: &Email,
+        code: &TwoFACode,
+    ) -> Result<(), TwoFACodeStoreError>;
+    /// Like `consume_code`, for codes added with `add_phone_number_code`, returning the number
+    /// the code was sent to. Codes that aren't bound to a phone number are treated as missing.
+    async fn consume_phone_number_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
         email: &Email,
-    ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError>;
+        code: &TwoFACode,
+    ) -> Result<PhoneNumber, TwoFACodeStoreError>;
+    /// Replaces the pending code with `code` so it can be sent again, returning the email the
+    /// attempt belongs to. The attempt keeps its original expiry and failure count. Fails if the
+    /// code was last sent less than `TWO_FA_RESEND_COOLDOWN_SECONDS` ago or has already been
+    /// resent `MAX_2FA_RESENDS` times.
+    async fn resend_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+        code: TwoFACode,
+    ) -> Result<Email, TwoFACodeStoreError>;
 }
 
 // Updated!
@@ -70,6 +375,12 @@ pub trait TwoFACodeStore {
 pub enum TwoFACodeStoreError {
     #[error("Login Attempt ID not found")]
     LoginAttemptIdNotFound,
+    #[error("Incorrect 2FA code")]
+    IncorrectCode,
+    #[error("2FA code was sent too recently")]
+    ResendCooldown { retry_after_seconds: u64 },
+    #[error("2FA code has been resent too many times")]
+    TooManyResends,
     #[error("Unexpected error")]
     UnexpectedError(#[source] Report),
 }
@@ -77,11 +388,23 @@ pub enum TwoFACodeStoreError {
 // New!
 impl PartialEq for TwoFACodeStoreError {
     fn eq(&self, other: &Self) -> bool {
-        matches!(
-            (self, other),
-            (Self::LoginAttemptIdNotFound, Self::LoginAttemptIdNotFound)
-                | (Self::UnexpectedError(_), Self::UnexpectedError(_))
-        )
+        match (self, other) {
+            (
+                Self::ResendCooldown {
+                    retry_after_seconds: a,
+                },
+                Self::ResendCooldown {
+                    retry_after_seconds: b,
+                },
+            ) => a == b,
+            _ => matches!(
+                (self, other),
+                (Self::LoginAttemptIdNotFound, Self::LoginAttemptIdNotFound)
+                    | (Self::IncorrectCode, Self::IncorrectCode)
+                    | (Self::TooManyResends, Self::TooManyResends)
+                    | (Self::UnexpectedError(_), Self::UnexpectedError(_))
+            ),
+        }
     }
 }
 
@@ -90,10 +413,18 @@ pub struct LoginAttemptId(Secret<String>);
 
 impl PartialEq for LoginAttemptId {
     fn eq(&self, other: &Self) -> bool {
-        self.0.expose_secret() == other.0.expose_secret()
+        constant_time_eq(&self.0, &other.0)
+    }
+}
+
+impl Hash for LoginAttemptId {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.0.expose_secret().hash(state);
     }
 }
 
+impl Eq for LoginAttemptId {}
+
 impl LoginAttemptId {
     pub fn parse(id: Secret<String>) -> Result<Self> {
         let id = uuid::Uuid::parse_str(id.expose_secret())
@@ -105,7 +436,7 @@ impl LoginAttemptId {
 impl ExposeSecret<String> for LoginAttemptId {
 
     fn expose_secret(&self) -> &String {
-        &self.0.expose_secret()
+        self.0.expose_secret()
     }
 }
 
@@ -126,7 +457,7 @@ pub struct TwoFACode(Secret<String>);
 
 impl PartialEq for TwoFACode {
     fn eq(&self, other: &Self) -> bool {
-        self.0.expose_secret() == other.0.expose_secret()
+        constant_time_eq(&self.0, &other.0)
     }
 }
 
@@ -152,4 +483,40 @@ impl AsRef<Secret<String>> for TwoFACode {
     fn as_ref(&self) -> &Secret<String> {
         &self.0
     }
-}
\ No newline at end of file
+}
+
+// Compares secrets without short-circuiting on the first differing byte, so response timing
+// doesn't reveal how much of a guessed code was correct.
+fn constant_time_eq(a: &Secret<String>, b: &Secret<String>) -> bool {
+    a.expose_secret()
+        .as_bytes()
+        .ct_eq(b.expose_secret().as_bytes())
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn two_fa_codes_with_same_digits_are_equal() {
+        let code = TwoFACode::parse(Secret::new("123456".to_owned())).unwrap();
+        let same_code = TwoFACode::parse(Secret::new("123456".to_owned())).unwrap();
+        assert_eq!(code, same_code);
+    }
+
+    #[test]
+    fn two_fa_codes_with_different_digits_are_not_equal() {
+        let code = TwoFACode::parse(Secret::new("123456".to_owned())).unwrap();
+        let other_code = TwoFACode::parse(Secret::new("123457".to_owned())).unwrap();
+        assert_ne!(code, other_code);
+    }
+
+    #[test]
+    fn login_attempt_ids_are_compared_by_value() {
+        let id = LoginAttemptId::default();
+        let same_id = LoginAttemptId::parse(id.as_ref().clone()).unwrap();
+        assert_eq!(id, same_id);
+        assert_ne!(id, LoginAttemptId::default());
+    }
+}
@@ -36,6 +36,18 @@ impl Email {
       )))
     }
   }
+
+  /// Hides all but the first character of the local part, e.g. `j***@example.com`
+  pub fn masked(&self) -> String {
+    let email = self.0.expose_secret();
+    match email.split_once('@') {
+      Some((local_part, domain)) => {
+        let first = local_part.chars().next().map(String::from).unwrap_or_default();
+        format!("{}***@{}", first, domain)
+      }
+      None => "***".to_owned(),
+    }
+  }
 }
 
 impl AsRef<Secret<String>> for Email {
@@ -70,6 +82,12 @@ mod tests {
         assert!(Email::parse(email).is_err());
     }
 
+    #[test]
+    fn email_is_masked() {
+        let email = Email::parse(Secret::new("jane.doe@example.com".to_string())).unwrap();
+        assert_eq!(email.masked(), "j***@example.com");
+    }
+
     #[derive(Debug, Clone)]
     struct ValidEmailFixture(pub String);
 
@@ -1,18 +1,28 @@
 use color_eyre::eyre::Report;
 use thiserror::Error;
 
+use super::PasswordPolicyViolation;
+
 #[derive(Debug, Error)]
 pub enum AuthAPIError {
     #[error("User already exists")]
     UserAlreadyExists,
     #[error("Invalid credentials")]
     InvalidCredentials,
+    #[error("Password does not meet the password policy")]
+    WeakPassword(Vec<PasswordPolicyViolation>),
     #[error("Incorrect credentials")]
     IncorrectCredentials,
     #[error("Missing token")]
     MissingToken,
     #[error("Invalid token")]
     InvalidToken,
+    #[error("Phone number not verified")]
+    PhoneNumberNotVerified,
+    #[error("Not found")]
+    NotFound,
+    #[error("Too many requests")]
+    TooManyRequests { retry_after_seconds: Option<u64> },
     #[error("Unexpected error")]
     UnexpectedError(#[source] Report),
 }
\ No newline at end of file
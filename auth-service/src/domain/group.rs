@@ -0,0 +1,71 @@
+use chrono::{DateTime, SubsecRound, Utc};
+use uuid::Uuid;
+
+/// A named set of users, as provisioned by an identity provider
+#[derive(Debug, Clone, PartialEq)]
+pub struct Group {
+    pub id: Uuid,
+    pub display_name: String,
+    pub external_id: Option<String>,
+    /// IDs of the member users
+    pub members: Vec<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Group {
+    pub fn new(display_name: String, external_id: Option<String>, members: Vec<Uuid>) -> Self {
+        // Postgres keeps timestamps to the microsecond
+        let now = Utc::now().trunc_subsecs(6);
+        let mut group = Self {
+            id: Uuid::new_v4(),
+            display_name,
+            external_id,
+            members: Vec::new(),
+            created_at: now,
+            updated_at: now,
+        };
+        group.set_members(members);
+        group
+    }
+
+    /// Replaces the members, ignoring duplicates
+    pub fn set_members(&mut self, members: Vec<Uuid>) {
+        self.members.clear();
+        for member in members {
+            self.add_member(member);
+        }
+    }
+
+    pub fn add_member(&mut self, member: Uuid) {
+        if !self.members.contains(&member) {
+            self.members.push(member);
+        }
+    }
+
+    pub fn remove_member(&mut self, member: Uuid) {
+        self.members.retain(|candidate| *candidate != member);
+    }
+
+    pub fn touch(&mut self) {
+        self.updated_at = Utc::now().trunc_subsecs(6);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn members_are_deduplicated() {
+        let member = Uuid::new_v4();
+        let mut group = Group::new("Engineering".to_owned(), None, vec![member, member]);
+        assert_eq!(group.members, vec![member]);
+
+        group.add_member(member);
+        assert_eq!(group.members, vec![member]);
+
+        group.remove_member(member);
+        assert!(group.members.is_empty());
+    }
+}
@@ -0,0 +1,10 @@
+use color_eyre::eyre::Result;
+
+// A dependency the service can't serve requests without, checked before it reports itself ready
+#[async_trait::async_trait]
+pub trait HealthCheck {
+    /// Names the dependency in readiness reports, e.g. `postgres`
+    fn name(&self) -> &'static str;
+
+    async fn check(&self) -> Result<()>;
+}
@@ -0,0 +1,181 @@
+use std::net::IpAddr;
+
+use chrono::{DateTime, Duration, SubsecRound, Utc};
+use rand::{distributions::Alphanumeric, Rng};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+const DEVICE_ID_LENGTH: usize = 32;
+
+/// The device a login came from, as far as the browser lets us tell devices apart
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoginDevice {
+    /// Random ID kept in a long-lived cookie, new when the browser doesn't send one
+    pub device_id: String,
+    pub user_agent: Option<String>,
+    pub ip: String,
+}
+
+impl LoginDevice {
+    pub fn new(device_id: Option<String>, user_agent: Option<String>, ip: String) -> Self {
+        let device_id = device_id
+            .filter(|device_id| Self::is_valid_device_id(device_id))
+            .unwrap_or_else(Self::generate_device_id);
+
+        Self {
+            device_id,
+            user_agent,
+            ip,
+        }
+    }
+
+    fn generate_device_id() -> String {
+        rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(DEVICE_ID_LENGTH)
+            .map(char::from)
+            .collect()
+    }
+
+    fn is_valid_device_id(device_id: &str) -> bool {
+        device_id.len() == DEVICE_ID_LENGTH && device_id.chars().all(|c| c.is_ascii_alphanumeric())
+    }
+
+    /// The network the login came from: the /24 of an IPv4 address or the /48 of an IPv6 one,
+    /// so that a device keeps its fingerprint when its ISP hands out a new address
+    pub fn ip_prefix(&self) -> String {
+        match self.ip.parse::<IpAddr>() {
+            Ok(IpAddr::V4(ip)) => {
+                let [a, b, c, _] = ip.octets();
+                format!("{}.{}.{}.0/24", a, b, c)
+            }
+            Ok(IpAddr::V6(ip)) => {
+                let [a, b, c, ..] = ip.segments();
+                format!("{:x}:{:x}:{:x}::/48", a, b, c)
+            }
+            Err(_) => self.ip.clone(),
+        }
+    }
+
+    /// Identifies the device among those a user has logged in from
+    pub fn fingerprint(&self) -> String {
+        let mut hasher = Sha256::new();
+        for part in [
+            self.device_id.as_str(),
+            self.user_agent.as_deref().unwrap_or_default(),
+            &self.ip_prefix(),
+        ] {
+            hasher.update((part.len() as u64).to_be_bytes());
+            hasher.update(part.as_bytes());
+        }
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+/// Whether a successful login came from a device the user has logged in from before
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceSighting {
+    /// The user's first login, from which there's nothing to compare against
+    First,
+    Known,
+    New,
+}
+
+/// A device the user chose to skip 2FA on until `expires_at`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrustedDevice {
+    pub id: Uuid,
+    pub user_agent: Option<String>,
+    pub ip_prefix: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl TrustedDevice {
+    pub fn new(device: &LoginDevice, trusted_for: Duration) -> Self {
+        // Postgres keeps timestamps to the microsecond
+        let created_at = Utc::now().trunc_subsecs(6);
+        Self {
+            id: Uuid::new_v4(),
+            user_agent: device.user_agent.clone(),
+            ip_prefix: device.ip_prefix(),
+            created_at,
+            expires_at: created_at + trusted_for,
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(device_id: &str, user_agent: &str, ip: &str) -> LoginDevice {
+        LoginDevice::new(
+            Some(device_id.to_owned()),
+            Some(user_agent.to_owned()),
+            ip.to_owned(),
+        )
+    }
+
+    const DEVICE_ID: &str = "abcdefghijklmnopqrstuvwxyz012345";
+
+    #[test]
+    fn should_generate_device_id_when_missing_or_malformed() {
+        let generated = LoginDevice::new(None, None, "127.0.0.1".to_owned());
+        assert!(LoginDevice::is_valid_device_id(&generated.device_id));
+
+        let replaced =
+            LoginDevice::new(Some("not valid!".to_owned()), None, "127.0.0.1".to_owned());
+        assert!(LoginDevice::is_valid_device_id(&replaced.device_id));
+        assert_ne!(replaced.device_id, "not valid!");
+
+        assert_eq!(device(DEVICE_ID, "ua", "127.0.0.1").device_id, DEVICE_ID);
+    }
+
+    #[test]
+    fn should_truncate_ip_to_prefix() {
+        assert_eq!(
+            device(DEVICE_ID, "ua", "203.0.113.77").ip_prefix(),
+            "203.0.113.0/24"
+        );
+        assert_eq!(
+            device(DEVICE_ID, "ua", "2001:db8:abcd:12::1").ip_prefix(),
+            "2001:db8:abcd::/48"
+        );
+        assert_eq!(device(DEVICE_ID, "ua", "unknown").ip_prefix(), "unknown");
+    }
+
+    #[test]
+    fn should_keep_fingerprint_within_network() {
+        let device = device(DEVICE_ID, "Firefox", "203.0.113.77");
+
+        assert_eq!(
+            device.fingerprint(),
+            LoginDevice {
+                ip: "203.0.113.8".to_owned(),
+                ..device.clone()
+            }
+            .fingerprint()
+        );
+        assert_ne!(
+            device.fingerprint(),
+            LoginDevice {
+                ip: "198.51.100.77".to_owned(),
+                ..device.clone()
+            }
+            .fingerprint()
+        );
+        assert_ne!(
+            device.fingerprint(),
+            LoginDevice {
+                user_agent: Some("Chrome".to_owned()),
+                ..device.clone()
+            }
+            .fingerprint()
+        );
+    }
+}
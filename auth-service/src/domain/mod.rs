@@ -1,13 +1,37 @@
+pub mod audit;
+pub mod breached_passwords;
 pub mod user;
 pub mod error;
 pub mod data_stores;
 pub mod email;
 pub mod password;
+pub mod password_policy;
 pub mod email_client;
+pub mod login_device;
+pub mod group;
+pub mod health;
+pub mod phone_number;
+pub mod rate_limit;
+pub mod scim;
+pub mod sms_client;
+pub mod two_fa_channel;
+pub mod webhook;
 
+pub use audit::*;
+pub use breached_passwords::*;
 pub use user::*;
 pub use error::*;
 pub use data_stores::*;
 pub use email::*;
 pub use password::*;
-pub use email_client::*;
\ No newline at end of file
+pub use password_policy::*;
+pub use email_client::*;
+pub use login_device::*;
+pub use group::*;
+pub use health::*;
+pub use phone_number::*;
+pub use rate_limit::*;
+pub use scim::*;
+pub use sms_client::*;
+pub use two_fa_channel::*;
+pub use webhook::*;
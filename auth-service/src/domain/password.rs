@@ -1,6 +1,10 @@
 use color_eyre::eyre::{eyre, Result};
+use rand::{distributions::Alphanumeric, Rng};
 use secrecy::{ExposeSecret, Secret};
 
+/// Shorter passwords are refused whatever the password policy
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
 #[derive(Debug, Clone)] // Updated
 pub struct Password(Secret<String>); //  Updated
 
@@ -13,13 +17,23 @@ impl PartialEq for Password {
 
 impl Password {
   pub fn parse(password: Secret<String>) -> Result<Password> {
-    if password.expose_secret().len() >= 8 {
+    if password.expose_secret().len() >= MIN_PASSWORD_LENGTH {
       Ok(Self(password))
     }
     else {
       Err(eyre!("Failed to parse string to a Password type"))
     }
   }
+
+  /// A password too long and random to guess, so that only a password reset lets the user in
+  pub fn unusable() -> Password {
+    let password: String = rand::thread_rng()
+      .sample_iter(&Alphanumeric)
+      .take(64)
+      .map(char::from)
+      .collect();
+    Self(Secret::new(password))
+  }
 }
 
 impl AsRef<Secret<String>> for Password {
@@ -0,0 +1,442 @@
+use std::{collections::HashMap, sync::Arc};
+
+use lazy_static::lazy_static;
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+
+use super::{Email, Password};
+
+lazy_static! {
+    static ref BUNDLED_COMMON_PASSWORDS: CommonPasswords =
+        CommonPasswords::parse(include_str!("common_passwords.txt"));
+}
+
+// Shorter dictionary matches are too likely to be coincidental to count against a password
+const MIN_DICTIONARY_MATCH: usize = 4;
+const MIN_PATTERN_RUN: usize = 3;
+
+/// Passwords attackers try first, most common first, one per line. Blank lines and lines
+/// starting with `#` are skipped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommonPasswords(Arc<HashMap<String, usize>>);
+
+impl CommonPasswords {
+    pub fn parse(list: &str) -> Self {
+        // Lowercased, each mapped to its frequency rank starting at 1
+        let ranks = list
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .enumerate()
+            .map(|(rank, password)| (password.to_lowercase(), rank + 1))
+            .collect::<HashMap<_, _>>();
+        Self(Arc::new(ranks))
+    }
+
+    fn rank(&self, password: &str) -> Option<usize> {
+        self.0.get(password).copied()
+    }
+}
+
+impl Default for CommonPasswords {
+    /// The list bundled with the service
+    fn default() -> Self {
+        BUNDLED_COMMON_PASSWORDS.clone()
+    }
+}
+
+/// The rules a new password must satisfy. Existing passwords are never re-checked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasswordPolicy {
+    /// In characters, not bytes
+    pub min_length: usize,
+    pub max_length: usize,
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    /// Reject passwords containing the local part of the user's email
+    pub reject_email: bool,
+    /// Reject passwords on `common_passwords`
+    pub reject_common: bool,
+    /// Also weakens the strength score of passwords built from its entries
+    pub common_passwords: CommonPasswords,
+    /// The lowest acceptable strength score from 0 to 4, see [`strength_score`]
+    pub min_strength_score: u8,
+    /// How many of the user's most recent passwords, the current one included, a new password
+    /// must differ from. Enforced by the user store, which holds the history.
+    pub history_size: usize,
+}
+
+impl PasswordPolicy {
+    /// A policy that only enforces the minimum length `Password::parse` already checks
+    pub fn minimal() -> Self {
+        Self {
+            min_length: 8,
+            max_length: 128,
+            require_lowercase: false,
+            require_uppercase: false,
+            require_digit: false,
+            require_symbol: false,
+            reject_email: false,
+            reject_common: false,
+            common_passwords: CommonPasswords::default(),
+            min_strength_score: 0,
+            history_size: 0,
+        }
+    }
+
+    /// The violation reported when a password is found in the user's history
+    pub fn reuse_violation(&self) -> PasswordPolicyViolation {
+        PasswordPolicyViolation::new(
+            PasswordRule::Reused,
+            format!(
+                "Password must differ from your last {} passwords",
+                self.history_size
+            ),
+        )
+    }
+
+    /// Checks `password` for `email`, returning every rule it breaks
+    pub fn check(
+        &self,
+        password: &Password,
+        email: &Email,
+    ) -> Result<(), Vec<PasswordPolicyViolation>> {
+        let password = password.as_ref().expose_secret();
+        let length = password.chars().count();
+        let mut violations = vec![];
+
+        if length < self.min_length {
+            violations.push(PasswordPolicyViolation::new(
+                PasswordRule::MinLength,
+                format!(
+                    "Password must be at least {} characters long",
+                    self.min_length
+                ),
+            ));
+        }
+        if length > self.max_length {
+            violations.push(PasswordPolicyViolation::new(
+                PasswordRule::MaxLength,
+                format!(
+                    "Password must be at most {} characters long",
+                    self.max_length
+                ),
+            ));
+        }
+
+        let classes = [
+            (
+                self.require_lowercase,
+                PasswordRule::Lowercase,
+                "a lowercase letter",
+                password.chars().any(char::is_lowercase),
+            ),
+            (
+                self.require_uppercase,
+                PasswordRule::Uppercase,
+                "an uppercase letter",
+                password.chars().any(char::is_uppercase),
+            ),
+            (
+                self.require_digit,
+                PasswordRule::Digit,
+                "a digit",
+                password.chars().any(|c| c.is_ascii_digit()),
+            ),
+            (
+                self.require_symbol,
+                PasswordRule::Symbol,
+                "a symbol",
+                password.chars().any(|c| !c.is_alphanumeric()),
+            ),
+        ];
+        for (required, rule, description, present) in classes {
+            if required && !present {
+                violations.push(PasswordPolicyViolation::new(
+                    rule,
+                    format!("Password must contain {}", description),
+                ));
+            }
+        }
+
+        let lowercase = password.to_lowercase();
+
+        if self.reject_email {
+            let email = email.as_ref().expose_secret().to_lowercase();
+            let local_part = email.split('@').next().unwrap_or_default();
+            if local_part.chars().count() >= MIN_PATTERN_RUN && lowercase.contains(local_part) {
+                violations.push(PasswordPolicyViolation::new(
+                    PasswordRule::ContainsEmail,
+                    "Password must not contain your email address".to_owned(),
+                ));
+            }
+        }
+
+        if self.reject_common && self.common_passwords.rank(&lowercase).is_some() {
+            violations.push(PasswordPolicyViolation::new(
+                PasswordRule::Common,
+                "Password is too common".to_owned(),
+            ));
+        }
+
+        let score = strength_score(password, &self.common_passwords);
+        if score < self.min_strength_score {
+            violations.push(PasswordPolicyViolation::new(
+                PasswordRule::Strength,
+                format!(
+                    "Password is too easy to guess (strength {} of 4, at least {} required)",
+                    score, self.min_strength_score
+                ),
+            ));
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: 10,
+            max_length: 128,
+            reject_email: true,
+            reject_common: true,
+            min_strength_score: 3,
+            history_size: 5,
+            ..Self::minimal()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PasswordRule {
+    MinLength,
+    MaxLength,
+    Lowercase,
+    Uppercase,
+    Digit,
+    Symbol,
+    ContainsEmail,
+    Common,
+    Strength,
+    Breached,
+    Reused,
+}
+
+/// A rule a password broke, with a message that can be shown to the user
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PasswordPolicyViolation {
+    pub rule: PasswordRule,
+    pub message: String,
+}
+
+impl PasswordPolicyViolation {
+    fn new(rule: PasswordRule, message: String) -> Self {
+        Self { rule, message }
+    }
+}
+
+/// Estimates how hard `password` is to guess on the same 0 to 4 scale as zxcvbn.
+///
+/// The password is split greedily into `common_passwords`, runs of a repeated character,
+/// sequences such as `abc` or `987`, and single characters. Each part contributes the number
+/// of guesses an attacker trying those patterns first would need, and the score is derived
+/// from their product.
+pub fn strength_score(password: &str, common_passwords: &CommonPasswords) -> u8 {
+    let chars: Vec<char> = password.to_lowercase().chars().collect();
+    let alphabet = alphabet_size(password) as f64;
+    let mut log10_guesses = 0.0;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let word = dictionary_match(&chars[i..], common_passwords);
+        let run = pattern_run(&chars[i..]);
+
+        // Whichever pattern covers more of the password wins
+        let (length, guesses) = match (word, run) {
+            (Some((word_length, _)), Some(run)) if run > word_length => {
+                (run, alphabet * run as f64)
+            }
+            (Some((word_length, rank)), _) => (word_length, rank as f64 * 2.0),
+            (None, Some(run)) => (run, alphabet * run as f64),
+            (None, None) => (1, alphabet),
+        };
+
+        log10_guesses += guesses.log10();
+        i += length;
+    }
+
+    match log10_guesses {
+        g if g < 3.0 => 0,
+        g if g < 6.0 => 1,
+        g if g < 8.0 => 2,
+        g if g < 10.0 => 3,
+        _ => 4,
+    }
+}
+
+// The number of characters in the classes `password` draws from
+fn alphabet_size(password: &str) -> usize {
+    let mut size = 0;
+    if password.chars().any(|c| c.is_ascii_lowercase()) {
+        size += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_uppercase()) {
+        size += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_digit()) {
+        size += 10;
+    }
+    if password
+        .chars()
+        .any(|c| c.is_ascii_punctuation() || c == ' ')
+    {
+        size += 33;
+    }
+    if !password.is_ascii() {
+        size += 100;
+    }
+    size.max(10)
+}
+
+// The longest common password at the start of `chars`, with its rank
+fn dictionary_match(chars: &[char], common_passwords: &CommonPasswords) -> Option<(usize, usize)> {
+    (MIN_DICTIONARY_MATCH..=chars.len())
+        .rev()
+        .find_map(|length| {
+            let candidate: String = chars[..length].iter().collect();
+            common_passwords.rank(&candidate).map(|rank| (length, rank))
+        })
+}
+
+// The length of a repeated character or sequence at the start of `chars`, if long enough
+fn pattern_run(chars: &[char]) -> Option<usize> {
+    if chars.len() < MIN_PATTERN_RUN {
+        return None;
+    }
+
+    let step = chars[1] as i64 - chars[0] as i64;
+    if step.abs() > 1 {
+        return None;
+    }
+
+    let length = 1 + chars
+        .windows(2)
+        .take_while(|pair| pair[1] as i64 - pair[0] as i64 == step)
+        .count();
+
+    (length >= MIN_PATTERN_RUN).then_some(length)
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+
+    use super::*;
+
+    fn check(policy: &PasswordPolicy, password: &str) -> Vec<PasswordRule> {
+        let password = Password::parse(Secret::new(password.to_owned())).unwrap();
+        let email = Email::parse(Secret::new("jane.doe@example.com".to_owned())).unwrap();
+        match policy.check(&password, &email) {
+            Ok(()) => vec![],
+            Err(violations) => violations.into_iter().map(|v| v.rule).collect(),
+        }
+    }
+
+    #[test]
+    fn minimal_policy_accepts_any_parsed_password() {
+        assert!(check(&PasswordPolicy::minimal(), "password").is_empty());
+    }
+
+    #[test]
+    fn default_policy_accepts_strong_passwords() {
+        let policy = PasswordPolicy::default();
+        assert!(check(&policy, "correct horse battery staple").is_empty());
+        assert!(check(&policy, "vX7#qLp2!mZr").is_empty());
+    }
+
+    #[test]
+    fn every_broken_rule_is_reported() {
+        let policy = PasswordPolicy::default();
+        assert_eq!(
+            check(&policy, "password"),
+            vec![
+                PasswordRule::MinLength,
+                PasswordRule::Common,
+                PasswordRule::Strength
+            ]
+        );
+        assert_eq!(
+            check(&policy, "jane.doe-rocks-2024"),
+            vec![PasswordRule::ContainsEmail]
+        );
+    }
+
+    #[test]
+    fn length_is_counted_in_characters() {
+        let policy = PasswordPolicy {
+            min_length: 8,
+            max_length: 8,
+            ..PasswordPolicy::minimal()
+        };
+        assert!(check(&policy, "ääääääää").is_empty());
+        assert_eq!(check(&policy, "123456789"), vec![PasswordRule::MaxLength]);
+    }
+
+    #[test]
+    fn character_classes_are_enforced() {
+        let policy = PasswordPolicy {
+            require_lowercase: true,
+            require_uppercase: true,
+            require_digit: true,
+            require_symbol: true,
+            ..PasswordPolicy::minimal()
+        };
+        assert_eq!(
+            check(&policy, "abcdefgh"),
+            vec![
+                PasswordRule::Uppercase,
+                PasswordRule::Digit,
+                PasswordRule::Symbol
+            ]
+        );
+        assert!(check(&policy, "Abcdefg1!").is_empty());
+    }
+
+    #[test]
+    fn common_passwords_are_matched_case_insensitively() {
+        let policy = PasswordPolicy {
+            reject_common: true,
+            ..PasswordPolicy::minimal()
+        };
+        assert_eq!(check(&policy, "PassWord123"), vec![PasswordRule::Common]);
+    }
+
+    #[test]
+    fn common_passwords_can_be_replaced() {
+        let policy = PasswordPolicy {
+            reject_common: true,
+            common_passwords: CommonPasswords::parse("# Ours\n\nAcmeCorp2024\n"),
+            ..PasswordPolicy::minimal()
+        };
+        assert_eq!(check(&policy, "acmecorp2024"), vec![PasswordRule::Common]);
+        assert!(check(&policy, "password").is_empty());
+    }
+
+    #[test]
+    fn predictable_passwords_score_low() {
+        let score = |password| strength_score(password, &CommonPasswords::default());
+        assert_eq!(score("password"), 0);
+        assert_eq!(score("aaaaaaaaaaaa"), 0);
+        assert!(score("abcdefghijkl") <= 1);
+        assert!(score("password2024") <= 2);
+        assert_eq!(score("vX7#qLp2!mZr"), 4);
+    }
+}
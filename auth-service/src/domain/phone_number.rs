@@ -0,0 +1,107 @@
+use color_eyre::eyre::{eyre, Result};
+use secrecy::{ExposeSecret, Secret};
+
+/// A phone number in E.164 format, e.g. `+14155551234`
+#[derive(Debug, Clone)]
+pub struct PhoneNumber(Secret<String>);
+
+impl PartialEq for PhoneNumber {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.expose_secret() == other.0.expose_secret()
+    }
+}
+
+impl PhoneNumber {
+    pub fn parse(phone_number: Secret<String>) -> Result<PhoneNumber> {
+        let digits = phone_number
+            .expose_secret()
+            .strip_prefix('+')
+            .ok_or(eyre!("Phone number must start with a country code"))?;
+
+        if (8..=15).contains(&digits.len()) && digits.bytes().all(|b| b.is_ascii_digit()) {
+            Ok(Self(phone_number))
+        } else {
+            Err(eyre!("Failed to parse string to a PhoneNumber type"))
+        }
+    }
+
+    /// Hides all but the country code and the last four digits, e.g. `+1 ***-***-1234`
+    pub fn masked(&self) -> String {
+        let digits = &self.0.expose_secret()[1..];
+        let (country_code, national_number) = digits.split_at(country_code_len(digits));
+        let (hidden, visible) = national_number.split_at(national_number.len().saturating_sub(4));
+
+        if country_code == "1" && national_number.len() == 10 {
+            format!("+1 ***-***-{}", visible)
+        } else {
+            format!("+{} {}{}", country_code, "*".repeat(hidden.len()), visible)
+        }
+    }
+}
+
+impl AsRef<Secret<String>> for PhoneNumber {
+    fn as_ref(&self) -> &Secret<String> {
+        &self.0
+    }
+}
+
+// ITU country codes are prefix-free, so the length can be read off the leading digits
+fn country_code_len(digits: &str) -> usize {
+    const TWO_DIGIT_CODES: [&str; 44] = [
+        "20", "27", "30", "31", "32", "33", "34", "36", "39", "40", "41", "43", "44", "45", "46",
+        "47", "48", "49", "51", "52", "53", "54", "55", "56", "57", "58", "60", "61", "62", "63",
+        "64", "65", "66", "81", "82", "84", "86", "90", "91", "92", "93", "94", "95", "98",
+    ];
+
+    match &digits[..1] {
+        "1" | "7" => 1,
+        _ if TWO_DIGIT_CODES.contains(&&digits[..2]) => 2,
+        _ => 3,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PhoneNumber;
+
+    use secrecy::Secret;
+
+    #[test]
+    fn empty_string_is_rejected() {
+        let phone_number = Secret::new("".to_owned());
+        assert!(PhoneNumber::parse(phone_number).is_err());
+    }
+
+    #[test]
+    fn phone_number_missing_country_code_is_rejected() {
+        let phone_number = Secret::new("4155551234".to_owned());
+        assert!(PhoneNumber::parse(phone_number).is_err());
+    }
+
+    #[test]
+    fn phone_number_with_non_digits_is_rejected() {
+        let phone_number = Secret::new("+1 (415) 555-1234".to_owned());
+        assert!(PhoneNumber::parse(phone_number).is_err());
+    }
+
+    #[test]
+    fn e164_phone_number_is_parsed_successfully() {
+        let phone_number = Secret::new("+14155551234".to_owned());
+        assert!(PhoneNumber::parse(phone_number).is_ok());
+    }
+
+    #[test]
+    fn north_american_number_is_masked() {
+        let phone_number = PhoneNumber::parse(Secret::new("+14155551234".to_owned())).unwrap();
+        assert_eq!(phone_number.masked(), "+1 ***-***-1234");
+    }
+
+    #[test]
+    fn international_number_is_masked() {
+        let phone_number = PhoneNumber::parse(Secret::new("+447911123456".to_owned())).unwrap();
+        assert_eq!(phone_number.masked(), "+44 ******3456");
+
+        let phone_number = PhoneNumber::parse(Secret::new("+353861234567".to_owned())).unwrap();
+        assert_eq!(phone_number.masked(), "+353 *****4567");
+    }
+}
@@ -0,0 +1,224 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use sha2::{Digest, Sha256};
+
+/// A token bucket holding up to `capacity` tokens, refilled with one token every `refill_interval`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitPolicy {
+    pub capacity: u32,
+    pub refill_interval: Duration,
+}
+
+impl RateLimitPolicy {
+    /// Allows bursts of up to `capacity` requests, refilling the whole bucket over `period`
+    pub fn new(capacity: u32, period: Duration) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            capacity,
+            refill_interval: period / capacity,
+        }
+    }
+
+    pub fn per_second(capacity: u32) -> Self {
+        Self::new(capacity, Duration::from_secs(1))
+    }
+
+    pub fn per_minute(capacity: u32) -> Self {
+        Self::new(capacity, Duration::from_secs(60))
+    }
+
+    /// Milliseconds each token adds to the bucket's theoretical arrival time
+    pub fn interval_ms(&self) -> i64 {
+        (self.refill_interval.as_millis() as i64).max(1)
+    }
+
+    /// Milliseconds a full bucket's worth of tokens adds to its theoretical arrival time
+    pub fn burst_ms(&self) -> i64 {
+        self.interval_ms() * self.capacity as i64
+    }
+
+    /// Takes a token at `now_ms` from a bucket whose state is its theoretical arrival time
+    /// (GCRA), i.e. the instant at which the bucket would be full again. Returns the decision
+    /// along with the bucket's new state, which is `None` when the request is rejected and the
+    /// bucket is left untouched.
+    pub fn apply(&self, tat_ms: Option<i64>, now_ms: i64) -> (RateLimitDecision, Option<i64>) {
+        let interval_ms = self.interval_ms();
+        let burst_ms = self.burst_ms();
+
+        let tat_ms = tat_ms.unwrap_or(now_ms).max(now_ms);
+        let new_tat_ms = tat_ms + interval_ms;
+        let allow_at_ms = new_tat_ms - burst_ms;
+
+        if now_ms < allow_at_ms {
+            let decision = RateLimitDecision {
+                allowed: false,
+                limit: self.capacity,
+                remaining: 0,
+                reset_after_seconds: ceil_seconds(tat_ms - now_ms),
+                retry_after_seconds: Some(ceil_seconds(allow_at_ms - now_ms)),
+            };
+            return (decision, None);
+        }
+
+        let decision = RateLimitDecision {
+            allowed: true,
+            limit: self.capacity,
+            remaining: ((now_ms - allow_at_ms) / interval_ms) as u32,
+            reset_after_seconds: ceil_seconds(new_tat_ms - now_ms),
+            retry_after_seconds: None,
+        };
+        (decision, Some(new_tat_ms))
+    }
+}
+
+fn ceil_seconds(ms: i64) -> u64 {
+    (ms.max(0) as u64).div_ceil(1000)
+}
+
+/// The outcome of taking a token from a bucket, in the shape of the `RateLimit-*` headers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds until the bucket is full again
+    pub reset_after_seconds: u64,
+    /// Seconds until a rejected request may be retried
+    pub retry_after_seconds: Option<u64>,
+}
+
+/// What a bucket is keyed by. Requests missing the email or API key fall back to their client IP.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RateLimitKey {
+    Ip,
+    /// The `email` field of the JSON request body
+    Email,
+    /// The value of the given request header
+    ApiKey {
+        header: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimitRule {
+    pub policy: RateLimitPolicy,
+    pub key: RateLimitKey,
+}
+
+impl RateLimitRule {
+    pub fn new(policy: RateLimitPolicy, key: RateLimitKey) -> Self {
+        Self { policy, key }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateLimitConfig {
+    /// Applied to every request, on top of any rule for its route
+    pub global: Option<RateLimitRule>,
+    /// Rules keyed by request path, e.g. `/login`
+    pub routes: HashMap<String, RateLimitRule>,
+    /// Take the client IP from the last `X-Forwarded-For` entry. Only enable this behind a
+    /// reverse proxy that sets the header, otherwise clients can pick their own IP.
+    pub trust_forwarded_for: bool,
+    /// Digests of the API keys whose requests are never limited, such as that of app-service,
+    /// which checks a token on every page view
+    pub exempt_api_keys: HashSet<String>,
+}
+
+impl RateLimitConfig {
+    /// A config that never limits anything
+    pub fn disabled() -> Self {
+        Self {
+            global: None,
+            routes: HashMap::new(),
+            trust_forwarded_for: false,
+            exempt_api_keys: HashSet::new(),
+        }
+    }
+
+    pub fn with_global(mut self, rule: RateLimitRule) -> Self {
+        self.global = Some(rule);
+        self
+    }
+
+    pub fn with_route(mut self, path: &str, rule: RateLimitRule) -> Self {
+        self.routes.insert(path.to_owned(), rule);
+        self
+    }
+
+    pub fn trust_forwarded_for(mut self, trust: bool) -> Self {
+        self.trust_forwarded_for = trust;
+        self
+    }
+
+    /// Exempts requests carrying `api_key` in the `x-api-key` header from every limit
+    pub fn exempt_api_key(mut self, api_key: &str) -> Self {
+        self.exempt_api_keys
+            .insert(api_key_digest(api_key.as_bytes()));
+        self
+    }
+}
+
+/// API keys are only kept as digests, both in bucket keys and in `exempt_api_keys`
+pub fn api_key_digest(api_key: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(api_key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fresh_bucket_allows_a_full_burst() {
+        let policy = RateLimitPolicy::per_minute(3);
+        let mut tat = None;
+
+        for expected_remaining in [2, 1, 0] {
+            let (decision, new_tat) = policy.apply(tat, 0);
+            assert!(decision.allowed);
+            assert_eq!(decision.limit, 3);
+            assert_eq!(decision.remaining, expected_remaining);
+            tat = new_tat;
+        }
+
+        let (decision, new_tat) = policy.apply(tat, 0);
+        assert!(!decision.allowed);
+        assert_eq!(decision.remaining, 0);
+        assert_eq!(decision.retry_after_seconds, Some(20));
+        assert_eq!(decision.reset_after_seconds, 60);
+        assert_eq!(new_tat, None);
+    }
+
+    #[test]
+    fn bucket_refills_one_token_per_interval() {
+        let policy = RateLimitPolicy::per_minute(3);
+        let mut tat = None;
+        for _ in 0..3 {
+            tat = policy.apply(tat, 0).1;
+        }
+
+        let (decision, _) = policy.apply(tat, 19_999);
+        assert!(!decision.allowed);
+        assert_eq!(decision.retry_after_seconds, Some(1));
+
+        let (decision, tat) = policy.apply(tat, 20_000);
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, 0);
+
+        let (decision, _) = policy.apply(tat, 20_000);
+        assert!(!decision.allowed);
+    }
+
+    #[test]
+    fn idle_bucket_does_not_exceed_capacity() {
+        let policy = RateLimitPolicy::per_second(2);
+        let (_, tat) = policy.apply(None, 0);
+
+        let (decision, _) = policy.apply(tat, 3_600_000);
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, 1);
+    }
+}
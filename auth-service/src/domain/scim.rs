@@ -0,0 +1,462 @@
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::Report;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+use super::{Group, User};
+
+pub const USER_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+pub const GROUP_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:Group";
+pub const LIST_RESPONSE_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+pub const PATCH_OP_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:PatchOp";
+pub const ERROR_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:Error";
+pub const SERVICE_PROVIDER_CONFIG_SCHEMA: &str =
+    "urn:ietf:params:scim:schemas:core:2.0:ServiceProviderConfig";
+
+/// Errors in the shape of RFC 7644 section 3.12, with the detail sent back to the client
+#[derive(Debug, Error)]
+pub enum ScimError {
+    #[error("{0}")]
+    InvalidFilter(String),
+    #[error("{0}")]
+    InvalidSyntax(String),
+    #[error("{0}")]
+    InvalidPath(String),
+    #[error("{0}")]
+    InvalidValue(String),
+    #[error("{0}")]
+    Mutability(String),
+    #[error("{0}")]
+    Uniqueness(String),
+    #[error("Resource not found")]
+    NotFound,
+    #[error("Missing or invalid bearer token")]
+    Unauthorized,
+    #[error("Unexpected error")]
+    UnexpectedError(#[source] Report),
+}
+
+impl ScimError {
+    /// The `scimType` that goes along with a 400 or 409
+    pub fn scim_type(&self) -> Option<&'static str> {
+        match self {
+            Self::InvalidFilter(_) => Some("invalidFilter"),
+            Self::InvalidSyntax(_) => Some("invalidSyntax"),
+            Self::InvalidPath(_) => Some("invalidPath"),
+            Self::InvalidValue(_) => Some("invalidValue"),
+            Self::Mutability(_) => Some("mutability"),
+            Self::Uniqueness(_) => Some("uniqueness"),
+            Self::NotFound | Self::Unauthorized | Self::UnexpectedError(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScimValue {
+    String(String),
+    Bool(bool),
+    Null,
+}
+
+impl ScimValue {
+    /// Some identity providers send booleans as `"True"` or `"False"`
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Bool(value) => Some(*value),
+            Self::String(value) => value.to_lowercase().parse().ok(),
+            Self::Null => None,
+        }
+    }
+}
+
+impl From<&serde_json::Value> for ScimValue {
+    fn from(value: &serde_json::Value) -> Self {
+        match value {
+            serde_json::Value::String(value) => Self::String(value.clone()),
+            serde_json::Value::Bool(value) => Self::Bool(*value),
+            serde_json::Value::Null => Self::Null,
+            other => Self::String(other.to_string()),
+        }
+    }
+}
+
+/// One `<attribute> eq <value>` comparison of a filter
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScimCondition {
+    /// Lowercased, since attribute names are case-insensitive
+    pub attribute: String,
+    pub value: ScimValue,
+}
+
+/// The subset of SCIM filters that identity providers use to look up resources: `eq`
+/// comparisons joined by `and`, e.g. `userName eq "bjensen@example.com" and active eq true`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScimFilter(Vec<ScimCondition>);
+
+impl ScimFilter {
+    pub fn parse(filter: &str) -> Result<Self, ScimError> {
+        let tokens = tokenize(filter)?;
+        let mut conditions = Vec::new();
+        let mut tokens = tokens.into_iter();
+
+        loop {
+            let (Some(attribute), Some(operator), Some(value)) =
+                (tokens.next(), tokens.next(), tokens.next())
+            else {
+                return Err(ScimError::InvalidFilter("Incomplete filter".to_owned()));
+            };
+
+            let Token::Word(attribute) = attribute else {
+                return Err(ScimError::InvalidFilter("Expected an attribute".to_owned()));
+            };
+            if !matches!(&operator, Token::Word(operator) if operator.eq_ignore_ascii_case("eq")) {
+                return Err(ScimError::InvalidFilter(
+                    "Only the eq operator is supported".to_owned(),
+                ));
+            }
+            let value = match value {
+                Token::Quoted(value) => ScimValue::String(value),
+                Token::Word(word) => match word.to_lowercase().as_str() {
+                    "true" => ScimValue::Bool(true),
+                    "false" => ScimValue::Bool(false),
+                    "null" => ScimValue::Null,
+                    _ => return Err(ScimError::InvalidFilter(format!("Invalid value {}", word))),
+                },
+            };
+
+            conditions.push(ScimCondition {
+                attribute: attribute_name(&attribute),
+                value,
+            });
+
+            match tokens.next() {
+                None => return Ok(Self(conditions)),
+                Some(Token::Word(word)) if word.eq_ignore_ascii_case("and") => continue,
+                Some(_) => {
+                    return Err(ScimError::InvalidFilter(
+                        "Only comparisons joined by and are supported".to_owned(),
+                    ))
+                }
+            }
+        }
+    }
+
+    pub fn conditions(&self) -> &[ScimCondition] {
+        &self.0
+    }
+}
+
+/// Lowercases an attribute name, dropping any schema URN prefix it was qualified with
+pub fn attribute_name(attribute: &str) -> String {
+    let attribute = match attribute.strip_prefix("urn:") {
+        Some(qualified) => qualified.rsplit(':').next().unwrap_or(qualified),
+        None => attribute,
+    };
+    attribute.to_lowercase()
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+}
+
+fn tokenize(filter: &str) -> Result<Vec<Token>, ScimError> {
+    let mut tokens = Vec::new();
+    let mut chars = filter.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some(escaped) => value.push(escaped),
+                        None => break,
+                    },
+                    Some(c) => value.push(c),
+                    None => return Err(ScimError::InvalidFilter("Unterminated string".to_owned())),
+                }
+            }
+            tokens.push(Token::Quoted(value));
+        } else if c == '(' || c == ')' || c == '[' || c == ']' {
+            return Err(ScimError::InvalidFilter(
+                "Grouping and complex filters are not supported".to_owned(),
+            ));
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '"' {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Word(word));
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScimPatchOp {
+    Add,
+    Replace,
+    Remove,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScimPatchRequest {
+    pub schemas: Vec<String>,
+    #[serde(rename = "Operations")]
+    pub operations: Vec<ScimPatchOperation>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScimPatchOperation {
+    pub op: String,
+    pub path: Option<String>,
+    pub value: Option<serde_json::Value>,
+}
+
+impl ScimPatchOperation {
+    /// Operation names are case-insensitive, and some identity providers capitalize them
+    pub fn op(&self) -> Result<ScimPatchOp, ScimError> {
+        match self.op.to_lowercase().as_str() {
+            "add" => Ok(ScimPatchOp::Add),
+            "replace" => Ok(ScimPatchOp::Replace),
+            "remove" => Ok(ScimPatchOp::Remove),
+            _ => Err(ScimError::InvalidSyntax(format!(
+                "Unknown operation {}",
+                self.op
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScimMeta {
+    #[serde(rename = "resourceType")]
+    pub resource_type: String,
+    pub created: DateTime<Utc>,
+    #[serde(rename = "lastModified")]
+    pub last_modified: DateTime<Utc>,
+    pub location: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScimEmail {
+    pub value: String,
+    pub primary: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScimUser {
+    pub schemas: Vec<String>,
+    pub id: Uuid,
+    #[serde(rename = "externalId", skip_serializing_if = "Option::is_none")]
+    pub external_id: Option<String>,
+    #[serde(rename = "userName")]
+    pub user_name: String,
+    pub active: bool,
+    pub emails: Vec<ScimEmail>,
+    pub meta: ScimMeta,
+}
+
+impl From<&User> for ScimUser {
+    fn from(user: &User) -> Self {
+        let email = user.email.as_ref().expose_secret().to_owned();
+        Self {
+            schemas: vec![USER_SCHEMA.to_owned()],
+            id: user.id,
+            external_id: user.external_id.clone(),
+            user_name: email.clone(),
+            active: user.active,
+            emails: vec![ScimEmail {
+                value: email,
+                primary: true,
+            }],
+            meta: ScimMeta {
+                resource_type: "User".to_owned(),
+                created: user.created_at,
+                last_modified: user.updated_at,
+                location: format!("/scim/v2/Users/{}", user.id),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScimMember {
+    pub value: Uuid,
+    /// The member's user name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display: Option<String>,
+    #[serde(rename = "$ref", skip_serializing_if = "Option::is_none")]
+    pub reference: Option<String>,
+}
+
+impl From<&User> for ScimMember {
+    fn from(user: &User) -> Self {
+        Self {
+            value: user.id,
+            display: Some(user.email.as_ref().expose_secret().to_owned()),
+            reference: Some(format!("/scim/v2/Users/{}", user.id)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScimGroup {
+    pub schemas: Vec<String>,
+    pub id: Uuid,
+    #[serde(rename = "externalId", skip_serializing_if = "Option::is_none")]
+    pub external_id: Option<String>,
+    #[serde(rename = "displayName")]
+    pub display_name: String,
+    pub members: Vec<ScimMember>,
+    pub meta: ScimMeta,
+}
+
+impl ScimGroup {
+    pub fn new(group: &Group, members: Vec<ScimMember>) -> Self {
+        Self {
+            schemas: vec![GROUP_SCHEMA.to_owned()],
+            id: group.id,
+            external_id: group.external_id.clone(),
+            display_name: group.display_name.clone(),
+            members,
+            meta: ScimMeta {
+                resource_type: "Group".to_owned(),
+                created: group.created_at,
+                last_modified: group.updated_at,
+                location: format!("/scim/v2/Groups/{}", group.id),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScimListResponse<T> {
+    pub schemas: Vec<String>,
+    #[serde(rename = "totalResults")]
+    pub total_results: usize,
+    /// 1-based index of the first resource
+    #[serde(rename = "startIndex")]
+    pub start_index: usize,
+    #[serde(rename = "itemsPerPage")]
+    pub items_per_page: usize,
+    #[serde(rename = "Resources")]
+    pub resources: Vec<T>,
+}
+
+impl<T> ScimListResponse<T> {
+    pub fn new(resources: Vec<T>, total_results: usize, start_index: usize) -> Self {
+        Self {
+            schemas: vec![LIST_RESPONSE_SCHEMA.to_owned()],
+            total_results,
+            start_index,
+            items_per_page: resources.len(),
+            resources,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ScimUserRequest {
+    #[serde(rename = "userName")]
+    pub user_name: String,
+    #[serde(rename = "externalId")]
+    pub external_id: Option<String>,
+    pub active: Option<bool>,
+    /// Users provisioned without a password can't log in with one
+    pub password: Option<Secret<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScimMemberRequest {
+    pub value: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScimGroupRequest {
+    #[serde(rename = "displayName")]
+    pub display_name: String,
+    #[serde(rename = "externalId")]
+    pub external_id: Option<String>,
+    #[serde(default)]
+    pub members: Vec<ScimMemberRequest>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_with_conjunction_is_parsed() {
+        let filter =
+            ScimFilter::parse(r#"userName eq "bjensen@example.com" and Active EQ true"#).unwrap();
+
+        assert_eq!(
+            filter.conditions(),
+            &[
+                ScimCondition {
+                    attribute: "username".to_owned(),
+                    value: ScimValue::String("bjensen@example.com".to_owned()),
+                },
+                ScimCondition {
+                    attribute: "active".to_owned(),
+                    value: ScimValue::Bool(true),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn filter_attributes_may_be_schema_qualified() {
+        let filter = ScimFilter::parse(
+            r#"urn:ietf:params:scim:schemas:core:2.0:User:externalId eq "a \"quoted\" id""#,
+        )
+        .unwrap();
+
+        assert_eq!(filter.conditions()[0].attribute, "externalid");
+        assert_eq!(
+            filter.conditions()[0].value,
+            ScimValue::String(r#"a "quoted" id"#.to_owned())
+        );
+    }
+
+    #[test]
+    fn unsupported_filters_are_rejected() {
+        for filter in [
+            "",
+            "userName",
+            r#"userName sw "b""#,
+            r#"userName eq "b" or active eq true"#,
+            r#"(userName eq "b")"#,
+            r#"emails[type eq "work"]"#,
+            r#"userName eq "unterminated"#,
+            "active eq maybe",
+        ] {
+            assert!(
+                matches!(ScimFilter::parse(filter), Err(ScimError::InvalidFilter(_))),
+                "{} should be rejected",
+                filter
+            );
+        }
+    }
+
+    #[test]
+    fn string_booleans_are_accepted() {
+        assert_eq!(ScimValue::String("False".to_owned()).as_bool(), Some(false));
+        assert_eq!(ScimValue::Bool(true).as_bool(), Some(true));
+        assert_eq!(ScimValue::String("no".to_owned()).as_bool(), None);
+    }
+}
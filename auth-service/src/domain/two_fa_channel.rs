@@ -0,0 +1,71 @@
+use color_eyre::eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{Email, PhoneNumber};
+
+/// How a user prefers to receive their 2FA codes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TwoFAChannel {
+    #[default]
+    Email,
+    Sms,
+}
+
+impl TwoFAChannel {
+    pub fn parse(channel: &str) -> Result<Self> {
+        match channel {
+            "email" => Ok(Self::Email),
+            "sms" => Ok(Self::Sms),
+            _ => Err(eyre!("{} is not a valid 2FA channel", channel)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Email => "email",
+            Self::Sms => "sms",
+        }
+    }
+}
+
+/// Where a particular 2FA code gets delivered
+#[derive(Debug, Clone, PartialEq)]
+pub enum TwoFADestination {
+    Email(Email),
+    Sms(PhoneNumber),
+}
+
+impl TwoFADestination {
+    pub fn channel(&self) -> TwoFAChannel {
+        match self {
+            Self::Email(_) => TwoFAChannel::Email,
+            Self::Sms(_) => TwoFAChannel::Sms,
+        }
+    }
+
+    /// A form of the destination that is safe to show to whoever is logging in
+    pub fn masked(&self) -> String {
+        match self {
+            Self::Email(email) => email.masked(),
+            Self::Sms(phone_number) => phone_number.masked(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channel_round_trips_through_str() {
+        for channel in [TwoFAChannel::Email, TwoFAChannel::Sms] {
+            assert_eq!(TwoFAChannel::parse(channel.as_str()).unwrap(), channel);
+        }
+    }
+
+    #[test]
+    fn unknown_channel_is_rejected() {
+        assert!(TwoFAChannel::parse("carrier-pigeon").is_err());
+    }
+}
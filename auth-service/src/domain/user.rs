@@ -1,19 +1,77 @@
-use super::{Email, Password};
+use chrono::{DateTime, SubsecRound, Utc};
+use uuid::Uuid;
+
+use super::{Email, Password, PhoneNumber, TwoFAChannel, TwoFADestination};
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct User {
+    /// Stable identifier that, unlike the email, never changes
+    pub id: Uuid,
     pub email: Email,
     pub password: Password,
     pub requires_2fa: bool,
+    pub phone_number: Option<PhoneNumber>,
+    pub phone_number_verified: bool,
+    pub two_fa_channel: TwoFAChannel,
+    /// Inactive users can't log in, e.g. after being deprovisioned over SCIM
+    pub active: bool,
+    /// The user's ID in the identity provider that provisioned them
+    pub external_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
 }
 
 impl User {
     // add a constructor function called `new`
     pub fn new(email: Email, password: Password, requires_2fa: bool) -> Self {
+        // Postgres keeps timestamps to the microsecond
+        let now = Utc::now().trunc_subsecs(6);
         User {
+            id: Uuid::new_v4(),
             email,
             password,
             requires_2fa,
+            phone_number: None,
+            phone_number_verified: false,
+            two_fa_channel: TwoFAChannel::default(),
+            active: true,
+            external_id: None,
+            created_at: now,
+            updated_at: now,
         }
     }
-}
\ No newline at end of file
+
+    /// Where this user's 2FA codes should be sent. Falls back to email unless the user prefers
+    /// SMS and has a verified phone number.
+    pub fn two_fa_destination(&self) -> TwoFADestination {
+        match (
+            &self.phone_number,
+            self.phone_number_verified,
+            self.two_fa_channel,
+        ) {
+            (Some(phone_number), true, TwoFAChannel::Sms) => {
+                TwoFADestination::Sms(phone_number.clone())
+            }
+            _ => TwoFADestination::Email(self.email.clone()),
+        }
+    }
+}
+
+/// Narrows down a listing of users. Criteria left out match every user.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UserQuery {
+    pub email: Option<Email>,
+    pub external_id: Option<String>,
+    pub active: Option<bool>,
+}
+
+impl UserQuery {
+    pub fn matches(&self, user: &User) -> bool {
+        self.email.as_ref().is_none_or(|email| *email == user.email)
+            && self
+                .external_id
+                .as_ref()
+                .is_none_or(|external_id| user.external_id.as_ref() == Some(external_id))
+            && self.active.is_none_or(|active| active == user.active)
+    }
+}
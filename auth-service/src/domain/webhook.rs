@@ -0,0 +1,210 @@
+use chrono::{DateTime, SubsecRound, Utc};
+use color_eyre::eyre::{eyre, Result};
+use rand::{distributions::Alphanumeric, Rng};
+use reqwest::Url;
+use secrecy::Secret;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+// Postgres keeps timestamps to the microsecond, so stored values compare equal to fresh ones
+fn now() -> DateTime<Utc> {
+    Utc::now().trunc_subsecs(6)
+}
+
+/// User lifecycle events that subscribers can be notified of
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum WebhookEventType {
+    #[serde(rename = "user.created")]
+    UserCreated,
+    #[serde(rename = "user.password_changed")]
+    UserPasswordChanged,
+    #[serde(rename = "user.deleted")]
+    UserDeleted,
+}
+
+impl WebhookEventType {
+    const ALL: [Self; 3] = [
+        Self::UserCreated,
+        Self::UserPasswordChanged,
+        Self::UserDeleted,
+    ];
+
+    pub fn parse(event_type: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|candidate| candidate.as_str() == event_type)
+            .ok_or(eyre!("{} is not a valid webhook event type", event_type))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::UserCreated => "user.created",
+            Self::UserPasswordChanged => "user.password_changed",
+            Self::UserDeleted => "user.deleted",
+        }
+    }
+}
+
+/// An event as it is sent to subscribers
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookEvent {
+    pub id: Uuid,
+    #[serde(rename = "type")]
+    pub event_type: WebhookEventType,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    pub data: serde_json::Value,
+}
+
+impl WebhookEvent {
+    pub fn new(event_type: WebhookEventType, data: serde_json::Value) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            event_type,
+            created_at: now(),
+            data,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WebhookSubscription {
+    pub id: Uuid,
+    pub url: Url,
+    pub event_types: Vec<WebhookEventType>,
+    /// Key the payloads sent to this subscription are signed with
+    pub secret: Secret<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl WebhookSubscription {
+    pub fn new(url: Url, event_types: Vec<WebhookEventType>, secret: Secret<String>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            url,
+            event_types,
+            secret,
+            created_at: now(),
+        }
+    }
+
+    pub fn parse_url(url: &str) -> Result<Url> {
+        let url = Url::parse(url)?;
+        match url.scheme() {
+            "http" | "https" if url.has_host() => Ok(url),
+            _ => Err(eyre!("webhook URL must be an absolute http(s) URL")),
+        }
+    }
+
+    pub fn generate_secret() -> Secret<String> {
+        let key: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(32)
+            .map(char::from)
+            .collect();
+        Secret::new(format!("whsec_{}", key))
+    }
+
+    pub fn is_subscribed_to(&self, event_type: WebhookEventType) -> bool {
+        self.event_types.contains(&event_type)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookDeliveryStatus {
+    Pending,
+    Succeeded,
+    /// Every attempt allowed by the retry policy failed
+    Failed,
+}
+
+impl WebhookDeliveryStatus {
+    pub fn parse(status: &str) -> Result<Self> {
+        [Self::Pending, Self::Succeeded, Self::Failed]
+            .into_iter()
+            .find(|candidate| candidate.as_str() == status)
+            .ok_or(eyre!("{} is not a valid webhook delivery status", status))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Succeeded => "succeeded",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+/// One event on its way to one subscription
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    #[serde(rename = "subscriptionId")]
+    pub subscription_id: Uuid,
+    #[serde(rename = "eventId")]
+    pub event_id: Uuid,
+    #[serde(rename = "eventType")]
+    pub event_type: WebhookEventType,
+    /// The exact body that is signed and sent
+    pub payload: String,
+    pub status: WebhookDeliveryStatus,
+    #[serde(rename = "nextAttemptAt")]
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+    /// Oldest first
+    pub attempts: Vec<WebhookDeliveryAttempt>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookDeliveryAttempt {
+    #[serde(rename = "attemptedAt")]
+    pub attempted_at: DateTime<Utc>,
+    /// Missing when no response was received
+    #[serde(rename = "responseStatus")]
+    pub response_status: Option<u16>,
+    pub error: Option<String>,
+}
+
+impl WebhookDeliveryAttempt {
+    pub fn succeeded(&self) -> bool {
+        self.response_status
+            .is_some_and(|status| (200..300).contains(&status))
+    }
+}
+
+/// A delivery that is due, along with where to send it
+#[derive(Debug, Clone)]
+pub struct DueWebhookDelivery {
+    pub delivery: WebhookDelivery,
+    pub url: Url,
+    pub secret: Secret<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_type_round_trips_through_str() {
+        for event_type in WebhookEventType::ALL {
+            assert_eq!(
+                WebhookEventType::parse(event_type.as_str()).unwrap(),
+                event_type
+            );
+            assert_eq!(
+                serde_json::to_string(&event_type).unwrap(),
+                format!("\"{}\"", event_type.as_str())
+            );
+        }
+    }
+
+    #[test]
+    fn only_absolute_http_urls_are_accepted() {
+        assert!(WebhookSubscription::parse_url("https://example.com/hooks").is_ok());
+        assert!(WebhookSubscription::parse_url("http://127.0.0.1:8080").is_ok());
+        assert!(WebhookSubscription::parse_url("ftp://example.com").is_err());
+        assert!(WebhookSubscription::parse_url("/hooks").is_err());
+    }
+}
@@ -1,42 +1,78 @@
 use std::error::Error;
 
 use app_state::AppState;
+use std::{future::Future, io, net::SocketAddr, pin::Pin, sync::Arc, time::Duration};
+
 use axum::{
-    http::{Method, StatusCode},
+    http::{header, HeaderValue, Method, StatusCode},
+    middleware,
     response::{IntoResponse, Response},
-    routing::post,
-    serve::Serve,
+    routing::{delete, get, post},
     Json, Router,
 };
-use domain::AuthAPIError;
+use axum_server::Handle;
+use domain::{AuthAPIError, PasswordPolicyViolation, ScimError, ERROR_SCHEMA};
+use notify::RecommendedWatcher;
 use redis::{Client, RedisResult};
-use routes::{login, logout, signup, verify_2fa, verify_token};
+use routes::{
+    change_password, confirm_report_login, health_live, health_ready, import_users, create_webhook,
+    delete_webhook, get_webhook, list_webhook_deliveries, list_webhooks, login, query_audit_log,
+    logout, report_login, resend_2fa_code, reset_password, set_phone_number, set_two_fa_channel,
+    signup, list_trusted_devices, revoke_trusted_device, verify_2fa, verify_phone_number,
+    verify_token, create_scim_group, create_scim_user, delete_scim_group, delete_scim_user,
+    get_scim_group, get_scim_service_provider_config, get_scim_user, list_scim_groups,
+    list_scim_users, patch_scim_group, patch_scim_user, replace_scim_group, replace_scim_user,
+};
 use secrecy::{ExposeSecret, Secret};
 use serde::{Deserialize, Serialize};
+use settings::ApplicationSettings;
 use sqlx::{postgres::PgPoolOptions, PgPool};
 use tower_http::{cors::CorsLayer, services::ServeDir, trace::TraceLayer};
-use utils::tracing::{make_span_with_request_id, on_request, on_response};
+use utils::{
+    audit::audit,
+    metrics::{metrics, record_request_metrics},
+    rate_limit::rate_limit,
+    shutdown::ShutdownHandle,
+    tls,
+    tracing::{assign_request_id, make_span_with_request_id, on_request, on_response},
+};
 
 pub mod app_state;
 pub mod domain;
 pub mod routes;
 pub mod services;
+pub mod settings;
 pub mod utils;
 
+type ServerFuture = Pin<Box<dyn Future<Output = io::Result<()>> + Send>>;
+
 pub struct Application {
-    server: Serve<Router, Router>,
+    server: ServerFuture,
+    // Redirects plain HTTP to HTTPS, when configured
+    redirect_server: Option<ServerFuture>,
+    // One for each server, to stop them with
+    server_handles: Vec<Handle>,
+    // Reloads the TLS certificate when it changes, for as long as it's kept
+    certificate_watcher: Option<RecommendedWatcher>,
     pub address: String,
+    pub redirect_address: Option<String>,
+    shutdown: ShutdownHandle,
+    shutdown_timeout: Duration,
 }
 
 impl Application {
-    pub async fn build(app_state: AppState, address: &str) -> Result<Self, Box<dyn Error>> {
-        let allowed_origins = [
-            "http://localhost:8000".parse()?,
-            "http://206.189.176.227:8000".parse()?,
-        ];
+    pub async fn build(
+        app_state: AppState,
+        settings: &ApplicationSettings,
+    ) -> Result<Self, Box<dyn Error>> {
+        let allowed_origins = settings
+            .allowed_origins
+            .iter()
+            .map(|origin| origin.parse::<HeaderValue>())
+            .collect::<Result<Vec<_>, _>>()?;
 
         let cors = CorsLayer::new()
-            .allow_methods([Method::GET, Method::POST])
+            .allow_methods([Method::GET, Method::POST, Method::DELETE])
             .allow_credentials(true)
             .allow_origin(allowed_origins);
 
@@ -45,10 +81,73 @@ impl Application {
             .route("/signup", post(signup))
             .route("/login", post(login))
             .route("/verify-2fa", post(verify_2fa))
+            .route("/verify-2fa/resend", post(resend_2fa_code))
             .route("/logout", post(logout))
+            .route("/change-password", post(change_password))
             .route("/verify-token", post(verify_token))
-            .with_state(app_state.into())
+            .route("/phone-number", post(set_phone_number))
+            .route("/phone-number/verify", post(verify_phone_number))
+            .route("/2fa-channel", post(set_two_fa_channel))
+            .route(
+                "/report-login",
+                get(confirm_report_login).post(report_login),
+            )
+            .route("/reset-password", post(reset_password))
+            .route("/trusted-devices", get(list_trusted_devices))
+            .route("/trusted-devices/:id", delete(revoke_trusted_device))
+            .route("/admin/audit", get(query_audit_log))
+            .route("/admin/users/import", post(import_users))
+            .route("/admin/webhooks", post(create_webhook).get(list_webhooks))
+            .route(
+                "/admin/webhooks/:id",
+                get(get_webhook).delete(delete_webhook),
+            )
+            .route(
+                "/admin/webhooks/:id/deliveries",
+                get(list_webhook_deliveries),
+            )
+            .route(
+                "/scim/v2/ServiceProviderConfig",
+                get(get_scim_service_provider_config),
+            )
+            .route(
+                "/scim/v2/Users",
+                post(create_scim_user).get(list_scim_users),
+            )
+            .route(
+                "/scim/v2/Users/:id",
+                get(get_scim_user)
+                    .put(replace_scim_user)
+                    .patch(patch_scim_user)
+                    .delete(delete_scim_user),
+            )
+            .route(
+                "/scim/v2/Groups",
+                post(create_scim_group).get(list_scim_groups),
+            )
+            .route(
+                "/scim/v2/Groups/:id",
+                get(get_scim_group)
+                    .put(replace_scim_group)
+                    .patch(patch_scim_group)
+                    .delete(delete_scim_group),
+            )
+            .layer(middleware::from_fn_with_state(
+                app_state.rate_limiter.clone(),
+                rate_limit,
+            ))
+            // Outside of rate limiting so that rejected requests are audited too
+            .layer(middleware::from_fn_with_state(app_state.clone(), audit))
+            // Probes and metrics scrapes are neither rate limited nor audited
+            .merge(
+                Router::new()
+                    .route("/health/live", get(health_live))
+                    .route("/health/ready", get(health_ready))
+                    .route("/metrics", get(metrics)),
+            )
+            .with_state(app_state)
             .layer(cors)
+            .layer(middleware::from_fn(record_request_metrics))
             .layer( // New!
                 // Add a TraceLayer for HTTP requests to enable detailed tracing
                 // This layer will create spans for each request using the make_span_with_request_id function,
@@ -57,51 +156,204 @@ impl Application {
                     .make_span_with(make_span_with_request_id)
                     .on_request(on_request)
                     .on_response(on_response),
-            );
+            )
+            // Outermost so the span and audit log see the same request ID
+            .layer(middleware::from_fn(assign_request_id));
 
-        let listener = tokio::net::TcpListener::bind(address).await?;
+        // Rate limiting keys on the client's address, so it has to be kept with each connection
+        let make_service = router.into_make_service_with_connect_info::<SocketAddr>();
+        let listener = bind(&settings.address)?;
         let address = listener.local_addr()?.to_string();
-        let server = axum::serve(listener, router);
+        let handle = Handle::new();
+        let mut server_handles = vec![handle.clone()];
 
-        Ok(Application { server, address })
+        let mut certificate_watcher = None;
+        let server: ServerFuture = match &settings.tls {
+            None => Box::pin(
+                axum_server::from_tcp(listener)
+                    .handle(handle)
+                    .serve(make_service),
+            ),
+            Some(tls_settings) => {
+                let config = tls::rustls_config(tls_settings)?;
+                certificate_watcher = Some(tls::watch_certificates(
+                    config.clone(),
+                    tls_settings.clone(),
+                )?);
+                Box::pin(
+                    axum_server::from_tcp_rustls(listener, config)
+                        .handle(handle)
+                        .serve(make_service),
+                )
+            }
+        };
+
+        let mut redirect_server = None;
+        let mut redirect_address = None;
+        if let Some(tls_settings) = &settings.tls {
+            if let Some(address) = &tls_settings.redirect_address {
+                let listener = bind(address)?;
+                redirect_address = Some(listener.local_addr()?.to_string());
+                let handle = Handle::new();
+                server_handles.push(handle.clone());
+                let router = Router::new()
+                    .fallback(tls::redirect_to_https)
+                    .with_state(Arc::<str>::from(settings.public_url.as_str()));
+                redirect_server = Some(Box::pin(
+                    axum_server::from_tcp(listener)
+                        .handle(handle)
+                        .serve(router.into_make_service()),
+                ) as ServerFuture);
+            }
+        }
+
+        Ok(Application {
+            server,
+            redirect_server,
+            server_handles,
+            certificate_watcher,
+            address,
+            redirect_address,
+            shutdown: ShutdownHandle::new(),
+            shutdown_timeout: settings.shutdown_timeout,
+        })
+    }
+
+    /// Triggering the handle stops the server accepting connections and lets `run` return once
+    /// the requests in flight have finished
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
     }
 
     pub async fn run(self) -> Result<(), std::io::Error> {
         tracing::info!("listening on {}", &self.address); // Updated!
-        self.server.await
+        if let Some(address) = &self.redirect_address {
+            tracing::info!("redirecting plain HTTP on {} to HTTPS", address);
+        }
+        let _certificate_watcher = self.certificate_watcher;
+
+        let shutdown = self.shutdown.clone();
+        let server_handles = self.server_handles;
+        let shutdown_timeout = self.shutdown_timeout;
+        // Connections still open when the timeout is up are closed, cutting off their requests
+        tokio::spawn(async move {
+            shutdown.triggered().await;
+            for handle in server_handles {
+                handle.graceful_shutdown(Some(shutdown_timeout));
+            }
+        });
+
+        let redirect_server = async {
+            match self.redirect_server {
+                Some(server) => server.await,
+                None => Ok(()),
+            }
+        };
+        tokio::try_join!(self.server, redirect_server)?;
+        tracing::info!("Server stopped");
+        Ok(())
     }
 }
 
+fn bind(address: &str) -> io::Result<std::net::TcpListener> {
+    let listener = std::net::TcpListener::bind(address)?;
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct ErrorResponse {
     pub error: String,
+    /// The password policy rules a rejected password broke
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<PasswordPolicyViolation>,
 }
 
 impl IntoResponse for AuthAPIError {
     fn into_response(self) -> Response {
         log_error_chain(&self); // New!
+        let retry_after_seconds = match self {
+            AuthAPIError::TooManyRequests {
+                retry_after_seconds,
+            } => retry_after_seconds,
+            _ => None,
+        };
+        let details = match &self {
+            AuthAPIError::WeakPassword(violations) => violations.clone(),
+            _ => vec![],
+        };
         let (status, error_message) = match self {
             AuthAPIError::UserAlreadyExists => (StatusCode::CONFLICT, "User already exists"),
             AuthAPIError::InvalidCredentials => (StatusCode::BAD_REQUEST, "Invalid Credentials"),
+            AuthAPIError::WeakPassword(_) => (
+                StatusCode::BAD_REQUEST,
+                "Password does not meet the password policy",
+            ),
             AuthAPIError::IncorrectCredentials => {
                 (StatusCode::UNAUTHORIZED, "Incorrect Credentials")
             }
             AuthAPIError::MissingToken => (StatusCode::BAD_REQUEST, "Missing Token"),
             AuthAPIError::InvalidToken => (StatusCode::UNAUTHORIZED, "Invalid Token"),
+            AuthAPIError::PhoneNumberNotVerified => {
+                (StatusCode::BAD_REQUEST, "Phone number not verified")
+            }
+            AuthAPIError::NotFound => (StatusCode::NOT_FOUND, "Not Found"),
+            AuthAPIError::TooManyRequests { .. } => {
+                (StatusCode::TOO_MANY_REQUESTS, "Too Many Requests")
+            }
             AuthAPIError::UnexpectedError(_) => { // Updated!
                 (StatusCode::INTERNAL_SERVER_ERROR, "Unexpected Error")
             }
         };
         let body = Json(ErrorResponse {
             error: error_message.to_string(),
+            details,
         });
-        (status, body).into_response()
+        let mut response = (status, body).into_response();
+        if let Some(seconds) = retry_after_seconds {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+        }
+        response
     }
 }
 
-pub async fn get_postgres_pool(url: &Secret<String>) -> Result<PgPool, sqlx::Error> {
+impl IntoResponse for ScimError {
+    fn into_response(self) -> Response {
+        log_error_chain(&self);
+        let status = match self {
+            ScimError::InvalidFilter(_)
+            | ScimError::InvalidSyntax(_)
+            | ScimError::InvalidPath(_)
+            | ScimError::InvalidValue(_)
+            | ScimError::Mutability(_) => StatusCode::BAD_REQUEST,
+            ScimError::Uniqueness(_) => StatusCode::CONFLICT,
+            ScimError::NotFound => StatusCode::NOT_FOUND,
+            ScimError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ScimError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        let mut body = serde_json::json!({
+            "schemas": [ERROR_SCHEMA],
+            "status": status.as_u16().to_string(),
+            "detail": self.to_string(),
+        });
+        if let Some(scim_type) = self.scim_type() {
+            body["scimType"] = scim_type.into();
+        }
+        utils::scim::ScimJson(status, body).into_response()
+    }
+}
+
+pub async fn get_postgres_pool(
+    url: &Secret<String>,
+    max_connections: u32,
+) -> Result<PgPool, sqlx::Error> {
     // Create a new PostgreSQL connection pool
-    PgPoolOptions::new().max_connections(5).connect(url.expose_secret()).await
+    PgPoolOptions::new()
+        .max_connections(max_connections)
+        .connect(url.expose_secret())
+        .await
 }
 
 pub fn get_redis_client(redis_hostname: String) -> RedisResult<Client> {
@@ -109,16 +361,16 @@ pub fn get_redis_client(redis_hostname: String) -> RedisResult<Client> {
     redis::Client::open(redis_url)
 }
 
+// Logs the error and each of its causes as fields of one event, so that log aggregators can
+// search on them
 fn log_error_chain(e: &(dyn Error + 'static)) {
-    let separator =
-        "\n-----------------------------------------------------------------------------------\n";
-    let mut report = format!("{}{:?}\n", separator, e);
-    let mut current = e.source();
-    while let Some(cause) = current {
-        let str = format!("Caused by:\n\n{:?}", cause);
-        report = format!("{}\n{}", report, str);
-        current = cause.source();
-    }
-    report = format!("{}\n{}", report, separator);
-    tracing::error!("{}", report);
+    let causes: Vec<String> = std::iter::successors(e.source(), |&cause| cause.source())
+        .map(ToString::to_string)
+        .collect();
+    tracing::error!(
+        error.message = %e,
+        error.causes = ?causes,
+        error.details = ?e,
+        "Request failed"
+    );
 }
\ No newline at end of file
@@ -1,20 +1,34 @@
 use sqlx::PgPool;
-use std::sync::Arc;
-use tokio::sync::RwLock;
-use secrecy::Secret;
+use std::{path::Path, sync::Arc};
+use redis::aio::ConnectionManager;
 use reqwest::Client;
 
 use auth_service::{
-    app_state::AppState,
-    domain::Email,
+    app_state::{AppState, HealthCheckType, RateLimitStoreType, WebhookStoreType},
+    domain::{BreachedPasswordCheck, CommonPasswords, PasswordPolicy},
     get_postgres_pool, get_redis_client,
     services::{
-        data_stores::{PostgresUserStore, RedisBannedTokenStore, RedisTwoFACodeStore},
-        postmark_email_client::PostmarkEmailClient
+        data_stores::{
+            HashmapRateLimitStore, PostgresAuditLogStore, PostgresGroupStore,
+            PostgresKnownDeviceStore, PostgresUserStore, PostgresWebhookStore,
+            RedisBannedTokenStore, RedisRateLimitStore, RedisTwoFACodeStore,
+        },
+        health_checks::{PostgresHealthCheck, RedisHealthCheck},
+        hibp_breached_passwords,
+        password_hasher::PasswordHasher,
+        http_sms_client::HttpSmsClient,
+        postmark_email_client::PostmarkEmailClient,
+        webhook_dispatcher::{RetryPolicy, WebhookDispatcher},
+    },
+    settings::{
+        EmailClientSettings, PasswordSettings, RateLimitStoreKind, Settings, SmsClientSettings,
+        WebhookSettings,
     },
     utils::{
-        constants::{prod, DATABASE_URL, REDIS_HOST_NAME, POSTMARK_AUTH_TOKEN}, 
-        tracing::init_tracing
+        metrics,
+        rate_limit::RateLimiter,
+        shutdown::{shutdown_signal, ShutdownHandle},
+        tracing::{init_tracing, shutdown_tracing},
     },
     Application,
 };
@@ -22,34 +36,110 @@ use auth_service::{
 #[tokio::main]
 async fn main() {
     color_eyre::install().expect("Failed to install color_eyre");
-    init_tracing().expect("Failed to initialize tracing"); // Updated!
 
-    
-    let pg_pool = configure_postgresql().await;
-    let redis_client = Arc::new(RwLock::new(configure_redis()));
+    // Every setting is checked up front, and all of the problems are reported at once
+    let settings = match Settings::load() {
+        Ok(settings) => Arc::new(settings),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    init_tracing(&settings.telemetry).expect("Failed to initialize tracing");
+
+    let pg_pool = configure_postgresql(&settings).await;
+    metrics::register_postgres_pool(pg_pool.clone()).expect("Failed to register pool metrics");
+    let redis_client = configure_redis(&settings).await;
 
-    let user_store = Arc::new(RwLock::new(PostgresUserStore::new(pg_pool)));
-    let banned_token_store = Arc::new(RwLock::new(RedisBannedTokenStore::new(redis_client.clone())));
-    let two_fa_code_store = Arc::new(RwLock::new(RedisTwoFACodeStore::new(redis_client)));
+    let user_store = Arc::new(PostgresUserStore::new(
+        pg_pool.clone(),
+        configure_password_hasher(&settings.passwords),
+    ));
+    let audit_log_store = Arc::new(PostgresAuditLogStore::new(pg_pool.clone()));
+    let webhook_store: WebhookStoreType = Arc::new(PostgresWebhookStore::new(pg_pool.clone()));
+    let group_store = Arc::new(PostgresGroupStore::new(pg_pool.clone()));
+    let known_device_store = Arc::new(PostgresKnownDeviceStore::new(pg_pool.clone()));
+    let banned_token_store = Arc::new(RedisBannedTokenStore::new(redis_client.clone()));
+    let two_fa_code_store = Arc::new(RedisTwoFACodeStore::new(redis_client.clone()));
+    let rate_limiter = configure_rate_limiter(&settings, redis_client.clone());
+    let phone_verification_store = Arc::new(RedisTwoFACodeStore::with_namespace(
+        redis_client.clone(),
+        "phone_verification",
+    ));
 
-    let email_client = Arc::new(configure_postmark_email_client()); // Updated!
+    let email_client = Arc::new(configure_postmark_email_client(&settings.email_client)); // Updated!
+    let sms_client = Arc::new(configure_sms_client(&settings.sms_client));
+    let password_policy = Arc::new(configure_password_policy(&settings.passwords));
+    let breached_password_check = configure_breached_password_check(&settings.passwords);
+
+    let mut health_checks: Vec<HealthCheckType> = vec![
+        Arc::new(PostgresHealthCheck::new(pg_pool.clone())),
+        Arc::new(RedisHealthCheck::new(redis_client)),
+    ];
+    if settings.health.check_email_provider {
+        health_checks.push(email_client.clone());
+    }
 
     let app_state = AppState::new(
+        settings.clone(),
         user_store,
         banned_token_store,
         two_fa_code_store,
+        phone_verification_store,
         email_client,
+        sms_client,
+        rate_limiter,
+        audit_log_store,
+        webhook_store.clone(),
+        group_store,
+        password_policy,
+        breached_password_check,
+        known_device_store,
+        health_checks,
+    );
+
+    let webhook_dispatcher_shutdown = ShutdownHandle::new();
+    let webhook_dispatcher = tokio::spawn(
+        configure_webhook_dispatcher(&settings.webhooks, webhook_store).run(
+            settings.webhooks.poll_interval,
+            webhook_dispatcher_shutdown.clone(),
+        ),
     );
 
-    let app = Application::build(app_state, prod::APP_ADDRESS)
+    let app = Application::build(app_state, &settings.application)
         .await
         .expect("Failed to build app");
 
+    let shutdown = app.shutdown_handle();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        shutdown.trigger();
+    });
+
     app.run().await.expect("Failed to run app");
+
+    // No more requests can queue webhooks, so send the ones they did before stopping
+    webhook_dispatcher_shutdown.trigger();
+    if tokio::time::timeout(settings.application.shutdown_timeout, webhook_dispatcher)
+        .await
+        .is_err()
+    {
+        tracing::warn!("Webhooks still being sent at shutdown will be retried on the next start");
+    }
+
+    // Closing waits for every connection to be returned, so it's bounded like the rest
+    if tokio::time::timeout(settings.application.shutdown_timeout, pg_pool.close())
+        .await
+        .is_err()
+    {
+        tracing::warn!("Database connections still in use at shutdown");
+    }
+    shutdown_tracing();
 }
 
-async fn configure_postgresql() -> PgPool {
-    let pg_pool = get_postgres_pool(&DATABASE_URL)
+async fn configure_postgresql(settings: &Settings) -> PgPool {
+    let pg_pool = get_postgres_pool(&settings.database.url, settings.database.max_connections)
         .await
         .expect("Failed to create Postgres connection pool!");
 
@@ -61,24 +151,97 @@ async fn configure_postgresql() -> PgPool {
     pg_pool
 }
 
-fn configure_redis() -> redis::Connection {
-    get_redis_client(REDIS_HOST_NAME.to_owned())
-        .expect("Failed to get Redis client")
-        .get_connection()
+// Reconnects by itself when the connection drops, e.g. when Redis restarts
+async fn configure_redis(settings: &Settings) -> ConnectionManager {
+    let client =
+        get_redis_client(settings.redis.host_name.clone()).expect("Failed to get Redis client");
+    ConnectionManager::new(client)
+        .await
         .expect("Failed to get Redis connection")
 }
 
+fn configure_rate_limiter(settings: &Settings, redis_client: ConnectionManager) -> RateLimiter {
+    let store: RateLimitStoreType = match settings.rate_limit.store {
+        RateLimitStoreKind::Memory => Arc::new(HashmapRateLimitStore::default()),
+        RateLimitStoreKind::Redis => Arc::new(RedisRateLimitStore::new(redis_client)),
+    };
+
+    RateLimiter::new(store, settings.rate_limit.config())
+}
+
+fn configure_password_hasher(settings: &PasswordSettings) -> PasswordHasher {
+    PasswordHasher::new(settings.argon2())
+        .expect("Invalid Argon2 parameters")
+        .with_peppers(settings.peppers.clone())
+}
+
+fn configure_password_policy(settings: &PasswordSettings) -> PasswordPolicy {
+    let mut policy = settings.policy();
+    if let Some(path) = &settings.common_passwords_path {
+        let list = std::fs::read_to_string(path).expect("Failed to load common passwords");
+        policy.common_passwords = CommonPasswords::parse(&list);
+    }
+
+    policy
+}
+
+fn configure_breached_password_check(settings: &PasswordSettings) -> Option<BreachedPasswordCheck> {
+    let path = settings.breached_passwords_path.as_ref()?;
+    let list =
+        hibp_breached_passwords::load(Path::new(path)).expect("Failed to load breached passwords");
+
+    Some(BreachedPasswordCheck::new(
+        list,
+        settings.breached_passwords_mode,
+    ))
+}
+
 // New!
-fn configure_postmark_email_client() -> PostmarkEmailClient {
+fn configure_postmark_email_client(settings: &EmailClientSettings) -> PostmarkEmailClient {
     let http_client = Client::builder()
-        .timeout(prod::email_client::TIMEOUT)
+        .timeout(settings.timeout)
         .build()
         .expect("Failed to build HTTP client");
 
     PostmarkEmailClient::new(
-        prod::email_client::BASE_URL.to_owned(),
-        Email::parse(Secret::new(prod::email_client::SENDER.to_owned())).unwrap(),
-        POSTMARK_AUTH_TOKEN.to_owned(),
+        settings.base_url.clone(),
+        settings.sender.clone(),
+        settings.authorization_token.clone(),
+        http_client,
+    )
+}
+
+fn configure_sms_client(settings: &SmsClientSettings) -> HttpSmsClient {
+    let http_client = Client::builder()
+        .timeout(settings.timeout)
+        .build()
+        .expect("Failed to build HTTP client");
+
+    HttpSmsClient::new(
+        settings.base_url.clone(),
+        settings.sender.clone(),
+        settings.authorization_token.clone(),
+        http_client,
+    )
+}
+
+fn configure_webhook_dispatcher(
+    settings: &WebhookSettings,
+    webhook_store: WebhookStoreType,
+) -> WebhookDispatcher {
+    let http_client = Client::builder()
+        .timeout(settings.timeout)
+        .build()
+        .expect("Failed to build HTTP client");
+
+    WebhookDispatcher::new(
+        webhook_store,
         http_client,
+        settings.timeout,
+        RetryPolicy {
+            base_delay: settings.retry_base_delay,
+            max_delay: settings.retry_max_delay,
+            max_attempts: settings.max_attempts,
+        },
     )
-}
\ No newline at end of file
+}
@@ -0,0 +1,90 @@
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use secrecy::Secret;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{AuditEvent, AuditEventType, AuditLogFilter, AuditOutcome, AuthAPIError, Email},
+    utils::auth::authenticate_admin,
+};
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 500;
+
+#[tracing::instrument(name = "Query Audit Log", skip_all)]
+pub async fn query_audit_log(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<AuditLogQuery>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    authenticate_admin(&headers, &state.settings.auth)?;
+
+    let email = query
+        .email
+        .map(|email| Email::parse(Secret::new(email)))
+        .transpose()
+        .map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let before = query
+        .cursor
+        .map(|cursor| cursor.parse::<i64>())
+        .transpose()
+        .map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let filter = AuditLogFilter {
+        event_type: query.event_type,
+        email,
+        ip: query.ip,
+        outcome: query.outcome,
+        since: query.since,
+        until: query.until,
+    };
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+
+    let events = state
+        .audit_log_store
+        .query(&filter, before, limit)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    // A short page means there is nothing older left to fetch
+    let next_cursor = (events.len() == limit)
+        .then(|| events.last().map(|event| event.id.to_string()))
+        .flatten();
+
+    let response = Json(AuditLogResponse {
+        events,
+        next_cursor,
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditLogQuery {
+    #[serde(rename = "eventType")]
+    pub event_type: Option<AuditEventType>,
+    pub email: Option<String>,
+    pub ip: Option<String>,
+    pub outcome: Option<AuditOutcome>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditLogResponse {
+    pub events: Vec<AuditEvent>,
+    #[serde(rename = "nextCursor")]
+    pub next_cursor: Option<String>,
+}
@@ -0,0 +1,71 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Password, UserStoreError, WebhookEventType},
+    utils::{
+        audit::AuditSubject, auth::authenticate, passwords::check_new_password,
+        webhooks::publish_event,
+    },
+};
+
+#[tracing::instrument(name = "Change Password", skip_all)]
+pub async fn change_password(
+    State(state): State<AppState>,
+    audit: AuditSubject,
+    jar: CookieJar,
+    Json(request): Json<ChangePasswordRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = authenticate(&jar, &state.settings.auth, state.banned_token_store.clone()).await?;
+    audit.set(&email);
+
+    let current_password = Password::parse(request.current_password)
+        .map_err(|_| AuthAPIError::IncorrectCredentials)?;
+    let new_password =
+        Password::parse(request.new_password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let user_store = &state.user_store;
+
+    match user_store.validate_user(&email, &current_password).await {
+        Ok(()) => {}
+        Err(UserStoreError::InvalidCredentials) | Err(UserStoreError::UserNotFound) => {
+            return Err(AuthAPIError::IncorrectCredentials)
+        }
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    check_new_password(&state, &new_password, &email).map_err(AuthAPIError::WeakPassword)?;
+
+    match user_store
+        .update_password(&email, new_password, state.password_policy.history_size)
+        .await
+    {
+        Ok(()) => {}
+        Err(UserStoreError::PasswordReused) => {
+            return Err(AuthAPIError::WeakPassword(vec![state
+                .password_policy
+                .reuse_violation()]))
+        }
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    publish_event(
+        &state,
+        WebhookEventType::UserPasswordChanged,
+        serde_json::json!({ "email": email.as_ref().expose_secret() }),
+    )
+    .await;
+
+    Ok(StatusCode::OK)
+}
+
+#[derive(Deserialize)]
+pub struct ChangePasswordRequest {
+    #[serde(rename = "currentPassword")]
+    pub current_password: Secret<String>,
+    #[serde(rename = "newPassword")]
+    pub new_password: Secret<String>,
+}
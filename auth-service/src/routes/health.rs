@@ -0,0 +1,111 @@
+use std::{collections::BTreeMap, time::Instant};
+
+use axum::{extract::State, http::StatusCode, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+
+// The process is up and serving requests, whatever the state of its dependencies
+#[tracing::instrument(name = "Liveness", skip_all)]
+pub async fn health_live() -> Json<HealthResponse> {
+    Json(HealthResponse {
+        status: HealthStatus::Ok,
+        checks: BTreeMap::new(),
+    })
+}
+
+// Checks every dependency at once, so a slow one doesn't hold up reporting the others
+#[tracing::instrument(name = "Readiness", skip_all)]
+pub async fn health_ready(State(state): State<AppState>) -> (StatusCode, Json<HealthResponse>) {
+    let timeout = state.settings.health.timeout;
+
+    let checks: Vec<_> = state
+        .health_checks
+        .iter()
+        .map(|health_check| {
+            let health_check = health_check.clone();
+            tokio::spawn(async move {
+                let started = Instant::now();
+                let outcome = tokio::time::timeout(timeout, health_check.check()).await;
+                let latency_ms = started.elapsed().as_millis() as u64;
+
+                // Probes may be reachable from outside, so why a check failed only goes to the logs
+                let error = match outcome {
+                    Ok(Ok(())) => None,
+                    Ok(Err(e)) => Some(format!("{:#}", e)),
+                    Err(_) => Some(format!("timed out after {} ms", timeout.as_millis())),
+                };
+                if let Some(error) = &error {
+                    tracing::warn!(
+                        latency_ms,
+                        "{} health check failed: {}",
+                        health_check.name(),
+                        error
+                    );
+                }
+
+                (
+                    health_check.name(),
+                    DependencyHealth::new(latency_ms, error.is_none()),
+                )
+            })
+        })
+        .collect();
+
+    let mut results = BTreeMap::new();
+    for check in checks {
+        let (name, health) = check.await.unwrap_or_else(|e| {
+            tracing::error!("Health check panicked: {}", e);
+            ("unknown", DependencyHealth::new(0, false))
+        });
+        results.insert(name.to_owned(), health);
+    }
+
+    let is_ready = results
+        .values()
+        .all(|health| health.status == HealthStatus::Ok);
+    let (status_code, status) = match is_ready {
+        true => (StatusCode::OK, HealthStatus::Ok),
+        false => (StatusCode::SERVICE_UNAVAILABLE, HealthStatus::Unavailable),
+    };
+
+    (
+        status_code,
+        Json(HealthResponse {
+            status,
+            checks: results,
+        }),
+    )
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Ok,
+    Unavailable,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HealthResponse {
+    pub status: HealthStatus,
+    /// Keyed by dependency name. Empty for liveness, which doesn't check any.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub checks: BTreeMap<String, DependencyHealth>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DependencyHealth {
+    pub status: HealthStatus,
+    #[serde(rename = "latencyMs")]
+    pub latency_ms: u64,
+}
+
+impl DependencyHealth {
+    fn new(latency_ms: u64, is_healthy: bool) -> Self {
+        let status = match is_healthy {
+            true => HealthStatus::Ok,
+            false => HealthStatus::Unavailable,
+        };
+        Self { status, latency_ms }
+    }
+}
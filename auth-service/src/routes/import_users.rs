@@ -0,0 +1,106 @@
+use axum::{extract::State, http::HeaderMap, response::IntoResponse, Json};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{data_stores::UserStoreError, AuthAPIError, Email, Password, User, WebhookEventType},
+    services::password_hasher::PasswordHasher,
+    utils::{auth::authenticate_admin, webhooks::publish_event},
+};
+
+const MAX_IMPORT_BATCH_SIZE: usize = 1000;
+
+/// Imports users from another system with their existing password hashes. Users that can't be
+/// imported are skipped and reported rather than failing the whole batch.
+#[tracing::instrument(name = "Import Users", skip_all)]
+pub async fn import_users(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<ImportUsersRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    authenticate_admin(&headers, &state.settings.auth)?;
+
+    if request.users.len() > MAX_IMPORT_BATCH_SIZE {
+        return Err(AuthAPIError::InvalidCredentials);
+    }
+
+    let mut imported = vec![];
+    let mut skipped = vec![];
+
+    for (index, imported_user) in request.users.into_iter().enumerate() {
+        let skip = |reason: &str| SkippedUser {
+            index,
+            reason: reason.to_owned(),
+        };
+
+        let Ok(email) = Email::parse(imported_user.email) else {
+            skipped.push(skip("Invalid email"));
+            continue;
+        };
+        if !PasswordHasher::is_supported(imported_user.password_hash.expose_secret()) {
+            skipped.push(skip("Unsupported password hash"));
+            continue;
+        }
+        // Never used to log in, the store verifies against the hash instead
+        let Ok(password) = Password::parse(imported_user.password_hash.clone()) else {
+            skipped.push(skip("Unsupported password hash"));
+            continue;
+        };
+
+        let user = User::new(email, password, imported_user.requires_2fa);
+        let event_data = serde_json::json!({
+            "email": user.email.as_ref().expose_secret(),
+            "requires2FA": user.requires_2fa,
+        });
+
+        let result = state
+            .user_store
+            .import_user(user, imported_user.password_hash)
+            .await;
+
+        match result {
+            Ok(()) => imported.push(event_data),
+            Err(UserStoreError::UserAlreadyExists) => skipped.push(skip("User already exists")),
+            Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+        }
+    }
+
+    let imported_count = imported.len();
+    for event_data in imported {
+        publish_event(&state, WebhookEventType::UserCreated, event_data).await;
+    }
+
+    Ok(Json(ImportUsersResponse {
+        imported: imported_count,
+        skipped,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct ImportUsersRequest {
+    pub users: Vec<ImportedUser>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedUser {
+    pub email: Secret<String>,
+    /// A bcrypt hash, or an Argon2, scrypt or PBKDF2 hash in PHC string format
+    pub password_hash: Secret<String>,
+    #[serde(default, rename = "requires2FA")]
+    pub requires_2fa: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ImportUsersResponse {
+    pub imported: usize,
+    pub skipped: Vec<SkippedUser>,
+}
+
+/// A user that wasn't imported, identified by its position in the request
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct SkippedUser {
+    pub index: usize,
+    pub reason: String,
+}
@@ -5,16 +5,48 @@ use secrecy::{ExposeSecret, Secret};
 
 use crate::{
     app_state::AppState,
-    domain::{AuthAPIError, Email, LoginAttemptId, Password, TwoFACode},
-    utils::auth::generate_auth_cookie,
+    domain::{
+        AuthAPIError, Email, LoginAttemptId, LoginDevice, Password, TwoFAChannel, TwoFACode, User,
+    },
+    utils::{
+        audit::AuditSubject,
+        auth::generate_auth_cookie,
+        login_devices::{is_trusted_device, record_login_device},
+        metrics,
+        two_fa::send_2fa_code,
+    },
 };
 
 #[tracing::instrument(name = "Login", skip_all)]
 pub async fn login(
     State(state): State<AppState>,
+    audit: AuditSubject,
+    device: LoginDevice,
     jar: CookieJar,
     Json(request): Json<LoginRequest>,
 ) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let (jar, result) = attempt_login(&state, &audit, &device, jar, request).await;
+
+    let outcome = match &result {
+        Ok((StatusCode::PARTIAL_CONTENT, _)) => "two_factor_required",
+        Ok(_) => "success",
+        Err(_) => "failed",
+    };
+    metrics::LOGINS.with_label_values(&[outcome]).inc();
+
+    (jar, result)
+}
+
+async fn attempt_login(
+    state: &AppState,
+    audit: &AuditSubject,
+    device: &LoginDevice,
+    jar: CookieJar,
+    request: LoginRequest,
+) -> (
+    CookieJar,
+    Result<(StatusCode, Json<LoginResponse>), AuthAPIError>,
+) {
     let password = match Password::parse(request.password) {
         Ok(password) => password,
         Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
@@ -24,8 +56,9 @@ pub async fn login(
         Ok(email) => email,
         Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
     };
+    audit.set(&email);
 
-    let user_store = &state.user_store.read().await;
+    let user_store = &state.user_store;
 
     if user_store.validate_user(&email, &password).await.is_err() {
         return (jar, Err(AuthAPIError::IncorrectCredentials));
@@ -36,15 +69,24 @@ pub async fn login(
         Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
     };
 
-    match user.requires_2fa {
-        true => handle_2fa(&user.email, &state, jar).await,
-        false => handle_no_2fa(&user.email, jar).await,
+    // Users deprovisioned over SCIM keep their account but can't log in
+    if !user.active {
+        return (jar, Err(AuthAPIError::IncorrectCredentials));
+    }
+
+    // Users skip 2FA on devices they trusted until the device expires or is revoked
+    let requires_2fa =
+        user.requires_2fa && !is_trusted_device(state, &user.email, device, &jar).await;
+
+    match requires_2fa {
+        true => handle_2fa(&user, state, jar).await,
+        false => handle_no_2fa(&user.email, device, state, jar).await,
     }
 }
 
 #[tracing::instrument(name = "Handle 2FA", skip_all)]
 async fn handle_2fa(
-    email: &Email,
+    user: &User,
     state: &AppState,
     jar: CookieJar,
 ) -> (
@@ -53,49 +95,54 @@ async fn handle_2fa(
 ) {
     let login_attempt_id = LoginAttemptId::default();
     let two_fa_code = TwoFACode::default();
+    let destination = user.two_fa_destination();
 
     // Updated!
     if let Err(e) = state
         .two_fa_code_store
-        .write()
-        .await
-        .add_code(email.clone(), login_attempt_id.clone(), two_fa_code.clone())
+        .add_code(
+            user.email.clone(),
+            login_attempt_id.clone(),
+            two_fa_code.clone(),
+        )
         .await
     {
         return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
     }
 
-    // Updated!
-    if let Err(e) = state
-        .email_client
-        .send_email(email, "2FA Code", two_fa_code.as_ref().expose_secret())
-        .await
-    {
+    if let Err(e) = send_2fa_code(state, &destination, &two_fa_code).await {
         return (jar, Err(AuthAPIError::UnexpectedError(e)));
     }
+    metrics::TWO_FA_CHALLENGES
+        .with_label_values(&["sent"])
+        .inc();
 
     let response = Json(LoginResponse::TwoFactorAuth(TwoFactorAuthResponse {
         message: "2FA required".to_owned(),
         login_attempt_id: login_attempt_id.expose_secret().clone(),
+        channel: destination.channel(),
+        destination: destination.masked(),
     }));
 
     (jar, Ok((StatusCode::PARTIAL_CONTENT, response)))
 }
 
-#[tracing::instrument(name = "Handle No 2FA")]
+#[tracing::instrument(name = "Handle No 2FA", skip(device, state))]
 async fn handle_no_2fa(
     email: &Email,
+    device: &LoginDevice,
+    state: &AppState,
     jar: CookieJar,
 ) -> (
     CookieJar,
     Result<(StatusCode, Json<LoginResponse>), AuthAPIError>,
 ) {
-    let auth_cookie = match generate_auth_cookie(email) {
+    let auth_cookie = match generate_auth_cookie(email, &state.settings.auth) {
         Ok(cookie) => cookie,
         Err(e) => return (jar, Err(AuthAPIError::UnexpectedError(e))), // Updated!
     };
 
-    let updated_jar = jar.add(auth_cookie);
+    let updated_jar = record_login_device(state, email, device, jar.add(auth_cookie)).await;
 
     (
         updated_jar,
@@ -121,4 +168,8 @@ pub struct TwoFactorAuthResponse {
     pub message: String,
     #[serde(rename = "loginAttemptId")]
     pub login_attempt_id: String,
+    /// Channel the 2FA code was sent over
+    pub channel: TwoFAChannel,
+    /// Masked email address or phone number the 2FA code was sent to
+    pub destination: String,
 }
\ No newline at end of file
@@ -1,15 +1,23 @@
 use axum::{extract::State, http::StatusCode, response::IntoResponse};
-use axum_extra::extract::{cookie, CookieJar};
+use axum_extra::extract::CookieJar;
 
 use crate::{
     app_state::AppState, 
-    domain::AuthAPIError, 
-    utils::{auth::validate_token, constants::JWT_COOKIE_NAME}
+    domain::{AuthAPIError, Email},
+    utils::{
+        audit::AuditSubject,
+        auth::{auth_cookie_removal, validate_token},
+        metrics,
+    },
 };
 
 #[tracing::instrument(name = "Logout", skip_all)]
-pub async fn logout(State(state): State<AppState>, jar: CookieJar) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
-    let cookie = match jar.get(JWT_COOKIE_NAME) {
+pub async fn logout(
+    State(state): State<AppState>,
+    audit: AuditSubject,
+    jar: CookieJar,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let cookie = match jar.get(&state.settings.auth.cookie.name) {
         Some(cookie) => cookie,
         None => return (jar, Err(AuthAPIError::MissingToken))
     };
@@ -17,21 +25,25 @@ pub async fn logout(State(state): State<AppState>, jar: CookieJar) -> (CookieJar
     let token = cookie.value().to_owned();
 
     let banned_token_store = state.banned_token_store;
-    let _ = match validate_token(&token, banned_token_store.clone()).await {
-        Ok(claims) => claims,
-        Err(_) => return (jar, Err(AuthAPIError::InvalidToken))
-    };
+    let claims =
+        match validate_token(&token, &state.settings.auth, banned_token_store.clone()).await {
+            Ok(claims) => claims,
+            Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
+        };
+    let ban_ttl_seconds = claims.ban_ttl_seconds();
+    if let Ok(email) = Email::parse(claims.sub.into()) {
+        audit.set(&email);
+    }
 
     if let Err(e) = banned_token_store
-        .write()
-        .await
-        .add_token(token.to_owned().into())
+        .add_token(token.to_owned().into(), ban_ttl_seconds)
         .await
     {
         return (jar, Err(AuthAPIError::UnexpectedError(e.into())));
     }
+    metrics::TOKENS_BANNED.with_label_values(&["logout"]).inc();
 
-    let jar = jar.remove(cookie::Cookie::from(JWT_COOKIE_NAME));
+    let jar = jar.remove(auth_cookie_removal(&state.settings.auth.cookie));
 
     (jar, Ok(StatusCode::OK))
 }
\ No newline at end of file
@@ -1,11 +1,39 @@
+mod audit_log;
+mod change_password;
+mod health;
+mod import_users;
 mod login;
 mod logout;
+mod phone_number;
+mod report_login;
+mod resend_2fa_code;
+mod reset_password;
+mod scim_groups;
+mod scim_service_provider_config;
+mod scim_users;
 mod signup;
+mod trusted_devices;
+mod two_fa_channel;
 mod verify_2fa;
 mod verify_token;
+mod webhooks;
 // re-export items from sub-modules
+pub use audit_log::*;
+pub use change_password::*;
+pub use health::*;
+pub use import_users::*;
 pub use login::*;
 pub use logout::*;
+pub use phone_number::*;
+pub use report_login::*;
+pub use resend_2fa_code::*;
+pub use reset_password::*;
+pub use scim_groups::*;
+pub use scim_service_provider_config::*;
+pub use scim_users::*;
 pub use signup::*;
+pub use trusted_devices::*;
+pub use two_fa_channel::*;
 pub use verify_2fa::*;
-pub use verify_token::*;
\ No newline at end of file
+pub use verify_token::*;
+pub use webhooks::*;
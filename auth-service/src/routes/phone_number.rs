@@ -0,0 +1,126 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuthAPIError, LoginAttemptId, PhoneNumber, TwoFACode, TwoFACodeStoreError,
+        TwoFADestination, UserStoreError,
+    },
+    utils::{audit::AuditSubject, auth::authenticate, two_fa::send_2fa_code},
+};
+
+#[tracing::instrument(name = "Set Phone Number", skip_all)]
+pub async fn set_phone_number(
+    State(state): State<AppState>,
+    audit: AuditSubject,
+    jar: CookieJar,
+    Json(request): Json<SetPhoneNumberRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = authenticate(&jar, &state.settings.auth, state.banned_token_store.clone()).await?;
+    audit.set(&email);
+
+    let phone_number =
+        PhoneNumber::parse(request.phone_number).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    // The number stays unverified, and unusable for 2FA, until the code sent to it is confirmed
+    state
+        .user_store
+        .set_phone_number(&email, phone_number.clone())
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let verification_id = LoginAttemptId::default();
+    let two_fa_code = TwoFACode::default();
+
+    // The code only ever verifies the number it was sent to
+    state
+        .phone_verification_store
+        .add_phone_number_code(
+            email,
+            phone_number.clone(),
+            verification_id.clone(),
+            two_fa_code.clone(),
+        )
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let destination = TwoFADestination::Sms(phone_number);
+    send_2fa_code(&state, &destination, &two_fa_code)
+        .await
+        .map_err(AuthAPIError::UnexpectedError)?;
+
+    let response = Json(SetPhoneNumberResponse {
+        message: "Verification code sent".to_owned(),
+        verification_id: verification_id.expose_secret().clone(),
+        destination: destination.masked(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+#[tracing::instrument(name = "Verify Phone Number", skip_all)]
+pub async fn verify_phone_number(
+    State(state): State<AppState>,
+    audit: AuditSubject,
+    jar: CookieJar,
+    Json(request): Json<VerifyPhoneNumberRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = authenticate(&jar, &state.settings.auth, state.banned_token_store.clone()).await?;
+    audit.set(&email);
+
+    let verification_id = LoginAttemptId::parse(request.verification_id.into())
+        .map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let two_fa_code = TwoFACode::parse(request.two_fa_code.into())
+        .map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let phone_verification_store = &state.phone_verification_store;
+
+    let phone_number = match phone_verification_store
+        .consume_phone_number_code(&verification_id, &email, &two_fa_code)
+        .await
+    {
+        Ok(phone_number) => phone_number,
+        Err(TwoFACodeStoreError::IncorrectCode | TwoFACodeStoreError::LoginAttemptIdNotFound) => {
+            return Err(AuthAPIError::IncorrectCredentials)
+        }
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+
+    let verified = state
+        .user_store
+        .verify_phone_number(&email, &phone_number)
+        .await;
+
+    match verified {
+        Ok(()) => Ok(StatusCode::OK),
+        // The number was replaced after the code was sent, so the code is of no use anymore
+        Err(UserStoreError::PhoneNumberChanged) => Err(AuthAPIError::IncorrectCredentials),
+        Err(e) => Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SetPhoneNumberRequest {
+    #[serde(rename = "phoneNumber")]
+    pub phone_number: Secret<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct SetPhoneNumberResponse {
+    pub message: String,
+    #[serde(rename = "verificationId")]
+    pub verification_id: String,
+    pub destination: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyPhoneNumberRequest {
+    #[serde(rename = "verificationId")]
+    pub verification_id: String,
+    #[serde(rename = "2FACode")]
+    pub two_fa_code: String,
+}
@@ -0,0 +1,150 @@
+use axum::{
+    extract::{Query, State},
+    response::Html,
+    Form,
+};
+use chrono::Utc;
+use color_eyre::eyre::Result;
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Email, Password, UserStoreError},
+    utils::{
+        audit::AuditSubject,
+        auth::{
+            generate_action_token, validate_action_token, ActionTokenPurpose,
+            PASSWORD_RESET_TOKEN_TTL_SECONDS,
+        },
+        metrics,
+    },
+};
+
+const REPORTED_PAGE: &str = "<!DOCTYPE html>\
+<html><head><meta charset=\"utf-8\"><title>Account secured</title></head>\
+<body><h1>Your account is secured</h1>\
+<p>You have been signed out everywhere and your password no longer works. \
+We've emailed you a link to choose a new one.</p></body></html>";
+
+/// Opened from the link in a new sign-in email. Only asks the user to confirm, so that link
+/// scanners and previews fetching the link can't lock them out of their account.
+#[tracing::instrument(name = "Confirm Report Login", skip_all)]
+pub async fn confirm_report_login(
+    State(state): State<AppState>,
+    audit: AuditSubject,
+    Query(query): Query<ReportLoginRequest>,
+) -> Result<Html<String>, AuthAPIError> {
+    let (email, _) = validate_action_token(
+        &query.token,
+        ActionTokenPurpose::ReportLogin,
+        &state.settings.auth,
+        state.banned_token_store.clone(),
+    )
+    .await?;
+    audit.set(&email);
+
+    // A valid token is a JWT, which only holds URL-safe characters and needs no escaping
+    Ok(Html(format!(
+        "<!DOCTYPE html>\
+<html><head><meta charset=\"utf-8\"><title>Secure your account</title></head>\
+<body><h1>Wasn't you?</h1>\
+<p>Securing your account signs you out everywhere and disables your password until you \
+choose a new one from the link we'll email you.</p>\
+<form method=\"post\" action=\"/report-login\">\
+<input type=\"hidden\" name=\"token\" value=\"{}\">\
+<button type=\"submit\">Secure my account</button></form></body></html>",
+        query.token.expose_secret()
+    )))
+}
+
+/// Submitted from the confirmation page by a user who didn't sign in. Signs them out everywhere
+/// and replaces their password with one nobody knows until they choose a new one.
+#[tracing::instrument(name = "Report Login", skip_all)]
+pub async fn report_login(
+    State(state): State<AppState>,
+    audit: AuditSubject,
+    Form(request): Form<ReportLoginRequest>,
+) -> Result<Html<&'static str>, AuthAPIError> {
+    let (email, claims) = validate_action_token(
+        &request.token,
+        ActionTokenPurpose::ReportLogin,
+        &state.settings.auth,
+        state.banned_token_store.clone(),
+    )
+    .await?;
+    audit.set(&email);
+
+    let revoked_before = Utc::now().timestamp();
+    state
+        .banned_token_store
+        .revoke_tokens_issued_before(&email, revoked_before)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    match state
+        .user_store
+        .update_password(
+            &email,
+            Password::unusable(),
+            state.password_policy.history_size,
+        )
+        .await
+    {
+        Ok(()) => {}
+        Err(UserStoreError::UserNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    state
+        .known_device_store
+        .forget_devices(&email)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    // The link stays usable until the reset email is out, so a failed send can be retried
+    send_password_reset_email(&state, &email)
+        .await
+        .map_err(AuthAPIError::UnexpectedError)?;
+
+    state
+        .banned_token_store
+        .add_token(request.token, claims.ban_ttl_seconds())
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    metrics::TOKENS_BANNED
+        .with_label_values(&["report_login"])
+        .inc();
+
+    Ok(Html(REPORTED_PAGE))
+}
+
+async fn send_password_reset_email(state: &AppState, email: &Email) -> Result<()> {
+    let token = generate_action_token(
+        email,
+        ActionTokenPurpose::PasswordReset,
+        &state.settings.auth,
+    )?;
+    let reset_url = format!(
+        "{}/?reset_token={}",
+        state.settings.application.public_url,
+        token.expose_secret()
+    );
+
+    let content = format!(
+        "You've been signed out everywhere and your old password no longer works. \
+         Open the link below within {} minutes to choose a new one:\n\n{}",
+        PASSWORD_RESET_TOKEN_TTL_SECONDS / 60,
+        reset_url
+    );
+
+    state
+        .email_client
+        .send_email(email, "Reset your password", &content)
+        .await
+}
+
+#[derive(Deserialize)]
+pub struct ReportLoginRequest {
+    pub token: Secret<String>,
+}
@@ -0,0 +1,96 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Email, LoginAttemptId, TwoFAChannel, TwoFACode, TwoFACodeStoreError},
+    utils::{audit::AuditSubject, metrics, two_fa::send_2fa_code},
+};
+
+#[tracing::instrument(name = "Resend 2FA Code", skip_all)]
+pub async fn resend_2fa_code(
+    State(state): State<AppState>,
+    audit: AuditSubject,
+    Json(request): Json<Resend2FACodeRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = Email::parse(request.email.into()).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    audit.set(&email);
+
+    let login_attempt_id = LoginAttemptId::parse(request.login_attempt_id.into())
+        .map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let two_fa_code_store = &state.two_fa_code_store;
+
+    // Only the user the login attempt belongs to may have its code resent
+    let (expected_email, _) = two_fa_code_store
+        .get_code(&login_attempt_id)
+        .await
+        .map_err(|_| AuthAPIError::IncorrectCredentials)?;
+
+    if email != expected_email {
+        return Err(AuthAPIError::IncorrectCredentials);
+    }
+
+    // A fresh code is generated so that the previously sent one stops working
+    let two_fa_code = TwoFACode::default();
+
+    let email = match two_fa_code_store
+        .resend_code(&login_attempt_id, two_fa_code.clone())
+        .await
+    {
+        Ok(email) => email,
+        Err(TwoFACodeStoreError::LoginAttemptIdNotFound) => {
+            return Err(AuthAPIError::IncorrectCredentials)
+        }
+        Err(TwoFACodeStoreError::ResendCooldown {
+            retry_after_seconds,
+        }) => {
+            return Err(AuthAPIError::TooManyRequests {
+                retry_after_seconds: Some(retry_after_seconds),
+            })
+        }
+        Err(TwoFACodeStoreError::TooManyResends) => {
+            return Err(AuthAPIError::TooManyRequests {
+                retry_after_seconds: None,
+            })
+        }
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    };
+
+    // Resend over the user's current channel, which may have changed since login
+    let destination = state
+        .user_store
+        .get_user(&email)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?
+        .two_fa_destination();
+
+    send_2fa_code(&state, &destination, &two_fa_code)
+        .await
+        .map_err(AuthAPIError::UnexpectedError)?;
+    metrics::TWO_FA_CHALLENGES
+        .with_label_values(&["resent"])
+        .inc();
+
+    let response = Json(Resend2FACodeResponse {
+        message: "2FA code resent".to_owned(),
+        channel: destination.channel(),
+        destination: destination.masked(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Resend2FACodeRequest {
+    pub email: String,
+    #[serde(rename = "loginAttemptId")]
+    pub login_attempt_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Resend2FACodeResponse {
+    pub message: String,
+    pub channel: TwoFAChannel,
+    pub destination: String,
+}
@@ -0,0 +1,83 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use chrono::Utc;
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Password, UserStoreError, WebhookEventType},
+    utils::{
+        audit::AuditSubject,
+        auth::{validate_action_token, ActionTokenPurpose},
+        metrics,
+        passwords::check_new_password,
+        webhooks::publish_event,
+    },
+};
+
+/// Sets a new password with the token from a password reset email, signing the user out of
+/// every existing session
+#[tracing::instrument(name = "Reset Password", skip_all)]
+pub async fn reset_password(
+    State(state): State<AppState>,
+    audit: AuditSubject,
+    Json(request): Json<ResetPasswordRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let (email, claims) = validate_action_token(
+        &request.token,
+        ActionTokenPurpose::PasswordReset,
+        &state.settings.auth,
+        state.banned_token_store.clone(),
+    )
+    .await?;
+    audit.set(&email);
+
+    let new_password =
+        Password::parse(request.new_password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    check_new_password(&state, &new_password, &email).map_err(AuthAPIError::WeakPassword)?;
+
+    match state
+        .user_store
+        .update_password(&email, new_password, state.password_policy.history_size)
+        .await
+    {
+        Ok(()) => {}
+        Err(UserStoreError::PasswordReused) => {
+            return Err(AuthAPIError::WeakPassword(vec![state
+                .password_policy
+                .reuse_violation()]))
+        }
+        Err(UserStoreError::UserNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+
+    let banned_token_store = &state.banned_token_store;
+    banned_token_store
+        .add_token(request.token, claims.ban_ttl_seconds())
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    metrics::TOKENS_BANNED
+        .with_label_values(&["password_reset"])
+        .inc();
+    banned_token_store
+        .revoke_tokens_issued_before(&email, Utc::now().timestamp())
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    publish_event(
+        &state,
+        WebhookEventType::UserPasswordChanged,
+        serde_json::json!({ "email": email.as_ref().expose_secret() }),
+    )
+    .await;
+
+    Ok(StatusCode::OK)
+}
+
+#[derive(Deserialize)]
+pub struct ResetPasswordRequest {
+    pub token: Secret<String>,
+    #[serde(rename = "newPassword")]
+    pub new_password: Secret<String>,
+}
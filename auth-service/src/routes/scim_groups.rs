@@ -0,0 +1,385 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        attribute_name, Group, GroupStoreError, ScimError, ScimFilter, ScimGroup, ScimGroupRequest,
+        ScimListResponse, ScimMember, ScimPatchOp, ScimPatchRequest, ScimValue, UserStoreError,
+        PATCH_OP_SCHEMA,
+    },
+    utils::{
+        auth::authenticate_scim,
+        scim::{ScimJson, ScimListQuery},
+    },
+};
+
+#[tracing::instrument(name = "SCIM Create Group", skip_all)]
+pub async fn create_scim_group(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<ScimGroupRequest>,
+) -> Result<impl IntoResponse, ScimError> {
+    authenticate_scim(&headers, &state.settings.auth)?;
+
+    let display_name = parse_display_name(&request.display_name)?;
+    let member_ids: Vec<String> = request
+        .members
+        .into_iter()
+        .map(|member| member.value)
+        .collect();
+    let members = parse_members(&state, &member_ids).await?;
+
+    let group = Group::new(display_name, request.external_id, members);
+
+    state
+        .group_store
+        .add_group(group.clone())
+        .await
+        .map_err(to_scim_error)?;
+
+    let resource = to_resource(&state, &group).await?;
+    let location = HeaderValue::from_str(&resource.meta.location)
+        .map_err(|e| ScimError::UnexpectedError(e.into()))?;
+
+    Ok((
+        [(header::LOCATION, location)],
+        ScimJson(StatusCode::CREATED, resource),
+    ))
+}
+
+#[tracing::instrument(name = "SCIM Get Group", skip_all)]
+pub async fn get_scim_group(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, ScimError> {
+    authenticate_scim(&headers, &state.settings.auth)?;
+
+    let group = get_group(&state, &id).await?;
+
+    Ok(ScimJson(StatusCode::OK, to_resource(&state, &group).await?))
+}
+
+#[tracing::instrument(name = "SCIM List Groups", skip_all)]
+pub async fn list_scim_groups(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<ScimListQuery>,
+) -> Result<impl IntoResponse, ScimError> {
+    authenticate_scim(&headers, &state.settings.auth)?;
+
+    let (start_index, offset, limit) = query.page();
+    let mut display_name = None;
+
+    for condition in query
+        .filter()?
+        .iter()
+        .flat_map(|filter| filter.conditions())
+    {
+        match (condition.attribute.as_str(), &condition.value) {
+            ("displayname", ScimValue::String(name)) => display_name = Some(name.clone()),
+            (attribute, _) => {
+                return Err(ScimError::InvalidFilter(format!(
+                    "Filtering on {} is not supported",
+                    attribute
+                )))
+            }
+        }
+    }
+
+    let (groups, total) = state
+        .group_store
+        .list_groups(display_name.as_deref(), offset, limit)
+        .await
+        .map_err(to_scim_error)?;
+
+    let mut resources = Vec::with_capacity(groups.len());
+    for group in &groups {
+        resources.push(to_resource(&state, group).await?);
+    }
+
+    Ok(ScimJson(
+        StatusCode::OK,
+        ScimListResponse::new(resources, total, start_index),
+    ))
+}
+
+#[tracing::instrument(name = "SCIM Replace Group", skip_all)]
+pub async fn replace_scim_group(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Json(request): Json<ScimGroupRequest>,
+) -> Result<impl IntoResponse, ScimError> {
+    authenticate_scim(&headers, &state.settings.auth)?;
+
+    let mut group = get_group(&state, &id).await?;
+
+    group.display_name = parse_display_name(&request.display_name)?;
+    group.external_id = request.external_id;
+    let member_ids: Vec<String> = request
+        .members
+        .into_iter()
+        .map(|member| member.value)
+        .collect();
+    group.set_members(parse_members(&state, &member_ids).await?);
+
+    let group = update_group(&state, group).await?;
+
+    Ok(ScimJson(StatusCode::OK, to_resource(&state, &group).await?))
+}
+
+#[tracing::instrument(name = "SCIM Patch Group", skip_all)]
+pub async fn patch_scim_group(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Json(request): Json<ScimPatchRequest>,
+) -> Result<impl IntoResponse, ScimError> {
+    authenticate_scim(&headers, &state.settings.auth)?;
+
+    if !request
+        .schemas
+        .iter()
+        .any(|schema| schema == PATCH_OP_SCHEMA)
+    {
+        return Err(ScimError::InvalidSyntax(
+            "Missing PatchOp schema".to_owned(),
+        ));
+    }
+
+    let mut group = get_group(&state, &id).await?;
+
+    for operation in &request.operations {
+        let op = operation.op()?;
+
+        // Without a path the value holds the attributes to set
+        let attributes: Vec<(String, Option<&serde_json::Value>)> = match &operation.path {
+            Some(path) => vec![(path.trim().to_owned(), operation.value.as_ref())],
+            None => match &operation.value {
+                Some(serde_json::Value::Object(values)) if op != ScimPatchOp::Remove => values
+                    .iter()
+                    .map(|(name, value)| (name.clone(), Some(value)))
+                    .collect(),
+                _ => return Err(ScimError::InvalidPath("A path is required".to_owned())),
+            },
+        };
+
+        for (path, value) in attributes {
+            apply_patch(&state, &mut group, op, &path, value).await?;
+        }
+    }
+
+    let group = update_group(&state, group).await?;
+
+    Ok(ScimJson(StatusCode::OK, to_resource(&state, &group).await?))
+}
+
+#[tracing::instrument(name = "SCIM Delete Group", skip_all)]
+pub async fn delete_scim_group(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, ScimError> {
+    authenticate_scim(&headers, &state.settings.auth)?;
+
+    let id = Uuid::parse_str(&id).map_err(|_| ScimError::NotFound)?;
+
+    state
+        .group_store
+        .delete_group(id)
+        .await
+        .map_err(to_scim_error)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn apply_patch(
+    state: &AppState,
+    group: &mut Group,
+    op: ScimPatchOp,
+    path: &str,
+    value: Option<&serde_json::Value>,
+) -> Result<(), ScimError> {
+    // A value filter on members picks out the member to remove, e.g. `members[value eq "..."]`
+    if let Some(filter) = path
+        .strip_suffix(']')
+        .and_then(|path| path.split_once('['))
+        .filter(|(attribute, _)| attribute_name(attribute) == "members")
+        .map(|(_, filter)| filter)
+    {
+        if op != ScimPatchOp::Remove {
+            return Err(ScimError::InvalidPath(
+                "Member filters are only supported when removing".to_owned(),
+            ));
+        }
+        let filter = ScimFilter::parse(filter).map_err(|_| invalid_member_filter())?;
+        for condition in filter.conditions() {
+            match (condition.attribute.as_str(), &condition.value) {
+                ("value", ScimValue::String(id)) => {
+                    if let Ok(id) = Uuid::parse_str(id) {
+                        group.remove_member(id);
+                    }
+                }
+                _ => return Err(invalid_member_filter()),
+            }
+        }
+        return Ok(());
+    }
+
+    match (attribute_name(path).as_str(), op) {
+        ("members", ScimPatchOp::Remove) => match value {
+            // Some identity providers list the members to remove in the value
+            Some(value) => {
+                for id in member_ids(value)? {
+                    if let Ok(id) = Uuid::parse_str(&id) {
+                        group.remove_member(id);
+                    }
+                }
+            }
+            None => group.set_members(vec![]),
+        },
+        ("members", op) => {
+            let members = parse_members(state, &member_ids(require(value)?)?).await?;
+            match op {
+                ScimPatchOp::Add => members.into_iter().for_each(|id| group.add_member(id)),
+                _ => group.set_members(members),
+            }
+        }
+        ("displayname", ScimPatchOp::Remove) => {
+            return Err(ScimError::Mutability("displayName is required".to_owned()))
+        }
+        ("displayname", _) => match require(value)? {
+            serde_json::Value::String(name) => group.display_name = parse_display_name(name)?,
+            _ => {
+                return Err(ScimError::InvalidValue(
+                    "displayName must be a string".to_owned(),
+                ))
+            }
+        },
+        ("externalid", ScimPatchOp::Remove) => group.external_id = None,
+        ("externalid", _) => match ScimValue::from(require(value)?) {
+            ScimValue::String(external_id) => group.external_id = Some(external_id),
+            ScimValue::Null => group.external_id = None,
+            ScimValue::Bool(_) => {
+                return Err(ScimError::InvalidValue(
+                    "externalId must be a string".to_owned(),
+                ))
+            }
+        },
+        (attribute, _) => {
+            return Err(ScimError::InvalidPath(format!(
+                "{} can't be modified",
+                attribute
+            )))
+        }
+    }
+
+    Ok(())
+}
+
+fn require(value: Option<&serde_json::Value>) -> Result<&serde_json::Value, ScimError> {
+    value.ok_or(ScimError::InvalidValue("Missing value".to_owned()))
+}
+
+fn invalid_member_filter() -> ScimError {
+    ScimError::InvalidPath("Members can only be filtered by value".to_owned())
+}
+
+// The IDs in a list of `{"value": "<id>"}` member objects
+fn member_ids(value: &serde_json::Value) -> Result<Vec<String>, ScimError> {
+    let invalid =
+        || ScimError::InvalidValue("members must be a list of {\"value\": id}".to_owned());
+
+    value
+        .as_array()
+        .ok_or_else(invalid)?
+        .iter()
+        .map(|member| {
+            member
+                .get("value")
+                .and_then(|value| value.as_str())
+                .map(str::to_owned)
+                .ok_or_else(invalid)
+        })
+        .collect()
+}
+
+// Resolves member IDs, all of which must belong to existing users
+async fn parse_members(state: &AppState, ids: &[String]) -> Result<Vec<Uuid>, ScimError> {
+    let user_store = &state.user_store;
+    let mut members = Vec::with_capacity(ids.len());
+
+    for id in ids {
+        let unknown = || ScimError::InvalidValue(format!("No user with ID {}", id));
+        let id = Uuid::parse_str(id).map_err(|_| unknown())?;
+
+        match user_store.get_user_by_id(id).await {
+            Ok(user) => members.push(user.id),
+            Err(UserStoreError::UserNotFound) => return Err(unknown()),
+            Err(e) => return Err(ScimError::UnexpectedError(e.into())),
+        }
+    }
+
+    Ok(members)
+}
+
+fn parse_display_name(display_name: &str) -> Result<String, ScimError> {
+    let display_name = display_name.trim();
+    if display_name.is_empty() {
+        return Err(ScimError::InvalidValue(
+            "displayName must not be empty".to_owned(),
+        ));
+    }
+    Ok(display_name.to_owned())
+}
+
+async fn get_group(state: &AppState, id: &str) -> Result<Group, ScimError> {
+    let id = Uuid::parse_str(id).map_err(|_| ScimError::NotFound)?;
+
+    state.group_store.get_group(id).await.map_err(to_scim_error)
+}
+
+async fn update_group(state: &AppState, mut group: Group) -> Result<Group, ScimError> {
+    group.touch();
+
+    state
+        .group_store
+        .update_group(&group)
+        .await
+        .map_err(to_scim_error)?;
+
+    Ok(group)
+}
+
+// Members are shown with their user names. Users deleted in the meantime are left out.
+async fn to_resource(state: &AppState, group: &Group) -> Result<ScimGroup, ScimError> {
+    let user_store = &state.user_store;
+    let mut members = Vec::with_capacity(group.members.len());
+
+    for id in &group.members {
+        match user_store.get_user_by_id(*id).await {
+            Ok(user) => members.push(ScimMember::from(&user)),
+            Err(UserStoreError::UserNotFound) => continue,
+            Err(e) => return Err(ScimError::UnexpectedError(e.into())),
+        }
+    }
+
+    Ok(ScimGroup::new(group, members))
+}
+
+fn to_scim_error(e: GroupStoreError) -> ScimError {
+    match e {
+        GroupStoreError::GroupNotFound => ScimError::NotFound,
+        GroupStoreError::GroupAlreadyExists => {
+            ScimError::Uniqueness("displayName is already taken".to_owned())
+        }
+        e => ScimError::UnexpectedError(e.into()),
+    }
+}
@@ -0,0 +1,41 @@
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+};
+
+use crate::{
+    app_state::AppState,
+    domain::{ScimError, SERVICE_PROVIDER_CONFIG_SCHEMA},
+    utils::{
+        auth::authenticate_scim,
+        scim::{ScimJson, MAX_PAGE_SIZE},
+    },
+};
+
+#[tracing::instrument(name = "SCIM Service Provider Config", skip_all)]
+pub async fn get_scim_service_provider_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, ScimError> {
+    authenticate_scim(&headers, &state.settings.auth)?;
+
+    Ok(ScimJson(
+        StatusCode::OK,
+        serde_json::json!({
+            "schemas": [SERVICE_PROVIDER_CONFIG_SCHEMA],
+            "patch": { "supported": true },
+            "bulk": { "supported": false, "maxOperations": 0, "maxPayloadSize": 0 },
+            "filter": { "supported": true, "maxResults": MAX_PAGE_SIZE },
+            "changePassword": { "supported": false },
+            "sort": { "supported": false },
+            "etag": { "supported": false },
+            "authenticationSchemes": [{
+                "type": "oauthbearertoken",
+                "name": "OAuth Bearer Token",
+                "description": "Authentication with the SCIM API token",
+                "primary": true,
+            }],
+        }),
+    ))
+}
@@ -0,0 +1,376 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use chrono::Utc;
+use secrecy::{ExposeSecret, Secret};
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        attribute_name, Email, Password, ScimError, ScimListResponse, ScimPatchOp,
+        ScimPatchRequest, ScimUser, ScimUserRequest, ScimValue, User, UserQuery, UserStoreError,
+        WebhookEventType, PATCH_OP_SCHEMA,
+    },
+    utils::{
+        audit::AuditSubject,
+        auth::authenticate_scim,
+        passwords::check_new_password,
+        scim::{ScimJson, ScimListQuery},
+        webhooks::publish_event,
+    },
+};
+
+#[tracing::instrument(name = "SCIM Create User", skip_all)]
+pub async fn create_scim_user(
+    State(state): State<AppState>,
+    audit: AuditSubject,
+    headers: HeaderMap,
+    Json(request): Json<ScimUserRequest>,
+) -> Result<impl IntoResponse, ScimError> {
+    authenticate_scim(&headers, &state.settings.auth)?;
+
+    let email = parse_user_name(&request.user_name)?;
+    audit.set(&email);
+
+    let password = match request.password {
+        Some(password) => {
+            let password = Password::parse(password)
+                .map_err(|_| ScimError::InvalidValue("password is too short".to_owned()))?;
+            check_new_password(&state, &password, &email).map_err(|violations| {
+                let messages: Vec<_> = violations.into_iter().map(|v| v.message).collect();
+                ScimError::InvalidValue(messages.join(". "))
+            })?;
+            password
+        }
+        // Provisioned without a password, the user can only sign in once they reset it
+        None => Password::unusable(),
+    };
+
+    let mut user = User::new(email, password, false);
+    user.active = request.active.unwrap_or(true);
+    user.external_id = request.external_id;
+
+    let user_store = &state.user_store;
+
+    if user_store.get_user(&user.email).await.is_ok() {
+        return Err(ScimError::Uniqueness(
+            "userName is already taken".to_owned(),
+        ));
+    }
+
+    // Checked again on adding, in case the same user is provisioned concurrently
+    user_store
+        .add_user(user.clone())
+        .await
+        .map_err(|e| match e {
+            UserStoreError::UserAlreadyExists => {
+                ScimError::Uniqueness("userName is already taken".to_owned())
+            }
+            e => ScimError::UnexpectedError(e.into()),
+        })?;
+
+    publish_event(
+        &state,
+        WebhookEventType::UserCreated,
+        serde_json::json!({
+            "email": user.email.as_ref().expose_secret(),
+            "requires2FA": user.requires_2fa,
+        }),
+    )
+    .await;
+
+    let resource = ScimUser::from(&user);
+    let location = HeaderValue::from_str(&resource.meta.location)
+        .map_err(|e| ScimError::UnexpectedError(e.into()))?;
+
+    Ok((
+        [(header::LOCATION, location)],
+        ScimJson(StatusCode::CREATED, resource),
+    ))
+}
+
+#[tracing::instrument(name = "SCIM Get User", skip_all)]
+pub async fn get_scim_user(
+    State(state): State<AppState>,
+    audit: AuditSubject,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, ScimError> {
+    authenticate_scim(&headers, &state.settings.auth)?;
+
+    let user = get_user(&state, &id).await?;
+    audit.set(&user.email);
+
+    Ok(ScimJson(StatusCode::OK, ScimUser::from(&user)))
+}
+
+#[tracing::instrument(name = "SCIM List Users", skip_all)]
+pub async fn list_scim_users(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<ScimListQuery>,
+) -> Result<impl IntoResponse, ScimError> {
+    authenticate_scim(&headers, &state.settings.auth)?;
+
+    let (start_index, offset, limit) = query.page();
+    let mut user_query = UserQuery::default();
+
+    for condition in query
+        .filter()?
+        .iter()
+        .flat_map(|filter| filter.conditions())
+    {
+        match (condition.attribute.as_str(), &condition.value) {
+            ("username", ScimValue::String(user_name)) => {
+                match Email::parse(Secret::new(user_name.clone())) {
+                    Ok(email) => user_query.email = Some(email),
+                    // No user can have a user name that isn't an email
+                    Err(_) => {
+                        return Ok(ScimJson(
+                            StatusCode::OK,
+                            ScimListResponse::new(vec![], 0, start_index),
+                        ))
+                    }
+                }
+            }
+            ("externalid", ScimValue::String(external_id)) => {
+                user_query.external_id = Some(external_id.clone())
+            }
+            ("active", value) => {
+                user_query.active = Some(value.as_bool().ok_or(ScimError::InvalidFilter(
+                    "active must be compared to a boolean".to_owned(),
+                ))?)
+            }
+            (attribute, _) => {
+                return Err(ScimError::InvalidFilter(format!(
+                    "Filtering on {} is not supported",
+                    attribute
+                )))
+            }
+        }
+    }
+
+    let (users, total) = state
+        .user_store
+        .list_users(&user_query, offset, limit)
+        .await
+        .map_err(|e| ScimError::UnexpectedError(e.into()))?;
+
+    let resources = users.iter().map(ScimUser::from).collect();
+
+    Ok(ScimJson(
+        StatusCode::OK,
+        ScimListResponse::new(resources, total, start_index),
+    ))
+}
+
+#[tracing::instrument(name = "SCIM Replace User", skip_all)]
+pub async fn replace_scim_user(
+    State(state): State<AppState>,
+    audit: AuditSubject,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Json(request): Json<ScimUserRequest>,
+) -> Result<impl IntoResponse, ScimError> {
+    authenticate_scim(&headers, &state.settings.auth)?;
+
+    let user = get_user(&state, &id).await?;
+    audit.set(&user.email);
+
+    check_user_name_unchanged(&user, &request.user_name)?;
+
+    let user = update_user(
+        &state,
+        &user,
+        request.active.unwrap_or(true),
+        request.external_id,
+    )
+    .await?;
+
+    Ok(ScimJson(StatusCode::OK, ScimUser::from(&user)))
+}
+
+#[tracing::instrument(name = "SCIM Patch User", skip_all)]
+pub async fn patch_scim_user(
+    State(state): State<AppState>,
+    audit: AuditSubject,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Json(request): Json<ScimPatchRequest>,
+) -> Result<impl IntoResponse, ScimError> {
+    authenticate_scim(&headers, &state.settings.auth)?;
+
+    if !request
+        .schemas
+        .iter()
+        .any(|schema| schema == PATCH_OP_SCHEMA)
+    {
+        return Err(ScimError::InvalidSyntax(
+            "Missing PatchOp schema".to_owned(),
+        ));
+    }
+
+    let user = get_user(&state, &id).await?;
+    audit.set(&user.email);
+
+    let mut active = user.active;
+    let mut external_id = user.external_id.clone();
+
+    for operation in &request.operations {
+        let op = operation.op()?;
+
+        // Without a path the value holds the attributes to set
+        let attributes: Vec<(String, Option<&serde_json::Value>)> = match &operation.path {
+            Some(path) => vec![(attribute_name(path), operation.value.as_ref())],
+            None => match &operation.value {
+                Some(serde_json::Value::Object(values)) if op != ScimPatchOp::Remove => values
+                    .iter()
+                    .map(|(name, value)| (attribute_name(name), Some(value)))
+                    .collect(),
+                _ => return Err(ScimError::InvalidPath("A path is required".to_owned())),
+            },
+        };
+
+        for (attribute, value) in attributes {
+            let value = match op {
+                ScimPatchOp::Remove => ScimValue::Null,
+                _ => value
+                    .map(ScimValue::from)
+                    .ok_or(ScimError::InvalidValue(format!(
+                        "Missing value for {}",
+                        attribute
+                    )))?,
+            };
+
+            match (attribute.as_str(), value) {
+                ("active", value) => {
+                    active = value.as_bool().ok_or(ScimError::InvalidValue(
+                        "active must be a boolean".to_owned(),
+                    ))?
+                }
+                ("externalid", ScimValue::String(value)) => external_id = Some(value),
+                ("externalid", ScimValue::Null) => external_id = None,
+                ("username", ScimValue::String(user_name)) => {
+                    check_user_name_unchanged(&user, &user_name)?
+                }
+                (attribute, _) => {
+                    return Err(ScimError::InvalidPath(format!(
+                        "{} can't be modified",
+                        attribute
+                    )))
+                }
+            }
+        }
+    }
+
+    let user = update_user(&state, &user, active, external_id).await?;
+
+    Ok(ScimJson(StatusCode::OK, ScimUser::from(&user)))
+}
+
+#[tracing::instrument(name = "SCIM Delete User", skip_all)]
+pub async fn delete_scim_user(
+    State(state): State<AppState>,
+    audit: AuditSubject,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, ScimError> {
+    authenticate_scim(&headers, &state.settings.auth)?;
+
+    let user = get_user(&state, &id).await?;
+    audit.set(&user.email);
+
+    // Signed out first, so that a failure can't leave sessions behind that a retry won't end
+    sign_out_everywhere(&state, &user.email).await?;
+    state
+        .user_store
+        .delete_user(&user.email)
+        .await
+        .map_err(to_scim_error)?;
+
+    publish_event(
+        &state,
+        WebhookEventType::UserDeleted,
+        serde_json::json!({ "email": user.email.as_ref().expose_secret() }),
+    )
+    .await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_user(state: &AppState, id: &str) -> Result<User, ScimError> {
+    let id = Uuid::parse_str(id).map_err(|_| ScimError::NotFound)?;
+
+    state
+        .user_store
+        .get_user_by_id(id)
+        .await
+        .map_err(to_scim_error)
+}
+
+// Applies the changes that differ from `user`, returning the updated user
+async fn update_user(
+    state: &AppState,
+    user: &User,
+    active: bool,
+    external_id: Option<String>,
+) -> Result<User, ScimError> {
+    let user_store = &state.user_store;
+
+    if active != user.active {
+        // Deprovisioning ends the sessions the user already has, not just their next login. They
+        // end first, so that a failure can't leave sessions behind that a retry won't end.
+        if !active {
+            sign_out_everywhere(state, &user.email).await?;
+        }
+        user_store
+            .set_active(&user.email, active)
+            .await
+            .map_err(to_scim_error)?;
+    }
+    if external_id != user.external_id {
+        user_store
+            .set_external_id(&user.email, external_id)
+            .await
+            .map_err(to_scim_error)?;
+    }
+
+    user_store
+        .get_user(&user.email)
+        .await
+        .map_err(to_scim_error)
+}
+
+async fn sign_out_everywhere(state: &AppState, email: &Email) -> Result<(), ScimError> {
+    state
+        .banned_token_store
+        .revoke_tokens_issued_before(email, Utc::now().timestamp())
+        .await
+        .map_err(|e| ScimError::UnexpectedError(e.into()))
+}
+
+fn parse_user_name(user_name: &str) -> Result<Email, ScimError> {
+    Email::parse(Secret::new(user_name.to_owned()))
+        .map_err(|_| ScimError::InvalidValue("userName must be an email address".to_owned()))
+}
+
+// User names are emails, which identify users everywhere else, so they can't be changed
+fn check_user_name_unchanged(user: &User, user_name: &str) -> Result<(), ScimError> {
+    if parse_user_name(user_name)? != user.email {
+        return Err(ScimError::Mutability(
+            "userName can't be changed".to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+fn to_scim_error(e: UserStoreError) -> ScimError {
+    match e {
+        UserStoreError::UserNotFound => ScimError::NotFound,
+        e => ScimError::UnexpectedError(e.into()),
+    }
+}
@@ -1,29 +1,46 @@
-use axum::{ extract::State, http::StatusCode, response::IntoResponse, Json };
-use serde::{ Deserialize, Serialize };
-use secrecy::Secret;
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+use secrecy::{ExposeSecret, Secret};
 
-use crate::{ app_state::AppState, domain::{ AuthAPIError, Email, Password, User } };
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Email, Password, User, UserStoreError, WebhookEventType},
+    utils::{audit::AuditSubject, metrics, passwords::check_new_password, webhooks::publish_event},
+};
 
 #[tracing::instrument(name = "Signup", skip_all)] // Updated
 pub async fn signup(
     State(state): State<AppState>,
+    audit: AuditSubject,
     Json(request): Json<SignupRequest>,
 ) -> Result<impl IntoResponse, AuthAPIError> {
     let email = Email::parse(request.email.clone()).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    audit.set(&email);
     let password = Password::parse(request.password.clone()).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    check_new_password(&state, &password, &email).map_err(AuthAPIError::WeakPassword)?;
 
     let user = User::new(email, password, request.requires_2fa);
 
-    let mut user_store = state.user_store.write().await;
-
-    if user_store.get_user(&user.email).await.is_ok() {
+    if state.user_store.get_user(&user.email).await.is_ok() {
         return Err(AuthAPIError::UserAlreadyExists);
     }
 
-    if let Err(e) = user_store.add_user(user).await {
-        return Err(AuthAPIError::UnexpectedError(e.into())); // Updated!
+    let event_data = serde_json::json!({
+        "email": user.email.as_ref().expose_secret(),
+        "requires2FA": user.requires_2fa,
+    });
+
+    // A concurrent signup with the same email can get here too, and only one of them is added
+    match state.user_store.add_user(user).await {
+        Ok(()) => {}
+        Err(UserStoreError::UserAlreadyExists) => return Err(AuthAPIError::UserAlreadyExists),
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())), // Updated!
     }
 
+    metrics::SIGNUPS.inc();
+
+    publish_event(&state, WebhookEventType::UserCreated, event_data).await;
+
     let response = Json(SignupResponse {
         message: "User created successfully!".to_string(),
     });
@@ -0,0 +1,90 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use axum_extra::extract::CookieJar;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, KnownDeviceStoreError, LoginDevice},
+    utils::{audit::AuditSubject, auth::authenticate, login_devices::trusted_device_id},
+};
+
+/// The devices the logged in user skips 2FA on
+#[tracing::instrument(name = "List Trusted Devices", skip_all)]
+pub async fn list_trusted_devices(
+    State(state): State<AppState>,
+    audit: AuditSubject,
+    device: LoginDevice,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = authenticate(&jar, &state.settings.auth, state.banned_token_store.clone()).await?;
+    audit.set(&email);
+
+    let devices = state
+        .known_device_store
+        .list_trusted_devices(&email)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let current_id = trusted_device_id(&state.settings.auth, &jar, &device);
+    let devices = devices
+        .into_iter()
+        .map(|device| TrustedDeviceResponse {
+            current: Some(device.id) == current_id,
+            id: device.id,
+            user_agent: device.user_agent,
+            ip_prefix: device.ip_prefix,
+            created_at: device.created_at,
+            expires_at: device.expires_at,
+        })
+        .collect();
+
+    Ok(Json(ListTrustedDevicesResponse { devices }))
+}
+
+/// Makes the logged in user go through 2FA on the device again
+#[tracing::instrument(name = "Revoke Trusted Device", skip_all)]
+pub async fn revoke_trusted_device(
+    State(state): State<AppState>,
+    audit: AuditSubject,
+    jar: CookieJar,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = authenticate(&jar, &state.settings.auth, state.banned_token_store.clone()).await?;
+    audit.set(&email);
+
+    let id = Uuid::parse_str(&id).map_err(|_| AuthAPIError::NotFound)?;
+
+    match state
+        .known_device_store
+        .revoke_trusted_device(&email, id)
+        .await
+    {
+        Ok(()) => Ok(StatusCode::NO_CONTENT),
+        Err(KnownDeviceStoreError::TrustedDeviceNotFound) => Err(AuthAPIError::NotFound),
+        Err(e) => Err(AuthAPIError::UnexpectedError(e.into())),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListTrustedDevicesResponse {
+    pub devices: Vec<TrustedDeviceResponse>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrustedDeviceResponse {
+    pub id: Uuid,
+    pub user_agent: Option<String>,
+    pub ip_prefix: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    /// Whether this is the device the request came from
+    pub current: bool,
+}
@@ -0,0 +1,48 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
+use serde::Deserialize;
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, TwoFAChannel},
+    utils::{audit::AuditSubject, auth::authenticate},
+};
+
+#[tracing::instrument(name = "Set 2FA Channel", skip_all)]
+pub async fn set_two_fa_channel(
+    State(state): State<AppState>,
+    audit: AuditSubject,
+    jar: CookieJar,
+    Json(request): Json<SetTwoFAChannelRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = authenticate(&jar, &state.settings.auth, state.banned_token_store.clone()).await?;
+    audit.set(&email);
+
+    let channel =
+        TwoFAChannel::parse(&request.channel).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let user_store = &state.user_store;
+
+    if channel == TwoFAChannel::Sms {
+        let user = user_store
+            .get_user(&email)
+            .await
+            .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+        if !user.phone_number_verified {
+            return Err(AuthAPIError::PhoneNumberNotVerified);
+        }
+    }
+
+    user_store
+        .set_two_fa_channel(&email, channel)
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    Ok(StatusCode::OK)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetTwoFAChannelRequest {
+    pub channel: String,
+}
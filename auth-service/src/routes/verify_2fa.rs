@@ -4,13 +4,23 @@ use serde::Deserialize;
 
 use crate::{
     app_state::AppState, 
-    domain::{AuthAPIError, Email, LoginAttemptId, TwoFACode}, 
-    utils::auth::generate_auth_cookie
+    domain::{
+        AuthAPIError, Email, LoginAttemptId, LoginDevice, TwoFACode, TwoFACodeStoreError,
+        UserStoreError,
+    },
+    utils::{
+        audit::AuditSubject,
+        auth::generate_auth_cookie,
+        login_devices::{record_login_device, trust_device},
+        metrics,
+    },
 };
 
 #[tracing::instrument(name = "Verify 2FA", skip_all)]
 pub async fn verify_2fa(
     State(state): State<AppState>, // New!
+    audit: AuditSubject,
+    device: LoginDevice,
     jar: CookieJar,
     Json(request): Json<Verify2FARequest>
 ) -> Result<(CookieJar, impl IntoResponse), AuthAPIError> {
@@ -18,6 +28,7 @@ pub async fn verify_2fa(
     // Validate the email in `request`
     let email = Email::parse(request.email.into())
                 .map_err(|_| AuthAPIError::InvalidCredentials)?;
+    audit.set(&email);
 
     // Validate the login attempt ID in `request`
     let login_attempt_id = LoginAttemptId::parse(request.login_attempt_id.into())
@@ -28,28 +39,49 @@ pub async fn verify_2fa(
                 .map_err(|_| AuthAPIError::InvalidCredentials)?;
     
     // New!
-    let mut two_fa_code_store = state.two_fa_code_store.write().await;
+    let two_fa_code_store = &state.two_fa_code_store;
 
-    // Call `two_fa_code_store.get_code`. If the call fails
-    // return a `AuthAPIError::IncorrectCredentials`.
-    let code_tuple = two_fa_code_store
-                    .get_code(&email)
-                    .await
-                    .map_err(|_| AuthAPIError::IncorrectCredentials)?;
+    // Check the code and remove it in one step, so that it can only be redeemed once. Every
+    // wrong guess counts towards the limit, after which the pending code is invalidated and the
+    // user has to log in again.
+    match two_fa_code_store
+        .consume_code(&login_attempt_id, &email, &two_fa_code)
+        .await
+    {
+        Ok(()) => {}
+        Err(TwoFACodeStoreError::IncorrectCode) => {
+            metrics::TWO_FA_CHALLENGES
+                .with_label_values(&["failed"])
+                .inc();
+            return Err(AuthAPIError::IncorrectCredentials);
+        }
+        Err(TwoFACodeStoreError::LoginAttemptIdNotFound) => {
+            return Err(AuthAPIError::IncorrectCredentials)
+        }
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
+    }
 
-    // Validate that the `login_attempt_id` and `two_fa_code`
-    // in the request body matches values in the `code_tuple`. 
-    // If not, return a `AuthAPIError::IncorrectCredentials`.
-    if (login_attempt_id, two_fa_code) != code_tuple {
-        return Err(AuthAPIError::IncorrectCredentials);
+    // Users deprovisioned over SCIM since they entered their password can't finish logging in
+    match state.user_store.get_user(&email).await {
+        Ok(user) if user.active => {}
+        Ok(_) | Err(UserStoreError::UserNotFound) => {
+            return Err(AuthAPIError::IncorrectCredentials)
+        }
+        Err(e) => return Err(AuthAPIError::UnexpectedError(e.into())),
     }
 
-    let cookie = generate_auth_cookie(&email)
-        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    let cookie = generate_auth_cookie(&email, &state.settings.auth)
+        .map_err(AuthAPIError::UnexpectedError)?;
     let updated_jar = jar.add(cookie);
 
-    two_fa_code_store.remove_code(&email).await
-        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+    metrics::TWO_FA_CHALLENGES
+        .with_label_values(&["verified"])
+        .inc();
+
+    let mut updated_jar = record_login_device(&state, &email, &device, updated_jar).await;
+    if request.remember_device {
+        updated_jar = trust_device(&state, &email, &device, updated_jar).await;
+    }
     
     Ok((updated_jar, StatusCode::OK))
 }
@@ -62,4 +94,7 @@ pub struct Verify2FARequest {
     pub login_attempt_id: String,
     #[serde(rename = "2FACode")]
     pub two_fa_code: String,
+    /// Skip 2FA on this browser from now on
+    #[serde(default, rename = "rememberDevice")]
+    pub remember_device: bool,
 }
\ No newline at end of file
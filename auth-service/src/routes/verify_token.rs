@@ -1,15 +1,31 @@
 use axum::{ extract::State, http::StatusCode, Json };
 use serde::Deserialize;
 
-use crate::{ app_state::AppState, domain::AuthAPIError, utils::auth::validate_token };
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Email},
+    utils::{audit::AuditSubject, auth::validate_token},
+};
 
 #[tracing::instrument(name = "Verify Token", skip_all)]
 pub async fn verify_token(
     State(state): State<AppState>,
+    audit: AuditSubject,
     Json(request): Json<VerifyTokenRequest>,
 ) -> Result<StatusCode, AuthAPIError> {
-    match validate_token(&request.token, state.banned_token_store.clone()).await {
-        Ok(_) => Ok(StatusCode::OK),
+    match validate_token(
+        &request.token,
+        &state.settings.auth,
+        state.banned_token_store.clone(),
+    )
+    .await
+    {
+        Ok(claims) => {
+            if let Ok(email) = Email::parse(claims.sub.into()) {
+                audit.set(&email);
+            }
+            Ok(StatusCode::OK)
+        }
         Err(_) => Err(AuthAPIError::InvalidToken),
     }
 }
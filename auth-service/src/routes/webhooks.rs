@@ -0,0 +1,227 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        data_stores::WebhookStoreError, AuthAPIError, WebhookDelivery, WebhookEventType,
+        WebhookSubscription,
+    },
+    utils::auth::authenticate_admin,
+};
+
+const DEFAULT_DELIVERIES_PAGE_SIZE: usize = 50;
+const MAX_DELIVERIES_PAGE_SIZE: usize = 500;
+
+#[tracing::instrument(name = "Create Webhook", skip_all)]
+pub async fn create_webhook(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<CreateWebhookRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    authenticate_admin(&headers, &state.settings.auth)?;
+
+    let url = WebhookSubscription::parse_url(&request.url)
+        .map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let mut event_types = Vec::new();
+    for event_type in &request.event_types {
+        let event_type =
+            WebhookEventType::parse(event_type).map_err(|_| AuthAPIError::InvalidCredentials)?;
+        if !event_types.contains(&event_type) {
+            event_types.push(event_type);
+        }
+    }
+    if event_types.is_empty() {
+        return Err(AuthAPIError::InvalidCredentials);
+    }
+
+    let secret = match request.secret {
+        Some(secret) if secret.expose_secret().is_empty() => {
+            return Err(AuthAPIError::InvalidCredentials)
+        }
+        Some(secret) => secret,
+        None => WebhookSubscription::generate_secret(),
+    };
+
+    let subscription = WebhookSubscription::new(url, event_types, secret);
+
+    state
+        .webhook_store
+        .add_subscription(subscription.clone())
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    // The secret is only ever returned here, so that subscribers can verify signatures
+    let response = Json(CreateWebhookResponse {
+        secret: subscription.secret.expose_secret().to_owned(),
+        subscription: WebhookSubscriptionResponse::from(&subscription),
+    });
+
+    Ok((StatusCode::CREATED, response))
+}
+
+#[tracing::instrument(name = "List Webhooks", skip_all)]
+pub async fn list_webhooks(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    authenticate_admin(&headers, &state.settings.auth)?;
+
+    let subscriptions = state
+        .webhook_store
+        .get_subscriptions()
+        .await
+        .map_err(|e| AuthAPIError::UnexpectedError(e.into()))?;
+
+    let response = Json(ListWebhooksResponse {
+        subscriptions: subscriptions
+            .iter()
+            .map(WebhookSubscriptionResponse::from)
+            .collect(),
+    });
+
+    Ok((StatusCode::OK, response))
+}
+
+#[tracing::instrument(name = "Get Webhook", skip_all)]
+pub async fn get_webhook(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    authenticate_admin(&headers, &state.settings.auth)?;
+    let id = parse_id(&id)?;
+
+    let subscription = state
+        .webhook_store
+        .get_subscription(id)
+        .await
+        .map_err(to_api_error)?;
+
+    let response = Json(WebhookSubscriptionResponse::from(&subscription));
+
+    Ok((StatusCode::OK, response))
+}
+
+#[tracing::instrument(name = "Delete Webhook", skip_all)]
+pub async fn delete_webhook(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    authenticate_admin(&headers, &state.settings.auth)?;
+    let id = parse_id(&id)?;
+
+    state
+        .webhook_store
+        .delete_subscription(id)
+        .await
+        .map_err(to_api_error)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[tracing::instrument(name = "List Webhook Deliveries", skip_all)]
+pub async fn list_webhook_deliveries(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Query(query): Query<WebhookDeliveriesQuery>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    authenticate_admin(&headers, &state.settings.auth)?;
+    let id = parse_id(&id)?;
+
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_DELIVERIES_PAGE_SIZE)
+        .clamp(1, MAX_DELIVERIES_PAGE_SIZE);
+
+    let webhook_store = &state.webhook_store;
+
+    // Tell an unknown subscription apart from one without deliveries
+    webhook_store
+        .get_subscription(id)
+        .await
+        .map_err(to_api_error)?;
+
+    let deliveries = webhook_store
+        .get_deliveries(id, limit)
+        .await
+        .map_err(to_api_error)?;
+
+    let response = Json(WebhookDeliveriesResponse { deliveries });
+
+    Ok((StatusCode::OK, response))
+}
+
+fn parse_id(id: &str) -> Result<Uuid, AuthAPIError> {
+    Uuid::parse_str(id).map_err(|_| AuthAPIError::NotFound)
+}
+
+fn to_api_error(e: WebhookStoreError) -> AuthAPIError {
+    match e {
+        WebhookStoreError::SubscriptionNotFound => AuthAPIError::NotFound,
+        e => AuthAPIError::UnexpectedError(e.into()),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CreateWebhookRequest {
+    pub url: String,
+    #[serde(rename = "eventTypes")]
+    pub event_types: Vec<String>,
+    /// Generated when left out
+    pub secret: Option<Secret<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct WebhookSubscriptionResponse {
+    pub id: Uuid,
+    pub url: String,
+    #[serde(rename = "eventTypes")]
+    pub event_types: Vec<WebhookEventType>,
+    #[serde(rename = "createdAt")]
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<&WebhookSubscription> for WebhookSubscriptionResponse {
+    fn from(subscription: &WebhookSubscription) -> Self {
+        Self {
+            id: subscription.id,
+            url: subscription.url.to_string(),
+            event_types: subscription.event_types.clone(),
+            created_at: subscription.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct CreateWebhookResponse {
+    #[serde(flatten)]
+    pub subscription: WebhookSubscriptionResponse,
+    pub secret: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct ListWebhooksResponse {
+    pub subscriptions: Vec<WebhookSubscriptionResponse>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WebhookDeliveriesQuery {
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebhookDeliveriesResponse {
+    pub deliveries: Vec<WebhookDelivery>,
+}
@@ -0,0 +1,147 @@
+use std::sync::RwLock;
+
+use chrono::Utc;
+use secrecy::ExposeSecret;
+
+use crate::domain::{
+    data_stores::{AuditLogStore, AuditLogStoreError},
+    AuditEvent, AuditLogFilter, NewAuditEvent,
+};
+
+#[derive(Default)]
+pub struct HashmapAuditLogStore {
+    // Oldest first, with each event's ID being its position plus one
+    events: RwLock<Vec<AuditEvent>>,
+}
+
+#[async_trait::async_trait]
+impl AuditLogStore for HashmapAuditLogStore {
+    async fn append(&self, event: NewAuditEvent) -> Result<(), AuditLogStoreError> {
+        let mut events = self.events.write().unwrap();
+        let id = events.len() as i64 + 1;
+        events.push(AuditEvent {
+            id,
+            occurred_at: Utc::now(),
+            event_type: event.event_type,
+            email: event
+                .email
+                .map(|email| email.as_ref().expose_secret().to_owned()),
+            ip: event.ip,
+            user_agent: event.user_agent,
+            request_id: event.request_id,
+            outcome: event.outcome,
+            status_code: event.status_code,
+        });
+        Ok(())
+    }
+
+    async fn query(
+        &self,
+        filter: &AuditLogFilter,
+        before: Option<i64>,
+        limit: usize,
+    ) -> Result<Vec<AuditEvent>, AuditLogStoreError> {
+        let events = self.events.read().unwrap();
+        let end = before.map_or(events.len(), |id| (id.max(1) - 1) as usize);
+
+        Ok(events[..end.min(events.len())]
+            .iter()
+            .rev()
+            .filter(|event| filter.matches(event))
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{AuditEventType, AuditOutcome, Email};
+    use secrecy::Secret;
+
+    fn event(event_type: AuditEventType, email: &str, outcome: AuditOutcome) -> NewAuditEvent {
+        NewAuditEvent {
+            event_type,
+            email: Some(Email::parse(Secret::new(email.to_owned())).unwrap()),
+            ip: Some("127.0.0.1".to_owned()),
+            user_agent: None,
+            request_id: None,
+            outcome,
+            status_code: 200,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_query_filters_newest_first() {
+        let store = HashmapAuditLogStore::default();
+        store
+            .append(event(
+                AuditEventType::Login,
+                "a@example.com",
+                AuditOutcome::Failure,
+            ))
+            .await
+            .unwrap();
+        store
+            .append(event(
+                AuditEventType::Login,
+                "b@example.com",
+                AuditOutcome::Success,
+            ))
+            .await
+            .unwrap();
+        store
+            .append(event(
+                AuditEventType::Login,
+                "a@example.com",
+                AuditOutcome::Success,
+            ))
+            .await
+            .unwrap();
+
+        let filter = AuditLogFilter {
+            email: Some(Email::parse(Secret::new("a@example.com".to_owned())).unwrap()),
+            ..Default::default()
+        };
+        let ids: Vec<i64> = store
+            .query(&filter, None, 10)
+            .await
+            .unwrap()
+            .iter()
+            .map(|event| event.id)
+            .collect();
+        assert_eq!(ids, vec![3, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_query_paginates_by_cursor() {
+        let store = HashmapAuditLogStore::default();
+        for _ in 0..5 {
+            store
+                .append(event(
+                    AuditEventType::Signup,
+                    "a@example.com",
+                    AuditOutcome::Success,
+                ))
+                .await
+                .unwrap();
+        }
+
+        let filter = AuditLogFilter::default();
+        let first_page = store.query(&filter, None, 2).await.unwrap();
+        assert_eq!(
+            first_page.iter().map(|e| e.id).collect::<Vec<_>>(),
+            vec![5, 4]
+        );
+
+        let second_page = store.query(&filter, Some(4), 2).await.unwrap();
+        assert_eq!(
+            second_page.iter().map(|e| e.id).collect::<Vec<_>>(),
+            vec![3, 2]
+        );
+
+        let last_page = store.query(&filter, Some(2), 2).await.unwrap();
+        assert_eq!(last_page.iter().map(|e| e.id).collect::<Vec<_>>(), vec![1]);
+    }
+}
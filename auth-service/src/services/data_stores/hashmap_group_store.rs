@@ -0,0 +1,151 @@
+use std::{collections::HashMap, sync::RwLock};
+
+use uuid::Uuid;
+
+use crate::domain::{
+    data_stores::{GroupStore, GroupStoreError},
+    Group,
+};
+
+#[derive(Default)]
+pub struct HashmapGroupStore {
+    groups: RwLock<HashMap<Uuid, Group>>,
+}
+
+fn name_taken(groups: &HashMap<Uuid, Group>, group: &Group) -> bool {
+    groups
+        .values()
+        .any(|other| other.id != group.id && other.display_name == group.display_name)
+}
+
+#[async_trait::async_trait]
+impl GroupStore for HashmapGroupStore {
+    async fn add_group(&self, group: Group) -> Result<(), GroupStoreError> {
+        let mut groups = self.groups.write().unwrap();
+        if groups.contains_key(&group.id) || name_taken(&groups, &group) {
+            return Err(GroupStoreError::GroupAlreadyExists);
+        }
+        groups.insert(group.id, group);
+        Ok(())
+    }
+
+    async fn get_group(&self, id: Uuid) -> Result<Group, GroupStoreError> {
+        self.groups
+            .read()
+            .unwrap()
+            .get(&id)
+            .cloned()
+            .ok_or(GroupStoreError::GroupNotFound)
+    }
+
+    async fn list_groups(
+        &self,
+        display_name: Option<&str>,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<Group>, usize), GroupStoreError> {
+        let groups = self.groups.read().unwrap();
+        let mut matches: Vec<_> = groups
+            .values()
+            .filter(|group| display_name.is_none_or(|name| group.display_name == name))
+            .collect();
+        matches.sort_by_key(|group| (group.created_at, group.id));
+
+        let total = matches.len();
+        let page = matches
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .cloned()
+            .collect();
+        Ok((page, total))
+    }
+
+    async fn update_group(&self, group: &Group) -> Result<(), GroupStoreError> {
+        let mut groups = self.groups.write().unwrap();
+        if !groups.contains_key(&group.id) {
+            return Err(GroupStoreError::GroupNotFound);
+        }
+        if name_taken(&groups, group) {
+            return Err(GroupStoreError::GroupAlreadyExists);
+        }
+        groups.insert(group.id, group.clone());
+        Ok(())
+    }
+
+    async fn delete_group(&self, id: Uuid) -> Result<(), GroupStoreError> {
+        self.groups
+            .write()
+            .unwrap()
+            .remove(&id)
+            .map(|_| ())
+            .ok_or(GroupStoreError::GroupNotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_display_names_are_unique() {
+        let store = HashmapGroupStore::default();
+        let group = Group::new("Engineering".to_owned(), None, vec![]);
+        store.add_group(group.clone()).await.unwrap();
+
+        assert_eq!(
+            store
+                .add_group(Group::new("Engineering".to_owned(), None, vec![]))
+                .await,
+            Err(GroupStoreError::GroupAlreadyExists)
+        );
+
+        let mut other = Group::new("Sales".to_owned(), None, vec![]);
+        store.add_group(other.clone()).await.unwrap();
+        other.display_name = "Engineering".to_owned();
+        assert_eq!(
+            store.update_group(&other).await,
+            Err(GroupStoreError::GroupAlreadyExists)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_groups() {
+        let store = HashmapGroupStore::default();
+        for name in ["Engineering", "Sales", "Support"] {
+            store
+                .add_group(Group::new(name.to_owned(), None, vec![]))
+                .await
+                .unwrap();
+        }
+
+        let (page, total) = store.list_groups(None, 1, 1).await.unwrap();
+        assert_eq!(total, 3);
+        assert_eq!(page.len(), 1);
+
+        let (page, total) = store.list_groups(Some("Sales"), 0, 10).await.unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(page[0].display_name, "Sales");
+    }
+
+    #[tokio::test]
+    async fn test_update_and_delete_group() {
+        let store = HashmapGroupStore::default();
+        let mut group = Group::new("Engineering".to_owned(), None, vec![]);
+        store.add_group(group.clone()).await.unwrap();
+
+        group.add_member(Uuid::new_v4());
+        store.update_group(&group).await.unwrap();
+        assert_eq!(store.get_group(group.id).await, Ok(group.clone()));
+
+        store.delete_group(group.id).await.unwrap();
+        assert_eq!(
+            store.get_group(group.id).await,
+            Err(GroupStoreError::GroupNotFound)
+        );
+        assert_eq!(
+            store.update_group(&group).await,
+            Err(GroupStoreError::GroupNotFound)
+        );
+    }
+}
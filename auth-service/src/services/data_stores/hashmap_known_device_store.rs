@@ -0,0 +1,213 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::RwLock,
+};
+
+use uuid::Uuid;
+
+use crate::domain::{
+    data_stores::{KnownDeviceStore, KnownDeviceStoreError},
+    DeviceSighting, Email, LoginDevice, TrustedDevice,
+};
+
+#[derive(Default)]
+pub struct HashmapKnownDeviceStore {
+    devices: RwLock<Devices>,
+}
+
+#[derive(Default)]
+struct Devices {
+    // Fingerprints of each user's devices
+    fingerprints: HashMap<Email, HashSet<String>>,
+    trusted: HashMap<Email, Vec<TrustedDevice>>,
+}
+
+#[async_trait::async_trait]
+impl KnownDeviceStore for HashmapKnownDeviceStore {
+    async fn record_device(
+        &self,
+        email: &Email,
+        device: &LoginDevice,
+    ) -> Result<DeviceSighting, KnownDeviceStoreError> {
+        let mut devices = self.devices.write().unwrap();
+        let fingerprints = devices.fingerprints.entry(email.clone()).or_default();
+        let is_first = fingerprints.is_empty();
+
+        Ok(match fingerprints.insert(device.fingerprint()) {
+            false => DeviceSighting::Known,
+            true if is_first => DeviceSighting::First,
+            true => DeviceSighting::New,
+        })
+    }
+
+    async fn forget_devices(&self, email: &Email) -> Result<(), KnownDeviceStoreError> {
+        let mut devices = self.devices.write().unwrap();
+        devices.fingerprints.remove(email);
+        devices.trusted.remove(email);
+        Ok(())
+    }
+
+    async fn trust_device(
+        &self,
+        email: &Email,
+        device: TrustedDevice,
+    ) -> Result<(), KnownDeviceStoreError> {
+        let mut devices = self.devices.write().unwrap();
+        let trusted = devices.trusted.entry(email.clone()).or_default();
+        trusted.retain(|device| !device.is_expired());
+        trusted.push(device);
+        Ok(())
+    }
+
+    async fn get_trusted_device(
+        &self,
+        email: &Email,
+        id: Uuid,
+    ) -> Result<TrustedDevice, KnownDeviceStoreError> {
+        self.devices
+            .read()
+            .unwrap()
+            .trusted
+            .get(email)
+            .and_then(|devices| devices.iter().find(|device| device.id == id))
+            .filter(|device| !device.is_expired())
+            .cloned()
+            .ok_or(KnownDeviceStoreError::TrustedDeviceNotFound)
+    }
+
+    async fn list_trusted_devices(
+        &self,
+        email: &Email,
+    ) -> Result<Vec<TrustedDevice>, KnownDeviceStoreError> {
+        Ok(self
+            .devices
+            .read()
+            .unwrap()
+            .trusted
+            .get(email)
+            .map(|devices| {
+                devices
+                    .iter()
+                    .rev()
+                    .filter(|device| !device.is_expired())
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    async fn revoke_trusted_device(
+        &self,
+        email: &Email,
+        id: Uuid,
+    ) -> Result<(), KnownDeviceStoreError> {
+        let mut devices = self.devices.write().unwrap();
+        let trusted = devices
+            .trusted
+            .get_mut(email)
+            .ok_or(KnownDeviceStoreError::TrustedDeviceNotFound)?;
+        let count = trusted.len();
+        trusted.retain(|device| device.id != id);
+
+        if trusted.len() == count {
+            return Err(KnownDeviceStoreError::TrustedDeviceNotFound);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use secrecy::Secret;
+
+    use super::*;
+
+    fn device(user_agent: &str) -> LoginDevice {
+        LoginDevice::new(
+            Some("abcdefghijklmnopqrstuvwxyz012345".to_owned()),
+            Some(user_agent.to_owned()),
+            "203.0.113.7".to_owned(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_record_device() {
+        let store = HashmapKnownDeviceStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
+
+        let sightings = [
+            store
+                .record_device(&email, &device("Firefox"))
+                .await
+                .unwrap(),
+            store
+                .record_device(&email, &device("Firefox"))
+                .await
+                .unwrap(),
+            store
+                .record_device(&email, &device("Chrome"))
+                .await
+                .unwrap(),
+        ];
+        assert_eq!(
+            sightings,
+            [
+                DeviceSighting::First,
+                DeviceSighting::Known,
+                DeviceSighting::New
+            ]
+        );
+
+        store.forget_devices(&email).await.unwrap();
+        assert_eq!(
+            store
+                .record_device(&email, &device("Chrome"))
+                .await
+                .unwrap(),
+            DeviceSighting::First
+        );
+    }
+
+    #[tokio::test]
+    async fn test_trusted_devices() {
+        let store = HashmapKnownDeviceStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
+        let other_email = Email::parse(Secret::new("other@example.com".to_owned())).unwrap();
+
+        let first = TrustedDevice::new(&device("Firefox"), Duration::days(30));
+        let second = TrustedDevice::new(&device("Chrome"), Duration::days(30));
+        let expired = TrustedDevice::new(&device("Safari"), Duration::zero());
+        for trusted_device in [first.clone(), second.clone(), expired.clone()] {
+            store.trust_device(&email, trusted_device).await.unwrap();
+        }
+
+        assert_eq!(
+            store.list_trusted_devices(&email).await.unwrap(),
+            vec![second.clone(), first.clone()]
+        );
+        assert_eq!(
+            store.get_trusted_device(&email, first.id).await.unwrap(),
+            first
+        );
+        for (email, id) in [(&email, expired.id), (&other_email, first.id)] {
+            assert!(matches!(
+                store.get_trusted_device(email, id).await,
+                Err(KnownDeviceStoreError::TrustedDeviceNotFound)
+            ));
+        }
+
+        store.revoke_trusted_device(&email, first.id).await.unwrap();
+        assert!(matches!(
+            store.revoke_trusted_device(&email, first.id).await,
+            Err(KnownDeviceStoreError::TrustedDeviceNotFound)
+        ));
+        assert_eq!(
+            store.list_trusted_devices(&email).await.unwrap(),
+            vec![second]
+        );
+
+        store.forget_devices(&email).await.unwrap();
+        assert!(store.list_trusted_devices(&email).await.unwrap().is_empty());
+    }
+}
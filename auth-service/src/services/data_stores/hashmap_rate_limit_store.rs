@@ -0,0 +1,103 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use chrono::Utc;
+
+use crate::domain::{
+    data_stores::{RateLimitStore, RateLimitStoreError},
+    RateLimitDecision, RateLimitPolicy,
+};
+
+// Full buckets carry no state, so they are swept out once the map doubles in size
+const MIN_PRUNE_THRESHOLD: usize = 1024;
+
+/// Keeps buckets in process memory, so limits are per instance
+#[derive(Default)]
+pub struct HashmapRateLimitStore {
+    buckets: Mutex<Buckets>,
+}
+
+struct Buckets {
+    // Theoretical arrival time (Unix ms) of each bucket
+    tats_ms: HashMap<String, i64>,
+    prune_threshold: usize,
+}
+
+impl Default for Buckets {
+    fn default() -> Self {
+        Self {
+            tats_ms: HashMap::new(),
+            prune_threshold: MIN_PRUNE_THRESHOLD,
+        }
+    }
+}
+
+impl Buckets {
+    fn take_token_at(
+        &mut self,
+        key: &str,
+        policy: &RateLimitPolicy,
+        now_ms: i64,
+    ) -> RateLimitDecision {
+        if self.tats_ms.len() >= self.prune_threshold {
+            self.tats_ms.retain(|_, tat_ms| *tat_ms > now_ms);
+            self.prune_threshold = (self.tats_ms.len() * 2).max(MIN_PRUNE_THRESHOLD);
+        }
+
+        let (decision, new_tat_ms) = policy.apply(self.tats_ms.get(key).copied(), now_ms);
+        if let Some(new_tat_ms) = new_tat_ms {
+            self.tats_ms.insert(key.to_owned(), new_tat_ms);
+        }
+
+        decision
+    }
+}
+
+#[async_trait::async_trait]
+impl RateLimitStore for HashmapRateLimitStore {
+    async fn take_token(
+        &self,
+        key: &str,
+        policy: &RateLimitPolicy,
+    ) -> Result<RateLimitDecision, RateLimitStoreError> {
+        let now_ms = Utc::now().timestamp_millis();
+        Ok(self
+            .buckets
+            .lock()
+            .unwrap()
+            .take_token_at(key, policy, now_ms))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_take_token_until_empty() {
+        let store = HashmapRateLimitStore::default();
+        let policy = RateLimitPolicy::per_minute(2);
+
+        assert!(store.take_token("a", &policy).await.unwrap().allowed);
+        assert!(store.take_token("a", &policy).await.unwrap().allowed);
+        assert!(!store.take_token("a", &policy).await.unwrap().allowed);
+
+        // Other keys have their own bucket
+        assert!(store.take_token("b", &policy).await.unwrap().allowed);
+    }
+
+    #[test]
+    fn test_full_buckets_are_pruned() {
+        let mut buckets = Buckets::default();
+        let policy = RateLimitPolicy::per_second(1);
+
+        for i in 0..MIN_PRUNE_THRESHOLD {
+            buckets.take_token_at(&i.to_string(), &policy, 0);
+        }
+        assert_eq!(buckets.tats_ms.len(), MIN_PRUNE_THRESHOLD);
+
+        // Every bucket has refilled by now
+        buckets.take_token_at("new", &policy, 10_000);
+        assert_eq!(buckets.tats_ms.len(), 1);
+        assert!(buckets.tats_ms.contains_key("new"));
+    }
+}
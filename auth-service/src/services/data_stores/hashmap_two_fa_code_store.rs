@@ -1,42 +1,245 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
 
-use crate::domain::{
-    data_stores::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError},
-    email::Email,
+use crate::{
+    domain::{
+        data_stores::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError},
+        email::Email,
+        phone_number::PhoneNumber,
+    },
+    utils::constants::{
+        MAX_2FA_RESENDS, MAX_FAILED_2FA_ATTEMPTS, MAX_PENDING_2FA_ATTEMPTS_PER_USER,
+        TWO_FA_RESEND_COOLDOWN_SECONDS,
+    },
 };
 
+#[derive(Debug, Clone)]
+struct PendingCode {
+    email: Email,
+    // The number a phone verification code was sent to
+    phone_number: Option<PhoneNumber>,
+    code: TwoFACode,
+    failed_attempts: u32,
+    resend_count: u32,
+    last_sent_at: Instant,
+}
+
 #[derive(Default)]
 pub struct HashmapTwoFACodeStore {
-    codes: HashMap<Email, (LoginAttemptId, TwoFACode)>,
+    pending: Mutex<PendingCodes>,
+}
+
+#[derive(Default)]
+struct PendingCodes {
+    codes: HashMap<LoginAttemptId, PendingCode>,
+    // Pending login attempt IDs per user, oldest first
+    attempts_by_user: HashMap<Email, VecDeque<LoginAttemptId>>,
+}
+
+impl PendingCodes {
+    fn add(
+        &mut self,
+        email: Email,
+        phone_number: Option<PhoneNumber>,
+        login_attempt_id: LoginAttemptId,
+        code: TwoFACode,
+    ) {
+        let attempts = self.attempts_by_user.entry(email.clone()).or_default();
+        attempts.push_back(login_attempt_id.clone());
+
+        while attempts.len() > MAX_PENDING_2FA_ATTEMPTS_PER_USER {
+            if let Some(oldest) = attempts.pop_front() {
+                self.codes.remove(&oldest);
+            }
+        }
+
+        self.codes.insert(
+            login_attempt_id,
+            PendingCode {
+                email,
+                phone_number,
+                code,
+                failed_attempts: 0,
+                resend_count: 0,
+                last_sent_at: Instant::now(),
+            },
+        );
+    }
+
+    fn remove(&mut self, login_attempt_id: &LoginAttemptId) -> Result<(), TwoFACodeStoreError> {
+        let pending = self
+            .codes
+            .remove(login_attempt_id)
+            .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)?;
+
+        if let Some(attempts) = self.attempts_by_user.get_mut(&pending.email) {
+            attempts.retain(|id| id != login_attempt_id);
+            if attempts.is_empty() {
+                self.attempts_by_user.remove(&pending.email);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Removes and returns the pending code if `email` and `code` match it, and otherwise counts a
+    // failed attempt
+    fn consume(
+        &mut self,
+        login_attempt_id: &LoginAttemptId,
+        email: &Email,
+        code: &TwoFACode,
+    ) -> Result<PendingCode, TwoFACodeStoreError> {
+        let pending = self
+            .codes
+            .get_mut(login_attempt_id)
+            .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)?;
+
+        // Both comparisons always run so the response time doesn't reveal which one failed
+        if (pending.email == *email) & (pending.code == *code) {
+            let pending = pending.clone();
+            self.remove(login_attempt_id)?;
+            return Ok(pending);
+        }
+
+        pending.failed_attempts += 1;
+        if pending.failed_attempts >= MAX_FAILED_2FA_ATTEMPTS {
+            self.remove(login_attempt_id)?;
+        }
+
+        Err(TwoFACodeStoreError::IncorrectCode)
+    }
 }
 
 #[async_trait::async_trait]
 impl TwoFACodeStore for HashmapTwoFACodeStore {
     async fn add_code(
-        &mut self,
+        &self,
+        email: Email,
+        login_attempt_id: LoginAttemptId,
+        code: TwoFACode,
+    ) -> Result<(), TwoFACodeStoreError> {
+        self.pending
+            .lock()
+            .unwrap()
+            .add(email, None, login_attempt_id, code);
+        Ok(())
+    }
+
+    async fn add_phone_number_code(
+        &self,
         email: Email,
+        phone_number: PhoneNumber,
         login_attempt_id: LoginAttemptId,
         code: TwoFACode,
     ) -> Result<(), TwoFACodeStoreError> {
-        self.codes.insert(email, (login_attempt_id, code));
+        self.pending
+            .lock()
+            .unwrap()
+            .add(email, Some(phone_number), login_attempt_id, code);
         Ok(())
     }
 
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError> {
-        match self.codes.remove(email) {
-            Some(_) => Ok(()),
+    async fn remove_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<(), TwoFACodeStoreError> {
+        self.pending.lock().unwrap().remove(login_attempt_id)
+    }
+
+    async fn get_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<(Email, TwoFACode), TwoFACodeStoreError> {
+        match self.pending.lock().unwrap().codes.get(login_attempt_id) {
+            Some(pending) => Ok((pending.email.clone(), pending.code.clone())),
             None => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
         }
     }
 
-    async fn get_code(
+    async fn get_phone_number_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<(Email, PhoneNumber, TwoFACode), TwoFACodeStoreError> {
+        let pending_codes = self.pending.lock().unwrap();
+        match pending_codes.codes.get(login_attempt_id) {
+            Some(PendingCode {
+                email,
+                phone_number: Some(phone_number),
+                code,
+                ..
+            }) => Ok((email.clone(), phone_number.clone(), code.clone())),
+            _ => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
+        }
+    }
+
+    async fn consume_code(
         &self,
+        login_attempt_id: &LoginAttemptId,
         email: &Email,
-    ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError> {
-        match self.codes.get(email) {
-            Some(value) => Ok(value.clone()),
-            None => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
+        code: &TwoFACode,
+    ) -> Result<(), TwoFACodeStoreError> {
+        self.pending
+            .lock()
+            .unwrap()
+            .consume(login_attempt_id, email, code)
+            .map(|_| ())
+    }
+
+    async fn consume_phone_number_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+        email: &Email,
+        code: &TwoFACode,
+    ) -> Result<PhoneNumber, TwoFACodeStoreError> {
+        let mut pending_codes = self.pending.lock().unwrap();
+        if !matches!(
+            pending_codes.codes.get(login_attempt_id),
+            Some(PendingCode {
+                phone_number: Some(_),
+                ..
+            })
+        ) {
+            return Err(TwoFACodeStoreError::LoginAttemptIdNotFound);
         }
+
+        pending_codes
+            .consume(login_attempt_id, email, code)?
+            .phone_number
+            .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)
+    }
+
+    async fn resend_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+        code: TwoFACode,
+    ) -> Result<Email, TwoFACodeStoreError> {
+        let mut pending_codes = self.pending.lock().unwrap();
+        let pending = pending_codes
+            .codes
+            .get_mut(login_attempt_id)
+            .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)?;
+
+        if pending.resend_count >= MAX_2FA_RESENDS {
+            return Err(TwoFACodeStoreError::TooManyResends);
+        }
+
+        let cooldown = Duration::from_secs(TWO_FA_RESEND_COOLDOWN_SECONDS);
+        let elapsed = pending.last_sent_at.elapsed();
+        if elapsed < cooldown {
+            return Err(TwoFACodeStoreError::ResendCooldown {
+                retry_after_seconds: (cooldown - elapsed).as_secs_f64().ceil() as u64,
+            });
+        }
+
+        pending.code = code;
+        pending.resend_count += 1;
+        pending.last_sent_at = Instant::now();
+
+        Ok(pending.email.clone())
     }
 }
 
@@ -45,10 +248,15 @@ mod tests {
     use secrecy::Secret;
 
     use super::*;
+
+    fn email() -> Email {
+        Email::parse(Secret::new("test@example.com".to_string())).unwrap()
+    }
+
     #[tokio::test]
     async fn test_add_code() {
-        let mut store = HashmapTwoFACodeStore::default();
-        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let store = HashmapTwoFACodeStore::default();
+        let email = email();
         let login_attempt_id = LoginAttemptId::default();
         let code = TwoFACode::default();
 
@@ -57,48 +265,152 @@ mod tests {
             .await;
 
         assert!(result.is_ok());
-        assert_eq!(store.codes.get(&email), Some(&(login_attempt_id, code)));
+        let pending = store.pending.lock().unwrap().codes[&login_attempt_id].clone();
+        assert_eq!(pending.email, email);
+        assert_eq!(pending.code, code);
+        assert_eq!(pending.failed_attempts, 0);
+        assert_eq!(pending.resend_count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_add_code_keeps_concurrent_attempts() {
+        let store = HashmapTwoFACodeStore::default();
+        let email = email();
+        let first_attempt_id = LoginAttemptId::default();
+        let second_attempt_id = LoginAttemptId::default();
+
+        store
+            .add_code(
+                email.clone(),
+                first_attempt_id.clone(),
+                TwoFACode::default(),
+            )
+            .await
+            .unwrap();
+        store
+            .add_code(
+                email.clone(),
+                second_attempt_id.clone(),
+                TwoFACode::default(),
+            )
+            .await
+            .unwrap();
+
+        assert!(store.get_code(&first_attempt_id).await.is_ok());
+        assert!(store.get_code(&second_attempt_id).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_add_code_evicts_oldest_attempt_over_cap() {
+        let store = HashmapTwoFACodeStore::default();
+        let email = email();
+        let login_attempt_ids: Vec<LoginAttemptId> = (0..=MAX_PENDING_2FA_ATTEMPTS_PER_USER)
+            .map(|_| LoginAttemptId::default())
+            .collect();
+
+        for login_attempt_id in &login_attempt_ids {
+            store
+                .add_code(
+                    email.clone(),
+                    login_attempt_id.clone(),
+                    TwoFACode::default(),
+                )
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            store.get_code(&login_attempt_ids[0]).await.unwrap_err(),
+            TwoFACodeStoreError::LoginAttemptIdNotFound
+        );
+        for login_attempt_id in &login_attempt_ids[1..] {
+            assert!(store.get_code(login_attempt_id).await.is_ok());
+        }
     }
 
     #[tokio::test]
     async fn test_remove_code() {
-        let mut store = HashmapTwoFACodeStore::default();
-        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let store = HashmapTwoFACodeStore::default();
         let login_attempt_id = LoginAttemptId::default();
-        let code = TwoFACode::default();
 
         store
-            .codes
-            .insert(email.clone(), (login_attempt_id.clone(), code.clone()));
+            .add_code(email(), login_attempt_id.clone(), TwoFACode::default())
+            .await
+            .unwrap();
 
-        let result = store.remove_code(&email).await;
+        let result = store.remove_code(&login_attempt_id).await;
 
         assert!(result.is_ok());
-        assert_eq!(store.codes.get(&email), None);
+        assert!(!store
+            .pending
+            .lock()
+            .unwrap()
+            .codes
+            .contains_key(&login_attempt_id));
+        assert!(store.pending.lock().unwrap().attempts_by_user.is_empty());
     }
 
     #[tokio::test]
     async fn test_get_code() {
-        let mut store = HashmapTwoFACodeStore::default();
-        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
+        let store = HashmapTwoFACodeStore::default();
+        let email = email();
         let login_attempt_id = LoginAttemptId::default();
         let code = TwoFACode::default();
         store
-            .codes
-            .insert(email.clone(), (login_attempt_id.clone(), code.clone()));
+            .add_code(email.clone(), login_attempt_id.clone(), code.clone())
+            .await
+            .unwrap();
 
-        let result = store.get_code(&email).await;
+        let result = store.get_code(&login_attempt_id).await;
 
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), (login_attempt_id, code));
+        assert_eq!(result.unwrap(), (email, code));
+    }
+
+    #[tokio::test]
+    async fn test_get_phone_number_code() {
+        let store = HashmapTwoFACodeStore::default();
+        let email = email();
+        let phone_number = PhoneNumber::parse(Secret::new("+14155551234".to_owned())).unwrap();
+        let login_attempt_id = LoginAttemptId::default();
+        let code = TwoFACode::default();
+        store
+            .add_phone_number_code(
+                email.clone(),
+                phone_number.clone(),
+                login_attempt_id.clone(),
+                code.clone(),
+            )
+            .await
+            .unwrap();
+
+        let result = store.get_phone_number_code(&login_attempt_id).await;
+
+        assert_eq!(result.unwrap(), (email, phone_number, code));
+    }
+
+    #[tokio::test]
+    async fn test_get_phone_number_code_not_bound_to_a_number() {
+        let store = HashmapTwoFACodeStore::default();
+        let login_attempt_id = LoginAttemptId::default();
+        store
+            .add_code(email(), login_attempt_id.clone(), TwoFACode::default())
+            .await
+            .unwrap();
+
+        let result = store.get_phone_number_code(&login_attempt_id).await;
+
+        assert_eq!(
+            result.unwrap_err(),
+            TwoFACodeStoreError::LoginAttemptIdNotFound
+        );
     }
 
     #[tokio::test]
     async fn test_get_code_not_found() {
         let store = HashmapTwoFACodeStore::default();
-        let email = Email::parse(Secret::new("test@example.com".to_string())).unwrap();
 
-        let result = store.get_code(&email).await;
+        let result = store.get_code(&LoginAttemptId::default()).await;
 
         assert!(result.is_err());
         assert_eq!(
@@ -106,4 +418,247 @@ mod tests {
             TwoFACodeStoreError::LoginAttemptIdNotFound
         );
     }
-}
\ No newline at end of file
+
+    fn code(code: &str) -> TwoFACode {
+        TwoFACode::parse(Secret::new(code.to_owned())).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_consume_code() {
+        let store = HashmapTwoFACodeStore::default();
+        let login_attempt_id = LoginAttemptId::default();
+        store
+            .add_code(email(), login_attempt_id.clone(), code("123456"))
+            .await
+            .unwrap();
+
+        let result = store
+            .consume_code(&login_attempt_id, &email(), &code("123456"))
+            .await;
+
+        assert!(result.is_ok());
+        assert_eq!(
+            store
+                .consume_code(&login_attempt_id, &email(), &code("123456"))
+                .await
+                .unwrap_err(),
+            TwoFACodeStoreError::LoginAttemptIdNotFound
+        );
+        assert!(store.pending.lock().unwrap().attempts_by_user.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_consume_code_with_wrong_email() {
+        let store = HashmapTwoFACodeStore::default();
+        let login_attempt_id = LoginAttemptId::default();
+        store
+            .add_code(email(), login_attempt_id.clone(), code("123456"))
+            .await
+            .unwrap();
+        let other_email = Email::parse(Secret::new("other@example.com".to_string())).unwrap();
+
+        let result = store
+            .consume_code(&login_attempt_id, &other_email, &code("123456"))
+            .await;
+
+        assert_eq!(result.unwrap_err(), TwoFACodeStoreError::IncorrectCode);
+        assert_eq!(
+            store.pending.lock().unwrap().codes[&login_attempt_id].failed_attempts,
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_consume_code_removes_code_at_limit() {
+        let store = HashmapTwoFACodeStore::default();
+        let login_attempt_id = LoginAttemptId::default();
+        store
+            .add_code(email(), login_attempt_id.clone(), code("123456"))
+            .await
+            .unwrap();
+
+        for _ in 0..MAX_FAILED_2FA_ATTEMPTS {
+            assert_eq!(
+                store
+                    .consume_code(&login_attempt_id, &email(), &code("654321"))
+                    .await
+                    .unwrap_err(),
+                TwoFACodeStoreError::IncorrectCode
+            );
+        }
+
+        assert!(!store
+            .pending
+            .lock()
+            .unwrap()
+            .codes
+            .contains_key(&login_attempt_id));
+        assert_eq!(
+            store
+                .consume_code(&login_attempt_id, &email(), &code("123456"))
+                .await
+                .unwrap_err(),
+            TwoFACodeStoreError::LoginAttemptIdNotFound
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_attempts_are_tracked_per_login_attempt() {
+        let store = HashmapTwoFACodeStore::default();
+        let first_attempt_id = LoginAttemptId::default();
+        let second_attempt_id = LoginAttemptId::default();
+        store
+            .add_code(email(), first_attempt_id.clone(), code("123456"))
+            .await
+            .unwrap();
+        store
+            .add_code(email(), second_attempt_id.clone(), code("123456"))
+            .await
+            .unwrap();
+
+        let _ = store
+            .consume_code(&first_attempt_id, &email(), &code("654321"))
+            .await;
+
+        let pending_codes = store.pending.lock().unwrap();
+        assert_eq!(pending_codes.codes[&first_attempt_id].failed_attempts, 1);
+        assert_eq!(pending_codes.codes[&second_attempt_id].failed_attempts, 0);
+    }
+
+    #[tokio::test]
+    async fn test_consume_phone_number_code() {
+        let store = HashmapTwoFACodeStore::default();
+        let phone_number = PhoneNumber::parse(Secret::new("+14155551234".to_owned())).unwrap();
+        let login_attempt_id = LoginAttemptId::default();
+        store
+            .add_phone_number_code(
+                email(),
+                phone_number.clone(),
+                login_attempt_id.clone(),
+                code("123456"),
+            )
+            .await
+            .unwrap();
+
+        let result = store
+            .consume_phone_number_code(&login_attempt_id, &email(), &code("123456"))
+            .await;
+
+        assert_eq!(result.unwrap(), phone_number);
+    }
+
+    #[tokio::test]
+    async fn test_consume_phone_number_code_not_bound_to_a_number() {
+        let store = HashmapTwoFACodeStore::default();
+        let login_attempt_id = LoginAttemptId::default();
+        store
+            .add_code(email(), login_attempt_id.clone(), code("123456"))
+            .await
+            .unwrap();
+
+        let result = store
+            .consume_phone_number_code(&login_attempt_id, &email(), &code("123456"))
+            .await;
+
+        assert_eq!(
+            result.unwrap_err(),
+            TwoFACodeStoreError::LoginAttemptIdNotFound
+        );
+        assert!(store.get_code(&login_attempt_id).await.is_ok());
+    }
+
+    // Pretends the pending code was last sent long enough ago to be resent
+    fn expire_cooldown(store: &HashmapTwoFACodeStore, login_attempt_id: &LoginAttemptId) {
+        store
+            .pending
+            .lock()
+            .unwrap()
+            .codes
+            .get_mut(login_attempt_id)
+            .unwrap()
+            .last_sent_at = Instant::now() - Duration::from_secs(TWO_FA_RESEND_COOLDOWN_SECONDS);
+    }
+
+    #[tokio::test]
+    async fn test_resend_code() {
+        let store = HashmapTwoFACodeStore::default();
+        let email = email();
+        let login_attempt_id = LoginAttemptId::default();
+        store
+            .add_code(email.clone(), login_attempt_id.clone(), code("123456"))
+            .await
+            .unwrap();
+        let _ = store
+            .consume_code(&login_attempt_id, &email, &code("654321"))
+            .await;
+        expire_cooldown(&store, &login_attempt_id);
+
+        let new_code = TwoFACode::default();
+        let result = store.resend_code(&login_attempt_id, new_code.clone()).await;
+
+        assert_eq!(result.unwrap(), email);
+        let pending = store.pending.lock().unwrap().codes[&login_attempt_id].clone();
+        assert_eq!(pending.code, new_code);
+        assert_eq!(pending.resend_count, 1);
+        assert_eq!(pending.failed_attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn test_resend_code_during_cooldown() {
+        let store = HashmapTwoFACodeStore::default();
+        let login_attempt_id = LoginAttemptId::default();
+        store
+            .add_code(email(), login_attempt_id.clone(), TwoFACode::default())
+            .await
+            .unwrap();
+
+        let result = store
+            .resend_code(&login_attempt_id, TwoFACode::default())
+            .await;
+
+        assert!(matches!(
+            result,
+            Err(TwoFACodeStoreError::ResendCooldown { retry_after_seconds })
+                if retry_after_seconds > 0 && retry_after_seconds <= TWO_FA_RESEND_COOLDOWN_SECONDS
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_resend_code_too_many_times() {
+        let store = HashmapTwoFACodeStore::default();
+        let login_attempt_id = LoginAttemptId::default();
+        store
+            .add_code(email(), login_attempt_id.clone(), TwoFACode::default())
+            .await
+            .unwrap();
+
+        for _ in 0..MAX_2FA_RESENDS {
+            expire_cooldown(&store, &login_attempt_id);
+            store
+                .resend_code(&login_attempt_id, TwoFACode::default())
+                .await
+                .unwrap();
+        }
+        expire_cooldown(&store, &login_attempt_id);
+
+        let result = store
+            .resend_code(&login_attempt_id, TwoFACode::default())
+            .await;
+
+        assert_eq!(result.unwrap_err(), TwoFACodeStoreError::TooManyResends);
+    }
+
+    #[tokio::test]
+    async fn test_resend_code_not_found() {
+        let store = HashmapTwoFACodeStore::default();
+
+        let result = store
+            .resend_code(&LoginAttemptId::default(), TwoFACode::default())
+            .await;
+
+        assert_eq!(
+            result.unwrap_err(),
+            TwoFACodeStoreError::LoginAttemptIdNotFound
+        );
+    }
+}
@@ -1,23 +1,93 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
 
-use crate::domain::{User, UserStoreError, UserStore, Password, Email};
-#[derive(Default, Clone)]
+use chrono::{SubsecRound, Utc};
+use secrecy::Secret;
+use uuid::Uuid;
+
+use crate::domain::{
+    Email, Password, PhoneNumber, TwoFAChannel, User, UserQuery, UserStore, UserStoreError,
+};
+use crate::services::password_hasher::{HashedPassword, PasswordHasher};
+
+#[derive(Default)]
 pub struct HashmapUserStore {
+    accounts: RwLock<Accounts>,
+}
+
+// Passwords are checked without holding the lock, as imported ones take a hash to verify
+#[derive(Default)]
+struct Accounts {
     users: HashMap<Email, User>,
+    // Hashes of imported users who haven't set a password here yet
+    imported_password_hashes: HashMap<Email, Secret<String>>,
+    // Passwords users had before their current one, newest first
+    password_history: HashMap<Email, Vec<PreviousPassword>>,
+}
+
+impl HashmapUserStore {
+    fn read(&self) -> RwLockReadGuard<'_, Accounts> {
+        self.accounts.read().unwrap()
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Accounts> {
+        self.accounts.write().unwrap()
+    }
+}
+
+#[derive(Clone)]
+enum PreviousPassword {
+    Plain(Password),
+    // Imported users' passwords are only known by their hash
+    Hashed(Secret<String>),
+}
+
+impl PreviousPassword {
+    async fn matches(&self, password: &Password) -> bool {
+        match self {
+            Self::Plain(previous) => previous == password,
+            Self::Hashed(hash) => PasswordHasher::default()
+                .verify(
+                    HashedPassword::unpeppered(hash.clone()),
+                    password.as_ref().to_owned(),
+                )
+                .await
+                .is_ok(),
+        }
+    }
 }
 
 #[async_trait::async_trait]
 impl UserStore for HashmapUserStore {
-    async fn add_user(&mut self, user: User) -> Result<(), UserStoreError> {
-        if self.users.contains_key(&user.email) {
+    async fn add_user(&self, user: User) -> Result<(), UserStoreError> {
+        let mut accounts = self.write();
+        if accounts.users.contains_key(&user.email) {
             return Err(UserStoreError::UserAlreadyExists);
         }
-        self.users.insert(user.email.clone(), user);
+        accounts.users.insert(user.email.clone(), user);
         return Ok(());
     }
 
+    async fn import_user(
+        &self,
+        user: User,
+        password_hash: Secret<String>,
+    ) -> Result<(), UserStoreError> {
+        let mut accounts = self.write();
+        if accounts.users.contains_key(&user.email) {
+            return Err(UserStoreError::UserAlreadyExists);
+        }
+        accounts
+            .imported_password_hashes
+            .insert(user.email.clone(), password_hash);
+        accounts.users.insert(user.email.clone(), user);
+        Ok(())
+    }
+
     async fn get_user(&self, email: &Email) -> Result<User, UserStoreError> {
-        match self.users.get(email) {
+        match self.read().users.get(email) {
             Some(user) => Ok(user.clone()),
             None => Err(UserStoreError::UserNotFound),
         }
@@ -28,7 +98,19 @@ impl UserStore for HashmapUserStore {
         email: &Email,
         password: &Password,
     ) -> Result<(), UserStoreError> {
-        match self.users.get(email) {
+        let imported_password_hash = self.read().imported_password_hashes.get(email).cloned();
+        if let Some(password_hash) = imported_password_hash {
+            return PasswordHasher::default()
+                .verify(
+                    HashedPassword::unpeppered(password_hash),
+                    password.as_ref().to_owned(),
+                )
+                .await
+                .map(|_| ())
+                .map_err(|_| UserStoreError::InvalidCredentials);
+        }
+
+        match self.read().users.get(email) {
             Some(user) => {
                 if user.password.eq(password) {
                     Ok(())
@@ -39,6 +121,170 @@ impl UserStore for HashmapUserStore {
             None => Err(UserStoreError::UserNotFound),
         }
     }
+
+    async fn update_password(
+        &self,
+        email: &Email,
+        password: Password,
+        history_size: usize,
+    ) -> Result<(), UserStoreError> {
+        let (current, history) = {
+            let accounts = self.read();
+            let current = match accounts.imported_password_hashes.get(email) {
+                Some(hash) => PreviousPassword::Hashed(hash.clone()),
+                None => PreviousPassword::Plain(
+                    accounts
+                        .users
+                        .get(email)
+                        .ok_or(UserStoreError::UserNotFound)?
+                        .password
+                        .clone(),
+                ),
+            };
+            let history = accounts.password_history.get(email).cloned();
+            (current, history)
+        };
+
+        let recent_passwords = std::iter::once(&current)
+            .chain(history.iter().flatten())
+            .take(history_size);
+        for previous in recent_passwords {
+            if previous.matches(&password).await {
+                return Err(UserStoreError::PasswordReused);
+            }
+        }
+
+        let accounts = &mut *self.write();
+        let user = accounts
+            .users
+            .get_mut(email)
+            .ok_or(UserStoreError::UserNotFound)?;
+        let history = accounts.password_history.entry(email.clone()).or_default();
+        history.insert(0, current);
+        history.truncate(history_size.saturating_sub(1));
+
+        user.password = password;
+        accounts.imported_password_hashes.remove(email);
+        user.updated_at = Utc::now().trunc_subsecs(6);
+        Ok(())
+    }
+
+    async fn set_phone_number(
+        &self,
+        email: &Email,
+        phone_number: PhoneNumber,
+    ) -> Result<(), UserStoreError> {
+        let mut accounts = self.write();
+        let user = accounts
+            .users
+            .get_mut(email)
+            .ok_or(UserStoreError::UserNotFound)?;
+        user.phone_number = Some(phone_number);
+        user.phone_number_verified = false;
+        user.two_fa_channel = TwoFAChannel::Email;
+        Ok(())
+    }
+
+    async fn verify_phone_number(
+        &self,
+        email: &Email,
+        phone_number: &PhoneNumber,
+    ) -> Result<(), UserStoreError> {
+        let mut accounts = self.write();
+        let user = accounts
+            .users
+            .get_mut(email)
+            .ok_or(UserStoreError::UserNotFound)?;
+        if user.phone_number.as_ref() != Some(phone_number) {
+            return Err(UserStoreError::PhoneNumberChanged);
+        }
+        user.phone_number_verified = true;
+        Ok(())
+    }
+
+    async fn set_two_fa_channel(
+        &self,
+        email: &Email,
+        channel: TwoFAChannel,
+    ) -> Result<(), UserStoreError> {
+        let mut accounts = self.write();
+        let user = accounts
+            .users
+            .get_mut(email)
+            .ok_or(UserStoreError::UserNotFound)?;
+        user.two_fa_channel = channel;
+        Ok(())
+    }
+
+    async fn get_user_by_id(&self, id: Uuid) -> Result<User, UserStoreError> {
+        self.read()
+            .users
+            .values()
+            .find(|user| user.id == id)
+            .cloned()
+            .ok_or(UserStoreError::UserNotFound)
+    }
+
+    async fn list_users(
+        &self,
+        query: &UserQuery,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<User>, usize), UserStoreError> {
+        let accounts = self.read();
+        let mut users: Vec<_> = accounts
+            .users
+            .values()
+            .filter(|user| query.matches(user))
+            .collect();
+        users.sort_by_key(|user| (user.created_at, user.id));
+
+        let total = users.len();
+        let page = users
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .cloned()
+            .collect();
+        Ok((page, total))
+    }
+
+    async fn set_active(&self, email: &Email, active: bool) -> Result<(), UserStoreError> {
+        let mut accounts = self.write();
+        let user = accounts
+            .users
+            .get_mut(email)
+            .ok_or(UserStoreError::UserNotFound)?;
+        user.active = active;
+        user.updated_at = Utc::now().trunc_subsecs(6);
+        Ok(())
+    }
+
+    async fn set_external_id(
+        &self,
+        email: &Email,
+        external_id: Option<String>,
+    ) -> Result<(), UserStoreError> {
+        let mut accounts = self.write();
+        let user = accounts
+            .users
+            .get_mut(email)
+            .ok_or(UserStoreError::UserNotFound)?;
+        user.external_id = external_id;
+        user.updated_at = Utc::now().trunc_subsecs(6);
+        Ok(())
+    }
+
+    async fn delete_user(&self, email: &Email) -> Result<(), UserStoreError> {
+        let mut accounts = self.write();
+        accounts.imported_password_hashes.remove(email);
+        accounts.password_history.remove(email);
+        accounts
+            .users
+            .remove(email)
+            .map(|_| ())
+            .ok_or(UserStoreError::UserNotFound)
+    }
 }
 
 // Add unit tests for your `HashmapUserStore` implementation
@@ -49,7 +295,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_add_user() {
-        let mut user_store = HashmapUserStore::default();
+        let user_store = HashmapUserStore::default();
         let email = Email::parse(Secret::new("email@example.com".to_owned())).unwrap();
         let password = Password::parse(Secret::new("password".to_owned())).unwrap();
         let user = User::new(email, password, false);
@@ -66,7 +312,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_user() {
-        let mut user_store = HashmapUserStore::default();
+        let user_store = HashmapUserStore::default();
         let email = Email::parse(Secret::new("email@example.com".to_owned())).unwrap();
         let password = Password::parse(Secret::new("password".to_owned())).unwrap();
         let user = User::new(email, password, false);
@@ -78,12 +324,12 @@ mod tests {
 
     #[tokio::test]
     async fn test_validate_user() {
-        let mut user_store = HashmapUserStore::default();
+        let user_store = HashmapUserStore::default();
         let email = Email::parse(Secret::new("example@email.com".to_owned())).unwrap();
         let password = Password::parse(Secret::new("password".to_owned())).unwrap();
         let user = User::new(email.clone(), password.clone(), false);
 
-        user_store.users.insert(email.clone(), user.clone());
+        user_store.write().users.insert(email.clone(), user.clone());
         let result = user_store.validate_user(&email, &password).await;
         assert_eq!(result, Ok(()));
 
@@ -102,4 +348,217 @@ mod tests {
 
         assert_eq!(result, Err(UserStoreError::UserNotFound));
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_update_password() {
+        let user_store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new("example@email.com".to_owned())).unwrap();
+        let password = Password::parse(Secret::new("password".to_owned())).unwrap();
+        let new_password = Password::parse(Secret::new("new-password".to_owned())).unwrap();
+        let user = User::new(email.clone(), password.clone(), false);
+        user_store.add_user(user).await.unwrap();
+
+        user_store
+            .update_password(&email, new_password.clone(), 0)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            user_store.validate_user(&email, &password).await,
+            Err(UserStoreError::InvalidCredentials)
+        );
+        assert_eq!(
+            user_store.validate_user(&email, &new_password).await,
+            Ok(())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_password_rejects_recent_passwords() {
+        let user_store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new("example@email.com".to_owned())).unwrap();
+        let passwords: Vec<_> = (0..4)
+            .map(|i| Password::parse(Secret::new(format!("password-{}", i))).unwrap())
+            .collect();
+        let user = User::new(email.clone(), passwords[0].clone(), false);
+        user_store.add_user(user).await.unwrap();
+
+        for password in &passwords[1..] {
+            user_store
+                .update_password(&email, password.clone(), 3)
+                .await
+                .unwrap();
+        }
+        assert_eq!(user_store.read().password_history[&email].len(), 2);
+
+        // The current password and the two before it are remembered, but not the first one
+        for password in &passwords[1..] {
+            assert_eq!(
+                user_store
+                    .update_password(&email, password.clone(), 3)
+                    .await,
+                Err(UserStoreError::PasswordReused)
+            );
+        }
+        user_store
+            .update_password(&email, passwords[0].clone(), 3)
+            .await
+            .unwrap();
+
+        // Without a history, anything goes
+        user_store
+            .update_password(&email, passwords[0].clone(), 0)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_import_user() {
+        let user_store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new("email@example.com".to_owned())).unwrap();
+        let password = Password::parse(Secret::new("password".to_owned())).unwrap();
+        let password_hash = Secret::new(bcrypt::hash("password", 4).unwrap());
+        let placeholder = Password::parse(password_hash.clone()).unwrap();
+        let user = User::new(email.clone(), placeholder.clone(), false);
+
+        user_store
+            .import_user(user.clone(), password_hash.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            user_store.import_user(user, password_hash).await,
+            Err(UserStoreError::UserAlreadyExists)
+        );
+
+        assert_eq!(user_store.validate_user(&email, &password).await, Ok(()));
+        assert_eq!(
+            user_store.validate_user(&email, &placeholder).await,
+            Err(UserStoreError::InvalidCredentials)
+        );
+
+        assert_eq!(
+            user_store.update_password(&email, password, 1).await,
+            Err(UserStoreError::PasswordReused)
+        );
+        let new_password = Password::parse(Secret::new("new-password".to_owned())).unwrap();
+        user_store
+            .update_password(&email, new_password.clone(), 1)
+            .await
+            .unwrap();
+        assert_eq!(
+            user_store.validate_user(&email, &new_password).await,
+            Ok(())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_and_verify_phone_number() {
+        let user_store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new("email@example.com".to_owned())).unwrap();
+        let password = Password::parse(Secret::new("password".to_owned())).unwrap();
+        user_store
+            .add_user(User::new(email.clone(), password, true))
+            .await
+            .unwrap();
+        let phone_number = PhoneNumber::parse(Secret::new("+14155551234".to_owned())).unwrap();
+
+        user_store
+            .set_phone_number(&email, phone_number.clone())
+            .await
+            .unwrap();
+        let user = user_store.get_user(&email).await.unwrap();
+        assert_eq!(user.phone_number, Some(phone_number.clone()));
+        assert!(!user.phone_number_verified);
+
+        user_store
+            .verify_phone_number(&email, &phone_number)
+            .await
+            .unwrap();
+        user_store
+            .set_two_fa_channel(&email, TwoFAChannel::Sms)
+            .await
+            .unwrap();
+        let user = user_store.get_user(&email).await.unwrap();
+        assert!(user.phone_number_verified);
+        assert_eq!(user.two_fa_channel, TwoFAChannel::Sms);
+
+        // Changing the number requires verifying it again
+        let new_phone_number = PhoneNumber::parse(Secret::new("+14155550000".to_owned())).unwrap();
+        user_store
+            .set_phone_number(&email, new_phone_number)
+            .await
+            .unwrap();
+        let user = user_store.get_user(&email).await.unwrap();
+        assert!(!user.phone_number_verified);
+        assert_eq!(user.two_fa_channel, TwoFAChannel::Email);
+
+        // Nor can the old number's verification carry over to the new one
+        let result = user_store.verify_phone_number(&email, &phone_number).await;
+        assert_eq!(result, Err(UserStoreError::PhoneNumberChanged));
+        let user = user_store.get_user(&email).await.unwrap();
+        assert!(!user.phone_number_verified);
+    }
+
+    #[tokio::test]
+    async fn test_set_phone_number_for_missing_user() {
+        let user_store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new("email@example.com".to_owned())).unwrap();
+        let phone_number = PhoneNumber::parse(Secret::new("+14155551234".to_owned())).unwrap();
+
+        let result = user_store.set_phone_number(&email, phone_number).await;
+
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_list_users() {
+        let user_store = HashmapUserStore::default();
+        let password = Password::parse(Secret::new("password".to_owned())).unwrap();
+        for i in 0..3 {
+            let email = Email::parse(Secret::new(format!("user{}@example.com", i))).unwrap();
+            user_store
+                .add_user(User::new(email, password.clone(), false))
+                .await
+                .unwrap();
+        }
+        let first = Email::parse(Secret::new("user0@example.com".to_owned())).unwrap();
+        user_store.set_active(&first, false).await.unwrap();
+
+        let (page, total) = user_store
+            .list_users(&UserQuery::default(), 1, 1)
+            .await
+            .unwrap();
+        assert_eq!(total, 3);
+        assert_eq!(page.len(), 1);
+
+        let query = UserQuery {
+            active: Some(false),
+            ..UserQuery::default()
+        };
+        let (page, total) = user_store.list_users(&query, 0, 10).await.unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(page[0].email, first);
+    }
+
+    #[tokio::test]
+    async fn test_delete_user() {
+        let user_store = HashmapUserStore::default();
+        let email = Email::parse(Secret::new("email@example.com".to_owned())).unwrap();
+        let password = Password::parse(Secret::new("password".to_owned())).unwrap();
+        let user = User::new(email.clone(), password, false);
+        user_store.add_user(user.clone()).await.unwrap();
+
+        assert_eq!(user_store.get_user_by_id(user.id).await, Ok(user));
+
+        user_store.delete_user(&email).await.unwrap();
+
+        assert_eq!(
+            user_store.get_user(&email).await,
+            Err(UserStoreError::UserNotFound)
+        );
+        assert_eq!(
+            user_store.delete_user(&email).await,
+            Err(UserStoreError::UserNotFound)
+        );
+    }
+}
@@ -0,0 +1,278 @@
+use std::{collections::HashMap, sync::RwLock};
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::domain::{
+    data_stores::{WebhookStore, WebhookStoreError},
+    DueWebhookDelivery, WebhookDelivery, WebhookDeliveryAttempt, WebhookDeliveryStatus,
+    WebhookEvent, WebhookSubscription,
+};
+
+#[derive(Default)]
+pub struct HashmapWebhookStore {
+    webhooks: RwLock<Webhooks>,
+}
+
+#[derive(Default)]
+struct Webhooks {
+    subscriptions: HashMap<Uuid, WebhookSubscription>,
+    // Oldest first
+    deliveries: Vec<WebhookDelivery>,
+}
+
+#[async_trait::async_trait]
+impl WebhookStore for HashmapWebhookStore {
+    async fn add_subscription(
+        &self,
+        subscription: WebhookSubscription,
+    ) -> Result<(), WebhookStoreError> {
+        let webhooks = &mut *self.webhooks.write().unwrap();
+        webhooks.subscriptions.insert(subscription.id, subscription);
+        Ok(())
+    }
+
+    async fn get_subscriptions(&self) -> Result<Vec<WebhookSubscription>, WebhookStoreError> {
+        let webhooks = self.webhooks.read().unwrap();
+        let mut subscriptions: Vec<_> = webhooks.subscriptions.values().cloned().collect();
+        subscriptions.sort_by_key(|subscription| subscription.created_at);
+        Ok(subscriptions)
+    }
+
+    async fn get_subscription(&self, id: Uuid) -> Result<WebhookSubscription, WebhookStoreError> {
+        let webhooks = self.webhooks.read().unwrap();
+        webhooks
+            .subscriptions
+            .get(&id)
+            .cloned()
+            .ok_or(WebhookStoreError::SubscriptionNotFound)
+    }
+
+    async fn delete_subscription(&self, id: Uuid) -> Result<(), WebhookStoreError> {
+        let webhooks = &mut *self.webhooks.write().unwrap();
+        webhooks
+            .subscriptions
+            .remove(&id)
+            .ok_or(WebhookStoreError::SubscriptionNotFound)?;
+        webhooks
+            .deliveries
+            .retain(|delivery| delivery.subscription_id != id);
+        Ok(())
+    }
+
+    async fn enqueue_event(&self, event: &WebhookEvent) -> Result<(), WebhookStoreError> {
+        let webhooks = &mut *self.webhooks.write().unwrap();
+        let payload = serde_json::to_string(event)
+            .map_err(|e| WebhookStoreError::UnexpectedError(e.into()))?;
+
+        let mut subscriptions: Vec<_> = webhooks
+            .subscriptions
+            .values()
+            .filter(|subscription| subscription.is_subscribed_to(event.event_type))
+            .collect();
+        subscriptions.sort_by_key(|subscription| subscription.created_at);
+
+        for subscription in subscriptions {
+            webhooks.deliveries.push(WebhookDelivery {
+                id: Uuid::new_v4(),
+                subscription_id: subscription.id,
+                event_id: event.id,
+                event_type: event.event_type,
+                payload: payload.clone(),
+                status: WebhookDeliveryStatus::Pending,
+                next_attempt_at: Some(event.created_at),
+                created_at: event.created_at,
+                attempts: Vec::new(),
+            });
+        }
+
+        Ok(())
+    }
+
+    async fn claim_due_deliveries(
+        &self,
+        now: DateTime<Utc>,
+        lease_until: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<DueWebhookDelivery>, WebhookStoreError> {
+        let webhooks = &mut *self.webhooks.write().unwrap();
+        let mut due = Vec::new();
+
+        for delivery in webhooks.deliveries.iter_mut() {
+            if due.len() == limit {
+                break;
+            }
+            if delivery.status != WebhookDeliveryStatus::Pending
+                || delivery.next_attempt_at.is_none_or(|at| at > now)
+            {
+                continue;
+            }
+
+            delivery.next_attempt_at = Some(lease_until);
+            let subscription = &webhooks.subscriptions[&delivery.subscription_id];
+            due.push(DueWebhookDelivery {
+                delivery: delivery.clone(),
+                url: subscription.url.clone(),
+                secret: subscription.secret.clone(),
+            });
+        }
+
+        Ok(due)
+    }
+
+    async fn record_attempt(
+        &self,
+        delivery_id: Uuid,
+        attempt: WebhookDeliveryAttempt,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<(), WebhookStoreError> {
+        let webhooks = &mut *self.webhooks.write().unwrap();
+        let delivery = webhooks
+            .deliveries
+            .iter_mut()
+            .find(|delivery| delivery.id == delivery_id)
+            .ok_or(WebhookStoreError::DeliveryNotFound)?;
+
+        (delivery.status, delivery.next_attempt_at) = match (attempt.succeeded(), retry_at) {
+            (true, _) => (WebhookDeliveryStatus::Succeeded, None),
+            (false, Some(retry_at)) => (WebhookDeliveryStatus::Pending, Some(retry_at)),
+            (false, None) => (WebhookDeliveryStatus::Failed, None),
+        };
+        delivery.attempts.push(attempt);
+
+        Ok(())
+    }
+
+    async fn get_deliveries(
+        &self,
+        subscription_id: Uuid,
+        limit: usize,
+    ) -> Result<Vec<WebhookDelivery>, WebhookStoreError> {
+        let webhooks = self.webhooks.read().unwrap();
+        Ok(webhooks
+            .deliveries
+            .iter()
+            .rev()
+            .filter(|delivery| delivery.subscription_id == subscription_id)
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::WebhookEventType;
+    use chrono::Duration;
+
+    fn subscription(event_types: Vec<WebhookEventType>) -> WebhookSubscription {
+        WebhookSubscription::new(
+            WebhookSubscription::parse_url("https://example.com/hooks").unwrap(),
+            event_types,
+            WebhookSubscription::generate_secret(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_enqueue_event_only_targets_subscribers() {
+        let store = HashmapWebhookStore::default();
+        let subscriber = subscription(vec![WebhookEventType::UserCreated]);
+        let other = subscription(vec![WebhookEventType::UserDeleted]);
+        store.add_subscription(subscriber.clone()).await.unwrap();
+        store.add_subscription(other.clone()).await.unwrap();
+
+        let event = WebhookEvent::new(WebhookEventType::UserCreated, serde_json::json!({}));
+        store.enqueue_event(&event).await.unwrap();
+
+        assert_eq!(
+            store.get_deliveries(subscriber.id, 10).await.unwrap().len(),
+            1
+        );
+        assert!(store.get_deliveries(other.id, 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_claimed_deliveries_are_leased() {
+        let store = HashmapWebhookStore::default();
+        store
+            .add_subscription(subscription(vec![WebhookEventType::UserCreated]))
+            .await
+            .unwrap();
+        let event = WebhookEvent::new(WebhookEventType::UserCreated, serde_json::json!({}));
+        store.enqueue_event(&event).await.unwrap();
+
+        let now = Utc::now();
+        let lease_until = now + Duration::seconds(60);
+
+        let due = store
+            .claim_due_deliveries(now, lease_until, 10)
+            .await
+            .unwrap();
+        assert_eq!(due.len(), 1);
+        assert!(store
+            .claim_due_deliveries(now, lease_until, 10)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // An expired lease makes the delivery due again
+        let due = store
+            .claim_due_deliveries(lease_until, lease_until + Duration::seconds(60), 10)
+            .await
+            .unwrap();
+        assert_eq!(due.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_record_attempt_updates_status() {
+        let store = HashmapWebhookStore::default();
+        let subscription = subscription(vec![WebhookEventType::UserCreated]);
+        store.add_subscription(subscription.clone()).await.unwrap();
+        let event = WebhookEvent::new(WebhookEventType::UserCreated, serde_json::json!({}));
+        store.enqueue_event(&event).await.unwrap();
+        let delivery_id = store.get_deliveries(subscription.id, 1).await.unwrap()[0].id;
+
+        let failed = WebhookDeliveryAttempt {
+            attempted_at: Utc::now(),
+            response_status: Some(500),
+            error: None,
+        };
+        let retry_at = Utc::now() + Duration::seconds(30);
+        store
+            .record_attempt(delivery_id, failed.clone(), Some(retry_at))
+            .await
+            .unwrap();
+
+        let delivery = &store.get_deliveries(subscription.id, 1).await.unwrap()[0];
+        assert_eq!(delivery.status, WebhookDeliveryStatus::Pending);
+        assert_eq!(delivery.next_attempt_at, Some(retry_at));
+
+        store
+            .record_attempt(delivery_id, failed, None)
+            .await
+            .unwrap();
+
+        let delivery = &store.get_deliveries(subscription.id, 1).await.unwrap()[0];
+        assert_eq!(delivery.status, WebhookDeliveryStatus::Failed);
+        assert_eq!(delivery.next_attempt_at, None);
+        assert_eq!(delivery.attempts.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_delete_subscription_drops_deliveries() {
+        let store = HashmapWebhookStore::default();
+        let subscription = subscription(vec![WebhookEventType::UserCreated]);
+        store.add_subscription(subscription.clone()).await.unwrap();
+        let event = WebhookEvent::new(WebhookEventType::UserCreated, serde_json::json!({}));
+        store.enqueue_event(&event).await.unwrap();
+
+        store.delete_subscription(subscription.id).await.unwrap();
+
+        assert!(store.webhooks.read().unwrap().deliveries.is_empty());
+        assert_eq!(
+            store.delete_subscription(subscription.id).await,
+            Err(WebhookStoreError::SubscriptionNotFound)
+        );
+    }
+}
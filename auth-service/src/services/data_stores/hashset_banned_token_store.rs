@@ -1,22 +1,57 @@
-use std::collections::HashSet;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::RwLock,
+};
+
 use secrecy::{ExposeSecret, Secret};
 
-use crate::domain::data_stores::{BannedTokenStore, BannedTokenStoreError};
+use crate::domain::{
+    data_stores::{BannedTokenStore, BannedTokenStoreError},
+    Email,
+};
 
 #[derive(Default)]
 pub struct HashsetBannedTokenStore {
-    tokens: HashSet<String>,
+    tokens: RwLock<HashSet<String>>,
+    revocations: RwLock<HashMap<Email, i64>>,
 }
 
 #[async_trait::async_trait]
 impl BannedTokenStore for HashsetBannedTokenStore {
-    async fn add_token(&mut self, token: Secret<String>) -> Result<(), BannedTokenStoreError> {
-        self.tokens.insert(token.expose_secret().to_string());
+    // Tokens are kept for as long as the store is, which outlasts any TTL
+    async fn add_token(
+        &self,
+        token: Secret<String>,
+        _ttl_seconds: u64,
+    ) -> Result<(), BannedTokenStoreError> {
+        self.tokens
+            .write()
+            .unwrap()
+            .insert(token.expose_secret().to_string());
         Ok(())
     }
 
     async fn contains_token(&self, token: &Secret<String>) -> Result<bool, BannedTokenStoreError> {
-        Ok(self.tokens.contains(token.expose_secret()))
+        Ok(self.tokens.read().unwrap().contains(token.expose_secret()))
+    }
+
+    async fn revoke_tokens_issued_before(
+        &self,
+        email: &Email,
+        timestamp: i64,
+    ) -> Result<(), BannedTokenStoreError> {
+        self.revocations
+            .write()
+            .unwrap()
+            .insert(email.clone(), timestamp);
+        Ok(())
+    }
+
+    async fn tokens_revoked_before(
+        &self,
+        email: &Email,
+    ) -> Result<Option<i64>, BannedTokenStoreError> {
+        Ok(self.revocations.read().unwrap().get(email).copied())
     }
 }
 
@@ -25,23 +60,48 @@ mod tests {
     use super::*;
     #[tokio::test]
     async fn test_add_token() {
-        let mut store = HashsetBannedTokenStore::default();
+        let store = HashsetBannedTokenStore::default();
         let token = Secret::new("test_token".to_owned());
 
-        let result = store.add_token(token.clone()).await;
+        let result = store.add_token(token.clone(), 600).await;
 
         assert!(result.is_ok());
-        assert!(store.tokens.contains(token.expose_secret()));
+        assert!(store.tokens.read().unwrap().contains(token.expose_secret()));
     }
 
     #[tokio::test]
     async fn test_contains_token() {
-        let mut store = HashsetBannedTokenStore::default();
+        let store = HashsetBannedTokenStore::default();
         let token = Secret::new("test_token".to_owned());
-        store.tokens.insert(token.expose_secret().to_string());
+        store
+            .tokens
+            .write()
+            .unwrap()
+            .insert(token.expose_secret().to_string());
 
         let result = store.contains_token(&token).await;
 
         assert!(result.unwrap());
     }
+
+    #[tokio::test]
+    async fn test_revoke_tokens_issued_before() {
+        let store = HashsetBannedTokenStore::default();
+        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
+        assert_eq!(store.tokens_revoked_before(&email).await.unwrap(), None);
+
+        store
+            .revoke_tokens_issued_before(&email, 100)
+            .await
+            .unwrap();
+        store
+            .revoke_tokens_issued_before(&email, 200)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.tokens_revoked_before(&email).await.unwrap(),
+            Some(200)
+        );
+    }
 }
\ No newline at end of file
@@ -1,13 +1,33 @@
+pub(crate) mod hashmap_audit_log_store;
+pub(crate) mod hashmap_group_store;
+pub(crate) mod hashmap_known_device_store;
 pub(crate) mod hashmap_user_store;
+pub(crate) mod hashmap_webhook_store;
 pub(crate) mod hashset_banned_token_store;
 pub(crate) mod hashmap_two_fa_code_store;
+pub(crate) mod hashmap_rate_limit_store;
+pub(crate) mod postgres_audit_log_store;
+pub(crate) mod postgres_group_store;
+pub(crate) mod postgres_known_device_store;
 pub(crate) mod postgres_user_store;
+pub(crate) mod postgres_webhook_store;
 pub(crate) mod redis_banned_token_store;
+pub(crate) mod redis_rate_limit_store;
 pub(crate) mod redis_two_fa_code_store;
 
+pub use hashmap_audit_log_store::*;
+pub use hashmap_group_store::*;
+pub use hashmap_known_device_store::*;
 pub use hashmap_user_store::*;
+pub use hashmap_webhook_store::*;
 pub use hashset_banned_token_store::*;
 pub use hashmap_two_fa_code_store::*;
+pub use hashmap_rate_limit_store::*;
+pub use postgres_audit_log_store::*;
+pub use postgres_group_store::*;
+pub use postgres_known_device_store::*;
 pub use postgres_user_store::*;
+pub use postgres_webhook_store::*;
 pub use redis_banned_token_store::*;
+pub use redis_rate_limit_store::*;
 pub use redis_two_fa_code_store::*;
\ No newline at end of file
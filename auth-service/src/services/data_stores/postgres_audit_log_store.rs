@@ -0,0 +1,97 @@
+use color_eyre::eyre::Context;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+
+use crate::domain::{
+    data_stores::{AuditLogStore, AuditLogStoreError},
+    AuditEvent, AuditEventType, AuditLogFilter, AuditOutcome, NewAuditEvent,
+};
+
+pub struct PostgresAuditLogStore {
+    pool: PgPool,
+}
+
+impl PostgresAuditLogStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl AuditLogStore for PostgresAuditLogStore {
+    #[tracing::instrument(name = "Appending audit event to PostgreSQL", skip_all)]
+    async fn append(&self, event: NewAuditEvent) -> Result<(), AuditLogStoreError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO audit_events (event_type, email, ip, user_agent, request_id, outcome, status_code)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+            event.event_type.as_str(),
+            event.email.as_ref().map(|email| email.as_ref().expose_secret().as_str()),
+            event.ip,
+            event.user_agent,
+            event.request_id,
+            event.outcome.as_str(),
+            event.status_code as i16
+        )
+        .execute(&self.pool)
+        .await
+        .wrap_err("failed to insert audit event")
+        .map_err(AuditLogStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Querying audit events from PostgreSQL", skip_all)]
+    async fn query(
+        &self,
+        filter: &AuditLogFilter,
+        before: Option<i64>,
+        limit: usize,
+    ) -> Result<Vec<AuditEvent>, AuditLogStoreError> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, occurred_at, event_type, email, ip, user_agent, request_id, outcome, status_code
+            FROM audit_events
+            WHERE ($1::BIGINT IS NULL OR id < $1)
+              AND ($2::TEXT IS NULL OR event_type = $2)
+              AND ($3::TEXT IS NULL OR email = $3)
+              AND ($4::TEXT IS NULL OR ip = $4)
+              AND ($5::TEXT IS NULL OR outcome = $5)
+              AND ($6::TIMESTAMPTZ IS NULL OR occurred_at >= $6)
+              AND ($7::TIMESTAMPTZ IS NULL OR occurred_at < $7)
+            ORDER BY id DESC
+            LIMIT $8
+            "#,
+            before,
+            filter.event_type.map(|event_type| event_type.as_str()),
+            filter.email.as_ref().map(|email| email.as_ref().expose_secret().as_str()),
+            filter.ip.as_deref(),
+            filter.outcome.map(|outcome| outcome.as_str()),
+            filter.since,
+            filter.until,
+            limit as i64
+        )
+        .fetch_all(&self.pool)
+        .await
+        .wrap_err("failed to query audit events")
+        .map_err(AuditLogStoreError::UnexpectedError)?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(AuditEvent {
+                    id: row.id,
+                    occurred_at: row.occurred_at,
+                    event_type: AuditEventType::parse(&row.event_type)?,
+                    email: row.email,
+                    ip: row.ip,
+                    user_agent: row.user_agent,
+                    request_id: row.request_id,
+                    outcome: AuditOutcome::parse(&row.outcome)?,
+                    status_code: row.status_code as u16,
+                })
+            })
+            .collect::<color_eyre::eyre::Result<_>>()
+            .map_err(AuditLogStoreError::UnexpectedError)
+    }
+}
@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+
+use color_eyre::eyre::{Context, Result};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::domain::{
+    data_stores::{GroupStore, GroupStoreError},
+    Group,
+};
+
+pub struct PostgresGroupStore {
+    pool: PgPool,
+}
+
+impl PostgresGroupStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    async fn get_members(&self, group_ids: &[Uuid]) -> Result<HashMap<Uuid, Vec<Uuid>>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT group_id, user_id
+            FROM group_members
+            WHERE group_id = ANY($1)
+            ORDER BY user_id
+            "#,
+            group_ids
+        )
+        .fetch_all(&self.pool)
+        .await
+        .wrap_err("failed to fetch group members")?;
+
+        let mut members: HashMap<Uuid, Vec<Uuid>> = HashMap::new();
+        for row in rows {
+            members.entry(row.group_id).or_default().push(row.user_id);
+        }
+
+        Ok(members)
+    }
+}
+
+#[async_trait::async_trait]
+impl GroupStore for PostgresGroupStore {
+    #[tracing::instrument(name = "Adding group to PostgreSQL", skip_all)]
+    async fn add_group(&self, group: Group) -> Result<(), GroupStoreError> {
+        let mut transaction = begin(&self.pool).await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO groups (id, display_name, external_id, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+            group.id,
+            group.display_name,
+            group.external_id,
+            group.created_at,
+            group.updated_at
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(to_store_error)?;
+
+        insert_members(&mut transaction, &group).await?;
+
+        commit(transaction).await
+    }
+
+    #[tracing::instrument(name = "Retrieving group from PostgreSQL", skip_all)]
+    async fn get_group(&self, id: Uuid) -> Result<Group, GroupStoreError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT id, display_name, external_id, created_at, updated_at
+            FROM groups
+            WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(to_store_error)?
+        .ok_or(GroupStoreError::GroupNotFound)?;
+
+        let mut members = self
+            .get_members(&[id])
+            .await
+            .map_err(GroupStoreError::UnexpectedError)?;
+
+        Ok(Group {
+            id: row.id,
+            display_name: row.display_name,
+            external_id: row.external_id,
+            members: members.remove(&row.id).unwrap_or_default(),
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        })
+    }
+
+    #[tracing::instrument(name = "Listing groups from PostgreSQL", skip_all)]
+    async fn list_groups(
+        &self,
+        display_name: Option<&str>,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<Group>, usize), GroupStoreError> {
+        let total = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "count!"
+            FROM groups
+            WHERE ($1::TEXT IS NULL OR display_name = $1)
+            "#,
+            display_name
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(to_store_error)?;
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, display_name, external_id, created_at, updated_at
+            FROM groups
+            WHERE ($1::TEXT IS NULL OR display_name = $1)
+            ORDER BY created_at, id
+            OFFSET $2
+            LIMIT $3
+            "#,
+            display_name,
+            offset as i64,
+            limit as i64
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(to_store_error)?;
+
+        let group_ids: Vec<Uuid> = rows.iter().map(|row| row.id).collect();
+        let mut members = self
+            .get_members(&group_ids)
+            .await
+            .map_err(GroupStoreError::UnexpectedError)?;
+
+        let groups = rows
+            .into_iter()
+            .map(|row| Group {
+                id: row.id,
+                display_name: row.display_name,
+                external_id: row.external_id,
+                members: members.remove(&row.id).unwrap_or_default(),
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+            })
+            .collect();
+
+        Ok((groups, total as usize))
+    }
+
+    #[tracing::instrument(name = "Updating group in PostgreSQL", skip_all)]
+    async fn update_group(&self, group: &Group) -> Result<(), GroupStoreError> {
+        let mut transaction = begin(&self.pool).await?;
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE groups
+            SET display_name = $2, external_id = $3, updated_at = $4
+            WHERE id = $1
+            "#,
+            group.id,
+            group.display_name,
+            group.external_id,
+            group.updated_at
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(to_store_error)?;
+
+        if result.rows_affected() == 0 {
+            return Err(GroupStoreError::GroupNotFound);
+        }
+
+        sqlx::query!(
+            r#"
+            DELETE FROM group_members
+            WHERE group_id = $1
+            "#,
+            group.id
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(to_store_error)?;
+
+        insert_members(&mut transaction, group).await?;
+
+        commit(transaction).await
+    }
+
+    #[tracing::instrument(name = "Deleting group from PostgreSQL", skip_all)]
+    async fn delete_group(&self, id: Uuid) -> Result<(), GroupStoreError> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM groups
+            WHERE id = $1
+            "#,
+            id
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(to_store_error)?;
+
+        if result.rows_affected() == 0 {
+            return Err(GroupStoreError::GroupNotFound);
+        }
+
+        Ok(())
+    }
+}
+
+async fn insert_members(
+    transaction: &mut Transaction<'_, Postgres>,
+    group: &Group,
+) -> Result<(), GroupStoreError> {
+    sqlx::query!(
+        r#"
+        INSERT INTO group_members (group_id, user_id)
+        SELECT $1, UNNEST($2::UUID[])
+        "#,
+        group.id,
+        &group.members
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(to_store_error)?;
+
+    Ok(())
+}
+
+async fn begin(pool: &PgPool) -> Result<Transaction<'static, Postgres>, GroupStoreError> {
+    pool.begin()
+        .await
+        .wrap_err("failed to begin transaction")
+        .map_err(GroupStoreError::UnexpectedError)
+}
+
+async fn commit(transaction: Transaction<'_, Postgres>) -> Result<(), GroupStoreError> {
+    transaction
+        .commit()
+        .await
+        .wrap_err("failed to commit transaction")
+        .map_err(GroupStoreError::UnexpectedError)
+}
+
+// Display names are unique, so a unique violation means the name is taken
+fn to_store_error(e: sqlx::Error) -> GroupStoreError {
+    match e.as_database_error() {
+        Some(db_error) if db_error.is_unique_violation() => GroupStoreError::GroupAlreadyExists,
+        _ => GroupStoreError::UnexpectedError(e.into()),
+    }
+}
@@ -0,0 +1,221 @@
+use color_eyre::eyre::Context;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::{
+    data_stores::{KnownDeviceStore, KnownDeviceStoreError},
+    DeviceSighting, Email, LoginDevice, TrustedDevice,
+};
+
+pub struct PostgresKnownDeviceStore {
+    pool: PgPool,
+}
+
+impl PostgresKnownDeviceStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl KnownDeviceStore for PostgresKnownDeviceStore {
+    #[tracing::instrument(name = "Recording login device in PostgreSQL", skip_all)]
+    async fn record_device(
+        &self,
+        email: &Email,
+        device: &LoginDevice,
+    ) -> Result<DeviceSighting, KnownDeviceStoreError> {
+        let fingerprint = device.fingerprint();
+
+        let user = sqlx::query!(
+            r#"
+            SELECT id,
+                   EXISTS(SELECT 1 FROM known_devices WHERE user_id = users.id) AS "has_devices!",
+                   EXISTS(
+                       SELECT 1 FROM known_devices WHERE user_id = users.id AND fingerprint = $2
+                   ) AS "is_known!"
+            FROM users
+            WHERE email = $1
+            "#,
+            email.as_ref().expose_secret(),
+            fingerprint
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .wrap_err("failed to look up known devices")
+        .map_err(KnownDeviceStoreError::UnexpectedError)?
+        .ok_or(KnownDeviceStoreError::UserNotFound)?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO known_devices (user_id, fingerprint, user_agent, ip_prefix)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (user_id, fingerprint) DO UPDATE SET last_seen_at = NOW()
+            "#,
+            user.id,
+            fingerprint,
+            device.user_agent,
+            device.ip_prefix()
+        )
+        .execute(&self.pool)
+        .await
+        .wrap_err("failed to record login device")
+        .map_err(KnownDeviceStoreError::UnexpectedError)?;
+
+        Ok(match (user.is_known, user.has_devices) {
+            (true, _) => DeviceSighting::Known,
+            (false, true) => DeviceSighting::New,
+            (false, false) => DeviceSighting::First,
+        })
+    }
+
+    #[tracing::instrument(name = "Forgetting login devices in PostgreSQL", skip_all)]
+    async fn forget_devices(&self, email: &Email) -> Result<(), KnownDeviceStoreError> {
+        sqlx::query!(
+            r#"
+            DELETE FROM known_devices
+            USING users
+            WHERE known_devices.user_id = users.id AND users.email = $1
+            "#,
+            email.as_ref().expose_secret()
+        )
+        .execute(&self.pool)
+        .await
+        .wrap_err("failed to forget login devices")
+        .map_err(KnownDeviceStoreError::UnexpectedError)?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM trusted_devices
+            USING users
+            WHERE trusted_devices.user_id = users.id AND users.email = $1
+            "#,
+            email.as_ref().expose_secret()
+        )
+        .execute(&self.pool)
+        .await
+        .wrap_err("failed to revoke trusted devices")
+        .map_err(KnownDeviceStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Trusting device in PostgreSQL", skip_all)]
+    async fn trust_device(
+        &self,
+        email: &Email,
+        device: TrustedDevice,
+    ) -> Result<(), KnownDeviceStoreError> {
+        let user_id = sqlx::query_scalar!(
+            "SELECT id FROM users WHERE email = $1",
+            email.as_ref().expose_secret()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .wrap_err("failed to look up user")
+        .map_err(KnownDeviceStoreError::UnexpectedError)?
+        .ok_or(KnownDeviceStoreError::UserNotFound)?;
+
+        // Expired devices are cleared out whenever the user trusts another one
+        sqlx::query!(
+            "DELETE FROM trusted_devices WHERE user_id = $1 AND expires_at <= NOW()",
+            user_id
+        )
+        .execute(&self.pool)
+        .await
+        .wrap_err("failed to prune expired trusted devices")
+        .map_err(KnownDeviceStoreError::UnexpectedError)?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO trusted_devices (id, user_id, user_agent, ip_prefix, created_at, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+            device.id,
+            user_id,
+            device.user_agent,
+            device.ip_prefix,
+            device.created_at,
+            device.expires_at
+        )
+        .execute(&self.pool)
+        .await
+        .wrap_err("failed to insert trusted device")
+        .map_err(KnownDeviceStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Retrieving trusted device from PostgreSQL", skip_all)]
+    async fn get_trusted_device(
+        &self,
+        email: &Email,
+        id: Uuid,
+    ) -> Result<TrustedDevice, KnownDeviceStoreError> {
+        sqlx::query_as!(
+            TrustedDevice,
+            r#"
+            SELECT trusted_devices.id, user_agent, ip_prefix, trusted_devices.created_at, expires_at
+            FROM trusted_devices
+            JOIN users ON users.id = trusted_devices.user_id
+            WHERE users.email = $1 AND trusted_devices.id = $2 AND expires_at > NOW()
+            "#,
+            email.as_ref().expose_secret(),
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .wrap_err("failed to get trusted device")
+        .map_err(KnownDeviceStoreError::UnexpectedError)?
+        .ok_or(KnownDeviceStoreError::TrustedDeviceNotFound)
+    }
+
+    #[tracing::instrument(name = "Listing trusted devices from PostgreSQL", skip_all)]
+    async fn list_trusted_devices(
+        &self,
+        email: &Email,
+    ) -> Result<Vec<TrustedDevice>, KnownDeviceStoreError> {
+        sqlx::query_as!(
+            TrustedDevice,
+            r#"
+            SELECT trusted_devices.id, user_agent, ip_prefix, trusted_devices.created_at, expires_at
+            FROM trusted_devices
+            JOIN users ON users.id = trusted_devices.user_id
+            WHERE users.email = $1 AND expires_at > NOW()
+            ORDER BY trusted_devices.created_at DESC
+            "#,
+            email.as_ref().expose_secret()
+        )
+        .fetch_all(&self.pool)
+        .await
+        .wrap_err("failed to list trusted devices")
+        .map_err(KnownDeviceStoreError::UnexpectedError)
+    }
+
+    #[tracing::instrument(name = "Revoking trusted device in PostgreSQL", skip_all)]
+    async fn revoke_trusted_device(
+        &self,
+        email: &Email,
+        id: Uuid,
+    ) -> Result<(), KnownDeviceStoreError> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM trusted_devices
+            USING users
+            WHERE trusted_devices.user_id = users.id AND users.email = $1 AND trusted_devices.id = $2
+            "#,
+            email.as_ref().expose_secret(),
+            id
+        )
+        .execute(&self.pool)
+        .await
+        .wrap_err("failed to revoke trusted device")
+        .map_err(KnownDeviceStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(KnownDeviceStoreError::TrustedDeviceNotFound);
+        }
+        Ok(())
+    }
+}
@@ -2,57 +2,132 @@
 use color_eyre::eyre::{eyre, Context, Result};
 use secrecy::{ExposeSecret, Secret}; // New!
 
-use argon2::{
-    password_hash::SaltString, Algorithm, Argon2, Params, PasswordHash, PasswordHasher,
-    PasswordVerifier, Version,
-};
-
-use sqlx::PgPool;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
 
 use crate::domain::{
     data_stores::{UserStore, UserStoreError},
-    Email, Password, User,
+    Email, Password, PhoneNumber, TwoFAChannel, User, UserQuery,
 };
+use crate::services::password_hasher::{HashedPassword, PasswordHasher};
+
 pub struct PostgresUserStore {
     pool: PgPool,
+    password_hasher: PasswordHasher,
 }
 
 impl PostgresUserStore {
-    pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+    pub fn new(pool: PgPool, password_hasher: PasswordHasher) -> Self {
+        Self {
+            pool,
+            password_hasher,
+        }
     }
-}
 
-#[async_trait::async_trait]
-impl UserStore for PostgresUserStore {
-
-    #[tracing::instrument(name = "Adding user to PostgreSQL", skip_all)]
-    async fn add_user(&mut self, user: User) -> Result<(), UserStoreError> {
-        let password_hash = compute_password_hash(user.password.as_ref().to_owned())
+    // Replaces an outdated hash now that the password is known. Failing to do so doesn't fail
+    // the login, the hash is simply upgraded next time.
+    #[tracing::instrument(name = "Upgrading password hash in PostgreSQL", skip_all)]
+    async fn upgrade_password_hash(
+        &self,
+        email: &Email,
+        old_password_hash: &HashedPassword,
+        password: &Password,
+    ) {
+        let password_hash = match self
+            .password_hasher
+            .hash(password.as_ref().to_owned())
             .await
-            .map_err(UserStoreError::UnexpectedError)?; // Updated!
+        {
+            Ok(password_hash) => password_hash,
+            Err(e) => {
+                tracing::warn!(error = ?e, "Failed to compute upgraded password hash");
+                return;
+            }
+        };
 
+        // Only replace the hash that was verified, in case the password changed meanwhile
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET password_hash = $3, password_pepper_version = $4
+            WHERE email = $1 AND password_hash = $2
+            "#,
+            email.as_ref().expose_secret(),
+            old_password_hash.hash.expose_secret(),
+            password_hash.hash.expose_secret(),
+            password_hash.pepper_version
+        )
+        .execute(&self.pool)
+        .await;
+
+        if let Err(e) = result {
+            tracing::warn!(error = ?e, "Failed to store upgraded password hash");
+        }
+    }
+
+    async fn insert_user(
+        &self,
+        user: &User,
+        password_hash: &HashedPassword,
+    ) -> Result<(), UserStoreError> {
         sqlx::query!(
             r#"
-            INSERT INTO users (email, password_hash, requires_2fa)
-            VALUES ($1, $2, $3)
+            INSERT INTO users (id, email, password_hash, password_pepper_version, requires_2fa, active, external_id, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
             "#,
+            user.id,
             user.email.as_ref().expose_secret(),
-            &password_hash.expose_secret(),
-            user.requires_2fa
+            password_hash.hash.expose_secret(),
+            password_hash.pepper_version,
+            user.requires_2fa,
+            user.active,
+            user.external_id,
+            user.created_at,
+            user.updated_at
         )
         .execute(&self.pool)
         .await
-        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?; // Updated!
+        .map_err(|e| match e.as_database_error() {
+            Some(db_error) if db_error.is_unique_violation() => UserStoreError::UserAlreadyExists,
+            _ => UserStoreError::UnexpectedError(e.into()),
+        })?;
 
         Ok(())
     }
+}
+
+#[async_trait::async_trait]
+impl UserStore for PostgresUserStore {
+
+    #[tracing::instrument(name = "Adding user to PostgreSQL", skip_all)]
+    async fn add_user(&self, user: User) -> Result<(), UserStoreError> {
+        let password_hash = self
+            .password_hasher
+            .hash(user.password.as_ref().to_owned())
+            .await
+            .map_err(UserStoreError::UnexpectedError)?; // Updated!
+
+        self.insert_user(&user, &password_hash).await
+    }
+
+    #[tracing::instrument(name = "Importing user into PostgreSQL", skip_all)]
+    async fn import_user(
+        &self,
+        user: User,
+        password_hash: Secret<String>,
+    ) -> Result<(), UserStoreError> {
+        self.insert_user(&user, &HashedPassword::unpeppered(password_hash))
+            .await
+    }
 
     #[tracing::instrument(name = "Retrieving user from PostgreSQL", skip_all)]
     async fn get_user(&self, email: &Email) -> Result<User, UserStoreError> {
-        sqlx::query!(
+        sqlx::query_as!(
+            UserRow,
             r#"
-            SELECT email, password_hash, requires_2fa
+            SELECT id, email, password_hash, requires_2fa, phone_number, phone_number_verified,
+                   two_fa_channel, active, external_id, created_at, updated_at
             FROM users
             WHERE email = $1
             "#,
@@ -61,71 +136,452 @@ impl UserStore for PostgresUserStore {
         .fetch_optional(&self.pool)
         .await
         .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
-        .map(|row| {
-            Ok(User {
-                email: Email::parse(Secret::new(row.email))
-                    .map_err(|e| UserStoreError::UnexpectedError(eyre!(e)))?, // Updated!
-                password: Password::parse(Secret::new(row.password_hash)) // Updated!
-                    .map_err(UserStoreError::UnexpectedError)?, // Updated!
-                requires_2fa: row.requires_2fa,
-            })
-        })
         .ok_or(UserStoreError::UserNotFound)?
+        .try_into()
     }
 
     #[tracing::instrument(name = "Validating user credentials in PostgreSQL", skip_all)] // New!
     async fn validate_user(&self, email: &Email, password: &Password) -> Result<(), UserStoreError> {
-        let user = self.get_user(email).await?;
+        let password_hash = sqlx::query!(
+            r#"
+            SELECT password_hash, password_pepper_version
+            FROM users
+            WHERE email = $1
+            "#,
+            email.as_ref().expose_secret()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
+        .map(|row| HashedPassword {
+            hash: Secret::new(row.password_hash),
+            pepper_version: row.password_pepper_version,
+        })
+        .ok_or(UserStoreError::UserNotFound)?;
+
+        let verification = self
+            .password_hasher
+            .verify(password_hash.clone(), password.as_ref().to_owned())
+            .await
+            .map_err(|_| UserStoreError::InvalidCredentials)?;
+
+        if verification.needs_rehash {
+            self.upgrade_password_hash(email, &password_hash, password)
+                .await;
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Updating password in PostgreSQL", skip_all)]
+    async fn update_password(
+        &self,
+        email: &Email,
+        password: Password,
+        history_size: usize,
+    ) -> Result<(), UserStoreError> {
+        // Hashing is slow, so it's done before the transaction rather than while holding one of
+        // the pool's few connections. A concurrent change in the meantime is caught by swapping
+        // in the new hash only if the current one is still the one checked against.
+        for _ in 0..MAX_PASSWORD_UPDATE_ATTEMPTS {
+            let current = sqlx::query!(
+                r#"
+                SELECT id, password_hash, password_pepper_version
+                FROM users
+                WHERE email = $1
+                "#,
+                email.as_ref().expose_secret()
+            )
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
+            .ok_or(UserStoreError::UserNotFound)?;
+
+            let previous = sqlx::query!(
+                r#"
+                SELECT password_hash, password_pepper_version
+                FROM password_history
+                WHERE user_id = $1
+                ORDER BY id DESC
+                LIMIT $2
+                "#,
+                current.id,
+                history_size.saturating_sub(1) as i64
+            )
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+            let current_hash = HashedPassword {
+                hash: Secret::new(current.password_hash),
+                pepper_version: current.password_pepper_version,
+            };
+            let recent_hashes = std::iter::once(current_hash.clone())
+                .chain(previous.into_iter().map(|row| HashedPassword {
+                    hash: Secret::new(row.password_hash),
+                    pepper_version: row.password_pepper_version,
+                }))
+                .take(history_size);
+            for recent_hash in recent_hashes {
+                let verification = self
+                    .password_hasher
+                    .verify(recent_hash, password.as_ref().to_owned())
+                    .await;
+                if verification.is_ok() {
+                    return Err(UserStoreError::PasswordReused);
+                }
+            }
+
+            let password_hash = self
+                .password_hasher
+                .hash(password.as_ref().to_owned())
+                .await
+                .map_err(UserStoreError::UnexpectedError)?;
+
+            let mut transaction = begin(&self.pool).await?;
+
+            let result = sqlx::query!(
+                r#"
+                UPDATE users
+                SET password_hash = $2, password_pepper_version = $3, updated_at = NOW()
+                WHERE id = $1 AND password_hash = $4
+                "#,
+                current.id,
+                password_hash.hash.expose_secret(),
+                password_hash.pepper_version,
+                current_hash.hash.expose_secret()
+            )
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+            // The password changed since it was checked against, so check the new history
+            if result.rows_affected() == 0 {
+                continue;
+            }
+
+            sqlx::query!(
+                r#"
+                INSERT INTO password_history (user_id, password_hash, password_pepper_version)
+                VALUES ($1, $2, $3)
+                "#,
+                current.id,
+                current_hash.hash.expose_secret(),
+                current_hash.pepper_version
+            )
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
 
-        verify_password_hash(
-            user.password.as_ref().to_owned(),
-            password.as_ref().to_owned(),
+            // The current password counts towards the history size
+            sqlx::query!(
+                r#"
+                DELETE FROM password_history
+                WHERE user_id = $1
+                  AND id NOT IN (
+                    SELECT id FROM password_history
+                    WHERE user_id = $1
+                    ORDER BY id DESC
+                    LIMIT $2
+                  )
+                "#,
+                current.id,
+                history_size.saturating_sub(1) as i64
+            )
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+            return commit(transaction).await;
+        }
+
+        Err(UserStoreError::UnexpectedError(eyre!(
+            "password kept changing while it was being updated"
+        )))
+    }
+
+    #[tracing::instrument(name = "Setting phone number in PostgreSQL", skip_all)]
+    async fn set_phone_number(
+        &self,
+        email: &Email,
+        phone_number: PhoneNumber,
+    ) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET phone_number = $2, phone_number_verified = FALSE, two_fa_channel = $3
+            WHERE email = $1
+            "#,
+            email.as_ref().expose_secret(),
+            phone_number.as_ref().expose_secret(),
+            TwoFAChannel::Email.as_str()
         )
+        .execute(&self.pool)
         .await
-        .map_err(|_| UserStoreError::InvalidCredentials)
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
     }
-}
 
-#[tracing::instrument(name = "Verify password hash", skip_all)] // New!
-pub async fn verify_password_hash(
-    expected_password_hash: Secret<String>,
-    password_candidate: Secret<String>,
-) -> Result<()> {
-    let current_span: tracing::Span = tracing::Span::current();
-    let result = tokio::task::spawn_blocking(move || {
-        current_span.in_scope(|| {
-            let expected_password_hash: PasswordHash<'_> =
-                PasswordHash::new(&expected_password_hash.expose_secret())?;
-
-            Argon2::default()
-                .verify_password(password_candidate.expose_secret().as_bytes(), &expected_password_hash)
-                .wrap_err("failed to verify password hash")
-        })
-    })
-    .await;
+    #[tracing::instrument(name = "Verifying phone number in PostgreSQL", skip_all)]
+    async fn verify_phone_number(
+        &self,
+        email: &Email,
+        phone_number: &PhoneNumber,
+    ) -> Result<(), UserStoreError> {
+        // Only the number the code was sent to may be verified, even if it was replaced since
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET phone_number_verified = TRUE
+            WHERE email = $1 AND phone_number = $2
+            "#,
+            email.as_ref().expose_secret(),
+            phone_number.as_ref().expose_secret()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            // Tell a missing user apart from one whose number changed
+            self.get_user(email).await?;
+            return Err(UserStoreError::PhoneNumberChanged);
+        }
 
-    result?
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Setting 2FA channel in PostgreSQL", skip_all)]
+    async fn set_two_fa_channel(
+        &self,
+        email: &Email,
+        channel: TwoFAChannel,
+    ) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET two_fa_channel = $2
+            WHERE email = $1
+            "#,
+            email.as_ref().expose_secret(),
+            channel.as_str()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Retrieving user by ID from PostgreSQL", skip_all)]
+    async fn get_user_by_id(&self, id: Uuid) -> Result<User, UserStoreError> {
+        sqlx::query_as!(
+            UserRow,
+            r#"
+            SELECT id, email, password_hash, requires_2fa, phone_number, phone_number_verified,
+                   two_fa_channel, active, external_id, created_at, updated_at
+            FROM users
+            WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
+        .ok_or(UserStoreError::UserNotFound)?
+        .try_into()
+    }
+
+    #[tracing::instrument(name = "Listing users from PostgreSQL", skip_all)]
+    async fn list_users(
+        &self,
+        query: &UserQuery,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<User>, usize), UserStoreError> {
+        let email = query
+            .email
+            .as_ref()
+            .map(|email| email.as_ref().expose_secret().as_str());
+
+        let total = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "count!"
+            FROM users
+            WHERE ($1::TEXT IS NULL OR email = $1)
+              AND ($2::TEXT IS NULL OR external_id = $2)
+              AND ($3::BOOLEAN IS NULL OR active = $3)
+            "#,
+            email,
+            query.external_id,
+            query.active
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        let users = sqlx::query_as!(
+            UserRow,
+            r#"
+            SELECT id, email, password_hash, requires_2fa, phone_number, phone_number_verified,
+                   two_fa_channel, active, external_id, created_at, updated_at
+            FROM users
+            WHERE ($1::TEXT IS NULL OR email = $1)
+              AND ($2::TEXT IS NULL OR external_id = $2)
+              AND ($3::BOOLEAN IS NULL OR active = $3)
+            ORDER BY created_at, id
+            OFFSET $4
+            LIMIT $5
+            "#,
+            email,
+            query.external_id,
+            query.active,
+            offset as i64,
+            limit as i64
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?
+        .into_iter()
+        .map(User::try_from)
+        .collect::<Result<_, _>>()?;
+
+        Ok((users, total as usize))
+    }
+
+    #[tracing::instrument(name = "Setting user active flag in PostgreSQL", skip_all)]
+    async fn set_active(&self, email: &Email, active: bool) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET active = $2, updated_at = NOW()
+            WHERE email = $1
+            "#,
+            email.as_ref().expose_secret(),
+            active
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Setting external ID in PostgreSQL", skip_all)]
+    async fn set_external_id(
+        &self,
+        email: &Email,
+        external_id: Option<String>,
+    ) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE users
+            SET external_id = $2, updated_at = NOW()
+            WHERE email = $1
+            "#,
+            email.as_ref().expose_secret(),
+            external_id
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Deleting user from PostgreSQL", skip_all)]
+    async fn delete_user(&self, email: &Email) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM users
+            WHERE email = $1
+            "#,
+            email.as_ref().expose_secret()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| UserStoreError::UnexpectedError(e.into()))?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
 }
 
-#[tracing::instrument(name = "Computing password hash", skip_all)] 
-pub async fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>> {
-    let current_span: tracing::Span = tracing::Span::current();
-
-    let result = tokio::task::spawn_blocking(move || {
-        current_span.in_scope(|| {
-            let salt: SaltString = SaltString::generate(&mut rand::thread_rng());
-            let password_hash = Argon2::new(
-                Algorithm::Argon2id,
-                Version::V0x13,
-                Params::new(15000, 2, 1, None)?,
-            )
-            .hash_password(password.expose_secret().as_bytes(), &salt)?
-            .to_string();
+struct UserRow {
+    id: Uuid,
+    email: String,
+    password_hash: String,
+    requires_2fa: bool,
+    phone_number: Option<String>,
+    phone_number_verified: bool,
+    two_fa_channel: String,
+    active: bool,
+    external_id: Option<String>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl TryFrom<UserRow> for User {
+    type Error = UserStoreError;
 
-            Ok(Secret::new(password_hash)) // Updated!
+    fn try_from(row: UserRow) -> Result<Self, Self::Error> {
+        Ok(User {
+            id: row.id,
+            email: Email::parse(Secret::new(row.email))
+                .map_err(|e| UserStoreError::UnexpectedError(eyre!(e)))?,
+            password: Password::parse(Secret::new(row.password_hash))
+                .map_err(UserStoreError::UnexpectedError)?,
+            requires_2fa: row.requires_2fa,
+            phone_number: row
+                .phone_number
+                .map(|phone_number| PhoneNumber::parse(Secret::new(phone_number)))
+                .transpose()
+                .map_err(UserStoreError::UnexpectedError)?,
+            phone_number_verified: row.phone_number_verified,
+            two_fa_channel: TwoFAChannel::parse(&row.two_fa_channel)
+                .map_err(UserStoreError::UnexpectedError)?,
+            active: row.active,
+            external_id: row.external_id,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
         })
-    })
-    .await;
+    }
+}
+
+async fn begin(pool: &PgPool) -> Result<Transaction<'static, Postgres>, UserStoreError> {
+    pool.begin()
+        .await
+        .wrap_err("failed to begin transaction")
+        .map_err(UserStoreError::UnexpectedError)
+}
+
+async fn commit(transaction: Transaction<'_, Postgres>) -> Result<(), UserStoreError> {
+    transaction
+        .commit()
+        .await
+        .wrap_err("failed to commit transaction")
+        .map_err(UserStoreError::UnexpectedError)
+}
 
-    result?
-}
\ No newline at end of file
+// Changes to a user's password rarely overlap, so a few attempts are plenty
+const MAX_PASSWORD_UPDATE_ATTEMPTS: usize = 3;
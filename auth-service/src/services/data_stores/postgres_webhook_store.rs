@@ -0,0 +1,377 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{Context, Result};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::{
+    data_stores::{WebhookStore, WebhookStoreError},
+    DueWebhookDelivery, WebhookDelivery, WebhookDeliveryAttempt, WebhookDeliveryStatus,
+    WebhookEvent, WebhookEventType, WebhookSubscription,
+};
+
+pub struct PostgresWebhookStore {
+    pool: PgPool,
+}
+
+impl PostgresWebhookStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    async fn get_attempts(
+        &self,
+        delivery_ids: &[Uuid],
+    ) -> Result<HashMap<Uuid, Vec<WebhookDeliveryAttempt>>> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT delivery_id, attempted_at, response_status, error
+            FROM webhook_delivery_attempts
+            WHERE delivery_id = ANY($1)
+            ORDER BY id
+            "#,
+            delivery_ids
+        )
+        .fetch_all(&self.pool)
+        .await
+        .wrap_err("failed to fetch webhook delivery attempts")?;
+
+        let mut attempts: HashMap<Uuid, Vec<WebhookDeliveryAttempt>> = HashMap::new();
+        for row in rows {
+            attempts
+                .entry(row.delivery_id)
+                .or_default()
+                .push(WebhookDeliveryAttempt {
+                    attempted_at: row.attempted_at,
+                    response_status: row.response_status.map(|status| status as u16),
+                    error: row.error,
+                });
+        }
+
+        Ok(attempts)
+    }
+}
+
+#[async_trait::async_trait]
+impl WebhookStore for PostgresWebhookStore {
+    #[tracing::instrument(name = "Adding webhook subscription to PostgreSQL", skip_all)]
+    async fn add_subscription(
+        &self,
+        subscription: WebhookSubscription,
+    ) -> Result<(), WebhookStoreError> {
+        let event_types: Vec<String> = subscription
+            .event_types
+            .iter()
+            .map(|event_type| event_type.as_str().to_owned())
+            .collect();
+
+        sqlx::query!(
+            r#"
+            INSERT INTO webhook_subscriptions (id, url, event_types, secret, created_at)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+            subscription.id,
+            subscription.url.as_str(),
+            &event_types,
+            subscription.secret.expose_secret(),
+            subscription.created_at
+        )
+        .execute(&self.pool)
+        .await
+        .wrap_err("failed to insert webhook subscription")
+        .map_err(WebhookStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Retrieving webhook subscriptions from PostgreSQL", skip_all)]
+    async fn get_subscriptions(&self) -> Result<Vec<WebhookSubscription>, WebhookStoreError> {
+        sqlx::query!(
+            r#"
+            SELECT id, url, event_types, secret, created_at
+            FROM webhook_subscriptions
+            ORDER BY created_at
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await
+        .wrap_err("failed to fetch webhook subscriptions")
+        .map_err(WebhookStoreError::UnexpectedError)?
+        .into_iter()
+        .map(|row| {
+            to_subscription(
+                row.id,
+                &row.url,
+                &row.event_types,
+                row.secret,
+                row.created_at,
+            )
+        })
+        .collect::<Result<_>>()
+        .map_err(WebhookStoreError::UnexpectedError)
+    }
+
+    #[tracing::instrument(name = "Retrieving webhook subscription from PostgreSQL", skip_all)]
+    async fn get_subscription(&self, id: Uuid) -> Result<WebhookSubscription, WebhookStoreError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT id, url, event_types, secret, created_at
+            FROM webhook_subscriptions
+            WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .wrap_err("failed to fetch webhook subscription")
+        .map_err(WebhookStoreError::UnexpectedError)?
+        .ok_or(WebhookStoreError::SubscriptionNotFound)?;
+
+        to_subscription(
+            row.id,
+            &row.url,
+            &row.event_types,
+            row.secret,
+            row.created_at,
+        )
+        .map_err(WebhookStoreError::UnexpectedError)
+    }
+
+    #[tracing::instrument(name = "Deleting webhook subscription from PostgreSQL", skip_all)]
+    async fn delete_subscription(&self, id: Uuid) -> Result<(), WebhookStoreError> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM webhook_subscriptions
+            WHERE id = $1
+            "#,
+            id
+        )
+        .execute(&self.pool)
+        .await
+        .wrap_err("failed to delete webhook subscription")
+        .map_err(WebhookStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(WebhookStoreError::SubscriptionNotFound);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Enqueueing webhook event in PostgreSQL", skip_all)]
+    async fn enqueue_event(&self, event: &WebhookEvent) -> Result<(), WebhookStoreError> {
+        let payload = serde_json::to_string(event)
+            .wrap_err("failed to serialize webhook event")
+            .map_err(WebhookStoreError::UnexpectedError)?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO webhook_deliveries
+                (id, subscription_id, event_id, event_type, payload, next_attempt_at, created_at)
+            SELECT gen_random_uuid(), id, $1, $2, $3, $4, $4
+            FROM webhook_subscriptions
+            WHERE $2 = ANY(event_types)
+            "#,
+            event.id,
+            event.event_type.as_str(),
+            payload,
+            event.created_at
+        )
+        .execute(&self.pool)
+        .await
+        .wrap_err("failed to enqueue webhook deliveries")
+        .map_err(WebhookStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Claiming due webhook deliveries from PostgreSQL", skip_all)]
+    async fn claim_due_deliveries(
+        &self,
+        now: DateTime<Utc>,
+        lease_until: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<DueWebhookDelivery>, WebhookStoreError> {
+        // SKIP LOCKED lets several dispatchers claim disjoint batches concurrently
+        let rows = sqlx::query!(
+            r#"
+            UPDATE webhook_deliveries AS d
+            SET next_attempt_at = $2
+            FROM webhook_subscriptions AS s
+            WHERE s.id = d.subscription_id
+              AND d.id IN (
+                SELECT id
+                FROM webhook_deliveries
+                WHERE status = 'pending' AND next_attempt_at <= $1
+                ORDER BY next_attempt_at
+                LIMIT $3
+                FOR UPDATE SKIP LOCKED
+              )
+            RETURNING d.id, d.subscription_id, d.event_id, d.event_type, d.payload, d.status,
+                      d.next_attempt_at, d.created_at, s.url, s.secret
+            "#,
+            now,
+            lease_until,
+            limit as i64
+        )
+        .fetch_all(&self.pool)
+        .await
+        .wrap_err("failed to claim due webhook deliveries")
+        .map_err(WebhookStoreError::UnexpectedError)?;
+
+        let delivery_ids: Vec<Uuid> = rows.iter().map(|row| row.id).collect();
+        let mut attempts = self
+            .get_attempts(&delivery_ids)
+            .await
+            .map_err(WebhookStoreError::UnexpectedError)?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(DueWebhookDelivery {
+                    delivery: WebhookDelivery {
+                        id: row.id,
+                        subscription_id: row.subscription_id,
+                        event_id: row.event_id,
+                        event_type: WebhookEventType::parse(&row.event_type)?,
+                        payload: row.payload,
+                        status: WebhookDeliveryStatus::parse(&row.status)?,
+                        next_attempt_at: row.next_attempt_at,
+                        created_at: row.created_at,
+                        attempts: attempts.remove(&row.id).unwrap_or_default(),
+                    },
+                    url: WebhookSubscription::parse_url(&row.url)?,
+                    secret: Secret::new(row.secret),
+                })
+            })
+            .collect::<Result<_>>()
+            .map_err(WebhookStoreError::UnexpectedError)
+    }
+
+    #[tracing::instrument(name = "Recording webhook delivery attempt in PostgreSQL", skip_all)]
+    async fn record_attempt(
+        &self,
+        delivery_id: Uuid,
+        attempt: WebhookDeliveryAttempt,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<(), WebhookStoreError> {
+        let (status, next_attempt_at) = match (attempt.succeeded(), retry_at) {
+            (true, _) => (WebhookDeliveryStatus::Succeeded, None),
+            (false, Some(retry_at)) => (WebhookDeliveryStatus::Pending, Some(retry_at)),
+            (false, None) => (WebhookDeliveryStatus::Failed, None),
+        };
+
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .wrap_err("failed to begin transaction")
+            .map_err(WebhookStoreError::UnexpectedError)?;
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE webhook_deliveries
+            SET status = $2, next_attempt_at = $3, attempts = attempts + 1
+            WHERE id = $1
+            "#,
+            delivery_id,
+            status.as_str(),
+            next_attempt_at
+        )
+        .execute(&mut *transaction)
+        .await
+        .wrap_err("failed to update webhook delivery")
+        .map_err(WebhookStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(WebhookStoreError::DeliveryNotFound);
+        }
+
+        sqlx::query!(
+            r#"
+            INSERT INTO webhook_delivery_attempts (delivery_id, attempted_at, response_status, error)
+            VALUES ($1, $2, $3, $4)
+            "#,
+            delivery_id,
+            attempt.attempted_at,
+            attempt.response_status.map(|status| status as i16),
+            attempt.error
+        )
+        .execute(&mut *transaction)
+        .await
+        .wrap_err("failed to insert webhook delivery attempt")
+        .map_err(WebhookStoreError::UnexpectedError)?;
+
+        transaction
+            .commit()
+            .await
+            .wrap_err("failed to commit transaction")
+            .map_err(WebhookStoreError::UnexpectedError)
+    }
+
+    #[tracing::instrument(name = "Retrieving webhook deliveries from PostgreSQL", skip_all)]
+    async fn get_deliveries(
+        &self,
+        subscription_id: Uuid,
+        limit: usize,
+    ) -> Result<Vec<WebhookDelivery>, WebhookStoreError> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, subscription_id, event_id, event_type, payload, status, next_attempt_at, created_at
+            FROM webhook_deliveries
+            WHERE subscription_id = $1
+            ORDER BY created_at DESC, id
+            LIMIT $2
+            "#,
+            subscription_id,
+            limit as i64
+        )
+        .fetch_all(&self.pool)
+        .await
+        .wrap_err("failed to fetch webhook deliveries")
+        .map_err(WebhookStoreError::UnexpectedError)?;
+
+        let delivery_ids: Vec<Uuid> = rows.iter().map(|row| row.id).collect();
+        let mut attempts = self
+            .get_attempts(&delivery_ids)
+            .await
+            .map_err(WebhookStoreError::UnexpectedError)?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(WebhookDelivery {
+                    id: row.id,
+                    subscription_id: row.subscription_id,
+                    event_id: row.event_id,
+                    event_type: WebhookEventType::parse(&row.event_type)?,
+                    payload: row.payload,
+                    status: WebhookDeliveryStatus::parse(&row.status)?,
+                    next_attempt_at: row.next_attempt_at,
+                    created_at: row.created_at,
+                    attempts: attempts.remove(&row.id).unwrap_or_default(),
+                })
+            })
+            .collect::<Result<_>>()
+            .map_err(WebhookStoreError::UnexpectedError)
+    }
+}
+
+fn to_subscription(
+    id: Uuid,
+    url: &str,
+    event_types: &[String],
+    secret: String,
+    created_at: DateTime<Utc>,
+) -> Result<WebhookSubscription> {
+    Ok(WebhookSubscription {
+        id,
+        url: WebhookSubscription::parse_url(url)?,
+        event_types: event_types
+            .iter()
+            .map(|event_type| WebhookEventType::parse(event_type))
+            .collect::<Result<_>>()?,
+        secret: Secret::new(secret),
+        created_at,
+    })
+}
@@ -1,21 +1,22 @@
-use std::sync::Arc;
-
 use color_eyre::eyre::Context;
-use redis::{Commands, Connection};
+use redis::{aio::ConnectionManager, AsyncCommands};
 use secrecy::{ExposeSecret, Secret};
-use tokio::sync::RwLock;
 
 use crate::{
-    domain::data_stores::{BannedTokenStore, BannedTokenStoreError},
-    utils::auth::TOKEN_TTL_SECONDS,
+    domain::{
+        data_stores::{BannedTokenStore, BannedTokenStoreError},
+        Email,
+    },
+    utils::auth::TOKEN_REVOCATION_TTL_SECONDS,
 };
 
 pub struct RedisBannedTokenStore {
-    conn: Arc<RwLock<Connection>>,
+    // Commands are multiplexed over the connection, so a clone is all it takes to send one
+    conn: ConnectionManager,
 }
 
 impl RedisBannedTokenStore {
-    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+    pub fn new(conn: ConnectionManager) -> Self {
         Self { conn }
     }
 }
@@ -24,21 +25,20 @@ impl RedisBannedTokenStore {
 impl BannedTokenStore for RedisBannedTokenStore {
 
     #[tracing::instrument(name = "Add Token", skip_all)]
-    async fn add_token(&mut self, token: Secret<String>) -> Result<(), BannedTokenStoreError> {
+    async fn add_token(
+        &self,
+        token: Secret<String>,
+        ttl_seconds: u64,
+    ) -> Result<(), BannedTokenStoreError> {
         let token_key = get_key(token.expose_secret());
 
         let value = true;
 
-        let ttl: u64 = TOKEN_TTL_SECONDS
-            .try_into()
-            .wrap_err("failed to cast TOKEN_TTL_SECONDS to u64") // New!
-            .map_err(BannedTokenStoreError::UnexpectedError)?; // Updated!
-
         let _: () = self
             .conn
-            .write()
+            .clone()
+            .set_ex(&token_key, value, ttl_seconds)
             .await
-            .set_ex(&token_key, value, ttl)
             .wrap_err("failed to set banned token in Redis") // New!
             .map_err(BannedTokenStoreError::UnexpectedError)?; // Updated!
 
@@ -51,19 +51,63 @@ impl BannedTokenStore for RedisBannedTokenStore {
 
         let is_banned: bool = self
             .conn
-            .write()
-            .await
+            .clone()
             .exists(&token_key)
+            .await
             .wrap_err("failed to check if token exists in Redis") // New!
             .map_err(BannedTokenStoreError::UnexpectedError)?; // Updated!
 
         Ok(is_banned)
     }
+
+    #[tracing::instrument(name = "Revoke Tokens", skip_all)]
+    async fn revoke_tokens_issued_before(
+        &self,
+        email: &Email,
+        timestamp: i64,
+    ) -> Result<(), BannedTokenStoreError> {
+        let key = get_revocation_key(email.as_ref().expose_secret());
+
+        let ttl: u64 = TOKEN_REVOCATION_TTL_SECONDS
+            .try_into()
+            .wrap_err("failed to cast TOKEN_REVOCATION_TTL_SECONDS to u64")
+            .map_err(BannedTokenStoreError::UnexpectedError)?;
+
+        let _: () = self
+            .conn
+            .clone()
+            .set_ex(&key, timestamp, ttl)
+            .await
+            .wrap_err("failed to set token revocation in Redis")
+            .map_err(BannedTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(name = "Tokens Revoked Before", skip_all)]
+    async fn tokens_revoked_before(
+        &self,
+        email: &Email,
+    ) -> Result<Option<i64>, BannedTokenStoreError> {
+        let key = get_revocation_key(email.as_ref().expose_secret());
+
+        self.conn
+            .clone()
+            .get(&key)
+            .await
+            .wrap_err("failed to get token revocation from Redis")
+            .map_err(BannedTokenStoreError::UnexpectedError)
+    }
 }
 
 // We are using a key prefix to prevent collisions and organize data!
 const BANNED_TOKEN_KEY_PREFIX: &str = "banned_token:";
+const TOKEN_REVOCATION_KEY_PREFIX: &str = "tokens_revoked_before:";
 
 fn get_key(token: &str) -> String {
     format!("{}{}", BANNED_TOKEN_KEY_PREFIX, token)
-}
\ No newline at end of file
+}
+
+fn get_revocation_key(email: &str) -> String {
+    format!("{}{}", TOKEN_REVOCATION_KEY_PREFIX, email)
+}
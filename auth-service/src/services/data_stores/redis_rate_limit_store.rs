@@ -0,0 +1,71 @@
+use color_eyre::eyre::Context;
+use lazy_static::lazy_static;
+use redis::{aio::ConnectionManager, Script};
+
+use crate::domain::{
+    data_stores::{RateLimitStore, RateLimitStoreError},
+    RateLimitDecision, RateLimitPolicy,
+};
+
+/// Keeps buckets in Redis so that every instance shares the same limits
+pub struct RedisRateLimitStore {
+    conn: ConnectionManager,
+}
+
+impl RedisRateLimitStore {
+    pub fn new(conn: ConnectionManager) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl RateLimitStore for RedisRateLimitStore {
+    #[tracing::instrument(name = "Take Rate Limit Token", skip_all)]
+    async fn take_token(
+        &self,
+        key: &str,
+        policy: &RateLimitPolicy,
+    ) -> Result<RateLimitDecision, RateLimitStoreError> {
+        let mut conn = self.conn.clone();
+
+        let (tat_ms, now_ms): (i64, i64) = TAKE_TOKEN
+            .key(get_key(key))
+            .arg(policy.interval_ms())
+            .arg(policy.burst_ms())
+            .invoke_async(&mut conn)
+            .await
+            .wrap_err("failed to take rate limit token in Redis")
+            .map_err(RateLimitStoreError::UnexpectedError)?;
+
+        // The script took the token on the same terms, so this only shapes its decision
+        Ok(policy.apply(Some(tat_ms), now_ms).0)
+    }
+}
+
+const RATE_LIMIT_PREFIX: &str = "rate_limit:";
+
+lazy_static! {
+    // Takes a token from the GCRA bucket at KEYS[1], given its policy's interval (ARGV[1]) and
+    // burst (ARGV[2]) in milliseconds, like `RateLimitPolicy::apply`. The time is Redis's, so
+    // instances with drifting clocks still agree. The bucket is full again once its TAT has
+    // passed, so it expires then. Returns the TAT the token was taken against and the time.
+    static ref TAKE_TOKEN: Script = Script::new(
+        r"
+        local time = redis.call('TIME')
+        local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+        local interval = tonumber(ARGV[1])
+        local burst = tonumber(ARGV[2])
+
+        local tat = math.max(tonumber(redis.call('GET', KEYS[1]) or now), now)
+        local new_tat = tat + interval
+        if now >= new_tat - burst then
+            redis.call('SET', KEYS[1], new_tat, 'PX', new_tat - now)
+        end
+        return {tat, now}
+        ",
+    );
+}
+
+fn get_key(key: &str) -> String {
+    format!("{}{}", RATE_LIMIT_PREFIX, key)
+}
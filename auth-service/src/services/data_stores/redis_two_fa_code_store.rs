@@ -1,23 +1,196 @@
-use std::sync::Arc;
-
-use redis::{Commands, Connection};
-use secrecy::ExposeSecret;
+use chrono::Utc;
+use lazy_static::lazy_static;
+use redis::{aio::ConnectionManager, AsyncCommands, Script};
+use secrecy::{ExposeSecret, Secret};
 use serde::{Deserialize, Serialize};
-use tokio::sync::RwLock;
-use color_eyre::eyre::Context;
+use color_eyre::eyre::{eyre, Context};
 
-use crate::domain::{
-    data_stores::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError},
-    Email,
+use crate::{
+    domain::{
+        data_stores::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError},
+        Email, PhoneNumber,
+    },
+    utils::constants::{
+        MAX_2FA_RESENDS, MAX_FAILED_2FA_ATTEMPTS, MAX_PENDING_2FA_ATTEMPTS_PER_USER,
+        TWO_FA_RESEND_COOLDOWN_SECONDS,
+    },
 };
 
 pub struct RedisTwoFACodeStore {
-    conn: Arc<RwLock<Connection>>,
+    conn: ConnectionManager,
+    namespace: &'static str,
 }
 
 impl RedisTwoFACodeStore {
-    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
-        Self { conn }
+    pub fn new(conn: ConnectionManager) -> Self {
+        Self::with_namespace(conn, DEFAULT_NAMESPACE)
+    }
+
+    /// Creates a store whose keys don't collide with those of stores in other namespaces
+    pub fn with_namespace(conn: ConnectionManager, namespace: &'static str) -> Self {
+        Self { conn, namespace }
+    }
+
+    fn get_key(&self, login_attempt_id: &LoginAttemptId) -> String {
+        self.get_key_for_id(login_attempt_id.expose_secret())
+    }
+
+    fn get_key_for_id(&self, login_attempt_id: &str) -> String {
+        format!("{}{}{}", self.namespace, CODE_SUFFIX, login_attempt_id)
+    }
+
+    fn get_failed_attempts_key(&self, login_attempt_id: &LoginAttemptId) -> String {
+        self.get_failed_attempts_key_for_id(login_attempt_id.expose_secret())
+    }
+
+    fn get_failed_attempts_key_for_id(&self, login_attempt_id: &str) -> String {
+        format!(
+            "{}{}{}",
+            self.namespace, FAILED_ATTEMPTS_SUFFIX, login_attempt_id
+        )
+    }
+
+    fn get_user_attempts_key(&self, email: &Email) -> String {
+        format!(
+            "{}{}{}",
+            self.namespace,
+            USER_ATTEMPTS_SUFFIX,
+            email.as_ref().expose_secret()
+        )
+    }
+
+    async fn get_pending_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<PendingTwoFACode, TwoFACodeStoreError> {
+        let value: Option<String> = self
+            .conn
+            .clone()
+            .get(self.get_key(login_attempt_id))
+            .await
+            .wrap_err("failed to get 2FA code from Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+
+        match value {
+            Some(value) => deserialize(&value),
+            None => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
+        }
+    }
+
+    // Returns the number the consumed code was sent to, if any
+    async fn consume_pending_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+        email: &Email,
+        code: &TwoFACode,
+        requires_phone_number: bool,
+    ) -> Result<Option<String>, TwoFACodeStoreError> {
+        let result: Vec<String> = CONSUME_CODE
+            .key(self.get_key(login_attempt_id))
+            .key(self.get_failed_attempts_key(login_attempt_id))
+            .arg(email.as_ref().expose_secret())
+            .arg(code.as_ref().expose_secret())
+            .arg(requires_phone_number)
+            .arg(MAX_FAILED_2FA_ATTEMPTS)
+            .arg(TEN_MINUTES_IN_SECONDS)
+            .invoke_async(&mut self.conn.clone())
+            .await
+            .wrap_err("failed to consume 2FA code in Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+
+        match result.as_slice() {
+            [outcome] if outcome == "consumed" => Ok(None),
+            [outcome, phone_number] if outcome == "consumed" => Ok(Some(phone_number.to_owned())),
+            [outcome] if outcome == "not_found" => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
+            [outcome] if outcome == "incorrect" => Err(TwoFACodeStoreError::IncorrectCode),
+            _ => Err(TwoFACodeStoreError::UnexpectedError(eyre!(
+                "unexpected result from consuming 2FA code in Redis: {:?}",
+                result
+            ))),
+        }
+    }
+
+    async fn add_pending_code(
+        &self,
+        email: Email,
+        phone_number: Option<PhoneNumber>,
+        login_attempt_id: LoginAttemptId,
+        code: TwoFACode,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let key = self.get_key(&login_attempt_id);
+        let user_attempts_key = self.get_user_attempts_key(&email);
+
+        let data = PendingTwoFACode {
+            email: email.as_ref().expose_secret().to_owned(),
+            phone_number: phone_number.map(|number| number.as_ref().expose_secret().to_owned()),
+            code: code.as_ref().expose_secret().to_owned(),
+            resend_count: 0,
+            last_sent_at: Utc::now().timestamp(),
+        };
+        let serialized_data = serialize(&data)?;
+
+        let mut conn = self.conn.clone();
+
+        let _: () = redis::pipe()
+            .atomic()
+            .set_ex(&key, serialized_data, TEN_MINUTES_IN_SECONDS)
+            .ignore()
+            .rpush(&user_attempts_key, login_attempt_id.expose_secret())
+            .ignore()
+            .expire(&user_attempts_key, TEN_MINUTES_IN_SECONDS as i64)
+            .ignore()
+            .query_async(&mut conn)
+            .await
+            .wrap_err("failed to set 2FA code in Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+
+        // Drop IDs whose codes have expired or been used, then evict the oldest
+        // pending attempts if the user is still over the cap
+        let attempt_ids: Vec<String> = conn
+            .lrange(&user_attempts_key, 0, -1)
+            .await
+            .wrap_err("failed to list pending login attempts in Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+
+        let mut pending_attempt_ids = Vec::with_capacity(attempt_ids.len());
+        for attempt_id in attempt_ids {
+            let exists: bool = conn
+                .exists(self.get_key_for_id(&attempt_id))
+                .await
+                .wrap_err("failed to check if 2FA code exists in Redis")
+                .map_err(TwoFACodeStoreError::UnexpectedError)?;
+
+            if exists {
+                pending_attempt_ids.push(attempt_id);
+            } else {
+                let _: () = conn
+                    .lrem(&user_attempts_key, 0, &attempt_id)
+                    .await
+                    .wrap_err("failed to prune pending login attempt in Redis")
+                    .map_err(TwoFACodeStoreError::UnexpectedError)?;
+            }
+        }
+
+        let excess = pending_attempt_ids
+            .len()
+            .saturating_sub(MAX_PENDING_2FA_ATTEMPTS_PER_USER);
+        for attempt_id in &pending_attempt_ids[..excess] {
+            let _: () = redis::pipe()
+                .atomic()
+                .del(&[
+                    self.get_key_for_id(attempt_id),
+                    self.get_failed_attempts_key_for_id(attempt_id),
+                ])
+                .ignore()
+                .lrem(&user_attempts_key, 0, attempt_id)
+                .ignore()
+                .query_async(&mut conn)
+                .await
+                .wrap_err("failed to evict pending login attempt from Redis")
+                .map_err(TwoFACodeStoreError::UnexpectedError)?;
+        }
+
+        Ok(())
     }
 }
 
@@ -26,43 +199,46 @@ impl TwoFACodeStore for RedisTwoFACodeStore {
 
     #[tracing::instrument(name = "Add Code", skip_all)]
     async fn add_code(
-        &mut self,
+        &self,
         email: Email,
         login_attempt_id: LoginAttemptId,
         code: TwoFACode,
     ) -> Result<(), TwoFACodeStoreError> {
-        let key = get_key(&email);
-
-        let data = TwoFATuple(
-            login_attempt_id.expose_secret().to_owned(),
-            code.as_ref().expose_secret().to_owned(),
-        );
-        let serialized_data = serde_json::to_string(&data)
-            .wrap_err("failed to serialize 2FA tuple") // New!
-            .map_err(TwoFACodeStoreError::UnexpectedError)?; // Updated!
-
-        let _: () = self
-            .conn
-            .write()
+        self.add_pending_code(email, None, login_attempt_id, code)
             .await
-            .set_ex(&key, serialized_data, TEN_MINUTES_IN_SECONDS)
-            .wrap_err("failed to set 2FA code in Redis") // New! 
-            .map_err(TwoFACodeStoreError::UnexpectedError)?; // Updated!
+    }
 
-        Ok(())
+    #[tracing::instrument(name = "Add Phone Number Code", skip_all)]
+    async fn add_phone_number_code(
+        &self,
+        email: Email,
+        phone_number: PhoneNumber,
+        login_attempt_id: LoginAttemptId,
+        code: TwoFACode,
+    ) -> Result<(), TwoFACodeStoreError> {
+        self.add_pending_code(email, Some(phone_number), login_attempt_id, code)
+            .await
     }
 
     #[tracing::instrument(name = "Remove Code", skip_all)]
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError> {
-        let key = get_key(email);
-
-        let _: () = self
-            .conn
-            .write()
+    async fn remove_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<(), TwoFACodeStoreError> {
+        // The ID is left in the user's attempt list and pruned on their next login
+        let (removed,): (u32,) = redis::pipe()
+            .atomic()
+            .del(self.get_key(login_attempt_id))
+            .del(self.get_failed_attempts_key(login_attempt_id))
+            .ignore()
+            .query_async(&mut self.conn.clone())
             .await
-            .del(&key)
-            .wrap_err("failed to delete 2FA code from Redis") // New!
-            .map_err(TwoFACodeStoreError::UnexpectedError)?; // Updated!
+            .wrap_err("failed to delete 2FA code from Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+
+        if removed == 0 {
+            return Err(TwoFACodeStoreError::LoginAttemptIdNotFound);
+        }
 
         Ok(())
     }
@@ -70,35 +246,202 @@ impl TwoFACodeStore for RedisTwoFACodeStore {
     #[tracing::instrument(name = "Get Code", skip_all)]
     async fn get_code(
         &self,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<(Email, TwoFACode), TwoFACodeStoreError> {
+        let data = self.get_pending_code(login_attempt_id).await?;
+
+        let email =
+            Email::parse(Secret::new(data.email)).map_err(TwoFACodeStoreError::UnexpectedError)?;
+
+        let email_code =
+            TwoFACode::parse(data.code.into()).map_err(TwoFACodeStoreError::UnexpectedError)?;
+
+        Ok((email, email_code))
+    }
+
+    #[tracing::instrument(name = "Get Phone Number Code", skip_all)]
+    async fn get_phone_number_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+    ) -> Result<(Email, PhoneNumber, TwoFACode), TwoFACodeStoreError> {
+        let data = self.get_pending_code(login_attempt_id).await?;
+
+        let phone_number = data
+            .phone_number
+            .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)?;
+        let phone_number = PhoneNumber::parse(Secret::new(phone_number))
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
+
+        let email =
+            Email::parse(Secret::new(data.email)).map_err(TwoFACodeStoreError::UnexpectedError)?;
+
+        let code =
+            TwoFACode::parse(data.code.into()).map_err(TwoFACodeStoreError::UnexpectedError)?;
+
+        Ok((email, phone_number, code))
+    }
+
+    #[tracing::instrument(name = "Consume Code", skip_all)]
+    async fn consume_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
         email: &Email,
-    ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError> {
-        let key = get_key(email);
+        code: &TwoFACode,
+    ) -> Result<(), TwoFACodeStoreError> {
+        self.consume_pending_code(login_attempt_id, email, code, false)
+            .await
+            .map(|_| ())
+    }
 
-        match self.conn.write().await.get::<_, String>(&key) {
-            Ok(value) => {
-                let data: TwoFATuple = serde_json::from_str(&value)
-                    .wrap_err("failed to deserialize 2FA tuple") // New!
-                    .map_err(TwoFACodeStoreError::UnexpectedError)?; // Updated!
+    #[tracing::instrument(name = "Consume Phone Number Code", skip_all)]
+    async fn consume_phone_number_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+        email: &Email,
+        code: &TwoFACode,
+    ) -> Result<PhoneNumber, TwoFACodeStoreError> {
+        let phone_number = self
+            .consume_pending_code(login_attempt_id, email, code, true)
+            .await?
+            .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)?;
 
-                let login_attempt_id =
-                    LoginAttemptId::parse(data.0.into()).map_err(TwoFACodeStoreError::UnexpectedError)?; // Updated!
+        PhoneNumber::parse(Secret::new(phone_number)).map_err(TwoFACodeStoreError::UnexpectedError)
+    }
 
-                let email_code =
-                    TwoFACode::parse(data.1.into()).map_err(TwoFACodeStoreError::UnexpectedError)?; // Updated!
+    #[tracing::instrument(name = "Resend Code", skip_all)]
+    async fn resend_code(
+        &self,
+        login_attempt_id: &LoginAttemptId,
+        code: TwoFACode,
+    ) -> Result<Email, TwoFACodeStoreError> {
+        // Checked and updated in one step, so concurrent resends can't both pass the checks
+        let result: Vec<String> = RESEND_CODE
+            .key(self.get_key(login_attempt_id))
+            .arg(code.as_ref().expose_secret())
+            .arg(Utc::now().timestamp())
+            .arg(MAX_2FA_RESENDS)
+            .arg(TWO_FA_RESEND_COOLDOWN_SECONDS)
+            .invoke_async(&mut self.conn.clone())
+            .await
+            .wrap_err("failed to resend 2FA code in Redis")
+            .map_err(TwoFACodeStoreError::UnexpectedError)?;
 
-                Ok((login_attempt_id, email_code))
+        match result.as_slice() {
+            [outcome, email] if outcome == "resent" => Email::parse(Secret::new(email.to_owned()))
+                .map_err(TwoFACodeStoreError::UnexpectedError),
+            [outcome] if outcome == "not_found" => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
+            [outcome] if outcome == "too_many_resends" => Err(TwoFACodeStoreError::TooManyResends),
+            [outcome, retry_after_seconds] if outcome == "cooldown" => {
+                Err(TwoFACodeStoreError::ResendCooldown {
+                    retry_after_seconds: retry_after_seconds
+                        .parse()
+                        .wrap_err("invalid resend cooldown from Redis")
+                        .map_err(TwoFACodeStoreError::UnexpectedError)?,
+                })
             }
-            Err(_) => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
+            _ => Err(TwoFACodeStoreError::UnexpectedError(eyre!(
+                "unexpected result from resending 2FA code in Redis: {:?}",
+                result
+            ))),
         }
     }
 }
 
+lazy_static! {
+    // Removes the pending attempt at KEYS[1] and its failure count at KEYS[2] if it belongs to
+    // email ARGV[1] and its code is ARGV[2], returning the number the code was sent to, if any.
+    // With ARGV[3] set, attempts that aren't bound to a number are treated as missing. Otherwise
+    // the failure count is incremented and kept for ARGV[5] seconds, and the attempt is removed
+    // once it reaches ARGV[4]. Every character is compared so the time taken doesn't reveal how
+    // much of the code was right.
+    static ref CONSUME_CODE: Script = Script::new(
+        r"
+        local value = redis.call('GET', KEYS[1])
+        if not value then
+            return {'not_found'}
+        end
+        local data = cjson.decode(value)
+        local phone_number = data.phone_number
+        if phone_number == cjson.null then
+            phone_number = nil
+        end
+        if ARGV[3] == '1' and not phone_number then
+            return {'not_found'}
+        end
+        local function matches(expected, actual)
+            local difference = #expected == #actual and 0 or 1
+            for i = 1, #actual do
+                difference = bit.bor(difference, bit.bxor(expected:byte(i) or 0, actual:byte(i)))
+            end
+            return difference == 0
+        end
+        local email_matches = matches(data.email, ARGV[1])
+        local code_matches = matches(data.code, ARGV[2])
+        if email_matches and code_matches then
+            redis.call('DEL', KEYS[1], KEYS[2])
+            return {'consumed', phone_number}
+        end
+        local failed_attempts = redis.call('INCR', KEYS[2])
+        redis.call('EXPIRE', KEYS[2], ARGV[5])
+        if failed_attempts >= tonumber(ARGV[4]) then
+            redis.call('DEL', KEYS[1], KEYS[2])
+        end
+        return {'incorrect'}
+        ",
+    );
+
+    // Replaces the code of the pending attempt at KEYS[1] with ARGV[1] and records it as sent at
+    // ARGV[2] (Unix seconds), unless it was resent ARGV[3] times already or last sent less than
+    // ARGV[4] seconds ago. KEEPTTL so that resending never extends the attempt's original expiry.
+    static ref RESEND_CODE: Script = Script::new(
+        r"
+        local value = redis.call('GET', KEYS[1])
+        if not value then
+            return {'not_found'}
+        end
+        local data = cjson.decode(value)
+        if data.resend_count >= tonumber(ARGV[3]) then
+            return {'too_many_resends'}
+        end
+        local elapsed = math.max(tonumber(ARGV[2]) - data.last_sent_at, 0)
+        if elapsed < tonumber(ARGV[4]) then
+            return {'cooldown', tostring(tonumber(ARGV[4]) - elapsed)}
+        end
+        data.code = ARGV[1]
+        data.resend_count = data.resend_count + 1
+        data.last_sent_at = tonumber(ARGV[2])
+        redis.call('SET', KEYS[1], cjson.encode(data), 'KEEPTTL')
+        return {'resent', data.email}
+        ",
+    );
+}
+
 #[derive(Serialize, Deserialize)]
-struct TwoFATuple(pub String, pub String);
+struct PendingTwoFACode {
+    email: String,
+    // The number a phone verification code was sent to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    phone_number: Option<String>,
+    code: String,
+    resend_count: u32,
+    // Unix timestamp (seconds) of when the code was last sent
+    last_sent_at: i64,
+}
 
-const TEN_MINUTES_IN_SECONDS: u64 = 600;
-const TWO_FA_CODE_PREFIX: &str = "two_fa_code:";
+fn serialize(data: &PendingTwoFACode) -> Result<String, TwoFACodeStoreError> {
+    serde_json::to_string(data)
+        .wrap_err("failed to serialize pending 2FA code")
+        .map_err(TwoFACodeStoreError::UnexpectedError)
+}
 
-fn get_key(email: &Email) -> String {
-    format!("{}{}", TWO_FA_CODE_PREFIX, email.as_ref().expose_secret())
-}
\ No newline at end of file
+fn deserialize(value: &str) -> Result<PendingTwoFACode, TwoFACodeStoreError> {
+    serde_json::from_str(value)
+        .wrap_err("failed to deserialize pending 2FA code")
+        .map_err(TwoFACodeStoreError::UnexpectedError)
+}
+
+const TEN_MINUTES_IN_SECONDS: u64 = 600;
+const DEFAULT_NAMESPACE: &str = "two_fa";
+const CODE_SUFFIX: &str = "_code:";
+const FAILED_ATTEMPTS_SUFFIX: &str = "_failed_attempts:";
+const USER_ATTEMPTS_SUFFIX: &str = "_login_attempts:";
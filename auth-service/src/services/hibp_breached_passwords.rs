@@ -0,0 +1,298 @@
+use std::{
+    f64::consts::LN_2,
+    fs::File,
+    io::{BufRead, BufReader, Read, Write},
+    path::Path,
+    sync::Arc,
+};
+
+use color_eyre::eyre::{eyre, Context, Result};
+
+use crate::domain::BreachedPasswordList;
+
+/// Identifies a file written by [`BloomFilter::write_to`]
+pub const FILTER_MAGIC: &[u8; 8] = b"HIBPBLM1";
+
+/// Loads a breach corpus from `path`, which is one of
+/// - a Bloom filter built by the `build_breach_filter` tool,
+/// - a Have I Been Pwned text file of `HASH:COUNT` lines, as produced by the official downloader,
+/// - a directory of HIBP range files, each named after its 5 character hash prefix and holding
+///   `SUFFIX:COUNT` lines.
+///
+/// Text corpora are held in memory in full, so the filter is the way to use the whole of HIBP.
+pub fn load(path: &Path) -> Result<Arc<dyn BreachedPasswordList + Send + Sync>> {
+    if path.is_file() {
+        let mut file = File::open(path).wrap_err("failed to open breach corpus")?;
+        let mut magic = [0; FILTER_MAGIC.len()];
+        if file.read_exact(&mut magic).is_ok() && &magic == FILTER_MAGIC {
+            let filter = BloomFilter::read_from(BufReader::new(file))?;
+            return Ok(Arc::new(filter));
+        }
+    }
+
+    let mut hashes = vec![];
+    read_hibp_entries(path, |hash, _| hashes.push(hash))?;
+    Ok(Arc::new(HashList::new(hashes)))
+}
+
+/// Calls `f` with the hash and breach count of every entry in the HIBP file or range file
+/// directory at `path`
+pub fn read_hibp_entries(path: &Path, mut f: impl FnMut([u8; 20], u64)) -> Result<()> {
+    if !path.is_dir() {
+        return read_hibp_file(path, "", &mut f);
+    }
+
+    let entries = path
+        .read_dir()
+        .wrap_err("failed to read breach corpus directory")?;
+    for entry in entries {
+        let entry = entry.wrap_err("failed to read breach corpus directory")?;
+        let file_path = entry.path();
+        let prefix = file_path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .filter(|stem| stem.len() == 5 && stem.chars().all(|c| c.is_ascii_hexdigit()))
+            .ok_or_else(|| eyre!("{} is not named after a hash prefix", file_path.display()))?
+            .to_owned();
+        read_hibp_file(&file_path, &prefix, &mut f)?;
+    }
+
+    Ok(())
+}
+
+fn read_hibp_file(path: &Path, prefix: &str, f: &mut impl FnMut([u8; 20], u64)) -> Result<()> {
+    let file = File::open(path).wrap_err_with(|| format!("failed to open {}", path.display()))?;
+
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.wrap_err_with(|| format!("failed to read {}", path.display()))?;
+        if let Some((hash, count)) = parse_hibp_line(prefix, &line)
+            .wrap_err_with(|| format!("invalid entry at {}:{}", path.display(), number + 1))?
+        {
+            f(hash, count);
+        }
+    }
+
+    Ok(())
+}
+
+// Parses a `HASH:COUNT` line, or `SUFFIX:COUNT` when `prefix` holds the rest of the hash.
+// The count is optional, and blank lines are skipped.
+fn parse_hibp_line(prefix: &str, line: &str) -> Result<Option<([u8; 20], u64)>> {
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(None);
+    }
+
+    let (hash, count) = match line.split_once(':') {
+        Some((hash, count)) => (hash, count.trim().parse().wrap_err("invalid count")?),
+        None => (line, 1),
+    };
+
+    let hex = format!("{}{}", prefix, hash.trim());
+    if hex.len() != 40 || !hex.is_ascii() {
+        return Err(eyre!("expected a 40 character SHA-1 hash"));
+    }
+
+    let mut bytes = [0; 20];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).wrap_err("invalid hash")?;
+    }
+
+    Ok(Some((bytes, count)))
+}
+
+/// An exact list of hashes, for corpora small enough to keep in memory
+pub struct HashList(Vec<[u8; 20]>);
+
+impl HashList {
+    pub fn new(mut hashes: Vec<[u8; 20]>) -> Self {
+        hashes.sort_unstable();
+        hashes.dedup();
+        Self(hashes)
+    }
+}
+
+impl BreachedPasswordList for HashList {
+    fn contains(&self, sha1: &[u8; 20]) -> bool {
+        self.0.binary_search(sha1).is_ok()
+    }
+}
+
+/// A Bloom filter over SHA-1 hashes. As the hashes are already uniformly distributed, the bit
+/// positions are derived from the hash itself rather than by hashing again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    num_bits: u64,
+    num_hashes: u32,
+}
+
+impl BloomFilter {
+    /// A filter sized to hold `expected_items` with the given false positive rate
+    pub fn new(expected_items: usize, false_positive_rate: f64) -> Self {
+        let items = expected_items.max(1) as f64;
+        let num_bits = (-items * false_positive_rate.ln() / (LN_2 * LN_2)).ceil() as u64;
+        let num_bits = num_bits.max(64);
+        let num_hashes = ((num_bits as f64 / items) * LN_2).round().clamp(1.0, 32.0) as u32;
+
+        Self {
+            bits: vec![0; num_bits.div_ceil(64) as usize],
+            num_bits,
+            num_hashes,
+        }
+    }
+
+    pub fn insert(&mut self, sha1: &[u8; 20]) {
+        for index in self.bit_indexes(sha1) {
+            self.bits[(index / 64) as usize] |= 1 << (index % 64);
+        }
+    }
+
+    // Double hashing with the first two 64-bit words of the hash
+    fn bit_indexes(&self, sha1: &[u8; 20]) -> impl Iterator<Item = u64> {
+        let h1 = u64::from_le_bytes(sha1[0..8].try_into().expect("8 bytes"));
+        let h2 = u64::from_le_bytes(sha1[8..16].try_into().expect("8 bytes")) | 1;
+        let num_bits = self.num_bits;
+
+        (0..self.num_hashes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % num_bits)
+    }
+
+    /// Writes the filter, starting with [`FILTER_MAGIC`]
+    pub fn write_to(&self, mut writer: impl Write) -> Result<()> {
+        writer.write_all(FILTER_MAGIC)?;
+        writer.write_all(&self.num_bits.to_le_bytes())?;
+        writer.write_all(&self.num_hashes.to_le_bytes())?;
+        for word in &self.bits {
+            writer.write_all(&word.to_le_bytes())?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads a filter written by [`BloomFilter::write_to`], after its magic
+    pub fn read_from(mut reader: impl Read) -> Result<Self> {
+        let mut num_bits = [0; 8];
+        let mut num_hashes = [0; 4];
+        reader
+            .read_exact(&mut num_bits)
+            .and_then(|_| reader.read_exact(&mut num_hashes))
+            .wrap_err("failed to read filter header")?;
+
+        let num_bits = u64::from_le_bytes(num_bits);
+        let num_hashes = u32::from_le_bytes(num_hashes);
+        if num_bits == 0 || num_hashes == 0 {
+            return Err(eyre!("invalid filter header"));
+        }
+
+        let mut bits = vec![0; num_bits.div_ceil(64) as usize];
+        let mut word = [0; 8];
+        for bits in bits.iter_mut() {
+            reader
+                .read_exact(&mut word)
+                .wrap_err("filter is truncated")?;
+            *bits = u64::from_le_bytes(word);
+        }
+
+        Ok(Self {
+            bits,
+            num_bits,
+            num_hashes,
+        })
+    }
+}
+
+impl BreachedPasswordList for BloomFilter {
+    fn contains(&self, sha1: &[u8; 20]) -> bool {
+        self.bit_indexes(sha1)
+            .all(|index| self.bits[(index / 64) as usize] & (1 << (index % 64)) != 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sha1::{Digest, Sha1};
+
+    use super::*;
+
+    fn sha1(password: &str) -> [u8; 20] {
+        Sha1::digest(password.as_bytes()).into()
+    }
+
+    fn hex(hash: &[u8; 20]) -> String {
+        hash.iter().map(|byte| format!("{:02X}", byte)).collect()
+    }
+
+    #[test]
+    fn hibp_lines_are_parsed() {
+        let hash = sha1("password");
+        let line = format!("{}:9545824", hex(&hash));
+        assert_eq!(parse_hibp_line("", &line).unwrap(), Some((hash, 9545824)));
+
+        let (prefix, suffix) = line.split_at(5);
+        assert_eq!(
+            parse_hibp_line(prefix, &suffix.to_lowercase()).unwrap(),
+            Some((hash, 9545824))
+        );
+
+        assert_eq!(parse_hibp_line("", "  ").unwrap(), None);
+        assert!(parse_hibp_line("", "ABC:1").is_err());
+        assert!(parse_hibp_line("", &format!("{}:many", hex(&hash))).is_err());
+    }
+
+    #[test]
+    fn bloom_filter_contains_inserted_hashes() {
+        let mut filter = BloomFilter::new(1000, 0.001);
+        for i in 0..1000 {
+            filter.insert(&sha1(&format!("password{}", i)));
+        }
+
+        assert!((0..1000).all(|i| filter.contains(&sha1(&format!("password{}", i)))));
+
+        let false_positives = (0..10_000)
+            .filter(|i| filter.contains(&sha1(&format!("other{}", i))))
+            .count();
+        assert!(false_positives < 50, "{} false positives", false_positives);
+    }
+
+    #[test]
+    fn bloom_filter_round_trips_through_file() {
+        let mut filter = BloomFilter::new(10, 0.01);
+        filter.insert(&sha1("password"));
+
+        let mut bytes = vec![];
+        filter.write_to(&mut bytes).unwrap();
+        assert!(bytes.starts_with(FILTER_MAGIC));
+
+        let read = BloomFilter::read_from(&bytes[FILTER_MAGIC.len()..]).unwrap();
+        assert_eq!(read, filter);
+        assert!(BloomFilter::read_from(&bytes[FILTER_MAGIC.len()..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn corpora_are_loaded_from_every_format() {
+        let dir = std::env::temp_dir().join(format!("hibp-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("ranges")).unwrap();
+
+        let hash = hex(&sha1("password"));
+        std::fs::write(dir.join("corpus.txt"), format!("{}:3\n", hash)).unwrap();
+        std::fs::write(
+            dir.join("ranges").join(format!("{}.txt", &hash[..5])),
+            format!("{}:3\n", &hash[5..]),
+        )
+        .unwrap();
+
+        let mut filter = BloomFilter::new(1, 0.001);
+        filter.insert(&sha1("password"));
+        filter
+            .write_to(File::create(dir.join("corpus.bloom")).unwrap())
+            .unwrap();
+
+        for path in ["corpus.txt", "ranges", "corpus.bloom"] {
+            let list = load(&dir.join(path)).unwrap();
+            assert!(list.contains(&sha1("password")), "{}", path);
+            assert!(!list.contains(&sha1("password1")), "{}", path);
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
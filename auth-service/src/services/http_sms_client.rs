@@ -0,0 +1,164 @@
+use color_eyre::eyre::Result;
+use reqwest::{Client, Url};
+use secrecy::{ExposeSecret, Secret};
+
+use crate::{
+    domain::{PhoneNumber, SmsClient},
+    utils::tracing::trace_context_headers,
+};
+
+// Sends SMS through a provider that accepts JSON messages over HTTP with bearer token auth
+pub struct HttpSmsClient {
+    http_client: Client,
+    base_url: String,
+    sender: PhoneNumber,
+    authorization_token: Secret<String>,
+}
+
+impl HttpSmsClient {
+    pub fn new(
+        base_url: String,
+        sender: PhoneNumber,
+        authorization_token: Secret<String>,
+        http_client: Client,
+    ) -> Self {
+        Self {
+            http_client,
+            base_url,
+            sender,
+            authorization_token,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl SmsClient for HttpSmsClient {
+    #[tracing::instrument(name = "Sending SMS", skip_all)]
+    async fn send_sms(&self, recipient: &PhoneNumber, content: &str) -> Result<()> {
+        let base = Url::parse(&self.base_url)?;
+        let url = base.join("/messages")?;
+
+        let request_body = SendSmsRequest {
+            from: self.sender.as_ref().expose_secret(),
+            to: recipient.as_ref().expose_secret(),
+            body: content,
+        };
+
+        let request = self
+            .http_client
+            .post(url)
+            .headers(trace_context_headers())
+            .bearer_auth(self.authorization_token.expose_secret())
+            .json(&request_body);
+
+        request.send().await?.error_for_status()?;
+
+        Ok(())
+    }
+}
+
+#[derive(serde::Serialize, Debug)]
+struct SendSmsRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    body: &'a str,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::constants::test;
+
+    use super::*;
+    use fake::faker::lorem::en::Sentence;
+    use fake::{Fake, Faker};
+    use wiremock::matchers::{any, header, header_regex, method, path};
+    use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+    fn content() -> String {
+        Sentence(1..5).fake()
+    }
+
+    fn phone_number() -> PhoneNumber {
+        let digits: u64 = (2_000_000_000..9_999_999_999).fake();
+        PhoneNumber::parse(Secret::new(format!("+1{}", digits))).unwrap()
+    }
+
+    fn sms_client(base_url: String) -> HttpSmsClient {
+        let http_client = Client::builder()
+            .timeout(test::sms_client::TIMEOUT)
+            .build()
+            .unwrap();
+        HttpSmsClient::new(
+            base_url,
+            phone_number(),
+            Secret::new(Faker.fake()),
+            http_client,
+        )
+    }
+
+    struct SendSmsBodyMatcher;
+
+    impl wiremock::Match for SendSmsBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                body.get("from").is_some() && body.get("to").is_some() && body.get("body").is_some()
+            } else {
+                false
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn send_sms_sends_the_expected_request() {
+        let mock_server = MockServer::start().await;
+        let sms_client = sms_client(mock_server.uri());
+
+        Mock::given(header_regex("Authorization", "^Bearer .+$"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/messages"))
+            .and(method("POST"))
+            .and(SendSmsBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = sms_client.send_sms(&phone_number(), &content()).await;
+
+        assert!(outcome.is_ok());
+    }
+
+    #[tokio::test]
+    async fn send_sms_fails_if_the_server_returns_500() {
+        let mock_server = MockServer::start().await;
+        let sms_client = sms_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = sms_client.send_sms(&phone_number(), &content()).await;
+
+        assert!(outcome.is_err());
+    }
+
+    #[tokio::test]
+    async fn send_sms_times_out_if_the_server_takes_too_long() {
+        let mock_server = MockServer::start().await;
+        let sms_client = sms_client(mock_server.uri());
+
+        let response = ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180));
+        Mock::given(any())
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = sms_client.send_sms(&phone_number(), &content()).await;
+
+        assert!(outcome.is_err());
+    }
+}
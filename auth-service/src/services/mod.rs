@@ -1,3 +1,9 @@
 pub mod data_stores;
+pub mod health_checks;
+pub mod hibp_breached_passwords;
+pub mod http_sms_client;
 pub mod mock_email_client;
-pub mod postmark_email_client;
\ No newline at end of file
+pub mod mock_sms_client;
+pub mod password_hasher;
+pub mod postmark_email_client;
+pub mod webhook_dispatcher;
@@ -0,0 +1,479 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use argon2::{
+    password_hash::SaltString, Algorithm, Argon2, Params, PasswordHash, PasswordHasher as _,
+    PasswordVerifier, Version,
+};
+use color_eyre::eyre::{eyre, Context, Result};
+use hmac::{Hmac, Mac};
+use pbkdf2::Pbkdf2;
+use scrypt::Scrypt;
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+
+use crate::utils::{
+    constants::{DEFAULT_ARGON2_ITERATIONS, DEFAULT_ARGON2_MEMORY_KIB, DEFAULT_ARGON2_PARALLELISM},
+    metrics,
+};
+
+/// Cost parameters for new Argon2id hashes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Argon2Params {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for Argon2Params {
+    fn default() -> Self {
+        Self {
+            memory_kib: DEFAULT_ARGON2_MEMORY_KIB,
+            iterations: DEFAULT_ARGON2_ITERATIONS,
+            parallelism: DEFAULT_ARGON2_PARALLELISM,
+        }
+    }
+}
+
+/// Secret keys held outside the database, one of which is mixed into every password with
+/// HMAC-SHA256 before it is hashed. New hashes use the highest version, and older versions are
+/// kept around to verify hashes that haven't been upgraded yet.
+#[derive(Debug, Clone, Default)]
+pub struct Peppers(Arc<BTreeMap<i32, Secret<String>>>);
+
+impl Peppers {
+    pub fn new(peppers: impl IntoIterator<Item = (i32, Secret<String>)>) -> Result<Self> {
+        let mut versions = BTreeMap::new();
+        for (version, pepper) in peppers {
+            if version <= 0 {
+                return Err(eyre!("pepper versions must be positive"));
+            }
+            if pepper.expose_secret().is_empty() {
+                return Err(eyre!("pepper {} is empty", version));
+            }
+            if versions.insert(version, pepper).is_some() {
+                return Err(eyre!("pepper {} is given more than once", version));
+            }
+        }
+        Ok(Self(Arc::new(versions)))
+    }
+
+    /// Parses comma separated `<version>:<pepper>` pairs, where an empty string means no pepper
+    pub fn parse(peppers: &str) -> Result<Self> {
+        let peppers = peppers
+            .split(',')
+            .map(str::trim)
+            .filter(|pepper| !pepper.is_empty())
+            .map(|pepper| {
+                let (version, pepper) = pepper
+                    .split_once(':')
+                    .ok_or(eyre!("expected <version>:<pepper>"))?;
+                let version = version.trim().parse().wrap_err("invalid pepper version")?;
+                Ok((version, Secret::new(pepper.to_owned())))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Self::new(peppers)
+    }
+
+    fn current_version(&self) -> Option<i32> {
+        self.0.keys().next_back().copied()
+    }
+
+    // The password to hash in place of `password`
+    fn apply(&self, version: Option<i32>, password: Secret<String>) -> Result<Secret<String>> {
+        let Some(version) = version else {
+            return Ok(password);
+        };
+        let pepper = self
+            .0
+            .get(&version)
+            .ok_or(eyre!("unknown pepper version {}", version))?;
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(pepper.expose_secret().as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(password.expose_secret().as_bytes());
+        Ok(Secret::new(format!("{:x}", mac.finalize().into_bytes())))
+    }
+}
+
+/// A password hash as stored, along with the pepper that went into it
+#[derive(Debug, Clone)]
+pub struct HashedPassword {
+    pub hash: Secret<String>,
+    pub pepper_version: Option<i32>,
+}
+
+impl HashedPassword {
+    /// A hash of the password itself, such as one imported from another system
+    pub fn unpeppered(hash: Secret<String>) -> Self {
+        Self {
+            hash,
+            pepper_version: None,
+        }
+    }
+}
+
+/// The outcome of a successful verification
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PasswordVerification {
+    /// The hash isn't an Argon2id hash with the current parameters and pepper, so it should be
+    /// replaced by a fresh one now that the password is known
+    pub needs_rehash: bool,
+}
+
+// The hash formats passwords can be verified against. Anything but Argon2id only comes from
+// importing users from other systems.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HashFormat {
+    Argon2,
+    Scrypt,
+    Pbkdf2,
+    Bcrypt,
+}
+
+impl HashFormat {
+    fn detect(hash: &str) -> Option<Self> {
+        if ["$2a$", "$2b$", "$2x$", "$2y$"]
+            .iter()
+            .any(|prefix| hash.starts_with(prefix))
+        {
+            return Some(Self::Bcrypt);
+        }
+
+        match PasswordHash::new(hash).ok()?.algorithm.as_str() {
+            "argon2id" | "argon2i" | "argon2d" => Some(Self::Argon2),
+            "scrypt" => Some(Self::Scrypt),
+            "pbkdf2" | "pbkdf2-sha256" | "pbkdf2-sha512" => Some(Self::Pbkdf2),
+            _ => None,
+        }
+    }
+}
+
+/// Hashes passwords with Argon2id, and verifies them against Argon2 hashes with any parameters
+/// as well as bcrypt hashes and scrypt or PBKDF2 hashes in PHC string format.
+#[derive(Debug, Clone, Default)]
+pub struct PasswordHasher {
+    params: Argon2Params,
+    peppers: Peppers,
+}
+
+impl PasswordHasher {
+    pub fn new(params: Argon2Params) -> Result<Self> {
+        Params::new(
+            params.memory_kib,
+            params.iterations,
+            params.parallelism,
+            None,
+        )
+        .map_err(|e| eyre!("invalid Argon2 parameters: {}", e))?;
+        Ok(Self {
+            params,
+            peppers: Peppers::default(),
+        })
+    }
+
+    pub fn with_peppers(self, peppers: Peppers) -> Self {
+        Self { peppers, ..self }
+    }
+
+    /// Whether `hash` is in a format passwords can be verified against
+    pub fn is_supported(hash: &str) -> bool {
+        HashFormat::detect(hash).is_some()
+    }
+
+    #[tracing::instrument(name = "Computing password hash", skip_all)]
+    pub async fn hash(&self, password: Secret<String>) -> Result<HashedPassword> {
+        let current_span: tracing::Span = tracing::Span::current();
+        let params = self.params;
+        let pepper_version = self.peppers.current_version();
+        let password = self.peppers.apply(pepper_version, password)?;
+
+        let result = tokio::task::spawn_blocking(move || {
+            current_span.in_scope(|| {
+                let _timer = metrics::PASSWORD_HASH_DURATION
+                    .with_label_values(&["hash"])
+                    .start_timer();
+                let salt: SaltString = SaltString::generate(&mut rand::thread_rng());
+                let password_hash = argon2(params)?
+                    .hash_password(password.expose_secret().as_bytes(), &salt)?
+                    .to_string();
+
+                Ok(HashedPassword {
+                    hash: Secret::new(password_hash),
+                    pepper_version,
+                })
+            })
+        })
+        .await;
+
+        result?
+    }
+
+    /// Fails unless `password_candidate` matches `expected_password_hash`
+    #[tracing::instrument(name = "Verify password hash", skip_all)]
+    pub async fn verify(
+        &self,
+        expected_password_hash: HashedPassword,
+        password_candidate: Secret<String>,
+    ) -> Result<PasswordVerification> {
+        let current_span: tracing::Span = tracing::Span::current();
+        let params = self.params;
+        let pepper_version = expected_password_hash.pepper_version;
+        let outdated_pepper = pepper_version != self.peppers.current_version();
+        let password_candidate = self.peppers.apply(pepper_version, password_candidate)?;
+
+        let result = tokio::task::spawn_blocking(move || {
+            current_span.in_scope(|| {
+                let _timer = metrics::PASSWORD_HASH_DURATION
+                    .with_label_values(&["verify"])
+                    .start_timer();
+                let hash = expected_password_hash.hash.expose_secret();
+                let password = password_candidate.expose_secret().as_bytes();
+                let format = HashFormat::detect(hash).ok_or(eyre!("unsupported password hash"))?;
+
+                let needs_rehash = match format {
+                    HashFormat::Bcrypt => {
+                        if !bcrypt::verify(password, hash).wrap_err("invalid bcrypt hash")? {
+                            return Err(eyre!("failed to verify password hash"));
+                        }
+                        true
+                    }
+                    _ => {
+                        let hash = PasswordHash::new(hash)?;
+                        let verified = match format {
+                            HashFormat::Argon2 => {
+                                Argon2::default().verify_password(password, &hash)
+                            }
+                            HashFormat::Scrypt => Scrypt.verify_password(password, &hash),
+                            _ => Pbkdf2.verify_password(password, &hash),
+                        };
+                        verified.wrap_err("failed to verify password hash")?;
+                        !is_current_argon2(&hash, params)
+                    }
+                };
+
+                Ok(PasswordVerification {
+                    needs_rehash: needs_rehash || outdated_pepper,
+                })
+            })
+        })
+        .await;
+
+        result?
+    }
+}
+
+fn argon2(params: Argon2Params) -> Result<Argon2<'static>> {
+    let params = Params::new(
+        params.memory_kib,
+        params.iterations,
+        params.parallelism,
+        None,
+    )
+    .map_err(|e| eyre!("invalid Argon2 parameters: {}", e))?;
+    Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+}
+
+fn is_current_argon2(hash: &PasswordHash, params: Argon2Params) -> bool {
+    let Ok(hash_params) = Params::try_from(hash) else {
+        return false;
+    };
+
+    hash.algorithm == Algorithm::Argon2id.ident()
+        && hash.version == Some(Version::V0x13.into())
+        && hash_params.m_cost() == params.memory_kib
+        && hash_params.t_cost() == params.iterations
+        && hash_params.p_cost() == params.parallelism
+}
+
+#[cfg(test)]
+mod tests {
+    use argon2::password_hash::PasswordHasher as _;
+
+    use super::*;
+
+    const PASSWORD: &str = "password123";
+
+    fn secret(value: &str) -> Secret<String> {
+        Secret::new(value.to_owned())
+    }
+
+    fn salt() -> SaltString {
+        SaltString::generate(&mut rand::thread_rng())
+    }
+
+    async fn verify(hasher: &PasswordHasher, hash: &str, password: &str) -> Option<bool> {
+        hasher
+            .verify(HashedPassword::unpeppered(secret(hash)), secret(password))
+            .await
+            .ok()
+            .map(|verification| verification.needs_rehash)
+    }
+
+    #[tokio::test]
+    async fn fresh_hashes_verify_without_rehash() {
+        let hasher = PasswordHasher::default();
+        let hash = hasher.hash(secret(PASSWORD)).await.unwrap();
+
+        assert!(hash
+            .hash
+            .expose_secret()
+            .starts_with("$argon2id$v=19$m=15000,t=2,p=1$"));
+        assert_eq!(
+            verify(&hasher, hash.hash.expose_secret(), PASSWORD).await,
+            Some(false)
+        );
+        assert_eq!(
+            verify(&hasher, hash.hash.expose_secret(), "wrong-password").await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn hashes_with_outdated_params_need_rehash() {
+        let old = PasswordHasher::new(Argon2Params {
+            memory_kib: 4096,
+            iterations: 1,
+            parallelism: 1,
+        })
+        .unwrap();
+        let hash = old.hash(secret(PASSWORD)).await.unwrap();
+
+        let hasher = PasswordHasher::default();
+        assert_eq!(
+            verify(&hasher, hash.hash.expose_secret(), PASSWORD).await,
+            Some(true)
+        );
+
+        let argon2i = Argon2::new(Algorithm::Argon2i, Version::V0x13, Params::default())
+            .hash_password(PASSWORD.as_bytes(), &salt())
+            .unwrap()
+            .to_string();
+        assert_eq!(verify(&hasher, &argon2i, PASSWORD).await, Some(true));
+    }
+
+    #[tokio::test]
+    async fn legacy_hashes_are_verified_and_need_rehash() {
+        let hasher = PasswordHasher::default();
+
+        let bcrypt = bcrypt::hash(PASSWORD, 4).unwrap();
+        let scrypt = Scrypt
+            .hash_password_customized(
+                PASSWORD.as_bytes(),
+                None,
+                None,
+                scrypt::Params::new(4, 8, 1, 32).unwrap(),
+                &salt(),
+            )
+            .unwrap()
+            .to_string();
+        let pbkdf2 = Pbkdf2
+            .hash_password_customized(
+                PASSWORD.as_bytes(),
+                None,
+                None,
+                pbkdf2::Params {
+                    rounds: 1000,
+                    output_length: 32,
+                },
+                &salt(),
+            )
+            .unwrap()
+            .to_string();
+
+        for hash in [bcrypt, scrypt, pbkdf2] {
+            assert!(PasswordHasher::is_supported(&hash), "{}", hash);
+            assert_eq!(
+                verify(&hasher, &hash, PASSWORD).await,
+                Some(true),
+                "{}",
+                hash
+            );
+            assert_eq!(
+                verify(&hasher, &hash, "wrong-password").await,
+                None,
+                "{}",
+                hash
+            );
+        }
+    }
+
+    fn peppers(versions: &[i32]) -> Peppers {
+        Peppers::new(
+            versions
+                .iter()
+                .map(|version| (*version, secret(&format!("pepper-{}", version)))),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn peppered_hashes_need_the_pepper() {
+        let hasher = PasswordHasher::default().with_peppers(peppers(&[1]));
+        let hash = hasher.hash(secret(PASSWORD)).await.unwrap();
+        assert_eq!(hash.pepper_version, Some(1));
+
+        let verification = hasher.verify(hash.clone(), secret(PASSWORD)).await.unwrap();
+        assert!(!verification.needs_rehash);
+        assert!(hasher
+            .verify(hash.clone(), secret("wrong-password"))
+            .await
+            .is_err());
+
+        // Without the pepper, the hash is useless
+        let unpeppered = HashedPassword::unpeppered(hash.hash.clone());
+        assert!(hasher.verify(unpeppered, secret(PASSWORD)).await.is_err());
+        assert!(PasswordHasher::default()
+            .verify(hash, secret(PASSWORD))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn hashes_with_outdated_peppers_need_rehash() {
+        let unpeppered = PasswordHasher::default()
+            .hash(secret(PASSWORD))
+            .await
+            .unwrap();
+        let old = PasswordHasher::default().with_peppers(peppers(&[1]));
+        let peppered = old.hash(secret(PASSWORD)).await.unwrap();
+
+        let hasher = PasswordHasher::default().with_peppers(peppers(&[1, 2]));
+        for hash in [unpeppered, peppered] {
+            let verification = hasher.verify(hash, secret(PASSWORD)).await.unwrap();
+            assert!(verification.needs_rehash);
+        }
+
+        let hash = hasher.hash(secret(PASSWORD)).await.unwrap();
+        assert_eq!(hash.pepper_version, Some(2));
+    }
+
+    #[test]
+    fn peppers_are_parsed() {
+        let peppers = Peppers::parse("1:first, 2:sec:ond").unwrap();
+        assert_eq!(peppers.current_version(), Some(2));
+        assert_eq!(peppers.0[&2].expose_secret(), "sec:ond");
+
+        assert_eq!(Peppers::parse("").unwrap().current_version(), None);
+        assert!(Peppers::parse("secret").is_err());
+        assert!(Peppers::parse("0:secret").is_err());
+        assert!(Peppers::parse("1:").is_err());
+        assert!(Peppers::parse("1:a,1:b").is_err());
+    }
+
+    #[test]
+    fn unknown_formats_are_unsupported() {
+        assert!(!PasswordHasher::is_supported("password123"));
+        assert!(!PasswordHasher::is_supported("$md5$salt$hash"));
+        assert!(!PasswordHasher::is_supported("$1$salt$hash"));
+    }
+
+    #[test]
+    fn invalid_params_are_rejected() {
+        let params = Argon2Params {
+            memory_kib: 1,
+            ..Argon2Params::default()
+        };
+        assert!(PasswordHasher::new(params).is_err());
+    }
+}
@@ -2,7 +2,10 @@ use color_eyre::eyre::Result; // For improved error handling and reporting
 use reqwest::{Client, Url}; // For making HTTP requests
 use secrecy::{ExposeSecret, Secret}; // For securely handling sensitive data
 
-use crate::domain::{Email, EmailClient}; // Import domain-specific modules
+use crate::{
+    domain::{Email, EmailClient, HealthCheck},
+    utils::tracing::trace_context_headers,
+}; // Import domain-specific modules
 
 // Define the PostmarkEmailClient struct
 pub struct PostmarkEmailClient {
@@ -51,6 +54,7 @@ impl EmailClient for PostmarkEmailClient {
         let request = self
             .http_client
             .post(url)
+            .headers(trace_context_headers()) // Lets the email provider's logs join our trace
             .header(
                 POSTMARK_AUTH_HEADER,
                 self.authorization_token.expose_secret(), // Securely expose the authorization token
@@ -64,6 +68,32 @@ impl EmailClient for PostmarkEmailClient {
     }
 }
 
+// Fetching the server's details checks both that Postmark is reachable and that it accepts the token
+#[async_trait::async_trait]
+impl HealthCheck for PostmarkEmailClient {
+    fn name(&self) -> &'static str {
+        "email"
+    }
+
+    #[tracing::instrument(name = "Checking Postmark", skip_all)]
+    async fn check(&self) -> Result<()> {
+        let url = Url::parse(&self.base_url)?.join("/server")?;
+
+        self.http_client
+            .get(url)
+            .header(
+                POSTMARK_AUTH_HEADER,
+                self.authorization_token.expose_secret(),
+            )
+            .header(reqwest::header::ACCEPT, "application/json")
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
+
 // Constants for message stream and authorization header
 const MESSAGE_STREAM: &str = "outbound";
 const POSTMARK_AUTH_HEADER: &str = "X-Postmark-Server-Token";
@@ -204,4 +234,36 @@ mod tests {
 
         assert!(outcome.is_err());
     }
-}
\ No newline at end of file
+
+    // Test that the health check asks Postmark for the server the token belongs to
+    #[tokio::test]
+    async fn check_requests_the_server_with_the_token() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists(POSTMARK_AUTH_HEADER))
+            .and(path("/server"))
+            .and(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        assert!(email_client.check().await.is_ok());
+    }
+
+    // Test that a rejected token fails the health check
+    #[tokio::test]
+    async fn check_fails_if_the_token_is_rejected() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(401))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        assert!(email_client.check().await.is_err());
+    }
+}
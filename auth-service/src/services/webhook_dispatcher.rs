@@ -0,0 +1,466 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use color_eyre::eyre::Result;
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+
+use crate::{
+    app_state::WebhookStoreType,
+    domain::{data_stores::WebhookStoreError, DueWebhookDelivery, WebhookDeliveryAttempt},
+    utils::shutdown::ShutdownHandle,
+};
+
+pub const WEBHOOK_ID_HEADER: &str = "webhook-id";
+pub const WEBHOOK_TIMESTAMP_HEADER: &str = "webhook-timestamp";
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "webhook-signature";
+
+// Deliveries handed to a dispatcher in one go
+const BATCH_SIZE: usize = 20;
+
+/// Exponential backoff between failed attempts at a delivery
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Attempts made before a delivery is given up for failed
+    pub max_attempts: u32,
+}
+
+impl RetryPolicy {
+    /// How long to wait after the given number of failed attempts, or `None` to give up
+    pub fn delay_after(&self, failed_attempts: u32) -> Option<Duration> {
+        if failed_attempts >= self.max_attempts {
+            return None;
+        }
+        let factor = 2u32.saturating_pow(failed_attempts.saturating_sub(1));
+        Some(self.base_delay.saturating_mul(factor).min(self.max_delay))
+    }
+}
+
+/// Sends queued webhook deliveries, signing each one with its subscription's secret
+pub struct WebhookDispatcher {
+    store: WebhookStoreType,
+    http_client: Client,
+    timeout: Duration,
+    retry_policy: RetryPolicy,
+}
+
+impl WebhookDispatcher {
+    pub fn new(
+        store: WebhookStoreType,
+        http_client: Client,
+        timeout: Duration,
+        retry_policy: RetryPolicy,
+    ) -> Self {
+        Self {
+            store,
+            http_client,
+            timeout,
+            retry_policy,
+        }
+    }
+
+    /// Polls for due deliveries until `shutdown` is triggered, then sends whatever is due one
+    /// last time so that deliveries queued just before aren't left waiting for another instance
+    pub async fn run(self, poll_interval: Duration, shutdown: ShutdownHandle) {
+        let mut interval = tokio::time::interval(poll_interval);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = shutdown.triggered() => break,
+            }
+            self.dispatch_and_log().await;
+        }
+
+        self.dispatch_and_log().await;
+        tracing::info!("Webhook dispatcher stopped");
+    }
+
+    async fn dispatch_and_log(&self) {
+        if let Err(e) = self.dispatch_due().await {
+            tracing::error!("Failed to dispatch webhooks: {:?}", e);
+        }
+    }
+
+    /// Sends every delivery that is currently due, returning how many were attempted
+    #[tracing::instrument(name = "Dispatching due webhooks", skip_all)]
+    pub async fn dispatch_due(&self) -> Result<usize> {
+        let mut attempted = 0;
+
+        loop {
+            let now = Utc::now();
+            // A dispatcher that dies mid-batch leaves its deliveries to be retried once this lapses
+            let lease_until = now + self.lease_duration();
+            let due = self
+                .store
+                .claim_due_deliveries(now, lease_until, BATCH_SIZE)
+                .await?;
+
+            let batch_size = due.len();
+            for delivery in due {
+                self.deliver(delivery).await;
+            }
+            attempted += batch_size;
+
+            if batch_size < BATCH_SIZE {
+                return Ok(attempted);
+            }
+        }
+    }
+
+    #[tracing::instrument(name = "Delivering webhook", skip_all, fields(delivery_id = %due.delivery.id))]
+    async fn deliver(&self, due: DueWebhookDelivery) {
+        let delivery = due.delivery;
+        let attempted_at = Utc::now();
+        let timestamp = attempted_at.timestamp();
+
+        let result = self
+            .http_client
+            .post(due.url)
+            .timeout(self.timeout)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(WEBHOOK_ID_HEADER, delivery.event_id.to_string())
+            .header(WEBHOOK_TIMESTAMP_HEADER, timestamp.to_string())
+            .header(
+                WEBHOOK_SIGNATURE_HEADER,
+                sign(&due.secret, timestamp, &delivery.payload),
+            )
+            .body(delivery.payload.clone())
+            .send()
+            .await;
+
+        let attempt = match result {
+            Ok(response) => WebhookDeliveryAttempt {
+                attempted_at,
+                response_status: Some(response.status().as_u16()),
+                error: None,
+            },
+            Err(e) => WebhookDeliveryAttempt {
+                attempted_at,
+                response_status: None,
+                error: Some(e.to_string()),
+            },
+        };
+
+        let retry_at = match attempt.succeeded() {
+            true => None,
+            false => {
+                let failed_attempts = delivery.attempts.len() as u32 + 1;
+                self.retry_policy
+                    .delay_after(failed_attempts)
+                    .map(|delay| attempted_at + delay)
+            }
+        };
+
+        if !attempt.succeeded() {
+            tracing::warn!(
+                status = ?attempt.response_status,
+                error = ?attempt.error,
+                "Webhook delivery attempt failed"
+            );
+        }
+
+        match self
+            .store
+            .record_attempt(delivery.id, attempt, retry_at)
+            .await
+        {
+            // The subscription was deleted while the request was in flight
+            Ok(()) | Err(WebhookStoreError::DeliveryNotFound) => {}
+            // The rest of the batch is still sent. This delivery is sent again once its lease
+            // lapses, which receivers dedupe by its webhook ID.
+            Err(e) => tracing::error!("Failed to record webhook delivery attempt: {:?}", e),
+        }
+    }
+
+    fn lease_duration(&self) -> chrono::Duration {
+        // Long enough for every request in a batch to time out
+        let lease = self.timeout.saturating_mul(BATCH_SIZE as u32 + 1);
+        chrono::Duration::from_std(lease).unwrap_or(chrono::Duration::minutes(5))
+    }
+}
+
+/// The `webhook-signature` header value: `t=<unix timestamp>,v1=<hex HMAC-SHA256>`, where the
+/// MAC is over `<timestamp>.<body>` so that a captured request can't be replayed later on.
+pub fn sign(secret: &Secret<String>, timestamp: i64, payload: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(format!("{}.{}", timestamp, payload).as_bytes());
+    format!("t={},v1={:x}", timestamp, mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    use chrono::DateTime;
+    use color_eyre::eyre::eyre;
+    use uuid::Uuid;
+    use wiremock::matchers::{header, header_exists, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::{
+        domain::{
+            data_stores::WebhookStore, WebhookDelivery, WebhookDeliveryAttempt,
+            WebhookDeliveryStatus, WebhookEvent, WebhookEventType, WebhookSubscription,
+        },
+        services::data_stores::HashmapWebhookStore,
+    };
+
+    // Fails to record the first attempt it is given, like a database connection dropping
+    #[derive(Default)]
+    struct FlakyWebhookStore {
+        inner: HashmapWebhookStore,
+        failed: AtomicBool,
+    }
+
+    #[async_trait::async_trait]
+    impl WebhookStore for FlakyWebhookStore {
+        async fn add_subscription(
+            &self,
+            subscription: WebhookSubscription,
+        ) -> Result<(), WebhookStoreError> {
+            self.inner.add_subscription(subscription).await
+        }
+
+        async fn get_subscriptions(&self) -> Result<Vec<WebhookSubscription>, WebhookStoreError> {
+            self.inner.get_subscriptions().await
+        }
+
+        async fn get_subscription(
+            &self,
+            id: Uuid,
+        ) -> Result<WebhookSubscription, WebhookStoreError> {
+            self.inner.get_subscription(id).await
+        }
+
+        async fn delete_subscription(&self, id: Uuid) -> Result<(), WebhookStoreError> {
+            self.inner.delete_subscription(id).await
+        }
+
+        async fn enqueue_event(&self, event: &WebhookEvent) -> Result<(), WebhookStoreError> {
+            self.inner.enqueue_event(event).await
+        }
+
+        async fn claim_due_deliveries(
+            &self,
+            now: DateTime<Utc>,
+            lease_until: DateTime<Utc>,
+            limit: usize,
+        ) -> Result<Vec<DueWebhookDelivery>, WebhookStoreError> {
+            self.inner
+                .claim_due_deliveries(now, lease_until, limit)
+                .await
+        }
+
+        async fn record_attempt(
+            &self,
+            delivery_id: Uuid,
+            attempt: WebhookDeliveryAttempt,
+            retry_at: Option<DateTime<Utc>>,
+        ) -> Result<(), WebhookStoreError> {
+            if !self.failed.swap(true, Ordering::SeqCst) {
+                return Err(WebhookStoreError::UnexpectedError(eyre!(
+                    "connection reset"
+                )));
+            }
+            self.inner
+                .record_attempt(delivery_id, attempt, retry_at)
+                .await
+        }
+
+        async fn get_deliveries(
+            &self,
+            subscription_id: Uuid,
+            limit: usize,
+        ) -> Result<Vec<WebhookDelivery>, WebhookStoreError> {
+            self.inner.get_deliveries(subscription_id, limit).await
+        }
+    }
+
+    const RETRY_POLICY: RetryPolicy = RetryPolicy {
+        base_delay: Duration::from_secs(30),
+        max_delay: Duration::from_secs(300),
+        max_attempts: 3,
+    };
+
+    async fn setup(server: &MockServer) -> (WebhookDispatcher, Arc<HashmapWebhookStore>, Uuid) {
+        let store = Arc::new(HashmapWebhookStore::default());
+        let subscription = WebhookSubscription::new(
+            WebhookSubscription::parse_url(&format!("{}/hooks", server.uri())).unwrap(),
+            vec![WebhookEventType::UserCreated],
+            Secret::new("whsec_test".to_owned()),
+        );
+        let subscription_id = subscription.id;
+        store.add_subscription(subscription).await.unwrap();
+
+        let event = WebhookEvent::new(WebhookEventType::UserCreated, serde_json::json!({}));
+        store.enqueue_event(&event).await.unwrap();
+
+        let dispatcher = WebhookDispatcher::new(
+            store.clone(),
+            Client::new(),
+            Duration::from_millis(200),
+            RETRY_POLICY,
+        );
+        (dispatcher, store, subscription_id)
+    }
+
+    #[test]
+    fn signature_covers_timestamp_and_body() {
+        let secret = Secret::new("whsec_test".to_owned());
+
+        let signature = sign(&secret, 1_700_000_000, "{}");
+
+        assert!(signature.starts_with("t=1700000000,v1="));
+        assert_eq!(signature.len(), "t=1700000000,v1=".len() + 64);
+        assert_ne!(signature, sign(&secret, 1_700_000_001, "{}"));
+        assert_ne!(signature, sign(&secret, 1_700_000_000, "[]"));
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_the_maximum() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            ..RETRY_POLICY
+        };
+
+        assert_eq!(policy.delay_after(1), Some(Duration::from_secs(30)));
+        assert_eq!(policy.delay_after(2), Some(Duration::from_secs(60)));
+        assert_eq!(policy.delay_after(4), Some(Duration::from_secs(240)));
+        assert_eq!(policy.delay_after(5), Some(Duration::from_secs(300)));
+        assert_eq!(policy.delay_after(10), None);
+    }
+
+    #[tokio::test]
+    async fn successful_delivery_is_signed_and_recorded() {
+        let server = MockServer::start().await;
+        let (dispatcher, store, subscription_id) = setup(&server).await;
+
+        Mock::given(method("POST"))
+            .and(path("/hooks"))
+            .and(header("Content-Type", "application/json"))
+            .and(header_exists(WEBHOOK_ID_HEADER))
+            .and(header_exists(WEBHOOK_TIMESTAMP_HEADER))
+            .and(header_exists(WEBHOOK_SIGNATURE_HEADER))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        assert_eq!(dispatcher.dispatch_due().await.unwrap(), 1);
+
+        let request = &server.received_requests().await.unwrap()[0];
+        let timestamp: i64 = request.headers[WEBHOOK_TIMESTAMP_HEADER]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        let body = String::from_utf8(request.body.clone()).unwrap();
+        assert_eq!(
+            request.headers[WEBHOOK_SIGNATURE_HEADER].to_str().unwrap(),
+            sign(&Secret::new("whsec_test".to_owned()), timestamp, &body)
+        );
+
+        let delivery = &store.get_deliveries(subscription_id, 1).await.unwrap()[0];
+        assert_eq!(delivery.status, WebhookDeliveryStatus::Succeeded);
+        assert_eq!(delivery.attempts[0].response_status, Some(204));
+    }
+
+    #[tokio::test]
+    async fn failed_delivery_is_rescheduled_with_backoff() {
+        let server = MockServer::start().await;
+        let (dispatcher, store, subscription_id) = setup(&server).await;
+
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let before = Utc::now();
+        assert_eq!(dispatcher.dispatch_due().await.unwrap(), 1);
+        // Not due again until the backoff has passed
+        assert_eq!(dispatcher.dispatch_due().await.unwrap(), 0);
+
+        let delivery = &store.get_deliveries(subscription_id, 1).await.unwrap()[0];
+        assert_eq!(delivery.status, WebhookDeliveryStatus::Pending);
+        assert!(delivery.next_attempt_at.unwrap() >= before + chrono::Duration::seconds(30));
+        assert_eq!(delivery.attempts[0].response_status, Some(500));
+    }
+
+    #[tokio::test]
+    async fn delivery_fails_once_attempts_run_out() {
+        let server = MockServer::start().await;
+        let (dispatcher, store, subscription_id) = setup(&server).await;
+
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .expect(3)
+            .mount(&server)
+            .await;
+
+        for _ in 0..3 {
+            // Make the pending retry due straight away
+            let far_future = Utc::now() + chrono::Duration::days(1);
+            for due in store
+                .claim_due_deliveries(far_future, Utc::now(), 10)
+                .await
+                .unwrap()
+            {
+                assert_eq!(due.delivery.status, WebhookDeliveryStatus::Pending);
+            }
+            dispatcher.dispatch_due().await.unwrap();
+        }
+
+        let delivery = &store.get_deliveries(subscription_id, 1).await.unwrap()[0];
+        assert_eq!(delivery.status, WebhookDeliveryStatus::Failed);
+        assert_eq!(delivery.attempts.len(), 3);
+        assert_eq!(delivery.next_attempt_at, None);
+    }
+
+    #[tokio::test]
+    async fn failing_to_record_an_attempt_does_not_stop_the_batch() {
+        let server = MockServer::start().await;
+        let store = Arc::new(FlakyWebhookStore::default());
+        let subscription = WebhookSubscription::new(
+            WebhookSubscription::parse_url(&format!("{}/hooks", server.uri())).unwrap(),
+            vec![WebhookEventType::UserCreated],
+            Secret::new("whsec_test".to_owned()),
+        );
+        let subscription_id = subscription.id;
+        store.add_subscription(subscription).await.unwrap();
+        for _ in 0..3 {
+            let event = WebhookEvent::new(WebhookEventType::UserCreated, serde_json::json!({}));
+            store.enqueue_event(&event).await.unwrap();
+        }
+        let dispatcher = WebhookDispatcher::new(
+            store.clone(),
+            Client::new(),
+            Duration::from_millis(200),
+            RETRY_POLICY,
+        );
+
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(3)
+            .mount(&server)
+            .await;
+
+        assert_eq!(dispatcher.dispatch_due().await.unwrap(), 3);
+
+        let deliveries = store.get_deliveries(subscription_id, 3).await.unwrap();
+        let succeeded = deliveries
+            .iter()
+            .filter(|delivery| delivery.status == WebhookDeliveryStatus::Succeeded)
+            .count();
+        assert_eq!(succeeded, 2);
+    }
+}
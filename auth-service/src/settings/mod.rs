@@ -0,0 +1,1454 @@
+//! Typed service settings, layered from built-in defaults, then `configuration/base.toml`, then
+//! `configuration/<APP_ENVIRONMENT>.toml`, then environment variables. Everything is validated
+//! when the settings are loaded, so a bad value stops the service at startup rather than on
+//! first use.
+
+use std::{collections::HashMap, env, path::Path, time::Duration};
+
+use axum::http::HeaderName;
+use axum_extra::extract::cookie::SameSite;
+use config::{Config, ConfigError, File, FileFormat, Source};
+use dotenvy::dotenv;
+use reqwest::Url;
+use secrecy::{ExposeSecret, Secret};
+use serde::{
+    de::{Error as _, Unexpected},
+    Deserialize, Deserializer,
+};
+use thiserror::Error;
+
+use crate::{
+    domain::{
+        BreachedPasswordMode, CommonPasswords, Email, PasswordPolicy, PhoneNumber, RateLimitConfig,
+        RateLimitKey, RateLimitPolicy, RateLimitRule, MIN_PASSWORD_LENGTH,
+    },
+    services::password_hasher::{Argon2Params, PasswordHasher, Peppers},
+    utils::constants::{
+        DEFAULT_ARGON2_ITERATIONS, DEFAULT_ARGON2_MEMORY_KIB, DEFAULT_ARGON2_PARALLELISM,
+        DEFAULT_DATABASE_MAX_CONNECTIONS, DEFAULT_HEALTH_CHECK_TIMEOUT_MILLISECONDS,
+        DEFAULT_REDIS_HOSTNAME, DEFAULT_SERVICE_NAME, DEFAULT_SHUTDOWN_TIMEOUT_SECONDS,
+        DEFAULT_TRUSTED_DEVICE_DAYS, JWT_COOKIE_NAME,
+    },
+};
+
+pub const CONFIGURATION_DIRECTORY_ENV_VAR: &str = "APP_CONFIGURATION_DIR";
+pub const ENVIRONMENT_ENV_VAR: &str = "APP_ENVIRONMENT";
+const DEFAULT_CONFIGURATION_DIRECTORY: &str = "configuration";
+
+// Any setting can be overridden with `APP_<SECTION>__<KEY>`, e.g. `APP_APPLICATION__ADDRESS`
+const ENV_OVERRIDE_PREFIX: &str = "APP_";
+const ENV_OVERRIDE_SEPARATOR: &str = "__";
+
+// Variables the service was configured with before it had settings files, which keep working
+const ENV_OVERRIDES: &[(&str, &str)] = &[
+    ("JWT_SECRET", "auth.jwt_secret"),
+    ("ADMIN_API_TOKEN", "auth.admin_api_token"),
+    ("SCIM_API_TOKEN", "auth.scim_api_token"),
+    ("TRUSTED_DEVICE_DAYS", "auth.trusted_device_days"),
+    ("AUTH_SERVICE_PUBLIC_URL", "application.public_url"),
+    ("DATABASE_URL", "database.url"),
+    ("REDIS_HOST_NAME", "redis.host_name"),
+    ("RATE_LIMIT_STORE", "rate_limit.store"),
+    ("RATE_LIMIT_EXEMPT_API_KEY", "rate_limit.exempt_api_key"),
+    ("POSTMARK_AUTH_TOKEN", "email_client.authorization_token"),
+    ("SMS_AUTH_TOKEN", "sms_client.authorization_token"),
+    ("ARGON2_MEMORY_KIB", "passwords.argon2_memory_kib"),
+    ("ARGON2_ITERATIONS", "passwords.argon2_iterations"),
+    ("ARGON2_PARALLELISM", "passwords.argon2_parallelism"),
+    ("PASSWORD_PEPPERS", "passwords.peppers"),
+    ("PASSWORD_HISTORY_SIZE", "passwords.history_size"),
+    (
+        "BREACHED_PASSWORDS_PATH",
+        "passwords.breached_passwords_path",
+    ),
+    (
+        "BREACHED_PASSWORDS_MODE",
+        "passwords.breached_passwords_mode",
+    ),
+    // The names OpenTelemetry SDKs read
+    ("OTEL_SERVICE_NAME", "telemetry.service_name"),
+    ("OTEL_EXPORTER_OTLP_ENDPOINT", "telemetry.otlp_endpoint"),
+    ("LOG_FORMAT", "telemetry.log_format"),
+    ("TLS_CERTIFICATE_PATH", "application.tls.certificate_path"),
+    ("TLS_PRIVATE_KEY_PATH", "application.tls.private_key_path"),
+    ("TLS_REDIRECT_ADDRESS", "application.tls.redirect_address"),
+];
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    pub application: ApplicationSettings,
+    #[serde(default)]
+    pub auth: AuthSettings,
+    #[serde(default)]
+    pub database: DatabaseSettings,
+    #[serde(default)]
+    pub redis: RedisSettings,
+    #[serde(default)]
+    pub rate_limit: RateLimitSettings,
+    pub email_client: EmailClientSettings,
+    pub sms_client: SmsClientSettings,
+    #[serde(default)]
+    pub passwords: PasswordSettings,
+    #[serde(default)]
+    pub health: HealthSettings,
+    #[serde(default)]
+    pub telemetry: TelemetrySettings,
+    #[serde(default)]
+    pub webhooks: WebhookSettings,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApplicationSettings {
+    pub address: String,
+    /// Where users reach the service, for links in the emails it sends. Has no trailing slash.
+    #[serde(deserialize_with = "public_url")]
+    pub public_url: String,
+    /// Origins browsers may call the API from with credentials
+    #[serde(default, deserialize_with = "list")]
+    pub allowed_origins: Vec<String>,
+    /// How long in-flight requests have to finish once the service is asked to stop, and then
+    /// how long the webhooks they queued have to be sent
+    #[serde(
+        rename = "shutdown_timeout_seconds",
+        default = "default_shutdown_timeout",
+        deserialize_with = "seconds"
+    )]
+    pub shutdown_timeout: Duration,
+    /// Serves HTTPS rather than plain HTTP when set
+    #[serde(default, deserialize_with = "tls")]
+    pub tls: Option<TlsSettings>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TlsSettings {
+    /// PEM file with the certificate chain, leaf first. Replacing it takes effect without a
+    /// restart.
+    pub certificate_path: String,
+    /// PEM file with the certificate's private key
+    pub private_key_path: String,
+    /// Where to listen for plain HTTP requests to redirect to `public_url`, e.g. `0.0.0.0:80`
+    pub redirect_address: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthSettings {
+    pub jwt_secret: Secret<String>,
+    pub admin_api_token: Secret<String>,
+    /// Bearer token identity providers use for the SCIM provisioning API
+    pub scim_api_token: Secret<String>,
+    /// How long users who tick "remember this browser" skip 2FA on it. 0 turns the option off.
+    pub trusted_device_days: u32,
+    pub cookie: CookieSettings,
+}
+
+impl Default for AuthSettings {
+    // The secrets have no default, which is reported when the settings are validated
+    fn default() -> Self {
+        Self {
+            jwt_secret: Secret::new(String::new()),
+            admin_api_token: Secret::new(String::new()),
+            scim_api_token: Secret::new(String::new()),
+            trusted_device_days: DEFAULT_TRUSTED_DEVICE_DAYS,
+            cookie: CookieSettings::default(),
+        }
+    }
+}
+
+/// Attributes of the cookies the service sets. The name and max age are only those of the
+/// session cookie, as the device cookies have their own.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CookieSettings {
+    pub name: String,
+    /// Shares the cookies with every host under this domain. They're host-only when unset.
+    #[serde(deserialize_with = "cookie_domain")]
+    pub domain: Option<String>,
+    pub path: String,
+    pub secure: bool,
+    #[serde(deserialize_with = "same_site")]
+    pub same_site: SameSite,
+    /// The session cookie is dropped when the browser closes when unset
+    #[serde(rename = "max_age_seconds", deserialize_with = "optional_seconds")]
+    pub max_age: Option<Duration>,
+}
+
+impl Default for CookieSettings {
+    fn default() -> Self {
+        Self {
+            name: JWT_COOKIE_NAME.to_owned(),
+            domain: None,
+            path: "/".to_owned(),
+            secure: false,
+            same_site: SameSite::Lax,
+            max_age: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseSettings {
+    pub url: Secret<String>,
+    /// Connections the pool may open at once
+    pub max_connections: u32,
+}
+
+impl Default for DatabaseSettings {
+    fn default() -> Self {
+        Self {
+            url: Secret::new(String::new()),
+            max_connections: DEFAULT_DATABASE_MAX_CONNECTIONS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedisSettings {
+    pub host_name: String,
+}
+
+impl Default for RedisSettings {
+    fn default() -> Self {
+        Self {
+            host_name: DEFAULT_REDIS_HOSTNAME.to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimitStoreKind {
+    /// Shares limits between instances
+    #[default]
+    Redis,
+    Memory,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitSettings {
+    pub store: RateLimitStoreKind,
+    /// Take the client IP from the last `X-Forwarded-For` entry. Only enable this behind a
+    /// reverse proxy that sets the header, otherwise clients can pick their own IP.
+    pub trust_forwarded_for: bool,
+    /// Requests carrying this key in the `x-api-key` header aren't limited, for internal callers
+    /// such as app-service. None are exempt when empty.
+    pub exempt_api_key: Secret<String>,
+    /// Applied to every request, on top of any rule for its route
+    #[serde(deserialize_with = "rate_limit_global")]
+    pub global: Option<RateLimitRule>,
+    /// Rules keyed by request path, e.g. `/login`
+    #[serde(deserialize_with = "rate_limit_routes")]
+    pub routes: HashMap<String, RateLimitRule>,
+}
+
+impl RateLimitSettings {
+    pub fn config(&self) -> RateLimitConfig {
+        let config = RateLimitConfig {
+            global: self.global.clone(),
+            routes: self.routes.clone(),
+            ..RateLimitConfig::disabled()
+        }
+        .trust_forwarded_for(self.trust_forwarded_for);
+
+        match self.exempt_api_key.expose_secret().as_str() {
+            "" => config,
+            api_key => config.exempt_api_key(api_key),
+        }
+    }
+}
+
+impl Default for RateLimitSettings {
+    // Nothing is limited unless configured, so the limits only live in `base.toml`
+    fn default() -> Self {
+        Self {
+            store: RateLimitStoreKind::default(),
+            trust_forwarded_for: false,
+            exempt_api_key: no_secret(),
+            global: None,
+            routes: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EmailClientSettings {
+    #[serde(deserialize_with = "url")]
+    pub base_url: String,
+    #[serde(deserialize_with = "email")]
+    pub sender: Email,
+    #[serde(default = "no_secret")]
+    pub authorization_token: Secret<String>,
+    #[serde(rename = "timeout_milliseconds", deserialize_with = "milliseconds")]
+    pub timeout: Duration,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SmsClientSettings {
+    #[serde(deserialize_with = "url")]
+    pub base_url: String,
+    #[serde(deserialize_with = "phone_number")]
+    pub sender: PhoneNumber,
+    #[serde(default = "no_secret")]
+    pub authorization_token: Secret<String>,
+    #[serde(rename = "timeout_milliseconds", deserialize_with = "milliseconds")]
+    pub timeout: Duration,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PasswordSettings {
+    /// Cost of new password hashes. Existing hashes are upgraded when their users next log in.
+    pub argon2_memory_kib: u32,
+    pub argon2_iterations: u32,
+    pub argon2_parallelism: u32,
+    #[serde(deserialize_with = "peppers")]
+    pub peppers: Peppers,
+    /// Length of new passwords, in characters
+    pub min_length: usize,
+    pub max_length: usize,
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    /// Reject passwords containing the local part of the user's email
+    pub reject_email: bool,
+    /// Reject passwords on the common password list
+    pub reject_common: bool,
+    /// Replaces the bundled common password list, one password per line, most common first
+    #[serde(deserialize_with = "optional_path")]
+    pub common_passwords_path: Option<String>,
+    /// The lowest acceptable strength score, from 0 to 4
+    pub min_strength_score: u8,
+    /// How many recent passwords, the current one included, can't be reused. 0 allows reuse.
+    pub history_size: usize,
+    /// A Have I Been Pwned corpus or a filter built from one. Breach checks are off when unset.
+    #[serde(deserialize_with = "optional_path")]
+    pub breached_passwords_path: Option<String>,
+    #[serde(deserialize_with = "breached_passwords_mode")]
+    pub breached_passwords_mode: BreachedPasswordMode,
+}
+
+impl PasswordSettings {
+    /// The policy new passwords must satisfy, with the bundled common password list. The one
+    /// at `common_passwords_path` is left to the caller to load.
+    pub fn policy(&self) -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: self.min_length,
+            max_length: self.max_length,
+            require_lowercase: self.require_lowercase,
+            require_uppercase: self.require_uppercase,
+            require_digit: self.require_digit,
+            require_symbol: self.require_symbol,
+            reject_email: self.reject_email,
+            reject_common: self.reject_common,
+            common_passwords: CommonPasswords::default(),
+            min_strength_score: self.min_strength_score,
+            history_size: self.history_size,
+        }
+    }
+
+    pub fn argon2(&self) -> Argon2Params {
+        Argon2Params {
+            memory_kib: self.argon2_memory_kib,
+            iterations: self.argon2_iterations,
+            parallelism: self.argon2_parallelism,
+        }
+    }
+}
+
+impl Default for PasswordSettings {
+    fn default() -> Self {
+        let policy = PasswordPolicy::default();
+        Self {
+            argon2_memory_kib: DEFAULT_ARGON2_MEMORY_KIB,
+            argon2_iterations: DEFAULT_ARGON2_ITERATIONS,
+            argon2_parallelism: DEFAULT_ARGON2_PARALLELISM,
+            peppers: Peppers::default(),
+            min_length: policy.min_length,
+            max_length: policy.max_length,
+            require_lowercase: policy.require_lowercase,
+            require_uppercase: policy.require_uppercase,
+            require_digit: policy.require_digit,
+            require_symbol: policy.require_symbol,
+            reject_email: policy.reject_email,
+            reject_common: policy.reject_common,
+            common_passwords_path: None,
+            min_strength_score: policy.min_strength_score,
+            history_size: policy.history_size,
+            breached_passwords_path: None,
+            breached_passwords_mode: BreachedPasswordMode::Reject,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthSettings {
+    /// Whether readiness also depends on the email provider accepting the service's token
+    pub check_email_provider: bool,
+    /// How long each dependency has to answer before readiness reports it as down
+    #[serde(rename = "timeout_milliseconds", deserialize_with = "milliseconds")]
+    pub timeout: Duration,
+}
+
+impl Default for HealthSettings {
+    fn default() -> Self {
+        Self {
+            check_email_provider: false,
+            timeout: Duration::from_millis(DEFAULT_HEALTH_CHECK_TIMEOUT_MILLISECONDS),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetrySettings {
+    /// Names the service in exported traces
+    pub service_name: String,
+    /// An OTLP/HTTP collector, e.g. `http://localhost:4318`. Traces aren't exported when unset.
+    #[serde(deserialize_with = "optional_url")]
+    pub otlp_endpoint: Option<String>,
+    pub log_format: LogFormat,
+}
+
+impl Default for TelemetrySettings {
+    fn default() -> Self {
+        Self {
+            service_name: DEFAULT_SERVICE_NAME.to_owned(),
+            otlp_endpoint: None,
+            log_format: LogFormat::Compact,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// One human-readable line per event
+    Compact,
+    /// One JSON object per event, for log aggregators
+    Json,
+}
+
+/// How events are delivered to webhook endpoints
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhookSettings {
+    /// How long an endpoint has to answer a delivery
+    #[serde(rename = "timeout_milliseconds", deserialize_with = "milliseconds")]
+    pub timeout: Duration,
+    /// How often the queue is checked for deliveries that are due
+    #[serde(
+        rename = "poll_interval_milliseconds",
+        deserialize_with = "milliseconds"
+    )]
+    pub poll_interval: Duration,
+    /// Wait after the first failed attempt, doubled after each further one
+    #[serde(
+        rename = "retry_base_delay_milliseconds",
+        deserialize_with = "milliseconds"
+    )]
+    pub retry_base_delay: Duration,
+    #[serde(
+        rename = "retry_max_delay_milliseconds",
+        deserialize_with = "milliseconds"
+    )]
+    pub retry_max_delay: Duration,
+    /// Attempts made before a delivery is given up for failed
+    pub max_attempts: u32,
+}
+
+impl Default for WebhookSettings {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+            poll_interval: Duration::from_secs(1),
+            retry_base_delay: Duration::from_secs(30),
+            retry_max_delay: Duration::from_secs(6 * 60 * 60),
+            max_attempts: 10,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SettingsError {
+    #[error("Failed to read settings file {path}: {source}")]
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("Invalid settings file {path}: {message}")]
+    Syntax { path: String, message: String },
+    #[error("Invalid settings:\n{}", .0.iter().map(|e| format!("  - {}", e)).collect::<Vec<_>>().join("\n"))]
+    Invalid(Vec<String>),
+}
+
+impl From<ConfigError> for SettingsError {
+    fn from(e: ConfigError) -> Self {
+        Self::Invalid(vec![e.to_string()])
+    }
+}
+
+impl Settings {
+    /// Loads the settings for the environment named by `APP_ENVIRONMENT`, if any
+    pub fn load() -> Result<Self, SettingsError> {
+        dotenv().ok();
+        Self::load_environment(env::var(ENVIRONMENT_ENV_VAR).ok().as_deref())
+    }
+
+    /// Loads the base settings, then those of `environment` on top, then environment variables
+    pub fn load_environment(environment: Option<&str>) -> Result<Self, SettingsError> {
+        dotenv().ok();
+        let directory = env::var(CONFIGURATION_DIRECTORY_ENV_VAR)
+            .unwrap_or(DEFAULT_CONFIGURATION_DIRECTORY.to_owned());
+        let directory = Path::new(&directory);
+
+        let mut files = vec![];
+        for name in std::iter::once("base").chain(environment) {
+            let path = directory.join(format!("{}.toml", name));
+            match std::fs::read_to_string(&path) {
+                Ok(contents) => files.push((path.display().to_string(), contents)),
+                // The base file may be left out when everything comes from the environment
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound && name == "base" => {}
+                Err(source) => {
+                    return Err(SettingsError::Io {
+                        path: path.display().to_string(),
+                        source,
+                    })
+                }
+            }
+        }
+
+        Self::from_sources(&files, env::vars())
+    }
+
+    /// Layers `files`, each a path and its contents, and then the `vars` overriding them
+    pub fn from_sources(
+        files: &[(String, String)],
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self, SettingsError> {
+        let mut builder = Config::builder();
+
+        for (path, contents) in files {
+            let file = File::from_str(contents, FileFormat::Toml);
+            // Parsed up front too, as errors from the merged settings don't name the file
+            file.collect().map_err(|e| SettingsError::Syntax {
+                path: path.clone(),
+                message: e.to_string(),
+            })?;
+            builder = builder.add_source(file);
+        }
+
+        for (name, value) in vars {
+            let key = if let Some((_, key)) = ENV_OVERRIDES.iter().find(|(var, _)| *var == name) {
+                // Compose passes unset variables along as empty strings
+                if value.is_empty() {
+                    continue;
+                }
+                key.to_string()
+            } else if let Some(key) = name
+                .strip_prefix(ENV_OVERRIDE_PREFIX)
+                .filter(|key| key.contains(ENV_OVERRIDE_SEPARATOR))
+            {
+                key.replace(ENV_OVERRIDE_SEPARATOR, ".").to_lowercase()
+            } else {
+                continue;
+            };
+            builder = builder.set_override(key, value)?;
+        }
+
+        let settings: Self = builder.build()?.try_deserialize()?;
+        match settings.validate() {
+            errors if errors.is_empty() => Ok(settings),
+            errors => Err(SettingsError::Invalid(errors)),
+        }
+    }
+
+    // The rules that span several settings, collected so they can be reported together
+    fn validate(&self) -> Vec<String> {
+        let mut errors = vec![];
+
+        for (key, secret) in [
+            ("auth.jwt_secret", &self.auth.jwt_secret),
+            ("auth.admin_api_token", &self.auth.admin_api_token),
+            ("auth.scim_api_token", &self.auth.scim_api_token),
+            ("database.url", &self.database.url),
+            (
+                "email_client.authorization_token",
+                &self.email_client.authorization_token,
+            ),
+            (
+                "sms_client.authorization_token",
+                &self.sms_client.authorization_token,
+            ),
+        ] {
+            if secret.expose_secret().is_empty() {
+                errors.push(missing(key));
+            }
+        }
+
+        let application = &self.application;
+        if application.address.is_empty() {
+            errors.push(missing("application.address"));
+        }
+        for origin in &application.allowed_origins {
+            if Url::parse(origin).is_err() || origin.parse::<axum::http::HeaderValue>().is_err() {
+                errors.push(format!(
+                    "`application.allowed_origins` has an invalid origin `{}`",
+                    origin
+                ));
+            }
+        }
+        // Redirecting to plain HTTP would loop
+        if let Some(TlsSettings {
+            redirect_address: Some(_),
+            ..
+        }) = &application.tls
+        {
+            if !application.public_url.starts_with("https://") {
+                errors.push(
+                    "`application.public_url` must be an https URL to redirect plain HTTP to"
+                        .to_owned(),
+                );
+            }
+        }
+
+        if self.database.max_connections == 0 {
+            errors.push("`database.max_connections` must be at least 1".to_owned());
+        }
+
+        let cookie = &self.auth.cookie;
+        let is_token = !cookie.name.is_empty()
+            && cookie
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c));
+        if !is_token {
+            errors.push("`auth.cookie.name` must be a valid cookie name".to_owned());
+        }
+        if !cookie.path.starts_with('/') || cookie.path.contains(';') {
+            errors.push("`auth.cookie.path` must be a path starting with `/`".to_owned());
+        }
+        // Browsers ignore cookies that break these rules
+        if cookie.same_site == SameSite::None && !cookie.secure {
+            errors.push("`auth.cookie.same_site` of `none` needs `auth.cookie.secure`".to_owned());
+        }
+        if cookie.name.starts_with("__Secure-") && !cookie.secure {
+            errors.push(
+                "`auth.cookie.name` with a `__Secure-` prefix needs `auth.cookie.secure`"
+                    .to_owned(),
+            );
+        }
+        if cookie.name.starts_with("__Host-")
+            && (!cookie.secure || cookie.domain.is_some() || cookie.path != "/")
+        {
+            errors.push(
+                "`auth.cookie.name` with a `__Host-` prefix needs `auth.cookie.secure`, \
+                 no `auth.cookie.domain` and an `auth.cookie.path` of `/`"
+                    .to_owned(),
+            );
+        }
+
+        let passwords = &self.passwords;
+        // `Password::parse` refuses shorter passwords, so a lower minimum wouldn't take effect
+        if passwords.min_length < MIN_PASSWORD_LENGTH {
+            errors.push(format!(
+                "`passwords.min_length` must be at least {}",
+                MIN_PASSWORD_LENGTH
+            ));
+        }
+        if passwords.max_length < passwords.min_length {
+            errors
+                .push("`passwords.max_length` must not be below `passwords.min_length`".to_owned());
+        }
+        if passwords.min_strength_score > 4 {
+            errors.push("`passwords.min_strength_score` must be from 0 to 4".to_owned());
+        }
+        if let Err(e) = PasswordHasher::new(passwords.argon2()) {
+            errors.push(format!("the Argon2 parameters are invalid: {:#}", e));
+        }
+
+        if self.telemetry.service_name.is_empty() {
+            errors.push("`telemetry.service_name` must not be empty".to_owned());
+        }
+
+        errors
+    }
+}
+
+// The error for a setting that has no default and wasn't given
+fn missing(key: &str) -> String {
+    let var = ENV_OVERRIDES
+        .iter()
+        .find(|(_, path)| *path == key)
+        .map(|(var, _)| *var)
+        .map(str::to_owned)
+        .unwrap_or_else(|| {
+            format!(
+                "{}{}",
+                ENV_OVERRIDE_PREFIX,
+                key.replace('.', ENV_OVERRIDE_SEPARATOR).to_uppercase()
+            )
+        });
+    format!("`{}` must be set in a settings file or with {}", key, var)
+}
+
+fn no_secret() -> Secret<String> {
+    Secret::new(String::new())
+}
+
+fn default_shutdown_timeout() -> Duration {
+    Duration::from_secs(DEFAULT_SHUTDOWN_TIMEOUT_SECONDS)
+}
+
+// Reads a string setting with `parse`, naming what was expected when it fails
+fn parse_with<'de, D, T, E>(
+    deserializer: D,
+    expected: &str,
+    parse: impl FnOnce(&str) -> Result<T, E>,
+) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    parse(&value).map_err(|_| D::Error::invalid_value(Unexpected::Str(&value), &expected))
+}
+
+fn seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    u64::deserialize(deserializer).map(Duration::from_secs)
+}
+
+fn optional_seconds<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    Option::<u64>::deserialize(deserializer).map(|seconds| seconds.map(Duration::from_secs))
+}
+
+fn milliseconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    u64::deserialize(deserializer).map(Duration::from_millis)
+}
+
+fn url<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    parse_with(deserializer, "a URL", |url| {
+        Url::parse(url).map(|_| url.to_owned())
+    })
+}
+
+fn optional_url<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    #[derive(Deserialize)]
+    struct Wrapper(#[serde(deserialize_with = "url")] String);
+
+    Option::<Wrapper>::deserialize(deserializer).map(|url| url.map(|Wrapper(url)| url))
+}
+
+fn public_url<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    url(deserializer).map(|url| url.trim_end_matches('/').to_owned())
+}
+
+// Environment variables give lists as comma separated strings
+fn list<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum List {
+        Items(Vec<String>),
+        Joined(String),
+    }
+
+    Ok(match List::deserialize(deserializer)? {
+        List::Items(items) => items,
+        List::Joined(items) => items
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(str::to_owned)
+            .collect(),
+    })
+}
+
+fn optional_path<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Option::<String>::deserialize(deserializer).map(|path| path.filter(|path| !path.is_empty()))
+}
+
+// TLS is on when both a certificate and its key are given, and off when neither is
+fn tls<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<TlsSettings>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct TlsFiles {
+        #[serde(default, deserialize_with = "optional_path")]
+        certificate_path: Option<String>,
+        #[serde(default, deserialize_with = "optional_path")]
+        private_key_path: Option<String>,
+        #[serde(default, deserialize_with = "optional_path")]
+        redirect_address: Option<String>,
+    }
+
+    let files = TlsFiles::deserialize(deserializer)?;
+    match (files.certificate_path, files.private_key_path) {
+        (Some(certificate_path), Some(private_key_path)) => Ok(Some(TlsSettings {
+            certificate_path,
+            private_key_path,
+            redirect_address: files.redirect_address,
+        })),
+        (None, None) if files.redirect_address.is_some() => Err(D::Error::custom(
+            "`application.tls.redirect_address` needs a certificate and private key",
+        )),
+        (None, None) => Ok(None),
+        (Some(_), None) => Err(D::Error::custom(missing(
+            "application.tls.private_key_path",
+        ))),
+        (None, Some(_)) => Err(D::Error::custom(missing(
+            "application.tls.certificate_path",
+        ))),
+    }
+}
+
+// A rule as written in settings files, e.g. `{ capacity = 10, period_seconds = 60, key = "email" }`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RateLimitRuleSettings {
+    capacity: u32,
+    period_seconds: u64,
+    #[serde(default)]
+    key: RateLimitKeyKind,
+    /// The header holding the API key, when keyed by `api_key`
+    header: Option<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum RateLimitKeyKind {
+    #[default]
+    Ip,
+    Email,
+    ApiKey,
+}
+
+impl RateLimitRuleSettings {
+    fn into_rule(self) -> Result<RateLimitRule, &'static str> {
+        if self.capacity == 0 || self.period_seconds == 0 {
+            return Err("needs a `capacity` and `period_seconds` of at least 1");
+        }
+        let key = match (self.key, self.header) {
+            (RateLimitKeyKind::Ip, None) => RateLimitKey::Ip,
+            (RateLimitKeyKind::Email, None) => RateLimitKey::Email,
+            (RateLimitKeyKind::ApiKey, Some(header)) if HeaderName::try_from(&header).is_ok() => {
+                RateLimitKey::ApiKey {
+                    header: header.to_ascii_lowercase(),
+                }
+            }
+            (RateLimitKeyKind::ApiKey, _) => {
+                return Err("keyed by `api_key` needs the `header` holding it")
+            }
+            (_, Some(_)) => return Err("only takes a `header` when keyed by `api_key`"),
+        };
+        let policy = RateLimitPolicy::new(self.capacity, Duration::from_secs(self.period_seconds));
+
+        Ok(RateLimitRule::new(policy, key))
+    }
+}
+
+fn rate_limit_global<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<RateLimitRule>, D::Error> {
+    Option::<RateLimitRuleSettings>::deserialize(deserializer)?
+        .map(|rule| {
+            rule.into_rule()
+                .map_err(|e| D::Error::custom(format!("`rate_limit.global` {}", e)))
+        })
+        .transpose()
+}
+
+fn rate_limit_routes<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<String, RateLimitRule>, D::Error> {
+    HashMap::<String, RateLimitRuleSettings>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(path, rule)| {
+            let rule = match path.starts_with('/') {
+                true => rule.into_rule(),
+                false => Err("must be keyed by a path starting with `/`"),
+            };
+            rule.map(|rule| (path.clone(), rule))
+                .map_err(|e| D::Error::custom(format!("`rate_limit.routes.\"{}\"` {}", path, e)))
+        })
+        .collect()
+}
+
+fn cookie_domain<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    #[derive(Deserialize)]
+    struct Domain(#[serde(deserialize_with = "domain")] String);
+
+    fn domain<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+        parse_with(
+            deserializer,
+            "a domain name such as `example.com`",
+            |domain| {
+                let domain = domain.trim_start_matches('.').to_ascii_lowercase();
+                let is_valid = domain.split('.').all(|label| {
+                    !label.is_empty()
+                        && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                });
+                is_valid.then_some(domain).ok_or(())
+            },
+        )
+    }
+
+    Option::<Domain>::deserialize(deserializer).map(|domain| domain.map(|Domain(domain)| domain))
+}
+
+fn same_site<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SameSite, D::Error> {
+    parse_with(
+        deserializer,
+        "`strict`, `lax` or `none`",
+        |same_site| match same_site {
+            "strict" => Ok(SameSite::Strict),
+            "lax" => Ok(SameSite::Lax),
+            "none" => Ok(SameSite::None),
+            _ => Err(()),
+        },
+    )
+}
+
+fn email<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Email, D::Error> {
+    parse_with(deserializer, "an email address", |email| {
+        Email::parse(Secret::new(email.to_owned()))
+    })
+}
+
+fn phone_number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PhoneNumber, D::Error> {
+    parse_with(
+        deserializer,
+        "a phone number in E.164 format",
+        |phone_number| PhoneNumber::parse(Secret::new(phone_number.to_owned())),
+    )
+}
+
+fn peppers<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Peppers, D::Error> {
+    // Not echoed back, as peppers are secrets
+    let peppers = String::deserialize(deserializer)?;
+    Peppers::parse(&peppers).map_err(|e| D::Error::custom(format!("invalid peppers: {}", e)))
+}
+
+fn breached_passwords_mode<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BreachedPasswordMode, D::Error> {
+    parse_with(
+        deserializer,
+        "`reject` or `warn`",
+        BreachedPasswordMode::parse,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use secrecy::ExposeSecret;
+
+    use super::*;
+    use crate::domain::api_key_digest;
+
+    const BASE: &str = r#"
+        [application]
+        address = "0.0.0.0:3000"
+        public_url = "https://auth.example.com/"
+        allowed_origins = ["http://localhost:8000"]
+
+        [email_client]
+        base_url = "https://api.postmarkapp.com/email"
+        sender = "sender@example.com"
+        timeout_milliseconds = 10000
+
+        [sms_client]
+        base_url = "https://sms.example.com"
+        sender = "+15005550006"
+        timeout_milliseconds = 10000
+    "#;
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn secrets() -> Vec<(String, String)> {
+        vars(&[
+            ("JWT_SECRET", "secret"),
+            ("ADMIN_API_TOKEN", "admin-token"),
+            ("SCIM_API_TOKEN", "scim-token"),
+            ("DATABASE_URL", "postgres://localhost"),
+            ("POSTMARK_AUTH_TOKEN", "postmark-token"),
+            ("SMS_AUTH_TOKEN", "sms-token"),
+        ])
+    }
+
+    fn load(files: &[&str], vars: Vec<(String, String)>) -> Result<Settings, SettingsError> {
+        let files: Vec<_> = files
+            .iter()
+            .enumerate()
+            .map(|(i, contents)| (format!("file{}.toml", i), contents.to_string()))
+            .collect();
+        Settings::from_sources(&files, vars)
+    }
+
+    fn errors(result: Result<Settings, SettingsError>) -> Vec<String> {
+        match result {
+            Err(SettingsError::Invalid(errors)) => errors,
+            other => panic!("expected invalid settings, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn should_load_file_with_defaults() {
+        let settings = load(&[BASE], secrets()).unwrap();
+
+        assert_eq!(settings.application.address, "0.0.0.0:3000");
+        assert_eq!(settings.application.public_url, "https://auth.example.com");
+        assert_eq!(
+            settings.application.allowed_origins,
+            vec!["http://localhost:8000"]
+        );
+        assert_eq!(settings.auth.jwt_secret.expose_secret(), "secret");
+        assert_eq!(
+            settings.auth.trusted_device_days,
+            DEFAULT_TRUSTED_DEVICE_DAYS
+        );
+        assert_eq!(
+            settings.database.max_connections,
+            DEFAULT_DATABASE_MAX_CONNECTIONS
+        );
+        assert_eq!(settings.redis.host_name, DEFAULT_REDIS_HOSTNAME);
+        assert_eq!(settings.rate_limit.store, RateLimitStoreKind::Redis);
+        assert_eq!(settings.email_client.timeout, Duration::from_secs(10));
+        assert_eq!(settings.passwords.argon2(), Argon2Params::default());
+        assert_eq!(settings.passwords.history_size, 5);
+        assert_eq!(settings.passwords.breached_passwords_path, None);
+        assert_eq!(
+            settings.passwords.breached_passwords_mode,
+            BreachedPasswordMode::Reject
+        );
+        assert!(!settings.health.check_email_provider);
+        assert_eq!(settings.health.timeout, Duration::from_secs(2));
+        assert_eq!(settings.telemetry.service_name, "auth-service");
+        assert_eq!(settings.telemetry.otlp_endpoint, None);
+        assert_eq!(settings.telemetry.log_format, LogFormat::Compact);
+        assert_eq!(
+            settings.application.shutdown_timeout,
+            Duration::from_secs(10)
+        );
+        assert!(settings.application.tls.is_none());
+        assert_eq!(settings.webhooks.max_attempts, 10);
+        assert_eq!(
+            settings.webhooks.retry_max_delay,
+            Duration::from_secs(6 * 60 * 60)
+        );
+    }
+
+    #[test]
+    fn should_layer_environment_file_and_variables() {
+        let local = r#"
+            [application]
+            address = "127.0.0.1:0"
+
+            [rate_limit]
+            store = "memory"
+
+            [passwords]
+            history_size = 3
+        "#;
+        let mut vars = secrets();
+        vars.extend(self::vars(&[
+            ("PASSWORD_HISTORY_SIZE", "0"),
+            (
+                "APP_APPLICATION__ALLOWED_ORIGINS",
+                "http://a.example, http://b.example",
+            ),
+            ("BREACHED_PASSWORDS_PATH", ""),
+            ("OTEL_EXPORTER_OTLP_ENDPOINT", "http://collector:4318"),
+            ("LOG_FORMAT", "json"),
+            ("UNRELATED", "ignored"),
+        ]));
+
+        let settings = load(&[BASE, local], vars).unwrap();
+
+        assert_eq!(settings.application.address, "127.0.0.1:0");
+        assert_eq!(settings.rate_limit.store, RateLimitStoreKind::Memory);
+        assert_eq!(settings.passwords.history_size, 0);
+        assert_eq!(
+            settings.application.allowed_origins,
+            vec!["http://a.example", "http://b.example"]
+        );
+        assert_eq!(settings.passwords.breached_passwords_path, None);
+        assert_eq!(
+            settings.telemetry.otlp_endpoint.as_deref(),
+            Some("http://collector:4318")
+        );
+        assert_eq!(settings.telemetry.log_format, LogFormat::Json);
+    }
+
+    #[test]
+    fn should_report_every_problem_at_once() {
+        let file = r#"
+            [application]
+            address = ""
+            public_url = "http://auth.example.com"
+            allowed_origins = ["http://localhost:8000", "not an origin"]
+
+            [application.tls]
+            certificate_path = "cert.pem"
+            private_key_path = "key.pem"
+            redirect_address = "0.0.0.0:80"
+
+            [auth.cookie]
+            name = "__Secure-jwt"
+
+            [database]
+            max_connections = 0
+
+            [email_client]
+            base_url = "https://api.postmarkapp.com/email"
+            sender = "sender@example.com"
+            timeout_milliseconds = 10000
+
+            [sms_client]
+            base_url = "https://sms.example.com"
+            sender = "+15005550006"
+            timeout_milliseconds = 10000
+        "#;
+        let vars = vars(&[
+            ("JWT_SECRET", ""),
+            ("ADMIN_API_TOKEN", "admin-token"),
+            ("OTEL_SERVICE_NAME", "ignored"),
+            ("APP_TELEMETRY__SERVICE_NAME", ""),
+        ]);
+
+        let errors = errors(load(&[file], vars));
+
+        assert_eq!(
+            errors,
+            vec![
+                "`auth.jwt_secret` must be set in a settings file or with JWT_SECRET",
+                "`auth.scim_api_token` must be set in a settings file or with SCIM_API_TOKEN",
+                "`database.url` must be set in a settings file or with DATABASE_URL",
+                "`email_client.authorization_token` must be set in a settings file or with \
+                 POSTMARK_AUTH_TOKEN",
+                "`sms_client.authorization_token` must be set in a settings file or with \
+                 SMS_AUTH_TOKEN",
+                "`application.address` must be set in a settings file or with \
+                 APP_APPLICATION__ADDRESS",
+                "`application.allowed_origins` has an invalid origin `not an origin`",
+                "`application.public_url` must be an https URL to redirect plain HTTP to",
+                "`database.max_connections` must be at least 1",
+                "`auth.cookie.name` with a `__Secure-` prefix needs `auth.cookie.secure`",
+                "`telemetry.service_name` must not be empty",
+            ]
+        );
+    }
+
+    #[test]
+    fn should_reject_values_of_the_wrong_type_or_format() {
+        for (file, vars, expected) in [
+            (
+                "[application]\ncolour = \"blue\"",
+                vec![],
+                "unknown field `colour`",
+            ),
+            (
+                "[email_client]\nsender = \"not an email\"",
+                vec![],
+                "invalid value: string \"not an email\", expected an email address",
+            ),
+            (
+                "[email_client]\ntimeout_milliseconds = -1",
+                vec![],
+                "email_client.timeout_milliseconds",
+            ),
+            (
+                "[application]\npublic_url = \"not a url\"",
+                vec![],
+                "invalid value: string \"not a url\", expected a URL",
+            ),
+            ("", vec![("RATE_LIMIT_STORE", "disk")], "disk"),
+            (
+                "",
+                vec![("ARGON2_MEMORY_KIB", "lots")],
+                "passwords.argon2_memory_kib",
+            ),
+        ] {
+            let mut vars = self::vars(&vars);
+            vars.extend(secrets());
+
+            let errors = errors(load(&[BASE, file], vars));
+
+            assert_eq!(errors.len(), 1, "{:#?}", errors);
+            assert!(
+                errors[0].contains(expected),
+                "expected {:?} in {:?}",
+                expected,
+                errors[0]
+            );
+        }
+    }
+
+    #[test]
+    fn should_reject_invalid_argon2_parameters_and_peppers() {
+        for (var, expected) in [
+            (("PASSWORD_PEPPERS", "1"), "invalid peppers"),
+            (
+                ("ARGON2_PARALLELISM", "0"),
+                "the Argon2 parameters are invalid",
+            ),
+        ] {
+            let mut vars = secrets();
+            vars.extend(self::vars(&[var]));
+
+            let errors = errors(load(&[BASE], vars));
+
+            assert_eq!(errors.len(), 1, "{:#?}", errors);
+            assert!(errors[0].starts_with(expected), "{:?}", errors[0]);
+        }
+    }
+
+    #[test]
+    fn should_read_rate_limit_rules() {
+        let file = r#"
+            [rate_limit]
+            trust_forwarded_for = true
+
+            [rate_limit.global]
+            capacity = 100
+            period_seconds = 10
+
+            [rate_limit.routes]
+            "/login" = { capacity = 3, period_seconds = 60, key = "email" }
+            "/verify-token" = { capacity = 60, period_seconds = 60, key = "api_key", header = "X-Api-Key" }
+        "#;
+
+        let config = load(&[BASE, file], secrets()).unwrap().rate_limit.config();
+
+        assert!(config.trust_forwarded_for);
+        assert_eq!(
+            config.global,
+            Some(RateLimitRule::new(
+                RateLimitPolicy::new(100, Duration::from_secs(10)),
+                RateLimitKey::Ip
+            ))
+        );
+        assert_eq!(config.routes.len(), 2);
+        assert_eq!(
+            config.routes["/login"],
+            RateLimitRule::new(RateLimitPolicy::per_minute(3), RateLimitKey::Email)
+        );
+        assert_eq!(
+            config.routes["/verify-token"].key,
+            RateLimitKey::ApiKey {
+                header: "x-api-key".to_owned()
+            }
+        );
+    }
+
+    #[test]
+    fn should_only_limit_what_is_configured() {
+        let settings = load(&[BASE], secrets()).unwrap();
+        assert_eq!(settings.rate_limit.config(), RateLimitConfig::disabled());
+
+        let base = include_str!("../../configuration/base.toml");
+        let config = load(&[base], secrets()).unwrap().rate_limit.config();
+        assert_eq!(
+            config.global,
+            Some(RateLimitRule::new(
+                RateLimitPolicy::per_second(20),
+                RateLimitKey::Ip
+            ))
+        );
+        assert!(config.routes.contains_key("/verify-token"));
+        assert!(config.exempt_api_keys.is_empty());
+    }
+
+    #[test]
+    fn should_exempt_the_api_key_from_the_environment() {
+        let mut vars = secrets();
+        vars.extend(self::vars(&[(
+            "RATE_LIMIT_EXEMPT_API_KEY",
+            "app-service-key",
+        )]));
+
+        let config = load(&[BASE], vars).unwrap().rate_limit.config();
+
+        assert_eq!(
+            config.exempt_api_keys,
+            HashSet::from([api_key_digest(b"app-service-key")])
+        );
+    }
+
+    #[test]
+    fn should_reject_invalid_rate_limit_rules() {
+        for (file, expected) in [
+            (
+                "[rate_limit.global]\ncapacity = 0\nperiod_seconds = 1",
+                "`rate_limit.global` needs a `capacity` and `period_seconds` of at least 1",
+            ),
+            (
+                "[rate_limit.routes]\n\"/login\" = { capacity = 1, period_seconds = 1, key = \"api_key\" }",
+                "`rate_limit.routes.\"/login\"` keyed by `api_key` needs the `header` holding it",
+            ),
+            (
+                "[rate_limit.routes]\n\"login\" = { capacity = 1, period_seconds = 1 }",
+                "`rate_limit.routes.\"login\"` must be keyed by a path starting with `/`",
+            ),
+        ] {
+            let errors = errors(load(&[BASE, file], secrets()));
+
+            assert_eq!(errors, vec![expected.to_owned()]);
+        }
+    }
+
+    #[test]
+    fn should_read_password_policy() {
+        let file = r#"
+            [passwords]
+            min_length = 12
+            max_length = 64
+            require_symbol = true
+            reject_email = false
+            min_strength_score = 2
+            history_size = 3
+        "#;
+        let base = include_str!("../../configuration/base.toml");
+
+        assert_eq!(
+            load(&[base], secrets()).unwrap().passwords.policy(),
+            PasswordPolicy::default()
+        );
+        assert_eq!(
+            load(&[BASE, file], secrets()).unwrap().passwords.policy(),
+            PasswordPolicy {
+                min_length: 12,
+                max_length: 64,
+                require_symbol: true,
+                reject_email: false,
+                min_strength_score: 2,
+                history_size: 3,
+                ..PasswordPolicy::default()
+            }
+        );
+    }
+
+    #[test]
+    fn should_reject_password_policies_that_cannot_apply() {
+        let file = "[passwords]\nmin_length = 6\nmax_length = 4\nmin_strength_score = 5";
+
+        let errors = errors(load(&[BASE, file], secrets()));
+
+        assert_eq!(
+            errors,
+            vec![
+                "`passwords.min_length` must be at least 8",
+                "`passwords.max_length` must not be below `passwords.min_length`",
+                "`passwords.min_strength_score` must be from 0 to 4",
+            ]
+        );
+    }
+
+    #[test]
+    fn should_read_cookie_attributes() {
+        let production = r#"
+            [auth.cookie]
+            name = "__Secure-jwt"
+            domain = ".Example.com"
+            path = "/auth"
+            secure = true
+            same_site = "none"
+            max_age_seconds = 3600
+        "#;
+
+        let cookie = load(&[BASE, production], secrets()).unwrap().auth.cookie;
+
+        assert_eq!(cookie.name, "__Secure-jwt");
+        assert_eq!(cookie.domain.as_deref(), Some("example.com"));
+        assert_eq!(cookie.path, "/auth");
+        assert!(cookie.secure);
+        assert_eq!(cookie.same_site, SameSite::None);
+        assert_eq!(cookie.max_age, Some(Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn should_reject_cookie_attributes_browsers_ignore() {
+        for (file, expected) in [
+            (
+                "[auth.cookie]\nsame_site = \"none\"",
+                "`auth.cookie.same_site` of `none` needs `auth.cookie.secure`",
+            ),
+            (
+                "[auth.cookie]\nname = \"__Host-jwt\"\nsecure = true\ndomain = \"example.com\"",
+                "`auth.cookie.name` with a `__Host-` prefix needs `auth.cookie.secure`, \
+                 no `auth.cookie.domain` and an `auth.cookie.path` of `/`",
+            ),
+            (
+                "[auth.cookie]\npath = \"auth\"",
+                "`auth.cookie.path` must be a path starting with `/`",
+            ),
+        ] {
+            let errors = errors(load(&[BASE, file], secrets()));
+
+            assert_eq!(errors, vec![expected.to_owned()]);
+        }
+    }
+
+    #[test]
+    fn should_read_tls_settings() {
+        let file =
+            "[application.tls]\ncertificate_path = \"cert.pem\"\nprivate_key_path = \"key.pem\"";
+        let mut vars = secrets();
+        vars.extend(self::vars(&[("TLS_REDIRECT_ADDRESS", "0.0.0.0:80")]));
+
+        let settings = load(&[BASE, file], vars).unwrap();
+
+        let tls = settings.application.tls.unwrap();
+        assert_eq!(tls.certificate_path, "cert.pem");
+        assert_eq!(tls.private_key_path, "key.pem");
+        assert_eq!(tls.redirect_address.as_deref(), Some("0.0.0.0:80"));
+    }
+
+    #[test]
+    fn should_reject_incomplete_tls_settings() {
+        for (file, expected) in [
+            (
+                "[application.tls]\ncertificate_path = \"cert.pem\"",
+                "`application.tls.private_key_path` must be set in a settings file or with \
+                 TLS_PRIVATE_KEY_PATH",
+            ),
+            (
+                "[application.tls]\nredirect_address = \"0.0.0.0:80\"",
+                "`application.tls.redirect_address` needs a certificate and private key",
+            ),
+            (
+                "[application]\npublic_url = \"http://auth.example.com\"\n\
+                 [application.tls]\ncertificate_path = \"cert.pem\"\n\
+                 private_key_path = \"key.pem\"\nredirect_address = \"0.0.0.0:80\"",
+                "`application.public_url` must be an https URL to redirect plain HTTP to",
+            ),
+        ] {
+            let errors = errors(load(&[BASE, file], secrets()));
+
+            assert_eq!(errors, vec![expected.to_owned()]);
+        }
+    }
+
+    #[test]
+    fn should_report_syntax_errors_with_file() {
+        let result = load(&[BASE, "[application\n"], secrets());
+
+        assert!(matches!(
+            result,
+            Err(SettingsError::Syntax { path, .. }) if path == "file1.toml"
+        ));
+    }
+}
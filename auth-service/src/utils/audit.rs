@@ -0,0 +1,83 @@
+use std::{
+    convert::Infallible,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Request, State},
+    http::{header, request::Parts},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::{
+    app_state::AppState,
+    domain::{AuditEventType, AuditOutcome, Email, NewAuditEvent},
+    utils::tracing::RequestId,
+};
+
+/// Lets a handler name the user its request acts on. The audit middleware records whoever was
+/// set here once the response is ready, so it is captured even when the handler bails out early.
+#[derive(Debug, Clone, Default)]
+pub struct AuditSubject(Arc<Mutex<Option<Email>>>);
+
+impl AuditSubject {
+    pub fn set(&self, email: &Email) {
+        *self.0.lock().expect("audit subject lock poisoned") = Some(email.clone());
+    }
+
+    fn take(&self) -> Option<Email> {
+        self.0.lock().expect("audit subject lock poisoned").take()
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for AuditSubject {
+    type Rejection = Infallible;
+
+    // Requests that aren't audited get a subject nobody reads
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts.extensions.get::<Self>().cloned().unwrap_or_default())
+    }
+}
+
+// Records an audit event for every request to an audited route, whatever its outcome
+pub async fn audit(State(state): State<AppState>, mut request: Request, next: Next) -> Response {
+    let Some(event_type) = AuditEventType::from_path(request.uri().path()) else {
+        return next.run(request).await;
+    };
+
+    let ip = state.rate_limiter.client_ip(&request);
+    let user_agent = request
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|user_agent| user_agent.to_str().ok())
+        .map(str::to_owned);
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .map(|RequestId(request_id)| request_id.clone());
+
+    let subject = AuditSubject::default();
+    request.extensions_mut().insert(subject.clone());
+
+    let response = next.run(request).await;
+
+    let event = NewAuditEvent {
+        event_type,
+        email: subject.take(),
+        ip: Some(ip),
+        user_agent,
+        request_id,
+        outcome: AuditOutcome::from_status(response.status()),
+        status_code: response.status().as_u16(),
+    };
+
+    // A failed write shouldn't undo a request that has already been handled
+    if let Err(e) = state.audit_log_store.append(event).await {
+        tracing::error!(error = ?e, "failed to record audit event");
+    }
+
+    response
+}
@@ -1,84 +1,255 @@
-use axum_extra::extract::cookie::{Cookie, SameSite};
+use std::time::Instant;
+
+use axum::http::{header, HeaderMap};
+use axum_extra::extract::{cookie::Cookie, CookieJar};
 use chrono::Utc;
+use hmac::{Hmac, Mac};
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Validation};
 use secrecy::{ExposeSecret, Secret};
 use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
 use color_eyre::eyre::{eyre, Context, ContextCompat, Result};
 
-use crate::{app_state::BannedTokenStoreType, domain::email::Email};
-
-use super::constants::{JWT_COOKIE_NAME, JWT_SECRET};
+use crate::{
+    app_state::BannedTokenStoreType,
+    domain::{email::Email, AuthAPIError, ScimError},
+    settings::{AuthSettings, CookieSettings},
+    utils::metrics,
+};
 
 #[tracing::instrument(name = "Generate Auth Cookie", skip_all)]
-pub fn generate_auth_cookie(email: &Email) -> Result<Cookie<'static>> {
-    let token = generate_auth_token(email)?;
-    Ok(create_auth_cookie(token))
+pub fn generate_auth_cookie(email: &Email, settings: &AuthSettings) -> Result<Cookie<'static>> {
+    let token = generate_auth_token(email, settings)?;
+    Ok(create_auth_cookie(token, &settings.cookie))
 }
 
 #[tracing::instrument(name = "Create Auth Cookie", skip_all)]
-fn create_auth_cookie(token: String) -> Cookie<'static> {
-    let cookie = Cookie::build((JWT_COOKIE_NAME, token))
-        .path("/")
-        .http_only(true)
-        .same_site(SameSite::Lax)
-        .build();
+fn create_auth_cookie(token: String, settings: &CookieSettings) -> Cookie<'static> {
+    let mut cookie = build_cookie(&settings.name, token, settings);
+    if let Some(max_age) = settings.max_age {
+        cookie.set_max_age(time::Duration::seconds(max_age.as_secs() as i64));
+    }
 
     cookie
 }
 
+/// The cookie that makes browsers drop the auth cookie
+pub fn auth_cookie_removal(settings: &CookieSettings) -> Cookie<'static> {
+    build_cookie(&settings.name, String::new(), settings)
+}
+
+/// An HTTP-only cookie with the configured domain, path, `Secure` and `SameSite` attributes,
+/// which every cookie the service sets shares
+pub fn build_cookie(name: &str, value: String, settings: &CookieSettings) -> Cookie<'static> {
+    let cookie = Cookie::build((name.to_owned(), value))
+        .path(settings.path.clone())
+        .http_only(true)
+        .secure(settings.secure)
+        .same_site(settings.same_site);
+
+    match &settings.domain {
+        Some(domain) => cookie.domain(domain.clone()).build(),
+        None => cookie.build(),
+    }
+}
+
 pub const TOKEN_TTL_SECONDS: i64 = 600;
+pub const REPORT_LOGIN_TOKEN_TTL_SECONDS: i64 = 24 * 60 * 60;
+pub const PASSWORD_RESET_TOKEN_TTL_SECONDS: i64 = TOKEN_TTL_SECONDS;
+// Outlives every token issued before a revocation
+pub const TOKEN_REVOCATION_TTL_SECONDS: i64 = REPORT_LOGIN_TOKEN_TTL_SECONDS;
 
 #[tracing::instrument(name = "Generate Auth Token", skip_all)]
-fn generate_auth_token(email: &Email) -> Result<String> {
-    let delta = chrono::Duration::try_seconds(TOKEN_TTL_SECONDS)
-        .wrap_err("failed to create 10 minute time delta")?;
+fn generate_auth_token(email: &Email, settings: &AuthSettings) -> Result<String> {
+    let claims = Claims::new(email, TOKEN_TTL_SECONDS)?;
+    create_token(&claims, settings.jwt_secret.expose_secret().as_bytes())
+}
 
-    let exp = Utc::now()
-        .checked_add_signed(delta)
-        .ok_or(eyre!("failed to add 10 minutes to current time"))?
-        .timestamp();
+#[tracing::instrument(name = "Validate Token", skip_all)]
+pub async fn validate_token(
+    token: &str,
+    settings: &AuthSettings,
+    banned_token_store: BannedTokenStoreType,
+) -> Result<Claims> {
+    let started = Instant::now();
+    let result = decode_token(
+        token,
+        settings.jwt_secret.expose_secret().as_bytes(),
+        banned_token_store,
+    )
+    .await;
+
+    let outcome = if result.is_ok() { "valid" } else { "invalid" };
+    metrics::TOKEN_VALIDATION_DURATION
+        .with_label_values(&[outcome])
+        .observe(started.elapsed().as_secs_f64());
 
-    let exp: usize = exp.try_into().wrap_err(format!(
-        "failed to cast exp time to usize. exp time: {}",
-        exp
-    ))?;
+    result
+}
 
-    let sub = email.as_ref().expose_secret().to_owned();
+/// What a single-purpose token sent by email lets its holder do. Each purpose signs its tokens
+/// with its own key, so they can't be passed off as one another or as session tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActionTokenPurpose {
+    ReportLogin,
+    PasswordReset,
+}
 
-    let claims = Claims { sub, exp };
+impl ActionTokenPurpose {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::ReportLogin => "report_login",
+            Self::PasswordReset => "password_reset",
+        }
+    }
 
-    create_token(&claims)
+    fn ttl_seconds(&self) -> i64 {
+        match self {
+            Self::ReportLogin => REPORT_LOGIN_TOKEN_TTL_SECONDS,
+            Self::PasswordReset => PASSWORD_RESET_TOKEN_TTL_SECONDS,
+        }
+    }
+
+    fn key(&self, settings: &AuthSettings) -> Result<Vec<u8>> {
+        derive_key(settings, self.as_str())
+    }
 }
 
-#[tracing::instrument(name = "Validate Token", skip_all)]
-pub async fn validate_token(
+/// A key for signing something other than session tokens, derived from the JWT secret so that
+/// each use gets its own without having to configure more secrets
+pub fn derive_key(settings: &AuthSettings, label: &str) -> Result<Vec<u8>> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(settings.jwt_secret.expose_secret().as_bytes())
+        .wrap_err("failed to derive key")?;
+    mac.update(label.as_bytes());
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+#[tracing::instrument(name = "Generate Action Token", skip_all)]
+pub fn generate_action_token(
+    email: &Email,
+    purpose: ActionTokenPurpose,
+    settings: &AuthSettings,
+) -> Result<Secret<String>> {
+    let claims = Claims::new(email, purpose.ttl_seconds())?;
+    create_token(&claims, &purpose.key(settings)?).map(Secret::new)
+}
+
+// Resolves an action token to the email of the user it was issued to, along with its claims.
+// Tokens are single-use, so the caller bans the token once it has acted on it.
+#[tracing::instrument(name = "Validate Action Token", skip_all)]
+pub async fn validate_action_token(
+    token: &Secret<String>,
+    purpose: ActionTokenPurpose,
+    settings: &AuthSettings,
+    banned_token_store: BannedTokenStoreType,
+) -> Result<(Email, Claims), AuthAPIError> {
+    let key = purpose
+        .key(settings)
+        .map_err(AuthAPIError::UnexpectedError)?;
+    let claims = decode_token(token.expose_secret(), &key, banned_token_store)
+        .await
+        .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let email =
+        Email::parse(Secret::new(claims.sub.clone())).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    Ok((email, claims))
+}
+
+// Checks the token's signature and expiry, and that it was neither banned nor revoked
+async fn decode_token(
     token: &str,
+    key: &[u8],
     banned_token_store: BannedTokenStoreType,
 ) -> Result<Claims> {
-    match banned_token_store.read().await.contains_token(&Secret::new(token.to_string())).await {
-        Ok(value) => {
-            if value {
-                return Err(eyre!("token is banned"));
-            }
-        }
-        Err(e) => return Err(e.into()),
+    if banned_token_store
+        .contains_token(&Secret::new(token.to_string()))
+        .await?
+    {
+        return Err(eyre!("token is banned"));
     }
 
-    decode::<Claims>(
+    let claims = decode::<Claims>(
         token,
-        &DecodingKey::from_secret(JWT_SECRET.expose_secret().as_bytes()),
+        &DecodingKey::from_secret(key),
         &Validation::default(),
     )
     .map(|data| data.claims)
-    .wrap_err("failed to decode token")
+    .wrap_err("failed to decode token")?;
+
+    let email = Email::parse(Secret::new(claims.sub.clone()))
+        .map_err(|_| eyre!("token subject is not an email"))?;
+    // Tokens issued in the same second as a revocation survive it, as `iat` has no finer
+    // resolution and a login made right after revoking shouldn't be undone
+    if let Some(revoked_before) = banned_token_store.tokens_revoked_before(&email).await? {
+        if (claims.iat as i64) < revoked_before {
+            return Err(eyre!("token was revoked"));
+        }
+    }
+
+    Ok(claims)
+}
+
+// Resolves the JWT cookie in `jar` to the email of the user it was issued to
+#[tracing::instrument(name = "Authenticate", skip_all)]
+pub async fn authenticate(
+    jar: &CookieJar,
+    settings: &AuthSettings,
+    banned_token_store: BannedTokenStoreType,
+) -> Result<Email, AuthAPIError> {
+    let token = jar
+        .get(&settings.cookie.name)
+        .ok_or(AuthAPIError::MissingToken)?
+        .value();
+
+    let claims = validate_token(token, settings, banned_token_store)
+        .await
+        .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    Email::parse(Secret::new(claims.sub)).map_err(|_| AuthAPIError::InvalidToken)
+}
+
+// Checks that the request carries the admin API token as a bearer token
+#[tracing::instrument(name = "Authenticate Admin", skip_all)]
+pub fn authenticate_admin(
+    headers: &HeaderMap,
+    settings: &AuthSettings,
+) -> Result<(), AuthAPIError> {
+    check_bearer_token(headers, &settings.admin_api_token)
+}
+
+// Checks that the request carries the SCIM API token as a bearer token
+#[tracing::instrument(name = "Authenticate SCIM Client", skip_all)]
+pub fn authenticate_scim(headers: &HeaderMap, settings: &AuthSettings) -> Result<(), ScimError> {
+    check_bearer_token(headers, &settings.scim_api_token).map_err(|_| ScimError::Unauthorized)
+}
+
+fn check_bearer_token(headers: &HeaderMap, expected: &Secret<String>) -> Result<(), AuthAPIError> {
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or(AuthAPIError::MissingToken)?;
+
+    let is_valid: bool = token
+        .as_bytes()
+        .ct_eq(expected.expose_secret().as_bytes())
+        .into();
+
+    if is_valid {
+        Ok(())
+    } else {
+        Err(AuthAPIError::InvalidToken)
+    }
 }
 
 #[tracing::instrument(name = "Create Token", skip_all)]
-fn create_token(claims: &Claims) -> Result<String> {
+fn create_token(claims: &Claims, key: &[u8]) -> Result<String> {
     encode(
         &jsonwebtoken::Header::default(),
         &claims,
-        &EncodingKey::from_secret(JWT_SECRET.expose_secret().as_bytes()),
+        &EncodingKey::from_secret(key),
     )
     .wrap_err("failed to create token")
 }
@@ -87,22 +258,75 @@ fn create_token(claims: &Claims) -> Result<String> {
 pub struct Claims {
     pub sub: String,
     pub exp: usize,
+    pub iat: usize,
+}
+
+impl Claims {
+    fn new(email: &Email, ttl_seconds: i64) -> Result<Self> {
+        let delta = chrono::Duration::try_seconds(ttl_seconds).wrap_err(format!(
+            "failed to create {} second time delta",
+            ttl_seconds
+        ))?;
+
+        let now = Utc::now();
+        let exp = now
+            .checked_add_signed(delta)
+            .ok_or(eyre!(
+                "failed to add {} seconds to current time",
+                ttl_seconds
+            ))?
+            .timestamp();
+
+        let exp: usize = exp.try_into().wrap_err(format!(
+            "failed to cast exp time to usize. exp time: {}",
+            exp
+        ))?;
+        let iat: usize = now
+            .timestamp()
+            .try_into()
+            .wrap_err("failed to cast iat time to usize")?;
+
+        let sub = email.as_ref().expose_secret().to_owned();
+
+        Ok(Self { sub, exp, iat })
+    }
+
+    /// How long a ban on the token has to last for it to stay unusable, allowing for the leeway
+    /// its expiry is checked with
+    pub fn ban_ttl_seconds(&self) -> u64 {
+        let usable_until = self.exp as u64 + Validation::default().leeway;
+        usable_until
+            .saturating_sub(Utc::now().timestamp() as u64)
+            .max(1)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::sync::Arc;
+    use std::{sync::Arc, time::Duration};
+    use axum_extra::extract::cookie::SameSite;
     use secrecy::Secret;
-    use tokio::sync::RwLock;
-
-    use crate::services::data_stores::HashsetBannedTokenStore;
+    use crate::{
+        domain::BannedTokenStore, services::data_stores::HashsetBannedTokenStore,
+        utils::constants::JWT_COOKIE_NAME,
+    };
 
     use super::*;
 
+    fn settings() -> AuthSettings {
+        AuthSettings {
+            jwt_secret: Secret::new("secret".to_owned()),
+            admin_api_token: Secret::new("admin-token".to_owned()),
+            scim_api_token: Secret::new("scim-token".to_owned()),
+            trusted_device_days: 30,
+            cookie: CookieSettings::default(),
+        }
+    }
+
     #[tokio::test]
     async fn test_generate_auth_cookie() {
         let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
-        let cookie = generate_auth_cookie(&email).unwrap();
+        let cookie = generate_auth_cookie(&email, &settings()).unwrap();
         assert_eq!(cookie.name(), JWT_COOKIE_NAME);
         assert_eq!(cookie.value().split('.').count(), 3);
         assert_eq!(cookie.path(), Some("/"));
@@ -113,27 +337,56 @@ mod tests {
     #[tokio::test]
     async fn test_create_auth_cookie() {
         let token = "test_token".to_owned();
-        let cookie = create_auth_cookie(token.clone());
+        let cookie = create_auth_cookie(token.clone(), &CookieSettings::default());
         assert_eq!(cookie.name(), JWT_COOKIE_NAME);
         assert_eq!(cookie.value(), token);
         assert_eq!(cookie.path(), Some("/"));
         assert_eq!(cookie.http_only(), Some(true));
         assert_eq!(cookie.same_site(), Some(SameSite::Lax));
+        assert_eq!(cookie.secure(), Some(false));
+        assert_eq!(cookie.domain(), None);
+        assert_eq!(cookie.max_age(), None);
+    }
+
+    #[tokio::test]
+    async fn test_create_auth_cookie_with_configured_attributes() {
+        let settings = CookieSettings {
+            name: "__Secure-session".to_owned(),
+            domain: Some("example.com".to_owned()),
+            path: "/auth".to_owned(),
+            secure: true,
+            same_site: SameSite::None,
+            max_age: Some(Duration::from_secs(3600)),
+        };
+        let cookie = create_auth_cookie("test_token".to_owned(), &settings);
+        assert_eq!(cookie.name(), "__Secure-session");
+        assert_eq!(cookie.domain(), Some("example.com"));
+        assert_eq!(cookie.path(), Some("/auth"));
+        assert_eq!(cookie.secure(), Some(true));
+        assert_eq!(cookie.same_site(), Some(SameSite::None));
+        assert_eq!(cookie.max_age(), Some(time::Duration::hours(1)));
+
+        let removal = auth_cookie_removal(&settings);
+        assert_eq!(removal.name(), "__Secure-session");
+        assert_eq!(removal.domain(), Some("example.com"));
+        assert_eq!(removal.path(), Some("/auth"));
     }
 
     #[tokio::test]
     async fn test_generate_auth_token() {
         let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
-        let result = generate_auth_token(&email).unwrap();
+        let result = generate_auth_token(&email, &settings()).unwrap();
         assert_eq!(result.split('.').count(), 3);
     }
 
     #[tokio::test]
     async fn test_validate_token_with_valid_token() {
         let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
-        let token = generate_auth_token(&email).unwrap();
-        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
-        let result = validate_token(&token, banned_token_store).await.unwrap();
+        let token = generate_auth_token(&email, &settings()).unwrap();
+        let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+        let result = validate_token(&token, &settings(), banned_token_store)
+            .await
+            .unwrap();
         assert_eq!(result.sub, "test@example.com");
 
         let exp = Utc::now()
@@ -147,16 +400,98 @@ mod tests {
     #[tokio::test]
     async fn test_validate_token_with_invalid_token() {
         let token = "invalid_token".to_owned();
-        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
-        let result = validate_token(&token, banned_token_store).await;
+        let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+        let result = validate_token(&token, &settings(), banned_token_store).await;
         assert!(result.is_err());
     }
 
     #[tokio::test]
     async fn test_validate_token_with_banned_token() {
         let token = "invalid_token".to_owned();
-        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
-        let result = validate_token(&token, banned_token_store).await;
+        let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+        let result = validate_token(&token, &settings(), banned_token_store).await;
         assert!(result.is_err());
     }
+
+    #[tokio::test]
+    async fn test_validate_token_with_revoked_token() {
+        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
+        let token = generate_auth_token(&email, &settings()).unwrap();
+        let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+
+        // Revoking in the same second the token was issued leaves it valid
+        let claims = validate_token(&token, &settings(), banned_token_store.clone())
+            .await
+            .unwrap();
+        let issued_at = claims.iat as i64;
+        banned_token_store
+            .revoke_tokens_issued_before(&email, issued_at)
+            .await
+            .unwrap();
+        assert!(
+            validate_token(&token, &settings(), banned_token_store.clone())
+                .await
+                .is_ok()
+        );
+
+        banned_token_store
+            .revoke_tokens_issued_before(&email, issued_at + 1)
+            .await
+            .unwrap();
+        assert!(validate_token(&token, &settings(), banned_token_store)
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_ban_outlasts_the_token() {
+        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
+        let claims = Claims::new(&email, REPORT_LOGIN_TOKEN_TTL_SECONDS).unwrap();
+
+        assert!(claims.ban_ttl_seconds() > REPORT_LOGIN_TOKEN_TTL_SECONDS as u64);
+    }
+
+    #[tokio::test]
+    async fn test_action_tokens_are_only_valid_for_their_purpose() {
+        let email = Email::parse(Secret::new("test@example.com".to_owned())).unwrap();
+        let banned_token_store = Arc::new(HashsetBannedTokenStore::default());
+        let token =
+            generate_action_token(&email, ActionTokenPurpose::ReportLogin, &settings()).unwrap();
+
+        let result = validate_action_token(
+            &token,
+            ActionTokenPurpose::ReportLogin,
+            &settings(),
+            banned_token_store.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.0, email);
+
+        let result = validate_action_token(
+            &token,
+            ActionTokenPurpose::PasswordReset,
+            &settings(),
+            banned_token_store.clone(),
+        )
+        .await;
+        assert!(matches!(result, Err(AuthAPIError::InvalidToken)));
+        assert!(validate_token(
+            token.expose_secret(),
+            &settings(),
+            banned_token_store.clone()
+        )
+        .await
+        .is_err());
+
+        let session_token = Secret::new(generate_auth_token(&email, &settings()).unwrap());
+        let result = validate_action_token(
+            &session_token,
+            ActionTokenPurpose::ReportLogin,
+            &settings(),
+            banned_token_store,
+        )
+        .await;
+        assert!(matches!(result, Err(AuthAPIError::InvalidToken)));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,440 @@
+//! Log formatting. Every field, whether on an event or on one of its spans, goes through
+//! `redact` before it's written, so credentials and email addresses never reach the logs.
+
+use std::fmt;
+
+use chrono::{SecondsFormat, Utc};
+use serde_json::{Map, Value};
+use tracing::{
+    field::{Field, Visit},
+    span, Event, Subscriber,
+};
+use tracing_subscriber::{
+    field::RecordFields,
+    fmt::{format::Writer, FmtContext, FormatEvent, FormatFields, FormattedFields},
+    registry::LookupSpan,
+};
+
+const REDACTED: &str = "[REDACTED]";
+
+// Fields with these names, or ending in `_<name>`, are never logged
+const SENSITIVE_SUFFIXES: &[&str] = &["password", "token", "secret", "email"];
+// Fields with exactly these names are never logged
+const SENSITIVE_NAMES: &[&str] = &[
+    "authorization",
+    "cookie",
+    "jar",
+    "code",
+    "pepper",
+    "peppers",
+];
+// Query parameters whose values are never logged, e.g. in the `uri` of a request span
+const SENSITIVE_PARAMETERS: &[&str] = &["token", "password", "code", "email"];
+
+/// Formats fields as `key=value` pairs for the compact format, or as a JSON object for the
+/// JSON format, redacting them either way
+#[derive(Debug, Clone, Copy)]
+pub struct RedactedFields {
+    json: bool,
+}
+
+impl RedactedFields {
+    pub fn text() -> Self {
+        Self { json: false }
+    }
+
+    pub fn json() -> Self {
+        Self { json: true }
+    }
+}
+
+impl<'writer> FormatFields<'writer> for RedactedFields {
+    fn format_fields<R: RecordFields>(
+        &self,
+        mut writer: Writer<'writer>,
+        fields: R,
+    ) -> fmt::Result {
+        let mut visitor = FieldVisitor::default();
+        fields.record(&mut visitor);
+
+        if self.json {
+            return write!(writer, "{}", Value::Object(visitor.fields));
+        }
+
+        let mut separator = "";
+        if let Some(message) = visitor.fields.remove("message") {
+            write!(writer, "{}", display(&message))?;
+            separator = " ";
+        }
+        for (name, value) in &visitor.fields {
+            write!(writer, "{}{}={}", separator, name, display(value))?;
+            separator = " ";
+        }
+        Ok(())
+    }
+
+    // Values recorded on a span after it was created are merged into its JSON object
+    fn add_fields(
+        &self,
+        current: &'writer mut FormattedFields<Self>,
+        fields: &span::Record<'_>,
+    ) -> fmt::Result {
+        if !self.json {
+            if !current.fields.is_empty() {
+                current.fields.push(' ');
+            }
+            return self.format_fields(current.as_writer(), fields);
+        }
+
+        let mut visitor = FieldVisitor {
+            fields: parse_object(&current.fields),
+        };
+        fields.record(&mut visitor);
+        current.fields = Value::Object(visitor.fields).to_string();
+        Ok(())
+    }
+}
+
+/// Writes each event as a single line of JSON, with the fields of the spans it happened in
+/// flattened into it so that `request_id`, `method` and `uri` sit next to `status` and
+/// `latency_ms`
+pub struct FlattenedJson;
+
+impl<S, N> FormatEvent<S, N> for FlattenedJson
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let metadata = event.metadata();
+        let mut line = Map::new();
+        line.insert(
+            "timestamp".to_owned(),
+            Utc::now()
+                .to_rfc3339_opts(SecondsFormat::Micros, true)
+                .into(),
+        );
+        line.insert("level".to_owned(), metadata.level().as_str().into());
+        line.insert("target".to_owned(), metadata.target().into());
+
+        // Inner spans' fields win over outer ones', and the event's over all of them
+        if let Some(scope) = ctx.event_scope() {
+            let mut innermost = None;
+            for span in scope.from_root() {
+                if let Some(fields) = span.extensions().get::<FormattedFields<N>>() {
+                    line.extend(parse_object(&fields.fields));
+                }
+                innermost = Some(span.name());
+            }
+            if let Some(name) = innermost {
+                line.insert("span".to_owned(), name.into());
+            }
+        }
+
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        line.extend(visitor.fields);
+
+        writeln!(writer, "{}", Value::Object(line))
+    }
+}
+
+#[derive(Default)]
+struct FieldVisitor {
+    fields: Map<String, Value>,
+}
+
+impl FieldVisitor {
+    fn insert(&mut self, field: &Field, value: Value) {
+        let value = match is_sensitive(field.name()) {
+            true => REDACTED.into(),
+            false => value,
+        };
+        self.fields.insert(field.name().to_owned(), value);
+    }
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, redact(value).into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.record_str(field, &format!("{:?}", value));
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        self.record_str(field, &value.to_string());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, value.into());
+    }
+}
+
+fn parse_object(fields: &str) -> Map<String, Value> {
+    match serde_json::from_str(fields) {
+        Ok(Value::Object(fields)) => fields,
+        _ => Map::new(),
+    }
+}
+
+fn display(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+fn is_sensitive(name: &str) -> bool {
+    let name = name.rsplit('.').next().unwrap_or(name).to_ascii_lowercase();
+    SENSITIVE_NAMES.contains(&name.as_str())
+        || SENSITIVE_SUFFIXES
+            .iter()
+            .any(|suffix| name == *suffix || name.ends_with(&format!("_{}", suffix)))
+}
+
+/// Masks email addresses and the values of sensitive query parameters in `value`
+pub fn redact(value: &str) -> String {
+    redact_emails(&redact_parameters(value))
+}
+
+fn redact_parameters(value: &str) -> String {
+    let mut redacted = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(start) = rest.find('=') {
+        let key_start = rest[..start]
+            .rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .map_or(0, |i| i + 1);
+        let is_sensitive = SENSITIVE_PARAMETERS.contains(&&rest[key_start..start])
+            && (key_start == 0 || rest[..key_start].ends_with(['?', '&', ' ']));
+
+        redacted.push_str(&rest[..=start]);
+        rest = &rest[start + 1..];
+
+        if is_sensitive {
+            let end = rest.find(['&', ' ', '"', '\'']).unwrap_or(rest.len());
+            redacted.push_str(REDACTED);
+            rest = &rest[end..];
+        }
+    }
+
+    redacted.push_str(rest);
+    redacted
+}
+
+fn redact_emails(value: &str) -> String {
+    let is_local = |c: char| c.is_ascii_alphanumeric() || "._%+-".contains(c);
+    let is_domain = |c: char| c.is_ascii_alphanumeric() || ".-".contains(c);
+
+    let mut redacted = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(at) = rest.find('@') {
+        let local_start = rest[..at]
+            .rfind(|c: char| !is_local(c))
+            .map_or(0, |i| i + 1);
+        let domain_end = rest[at + 1..]
+            .find(|c: char| !is_domain(c))
+            .map_or(rest.len(), |i| at + 1 + i);
+        let domain = rest[at + 1..domain_end].trim_end_matches('.');
+
+        if local_start < at && domain.contains('.') {
+            redacted.push_str(&rest[..local_start]);
+            redacted.push_str(REDACTED);
+            rest = &rest[at + 1 + domain.len()..];
+        } else {
+            redacted.push_str(&rest[..=at]);
+            rest = &rest[at + 1..];
+        }
+    }
+
+    redacted.push_str(rest);
+    redacted
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+
+    use secrecy::Secret;
+    use tracing_subscriber::fmt::MakeWriter;
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Output(Arc<Mutex<Vec<u8>>>);
+
+    impl Output {
+        fn lines(&self) -> Vec<String> {
+            String::from_utf8(self.0.lock().unwrap().clone())
+                .unwrap()
+                .lines()
+                .map(str::to_owned)
+                .collect()
+        }
+    }
+
+    impl io::Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Output {
+        type Writer = Self;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    fn log_request(output: &Output, json: bool) {
+        let layer = tracing_subscriber::fmt::layer()
+            .with_writer(output.clone())
+            .with_ansi(false);
+        let subscriber = tracing_subscriber::registry();
+        let _guard = match json {
+            true => {
+                tracing::subscriber::set_default(tracing_subscriber::layer::SubscriberExt::with(
+                    subscriber,
+                    layer
+                        .event_format(FlattenedJson)
+                        .fmt_fields(RedactedFields::json()),
+                ))
+            }
+            false => {
+                tracing::subscriber::set_default(tracing_subscriber::layer::SubscriberExt::with(
+                    subscriber,
+                    layer.compact().fmt_fields(RedactedFields::text()),
+                ))
+            }
+        };
+
+        let span = tracing::info_span!(
+            "[REQUEST]",
+            method = "GET",
+            uri = "/report-login?token=eyJhbGciOi.payload.sig&from=email",
+            request_id = "abc-123",
+            status = tracing::field::Empty,
+        );
+        let _entered = span.enter();
+        span.record("status", 200);
+
+        tracing::info!(
+            email = "user@example.com",
+            jwt_secret = ?Secret::new("secret".to_owned()),
+            latency_ms = 12u64,
+            "Sent a reset link to user@example.com"
+        );
+    }
+
+    #[test]
+    fn json_lines_have_span_fields_flattened_and_redacted() {
+        let output = Output::default();
+
+        log_request(&output, true);
+
+        let lines = output.lines();
+        assert_eq!(lines.len(), 1, "{:?}", lines);
+        let line: Value = serde_json::from_str(&lines[0]).unwrap();
+
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["span"], "[REQUEST]");
+        assert_eq!(line["request_id"], "abc-123");
+        assert_eq!(line["method"], "GET");
+        assert_eq!(line["uri"], "/report-login?token=[REDACTED]&from=email");
+        assert_eq!(line["status"], 200);
+        assert_eq!(line["latency_ms"], 12);
+        assert_eq!(line["email"], REDACTED);
+        assert_eq!(line["jwt_secret"], REDACTED);
+        assert_eq!(line["message"], "Sent a reset link to [REDACTED]");
+        assert!(line["timestamp"].as_str().is_some());
+    }
+
+    #[test]
+    fn compact_lines_are_redacted() {
+        let output = Output::default();
+
+        log_request(&output, false);
+
+        let lines = output.lines();
+        assert_eq!(lines.len(), 1, "{:?}", lines);
+        assert!(!lines[0].contains("user@example.com"), "{}", lines[0]);
+        assert!(!lines[0].contains("eyJhbGciOi"), "{}", lines[0]);
+        assert!(lines[0].contains("request_id=abc-123"), "{}", lines[0]);
+        assert!(lines[0].contains("latency_ms=12"), "{}", lines[0]);
+    }
+
+    #[test]
+    fn sensitive_field_names_are_recognised() {
+        for name in [
+            "password",
+            "new_password",
+            "token",
+            "auth_token",
+            "jwt_secret",
+            "email",
+            "error.email",
+            "cookie",
+            "code",
+        ] {
+            assert!(is_sensitive(name), "{}", name);
+        }
+        for name in [
+            "status",
+            "status_code",
+            "request_id",
+            "latency_ms",
+            "message",
+            "tokens",
+        ] {
+            assert!(!is_sensitive(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn values_are_redacted() {
+        for (value, expected) in [
+            ("no secrets here", "no secrets here"),
+            ("user@example.com", "[REDACTED]"),
+            ("Email(Secret([REDACTED]))", "Email(Secret([REDACTED]))"),
+            ("from a.b+c@mail.example.co.uk.", "from [REDACTED]."),
+            ("not@an-address", "not@an-address"),
+            ("@example.com", "@example.com"),
+            ("/verify?token=abc&x=1", "/verify?token=[REDACTED]&x=1"),
+            ("/path?next_token=abc", "/path?next_token=abc"),
+            (
+                "password=hunter2 and email=a@b.io",
+                "password=[REDACTED] and email=[REDACTED]",
+            ),
+        ] {
+            assert_eq!(redact(value), expected, "{}", value);
+        }
+    }
+}
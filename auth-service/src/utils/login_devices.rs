@@ -0,0 +1,261 @@
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header, request::Parts},
+};
+use axum_extra::extract::{cookie::Cookie, CookieJar};
+use chrono::Utc;
+use color_eyre::eyre::{Context, Result};
+use hmac::{Hmac, Mac};
+use secrecy::ExposeSecret;
+use sha2::Sha256;
+use std::convert::Infallible;
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    domain::{DeviceSighting, Email, KnownDeviceStoreError, LoginDevice, TrustedDevice},
+    settings::{AuthSettings, CookieSettings},
+    utils::{
+        auth::{build_cookie, derive_key, generate_action_token, ActionTokenPurpose},
+        constants::{DEVICE_COOKIE_MAX_AGE_DAYS, DEVICE_COOKIE_NAME, TRUSTED_DEVICE_COOKIE_NAME},
+    },
+};
+
+const TRUSTED_DEVICE_KEY_LABEL: &str = "trusted_device";
+
+#[async_trait]
+impl FromRequestParts<AppState> for LoginDevice {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let device_id = CookieJar::from_headers(&parts.headers)
+            .get(DEVICE_COOKIE_NAME)
+            .map(|cookie| cookie.value().to_owned());
+        let user_agent = parts
+            .headers
+            .get(header::USER_AGENT)
+            .and_then(|user_agent| user_agent.to_str().ok())
+            .map(str::to_owned);
+        let ip = state.rate_limiter.client_ip_from_parts(parts);
+
+        Ok(LoginDevice::new(device_id, user_agent, ip))
+    }
+}
+
+// Remembers the device a login succeeded from and tells the user about it if it's new to them.
+// Failures only get logged, as the user has already proven who they are.
+#[tracing::instrument(name = "Record Login Device", skip_all)]
+pub async fn record_login_device(
+    state: &AppState,
+    email: &Email,
+    device: &LoginDevice,
+    jar: CookieJar,
+) -> CookieJar {
+    let sighting = state.known_device_store.record_device(email, device).await;
+
+    match sighting {
+        Ok(DeviceSighting::New) => {
+            if let Err(e) = send_new_sign_in_email(state, email, device).await {
+                tracing::error!(error = ?e, "failed to send new sign-in email");
+            }
+        }
+        Ok(DeviceSighting::First | DeviceSighting::Known) => {}
+        Err(e) => tracing::error!(error = ?e, "failed to record login device"),
+    }
+
+    jar.add(create_device_cookie(&state.settings.auth.cookie, device))
+}
+
+fn create_device_cookie(settings: &CookieSettings, device: &LoginDevice) -> Cookie<'static> {
+    let mut cookie = build_cookie(DEVICE_COOKIE_NAME, device.device_id.clone(), settings);
+    cookie.set_max_age(time::Duration::days(DEVICE_COOKIE_MAX_AGE_DAYS));
+    cookie
+}
+
+/// Lets the user skip 2FA on `device` for the configured number of days, handing it a cookie
+/// that only works along with the device's own cookie. Like `record_login_device`, failures
+/// only get logged.
+#[tracing::instrument(name = "Trust Device", skip_all)]
+pub async fn trust_device(
+    state: &AppState,
+    email: &Email,
+    device: &LoginDevice,
+    jar: CookieJar,
+) -> CookieJar {
+    let days = state.settings.auth.trusted_device_days as i64;
+    if days == 0 {
+        return jar;
+    }
+
+    match create_trusted_device_cookie(state, email, device, days).await {
+        Ok(cookie) => jar.add(cookie),
+        Err(e) => {
+            tracing::error!(error = ?e, "failed to trust device");
+            jar
+        }
+    }
+}
+
+async fn create_trusted_device_cookie(
+    state: &AppState,
+    email: &Email,
+    device: &LoginDevice,
+    days: i64,
+) -> Result<Cookie<'static>> {
+    let trusted_device = TrustedDevice::new(device, chrono::Duration::days(days));
+    let value = format!(
+        "{}.{}",
+        trusted_device.id,
+        sign_trusted_device(&state.settings.auth, trusted_device.id, device)?
+    );
+
+    state
+        .known_device_store
+        .trust_device(email, trusted_device)
+        .await?;
+
+    let mut cookie = build_cookie(
+        TRUSTED_DEVICE_COOKIE_NAME,
+        value,
+        &state.settings.auth.cookie,
+    );
+    cookie.set_max_age(time::Duration::days(days));
+    Ok(cookie)
+}
+
+// Whether the user trusted `device` and hasn't revoked it since. Store failures fall back to 2FA.
+#[tracing::instrument(name = "Is Trusted Device", skip_all)]
+pub async fn is_trusted_device(
+    state: &AppState,
+    email: &Email,
+    device: &LoginDevice,
+    jar: &CookieJar,
+) -> bool {
+    let Some(id) = trusted_device_id(&state.settings.auth, jar, device) else {
+        return false;
+    };
+
+    match state.known_device_store.get_trusted_device(email, id).await {
+        Ok(_) => true,
+        Err(KnownDeviceStoreError::TrustedDeviceNotFound) => false,
+        Err(e) => {
+            tracing::error!(error = ?e, "failed to look up trusted device");
+            false
+        }
+    }
+}
+
+/// The trusted device the cookie in `jar` stands for, if the cookie was issued to `device`
+pub fn trusted_device_id(
+    settings: &AuthSettings,
+    jar: &CookieJar,
+    device: &LoginDevice,
+) -> Option<Uuid> {
+    let (id, signature) = jar
+        .get(TRUSTED_DEVICE_COOKIE_NAME)?
+        .value()
+        .split_once('.')?;
+    let id = Uuid::parse_str(id).ok()?;
+    let expected = sign_trusted_device(settings, id, device).ok()?;
+
+    let is_valid: bool = signature.as_bytes().ct_eq(expected.as_bytes()).into();
+    is_valid.then_some(id)
+}
+
+fn sign_trusted_device(settings: &AuthSettings, id: Uuid, device: &LoginDevice) -> Result<String> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(&derive_key(settings, TRUSTED_DEVICE_KEY_LABEL)?)
+        .wrap_err("failed to create trusted device signer")?;
+    mac.update(id.as_bytes());
+    mac.update(device.device_id.as_bytes());
+    Ok(format!("{:x}", mac.finalize().into_bytes()))
+}
+
+async fn send_new_sign_in_email(
+    state: &AppState,
+    email: &Email,
+    device: &LoginDevice,
+) -> Result<()> {
+    let token =
+        generate_action_token(email, ActionTokenPurpose::ReportLogin, &state.settings.auth)?;
+    let report_url = format!(
+        "{}/report-login?token={}",
+        state.settings.application.public_url,
+        token.expose_secret()
+    );
+
+    let content = format!(
+        "Your account was just signed in to from a device we haven't seen before.\n\n\
+         Time: {}\nDevice: {}\nNetwork: {}\n\n\
+         If this was you, there's nothing you need to do. If it wasn't, open the link below to \
+         sign out everywhere and choose a new password:\n\n{}",
+        Utc::now().format("%Y-%m-%d %H:%M UTC"),
+        device.user_agent.as_deref().unwrap_or("Unknown"),
+        device.ip_prefix(),
+        report_url
+    );
+
+    state
+        .email_client
+        .send_email(email, "New sign-in to your account", &content)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+
+    use super::*;
+
+    fn device(device_id: &str) -> LoginDevice {
+        LoginDevice::new(Some(device_id.to_owned()), None, "127.0.0.1".to_owned())
+    }
+
+    fn settings() -> AuthSettings {
+        AuthSettings {
+            jwt_secret: Secret::new("secret".to_owned()),
+            admin_api_token: Secret::new("admin-token".to_owned()),
+            scim_api_token: Secret::new("scim-token".to_owned()),
+            trusted_device_days: 30,
+            cookie: CookieSettings::default(),
+        }
+    }
+
+    fn jar_with(value: String) -> CookieJar {
+        CookieJar::new().add(Cookie::new(TRUSTED_DEVICE_COOKIE_NAME, value))
+    }
+
+    #[test]
+    fn should_only_accept_trusted_device_cookie_on_its_device() {
+        let trusted = device("abcdefghijklmnopqrstuvwxyz012345");
+        let other = device("012345abcdefghijklmnopqrstuvwxyz");
+        let id = Uuid::new_v4();
+        let signature = sign_trusted_device(&settings(), id, &trusted).unwrap();
+
+        let jar = jar_with(format!("{}.{}", id, signature));
+        assert_eq!(trusted_device_id(&settings(), &jar, &trusted), Some(id));
+        assert_eq!(trusted_device_id(&settings(), &jar, &other), None);
+
+        for value in [
+            format!("{}.{}", Uuid::new_v4(), signature),
+            format!("{}.", id),
+            id.to_string(),
+            "not-a-cookie".to_owned(),
+        ] {
+            assert_eq!(
+                trusted_device_id(&settings(), &jar_with(value.clone()), &trusted),
+                None,
+                "{}",
+                value
+            );
+        }
+        assert_eq!(
+            trusted_device_id(&settings(), &CookieJar::new(), &trusted),
+            None
+        );
+    }
+}
@@ -0,0 +1,216 @@
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, Request},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use lazy_static::lazy_static;
+use prometheus::{
+    core::{Collector, Desc},
+    proto::MetricFamily,
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+use sqlx::PgPool;
+
+const NAMESPACE: &str = "auth";
+
+// Argon2 takes tens of milliseconds with the default parameters, and much longer when tuned up
+const PASSWORD_HASH_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5];
+const TOKEN_VALIDATION_BUCKETS: &[f64] = &[0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1];
+
+lazy_static! {
+    static ref REGISTRY: Registry =
+        Registry::new_custom(Some(NAMESPACE.to_owned()), None).expect("valid registry");
+    pub static ref HTTP_REQUESTS: IntCounterVec = register(IntCounterVec::new(
+        Opts::new("http_requests_total", "HTTP requests handled, by route and status"),
+        &["method", "route", "status"],
+    ));
+    pub static ref HTTP_REQUEST_DURATION: HistogramVec = register(HistogramVec::new(
+        HistogramOpts::new(
+            "http_request_duration_seconds",
+            "Time taken to handle HTTP requests, by route",
+        ),
+        &["method", "route"],
+    ));
+    pub static ref SIGNUPS: IntCounter = register(IntCounter::new(
+        "signups_total",
+        "Accounts created through signup",
+    ));
+    /// Logins by outcome: `success`, `two_factor_required` or `failed`
+    pub static ref LOGINS: IntCounterVec = register(IntCounterVec::new(
+        Opts::new("logins_total", "Login attempts, by outcome"),
+        &["outcome"],
+    ));
+    /// 2FA challenges by event: `sent`, `resent`, `verified` or `failed`
+    pub static ref TWO_FA_CHALLENGES: IntCounterVec = register(IntCounterVec::new(
+        Opts::new("two_fa_challenges_total", "2FA challenges, by event"),
+        &["event"],
+    ));
+    /// Banned tokens by reason: `logout`, `password_reset` or `report_login`
+    pub static ref TOKENS_BANNED: IntCounterVec = register(IntCounterVec::new(
+        Opts::new("tokens_banned_total", "Tokens banned before they expired, by reason"),
+        &["reason"],
+    ));
+    pub static ref TOKEN_VALIDATION_DURATION: HistogramVec = register(HistogramVec::new(
+        HistogramOpts::new(
+            "token_validation_duration_seconds",
+            "Time taken to validate session tokens, by outcome",
+        )
+        .buckets(TOKEN_VALIDATION_BUCKETS.to_vec()),
+        &["outcome"],
+    ));
+    /// Password hashing by operation: `hash` or `verify`
+    pub static ref PASSWORD_HASH_DURATION: HistogramVec = register(HistogramVec::new(
+        HistogramOpts::new(
+            "password_hash_duration_seconds",
+            "Time spent hashing and verifying passwords, by operation",
+        )
+        .buckets(PASSWORD_HASH_BUCKETS.to_vec()),
+        &["operation"],
+    ));
+}
+
+fn register<T: Collector + Clone + 'static>(metric: prometheus::Result<T>) -> T {
+    let metric = metric.expect("valid metric");
+    REGISTRY
+        .register(Box::new(metric.clone()))
+        .expect("metric registered once");
+    metric
+}
+
+/// Reports how many of the pool's connections are in use when metrics are scraped
+pub fn register_postgres_pool(pool: PgPool) -> prometheus::Result<()> {
+    REGISTRY.register(Box::new(PostgresPoolCollector::new(pool)?))
+}
+
+struct PostgresPoolCollector {
+    pool: PgPool,
+    active: IntGauge,
+    idle: IntGauge,
+    max: IntGauge,
+}
+
+impl PostgresPoolCollector {
+    fn new(pool: PgPool) -> prometheus::Result<Self> {
+        Ok(Self {
+            pool,
+            active: IntGauge::new("db_pool_active_connections", "Connections in use")?,
+            idle: IntGauge::new("db_pool_idle_connections", "Open connections not in use")?,
+            max: IntGauge::new("db_pool_max_connections", "Connections the pool may open")?,
+        })
+    }
+}
+
+impl Collector for PostgresPoolCollector {
+    fn desc(&self) -> Vec<&Desc> {
+        [&self.active, &self.idle, &self.max]
+            .into_iter()
+            .flat_map(|gauge| gauge.desc())
+            .collect()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        let idle = self.pool.num_idle() as i64;
+        self.active.set(self.pool.size() as i64 - idle);
+        self.idle.set(idle);
+        self.max
+            .set(self.pool.options().get_max_connections() as i64);
+
+        [&self.active, &self.idle, &self.max]
+            .into_iter()
+            .flat_map(|gauge| gauge.collect())
+            .collect()
+    }
+}
+
+// Counts and times every request by the route that matched it rather than its path, so that
+// IDs in paths don't create a series each
+pub async fn record_request_metrics(request: Request, next: Next) -> Response {
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned())
+        .unwrap_or_else(|| "unmatched".to_owned());
+    let started = Instant::now();
+
+    let response = next.run(request).await;
+
+    HTTP_REQUEST_DURATION
+        .with_label_values(&[&method, &route])
+        .observe(started.elapsed().as_secs_f64());
+    HTTP_REQUESTS
+        .with_label_values(&[&method, &route, response.status().as_str()])
+        .inc();
+
+    response
+}
+
+#[tracing::instrument(name = "Metrics", skip_all)]
+pub async fn metrics() -> Response {
+    let mut buffer = vec![];
+    let encoder = TextEncoder::new();
+    if let Err(e) = encoder.encode(&REGISTRY.gather(), &mut buffer) {
+        tracing::error!("Failed to encode metrics: {}", e);
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    (
+        [(header::CONTENT_TYPE, encoder.format_type().to_owned())],
+        buffer,
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::postgres::PgPoolOptions;
+
+    use super::*;
+
+    fn gather(registry: &Registry) -> String {
+        let mut buffer = vec![];
+        TextEncoder::new()
+            .encode(&registry.gather(), &mut buffer)
+            .unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[tokio::test]
+    async fn pool_collector_reports_connection_usage() {
+        let pool = PgPoolOptions::new()
+            .max_connections(7)
+            .connect_lazy("postgres://localhost/unused")
+            .unwrap();
+        let registry = Registry::new();
+        registry
+            .register(Box::new(PostgresPoolCollector::new(pool).unwrap()))
+            .unwrap();
+
+        let output = gather(&registry);
+
+        assert!(
+            output.contains("db_pool_active_connections 0"),
+            "{}",
+            output
+        );
+        assert!(output.contains("db_pool_idle_connections 0"), "{}", output);
+        assert!(output.contains("db_pool_max_connections 7"), "{}", output);
+    }
+
+    #[test]
+    fn metrics_are_namespaced() {
+        SIGNUPS.inc();
+
+        let output = gather(&REGISTRY);
+
+        assert!(
+            output.contains("# TYPE auth_signups_total counter"),
+            "{}",
+            output
+        );
+    }
+}
@@ -1,3 +1,14 @@
+pub mod audit;
 pub mod constants;
 pub mod auth;
-pub mod tracing;
\ No newline at end of file
+pub mod logging;
+pub mod login_devices;
+pub mod metrics;
+pub mod passwords;
+pub mod rate_limit;
+pub mod scim;
+pub mod shutdown;
+pub mod tls;
+pub mod tracing;
+pub mod two_fa;
+pub mod webhooks;
@@ -0,0 +1,30 @@
+use crate::{
+    app_state::AppState,
+    domain::{Email, Password, PasswordPolicyViolation},
+};
+
+// Checks a password a user is about to set against the password policy and, if one is
+// configured, the breach corpus
+pub fn check_new_password(
+    state: &AppState,
+    password: &Password,
+    email: &Email,
+) -> Result<(), Vec<PasswordPolicyViolation>> {
+    let mut violations = state
+        .password_policy
+        .check(password, email)
+        .err()
+        .unwrap_or_default();
+
+    if let Some(breached_password_check) = &state.breached_password_check {
+        if let Err(violation) = breached_password_check.check(password) {
+            violations.push(violation);
+        }
+    }
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(violations)
+    }
+}
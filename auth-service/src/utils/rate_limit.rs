@@ -0,0 +1,208 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    body::{to_bytes, Body},
+    extract::{ConnectInfo, Request, State},
+    http::{request::Parts, Extensions, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{
+    app_state::RateLimitStoreType,
+    domain::{
+        api_key_digest, AuthAPIError, RateLimitConfig, RateLimitDecision, RateLimitKey,
+        RateLimitRule,
+    },
+};
+
+// Matches axum's default request body limit, which the `Json` extractor applies anyway
+const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+const API_KEY: HeaderName = HeaderName::from_static("x-api-key");
+
+#[derive(Clone)]
+pub struct RateLimiter {
+    store: RateLimitStoreType,
+    config: Arc<RateLimitConfig>,
+}
+
+impl RateLimiter {
+    pub fn new(store: RateLimitStoreType, config: RateLimitConfig) -> Self {
+        Self {
+            store,
+            config: Arc::new(config),
+        }
+    }
+
+    /// The address of the client that sent `request`, honouring `trust_forwarded_for`
+    pub fn client_ip(&self, request: &Request) -> String {
+        client_ip(
+            request.headers(),
+            request.extensions(),
+            self.config.trust_forwarded_for,
+        )
+    }
+
+    /// Like `client_ip`, for extractors that only see the request's parts
+    pub fn client_ip_from_parts(&self, parts: &Parts) -> String {
+        client_ip(
+            &parts.headers,
+            &parts.extensions,
+            self.config.trust_forwarded_for,
+        )
+    }
+
+    fn is_exempt(&self, headers: &HeaderMap) -> bool {
+        headers.get(API_KEY).is_some_and(|api_key| {
+            self.config
+                .exempt_api_keys
+                .contains(&api_key_digest(api_key.as_bytes()))
+        })
+    }
+
+    // Takes a token from the bucket `rule` assigns the request to. Store failures let the
+    // request through rather than locking every client out while Redis is unavailable.
+    async fn check(
+        &self,
+        scope: &str,
+        rule: &RateLimitRule,
+        client_ip: &str,
+        headers: &HeaderMap,
+        email: Option<&str>,
+    ) -> Option<RateLimitDecision> {
+        let key = match &rule.key {
+            RateLimitKey::Ip => None,
+            RateLimitKey::Email => email.map(|email| format!("email:{}", email)),
+            RateLimitKey::ApiKey { header } => headers
+                .get(header.as_str())
+                .map(|api_key| format!("api_key:{}", api_key_digest(api_key.as_bytes()))),
+        }
+        .unwrap_or_else(|| format!("ip:{}", client_ip));
+
+        match self
+            .store
+            .take_token(&format!("{}:{}", scope, key), &rule.policy)
+            .await
+        {
+            Ok(decision) => Some(decision),
+            Err(e) => {
+                tracing::error!(error = ?e, "rate limit store unavailable, allowing request");
+                None
+            }
+        }
+    }
+}
+
+#[tracing::instrument(name = "Rate Limit", skip_all)]
+pub async fn rate_limit(
+    State(rate_limiter): State<RateLimiter>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let config = rate_limiter.config.clone();
+    let route_rule = config.routes.get(request.uri().path());
+
+    if (config.global.is_none() && route_rule.is_none())
+        || rate_limiter.is_exempt(request.headers())
+    {
+        return next.run(request).await;
+    }
+
+    let client_ip = rate_limiter.client_ip(&request);
+
+    // Keying by email means peeking at the JSON body, which then has to be put back
+    let (request, email) = match route_rule {
+        Some(rule) if rule.key == RateLimitKey::Email => {
+            let (parts, body) = request.into_parts();
+            let bytes = match to_bytes(body, MAX_BODY_BYTES).await {
+                Ok(bytes) => bytes,
+                Err(_) => return StatusCode::PAYLOAD_TOO_LARGE.into_response(),
+            };
+            let email = serde_json::from_slice::<serde_json::Value>(&bytes)
+                .ok()
+                .and_then(|body| {
+                    body.get("email")?
+                        .as_str()
+                        .map(|email| email.trim().to_lowercase())
+                });
+            (Request::from_parts(parts, Body::from(bytes)), email)
+        }
+        _ => (request, None),
+    };
+
+    let mut decisions = Vec::with_capacity(2);
+    if let Some(rule) = &config.global {
+        decisions.extend(
+            rate_limiter
+                .check("global", rule, &client_ip, request.headers(), None)
+                .await,
+        );
+    }
+    if let Some(rule) = route_rule {
+        let scope = format!("route:{}", request.uri().path());
+        decisions.extend(
+            rate_limiter
+                .check(
+                    &scope,
+                    rule,
+                    &client_ip,
+                    request.headers(),
+                    email.as_deref(),
+                )
+                .await,
+        );
+    }
+
+    let rejection = decisions
+        .iter()
+        .filter(|decision| !decision.allowed)
+        .max_by_key(|decision| decision.retry_after_seconds);
+
+    // Report the bucket closest to running out
+    let (decision, mut response) = match rejection {
+        Some(decision) => {
+            let error = AuthAPIError::TooManyRequests {
+                retry_after_seconds: decision.retry_after_seconds,
+            };
+            (Some(decision), error.into_response())
+        }
+        None => (
+            decisions.iter().min_by_key(|decision| decision.remaining),
+            next.run(request).await,
+        ),
+    };
+
+    if let Some(decision) = decision {
+        let headers = response.headers_mut();
+        headers.insert(RATELIMIT_LIMIT, HeaderValue::from(decision.limit));
+        headers.insert(RATELIMIT_REMAINING, HeaderValue::from(decision.remaining));
+        headers.insert(
+            RATELIMIT_RESET,
+            HeaderValue::from(decision.reset_after_seconds),
+        );
+    }
+
+    response
+}
+
+fn client_ip(headers: &HeaderMap, extensions: &Extensions, trust_forwarded_for: bool) -> String {
+    // The proxy in front of us appends the address it saw, so only the last entry is trustworthy
+    let forwarded_for = trust_forwarded_for
+        .then(|| headers.get("x-forwarded-for")?.to_str().ok())
+        .flatten()
+        .and_then(|value| value.rsplit(',').next())
+        .map(|ip| ip.trim().to_owned())
+        .filter(|ip| !ip.is_empty());
+
+    forwarded_for
+        .or_else(|| {
+            extensions
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(addr)| addr.ip().to_string())
+        })
+        .unwrap_or_else(|| "unknown".to_owned())
+}
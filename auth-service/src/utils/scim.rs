@@ -0,0 +1,51 @@
+use axum::{
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::domain::{ScimError, ScimFilter};
+
+pub const SCIM_CONTENT_TYPE: &str = "application/scim+json";
+
+const DEFAULT_PAGE_SIZE: usize = 100;
+pub const MAX_PAGE_SIZE: usize = 200;
+
+/// A JSON response served as `application/scim+json`
+pub struct ScimJson<T>(pub StatusCode, pub T);
+
+impl<T: Serialize> IntoResponse for ScimJson<T> {
+    fn into_response(self) -> Response {
+        let mut response = (self.0, Json(self.1)).into_response();
+        response.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(SCIM_CONTENT_TYPE),
+        );
+        response
+    }
+}
+
+/// Query parameters of a SCIM list request
+#[derive(Debug, Deserialize)]
+pub struct ScimListQuery {
+    pub filter: Option<String>,
+    /// 1-based index of the first resource to return
+    #[serde(rename = "startIndex")]
+    pub start_index: Option<usize>,
+    pub count: Option<usize>,
+}
+
+impl ScimListQuery {
+    pub fn filter(&self) -> Result<Option<ScimFilter>, ScimError> {
+        self.filter.as_deref().map(ScimFilter::parse).transpose()
+    }
+
+    /// The 1-based start index, along with the offset and limit it translates to
+    pub fn page(&self) -> (usize, usize, usize) {
+        // Out of range values are interpreted as the nearest valid one (RFC 7644 section 3.4.2.4)
+        let start_index = self.start_index.unwrap_or(1).max(1);
+        let count = self.count.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+        (start_index, start_index - 1, count)
+    }
+}
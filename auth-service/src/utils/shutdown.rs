@@ -0,0 +1,102 @@
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+/// Tells whatever holds a clone of it to stop. Triggering it more than once has no further effect.
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl ShutdownHandle {
+    pub fn new() -> Self {
+        Self {
+            sender: Arc::new(watch::channel(false).0),
+        }
+    }
+
+    pub fn trigger(&self) {
+        self.sender.send_replace(true);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    /// Resolves once the handle has been triggered, straight away if it already has been
+    pub async fn triggered(&self) {
+        let mut receiver = self.sender.subscribe();
+        // Can't fail, as this handle keeps the sender alive
+        let _ = receiver.wait_for(|triggered| *triggered).await;
+    }
+}
+
+impl Default for ShutdownHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Resolves when the process is asked to stop, with Ctrl+C or by the container runtime's SIGTERM
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Failed to listen for Ctrl+C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => tracing::info!("Received Ctrl+C, shutting down"),
+        _ = terminate => tracing::info!("Received SIGTERM, shutting down"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn clones_are_triggered_together() {
+        let handle = ShutdownHandle::new();
+        let clone = handle.clone();
+        let waiting = tokio::spawn(async move { clone.triggered().await });
+
+        assert!(!handle.is_triggered());
+        handle.trigger();
+
+        tokio::time::timeout(Duration::from_secs(1), waiting)
+            .await
+            .expect("waiter was not woken")
+            .unwrap();
+        assert!(handle.is_triggered());
+    }
+
+    #[tokio::test]
+    async fn waiting_after_trigger_resolves_immediately() {
+        let handle = ShutdownHandle::new();
+        handle.trigger();
+        handle.trigger();
+
+        tokio::time::timeout(Duration::from_millis(100), handle.triggered())
+            .await
+            .expect("already triggered");
+    }
+}
@@ -0,0 +1,205 @@
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use axum::{extract::State, http::Uri, response::Redirect};
+use axum_server::tls_rustls::RustlsConfig;
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use rustls::{crypto::ring, ServerConfig};
+
+use crate::settings::TlsSettings;
+
+/// Loads the certificate chain and private key the service presents to clients
+pub fn rustls_config(settings: &TlsSettings) -> Result<RustlsConfig> {
+    Ok(RustlsConfig::from_config(server_config(settings)?))
+}
+
+fn server_config(settings: &TlsSettings) -> Result<Arc<ServerConfig>> {
+    let certificates = rustls_pemfile::certs(&mut open(&settings.certificate_path)?)
+        .collect::<Result<Vec<_>, _>>()
+        .wrap_err_with(|| format!("Invalid certificate in {}", settings.certificate_path))?;
+    if certificates.is_empty() {
+        return Err(eyre!("No certificate in {}", settings.certificate_path));
+    }
+    let private_key = rustls_pemfile::private_key(&mut open(&settings.private_key_path)?)
+        .wrap_err_with(|| format!("Invalid private key in {}", settings.private_key_path))?
+        .ok_or_else(|| eyre!("No private key in {}", settings.private_key_path))?;
+
+    // The provider is chosen here rather than installed process-wide, as other dependencies
+    // bring in rustls too
+    let mut config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certificates, private_key)
+        .wrap_err("The private key doesn't match the certificate")?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(Arc::new(config))
+}
+
+fn open(path: &str) -> Result<BufReader<File>> {
+    let file = File::open(path).wrap_err_with(|| format!("Failed to open {}", path))?;
+    Ok(BufReader::new(file))
+}
+
+/// Reloads the certificate and key into `config` whenever either changes on disk, until the
+/// returned watcher is dropped. The current ones are kept if the new ones don't load, e.g. when
+/// only one of the two files has been replaced so far.
+pub fn watch_certificates(
+    config: RustlsConfig,
+    settings: TlsSettings,
+) -> Result<RecommendedWatcher> {
+    // Directories are watched rather than the files themselves, as renewals usually replace
+    // files rather than write to them, e.g. by renaming or by swapping a Kubernetes secret's
+    // symlink
+    let directories: BTreeSet<PathBuf> = [&settings.certificate_path, &settings.private_key_path]
+        .into_iter()
+        .map(|path| match Path::new(path).parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_owned(),
+            _ => PathBuf::from("."),
+        })
+        .collect();
+
+    let mut watcher =
+        notify::recommended_watcher(move |event: notify::Result<Event>| match event {
+            // Reading the files to reload them is an access too
+            Ok(event) if event.kind.is_access() => {}
+            Ok(_) => reload(&config, &settings),
+            Err(e) => tracing::error!("Failed to watch TLS certificate: {}", e),
+        })?;
+    for directory in &directories {
+        watcher
+            .watch(directory, RecursiveMode::NonRecursive)
+            .wrap_err_with(|| format!("Failed to watch {}", directory.display()))?;
+    }
+
+    Ok(watcher)
+}
+
+fn reload(config: &RustlsConfig, settings: &TlsSettings) {
+    match server_config(settings) {
+        Ok(server_config) => {
+            config.reload_from_config(server_config);
+            tracing::info!(
+                "Reloaded TLS certificate from {}",
+                settings.certificate_path
+            );
+        }
+        Err(e) => tracing::warn!("Kept the current TLS certificate: {:?}", e),
+    }
+}
+
+// Sends plain HTTP requests to the same path and query under the service's public HTTPS URL.
+// The URL is configured rather than taken from the Host header, which the client controls.
+pub async fn redirect_to_https(State(public_url): State<Arc<str>>, uri: Uri) -> Redirect {
+    let path = uri.path_and_query().map_or("/", |path| path.as_str());
+    Redirect::permanent(&format!("{}{}", public_url, path))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rcgen::{generate_simple_self_signed, CertifiedKey};
+
+    use super::*;
+
+    struct Files {
+        directory: PathBuf,
+        settings: TlsSettings,
+    }
+
+    impl Files {
+        fn new() -> Self {
+            let directory = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+            std::fs::create_dir_all(&directory).unwrap();
+            let path = |name: &str| directory.join(name).to_string_lossy().into_owned();
+            let settings = TlsSettings {
+                certificate_path: path("cert.pem"),
+                private_key_path: path("key.pem"),
+                redirect_address: None,
+            };
+            Self {
+                directory,
+                settings,
+            }
+        }
+
+        fn write(&self, certified_key: &CertifiedKey) {
+            std::fs::write(&self.settings.certificate_path, certified_key.cert.pem()).unwrap();
+            std::fs::write(
+                &self.settings.private_key_path,
+                certified_key.key_pair.serialize_pem(),
+            )
+            .unwrap();
+        }
+    }
+
+    impl Drop for Files {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.directory);
+        }
+    }
+
+    fn certified_key() -> CertifiedKey {
+        generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap()
+    }
+
+    #[test]
+    fn loads_certificate_and_key() {
+        let files = Files::new();
+        files.write(&certified_key());
+
+        let config = server_config(&files.settings).unwrap();
+
+        assert_eq!(config.alpn_protocols[1], b"http/1.1");
+    }
+
+    #[test]
+    fn rejects_missing_and_mismatched_files() {
+        let files = Files::new();
+
+        let error = server_config(&files.settings).unwrap_err();
+        assert!(error.to_string().starts_with("Failed to open"), "{}", error);
+
+        std::fs::write(&files.settings.certificate_path, certified_key().cert.pem()).unwrap();
+        std::fs::write(
+            &files.settings.private_key_path,
+            certified_key().key_pair.serialize_pem(),
+        )
+        .unwrap();
+        let error = server_config(&files.settings).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The private key doesn't match the certificate"
+        );
+
+        std::fs::write(&files.settings.private_key_path, "").unwrap();
+        let error = server_config(&files.settings).unwrap_err();
+        assert!(error.to_string().starts_with("No private key"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn reloads_replaced_certificate() {
+        let files = Files::new();
+        files.write(&certified_key());
+        let config = rustls_config(&files.settings).unwrap();
+        let original = config.get_inner();
+        let _watcher = watch_certificates(config.clone(), files.settings.clone()).unwrap();
+
+        files.write(&certified_key());
+
+        for _ in 0..100 {
+            if !Arc::ptr_eq(&original, &config.get_inner()) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("Certificate was not reloaded");
+    }
+}
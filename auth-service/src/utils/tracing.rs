@@ -1,16 +1,64 @@
 use std::time::Duration;
 
-use axum::{body::Body, extract::Request, response::Response};
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{HeaderMap, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use opentelemetry::{
+    global,
+    propagation::{Extractor, Injector},
+    trace::{TraceContextExt, TracerProvider as _},
+    KeyValue,
+};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    runtime,
+    trace::{Config, Tracer, TracerProvider},
+    Resource,
+};
 use tracing::{Level, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 
 use color_eyre::eyre::Result;
 use tracing_error::ErrorLayer;
 use tracing_subscriber::prelude::*;
 use tracing_subscriber::{fmt, EnvFilter};
 
-pub fn init_tracing() -> Result<()> {
-    // Create a formatting layer for tracing output with a compact format
-    let fmt_layer = fmt::layer().compact();
+use crate::settings::{LogFormat, TelemetrySettings};
+
+use super::logging::{FlattenedJson, RedactedFields};
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+// Longer inbound request IDs are replaced rather than stored in every log line and audit event
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+pub fn init_tracing(settings: &TelemetrySettings) -> Result<()> {
+    // Create a formatting layer for tracing output in the configured format. Both redact
+    // secrets and email addresses from every field they write.
+    let (compact_layer, json_layer) = match settings.log_format {
+        LogFormat::Compact => (
+            Some(fmt::layer().compact().fmt_fields(RedactedFields::text())),
+            None,
+        ),
+        LogFormat::Json => (
+            None,
+            Some(
+                fmt::layer()
+                    .event_format(FlattenedJson)
+                    .fmt_fields(RedactedFields::json()),
+            ),
+        ),
+    };
+
+    // Spans join the trace of the request that caused them, and are exported if a collector
+    // is configured
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    let telemetry_layer = tracing_opentelemetry::layer().with_tracer(init_tracer(settings)?);
 
     // Create a filter layer to control the verbosity of logs
     // Try to get the filter configuration from the environment variables
@@ -21,25 +69,141 @@ pub fn init_tracing() -> Result<()> {
     // the filter layer, and the error layer for enhanced error reporting
     tracing_subscriber::registry()
         .with(filter_layer) // Add the filter layer to control log verbosity
-        .with(fmt_layer) // Add the formatting layer for compact log output
+        .with(compact_layer) // Add the formatting layer for log output, whichever is enabled
+        .with(json_layer)
         .with(ErrorLayer::default()) // Add the error layer to capture error contexts
+        .with(telemetry_layer)
         .init(); // Initialize the tracing subscriber
 
     Ok(())
 }
 
+fn init_tracer(settings: &TelemetrySettings) -> Result<Tracer> {
+    let config = Config::default().with_resource(Resource::new([KeyValue::new(
+        "service.name",
+        settings.service_name.clone(),
+    )]));
+    let mut builder = TracerProvider::builder().with_config(config);
+
+    if let Some(endpoint) = &settings.otlp_endpoint {
+        let exporter = opentelemetry_otlp::new_exporter()
+            .http()
+            .with_endpoint(endpoint)
+            .build_span_exporter()?;
+        builder = builder.with_batch_exporter(exporter, runtime::Tokio);
+    }
+
+    let provider = builder.build();
+    let tracer = provider.tracer(settings.service_name.clone());
+    global::set_tracer_provider(provider);
+
+    Ok(tracer)
+}
+
+/// Exports the spans that are still buffered. Call before the process exits.
+pub fn shutdown_tracing() {
+    global::shutdown_tracer_provider();
+}
+
+/// Unique ID of the request being handled, stored in the request's extensions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+// Assigns each incoming request its ID before any span or handler sees it, so that logs and
+// audit events for the same request can be correlated. An ID set by the caller, such as a proxy
+// or app-service, is kept so its logs line up with ours, and the ID is returned either way.
+pub async fn assign_request_id(mut request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| is_valid_request_id(value))
+        .map(str::to_owned)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    request
+        .extensions_mut()
+        .insert(RequestId(request_id.clone()));
+
+    let mut response = next.run(request).await;
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+fn is_valid_request_id(request_id: &str) -> bool {
+    !request_id.is_empty()
+        && request_id.len() <= MAX_REQUEST_ID_LENGTH
+        && request_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.:".contains(c))
+}
+
 // Creates a new tracing span with a unique request ID for each incoming request.
 // This helps in tracking and correlating logs for individual requests.
 pub fn make_span_with_request_id(request: &Request<Body>) -> Span {
-    let request_id = uuid::Uuid::new_v4();
-    tracing::span!(
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .map(|RequestId(request_id)| request_id.clone())
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let span = tracing::span!(
         Level::INFO,
         "[REQUEST]",
         method = tracing::field::display(request.method()),
         uri = tracing::field::display(request.uri()),
         version = tracing::field::debug(request.version()),
         request_id = tracing::field::display(request_id),
-    )
+        trace_id = tracing::field::Empty,
+    );
+
+    // Continue the caller's trace when it sent a `traceparent` header
+    let parent = global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(request.headers()))
+    });
+    span.set_parent(parent);
+
+    let trace_id = span.context().span().span_context().trace_id();
+    span.record("trace_id", tracing::field::display(trace_id));
+
+    span
+}
+
+/// Headers that carry the current span's trace to the service an outbound request is sent to
+pub fn trace_context_headers() -> reqwest::header::HeaderMap {
+    let mut headers = reqwest::header::HeaderMap::new();
+    let context = Span::current().context();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(&mut headers))
+    });
+    headers
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(HeaderName::as_str).collect()
+    }
+}
+
+// reqwest is on an older version of `http` than axum, so its headers are a different type
+struct HeaderInjector<'a>(&'a mut reqwest::header::HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            reqwest::header::HeaderName::from_bytes(key.as_bytes()),
+            reqwest::header::HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
 }
 
 // Logs an event indicating the start of a request.
@@ -58,7 +222,7 @@ pub fn on_response(response: &Response, latency: Duration, _span: &Span) {
         4..=5 => {
             tracing::event!(
                 Level::ERROR,
-                latency = ?latency,
+                latency_ms = latency.as_millis() as u64,
                 status = status_code,
                 "[REQUEST END]"
             )
@@ -66,10 +230,74 @@ pub fn on_response(response: &Response, latency: Duration, _span: &Span) {
         _ => {
             tracing::event!(
                 Level::INFO,
-                latency = ?latency,
+                latency_ms = latency.as_millis() as u64,
                 status = status_code,
                 "[REQUEST END]"
             )
         }
     };
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    fn with_telemetry<T>(f: impl FnOnce() -> T) -> T {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        // Tracers stop creating spans once their provider is dropped
+        let provider = TracerProvider::builder().build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        tracing::subscriber::with_default(subscriber, f)
+    }
+
+    fn request(traceparent: Option<&str>) -> Request<Body> {
+        let mut builder = Request::builder().uri("/login");
+        if let Some(traceparent) = traceparent {
+            builder = builder.header("traceparent", traceparent);
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    fn outbound_traceparent(span: &Span) -> String {
+        let headers = span.in_scope(trace_context_headers);
+        headers["traceparent"].to_str().unwrap().to_owned()
+    }
+
+    #[test]
+    fn request_span_continues_callers_trace() {
+        with_telemetry(|| {
+            let span = make_span_with_request_id(&request(Some(TRACEPARENT)));
+
+            let traceparent = outbound_traceparent(&span);
+
+            // Same trace, but with this service's span as the parent
+            assert!(traceparent.starts_with(&format!("00-{}-", TRACE_ID)));
+            assert_ne!(traceparent, TRACEPARENT);
+        });
+    }
+
+    #[test]
+    fn request_span_starts_trace_without_traceparent() {
+        with_telemetry(|| {
+            let first = outbound_traceparent(&make_span_with_request_id(&request(None)));
+            let second = outbound_traceparent(&make_span_with_request_id(&request(None)));
+
+            assert_ne!(first[3..35], second[3..35]);
+            assert!(!first.contains(TRACE_ID));
+        });
+    }
+
+    #[test]
+    fn request_ids_are_validated() {
+        assert!(is_valid_request_id("app-service_1.2:3"));
+        assert!(is_valid_request_id(&uuid::Uuid::new_v4().to_string()));
+        assert!(!is_valid_request_id(""));
+        assert!(!is_valid_request_id("with space"));
+        assert!(!is_valid_request_id("line\nbreak"));
+        assert!(!is_valid_request_id(&"a".repeat(MAX_REQUEST_ID_LENGTH + 1)));
+    }
+}
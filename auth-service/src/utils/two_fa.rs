@@ -0,0 +1,27 @@
+use color_eyre::eyre::Result;
+use secrecy::ExposeSecret;
+
+use crate::{
+    app_state::AppState,
+    domain::{TwoFACode, TwoFADestination},
+};
+
+// Delivers a 2FA code over the channel the destination belongs to
+#[tracing::instrument(name = "Send 2FA Code", skip_all)]
+pub async fn send_2fa_code(
+    state: &AppState,
+    destination: &TwoFADestination,
+    code: &TwoFACode,
+) -> Result<()> {
+    let code = code.as_ref().expose_secret();
+
+    match destination {
+        TwoFADestination::Email(email) => {
+            state.email_client.send_email(email, "2FA Code", code).await
+        }
+        TwoFADestination::Sms(phone_number) => {
+            let content = format!("Your verification code is {}", code);
+            state.sms_client.send_sms(phone_number, &content).await
+        }
+    }
+}
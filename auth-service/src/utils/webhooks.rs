@@ -0,0 +1,18 @@
+use crate::{
+    app_state::AppState,
+    domain::{WebhookEvent, WebhookEventType},
+};
+
+// Queues an event for subscribers. By the time an event is published the change it describes has
+// already been made, so a failure is logged rather than failing the request.
+pub async fn publish_event(
+    state: &AppState,
+    event_type: WebhookEventType,
+    data: serde_json::Value,
+) {
+    let event = WebhookEvent::new(event_type, data);
+
+    if let Err(e) = state.webhook_store.enqueue_event(&event).await {
+        tracing::error!("Failed to enqueue {} webhook: {:?}", event_type.as_str(), e);
+    }
+}
@@ -0,0 +1,232 @@
+use auth_service::{
+    domain::{AuditEventType, AuditOutcome},
+    routes::AuditLogResponse,
+};
+
+use crate::helpers::{get_random_email, TestApp};
+
+async fn get_events(app: &TestApp, query: &[(&str, &str)]) -> AuditLogResponse {
+    let response = app.get_audit_log(query).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    response
+        .json::<AuditLogResponse>()
+        .await
+        .expect("Could not deserialize response body to AuditLogResponse")
+}
+
+#[tokio::test]
+async fn should_return_400_if_admin_token_missing() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .http_client
+        .get(format!("{}/admin/audit", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_admin_token_invalid() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .http_client
+        .get(format!("{}/admin/audit", &app.address))
+        .bearer_auth("not-the-admin-token")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_invalid_filter() {
+    let mut app = TestApp::new().await;
+
+    for query in [
+        [("eventType", "not-an-event")],
+        [("outcome", "maybe")],
+        [("email", "not-an-email")],
+        [("cursor", "abc")],
+    ] {
+        let response = app.get_audit_log(&query).await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "Failed for query: {:?}",
+            query
+        );
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_record_failed_login_with_request_details() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+
+    let response = app
+        .http_client
+        .post(format!("{}/login", &app.address))
+        .header("user-agent", "audit-test/1.0")
+        .json(&serde_json::json!({
+            "email": random_email,
+            "password": "password123",
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    let page = get_events(&app, &[("email", &random_email)]).await;
+
+    assert_eq!(page.events.len(), 1);
+    let event = &page.events[0];
+    assert_eq!(event.event_type, AuditEventType::Login);
+    assert_eq!(event.outcome, AuditOutcome::Failure);
+    assert_eq!(event.status_code, 401);
+    assert_eq!(event.email.as_deref(), Some(random_email.as_str()));
+    assert_eq!(event.ip.as_deref(), Some("127.0.0.1"));
+    assert_eq!(event.user_agent.as_deref(), Some("audit-test/1.0"));
+    assert!(event.request_id.is_some());
+    assert_eq!(page.next_cursor, None);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_record_request_id_sent_by_caller() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+
+    let response = app
+        .http_client
+        .post(format!("{}/login", &app.address))
+        .header("x-request-id", "app-service-1234")
+        .json(&serde_json::json!({
+            "email": random_email,
+            "password": "password123",
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        response.headers().get("x-request-id").unwrap(),
+        "app-service-1234"
+    );
+
+    let page = get_events(&app, &[("email", &random_email)]).await;
+
+    assert_eq!(page.events.len(), 1);
+    assert_eq!(
+        page.events[0].request_id.as_deref(),
+        Some("app-service-1234")
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_record_each_step_of_a_session() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": random_email,
+            "password": "password123",
+            "requires2FA": false
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": random_email,
+            "password": "password123",
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The user is only known from the JWT here
+    let response = app.post_logout().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let page = get_events(&app, &[("email", &random_email)]).await;
+
+    let event_types: Vec<_> = page.events.iter().map(|event| event.event_type).collect();
+    assert_eq!(
+        event_types,
+        vec![
+            AuditEventType::Logout,
+            AuditEventType::Login,
+            AuditEventType::Signup
+        ]
+    );
+    assert!(page
+        .events
+        .iter()
+        .all(|event| event.outcome == AuditOutcome::Success));
+
+    let page = get_events(&app, &[("email", &random_email), ("eventType", "login")]).await;
+
+    assert_eq!(page.events.len(), 1);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_paginate_with_cursor() {
+    let mut app = TestApp::new().await;
+
+    for _ in 0..3 {
+        let response = app
+            .post_verify_token(&serde_json::json!({ "token": "invalid" }))
+            .await;
+
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    let query = [
+        ("eventType", "verify_token"),
+        ("outcome", "failure"),
+        ("limit", "2"),
+    ];
+    let first_page = get_events(&app, &query).await;
+
+    assert_eq!(first_page.events.len(), 2);
+    let cursor = first_page
+        .next_cursor
+        .expect("Expected a cursor to the next page");
+
+    let mut next_query = query.to_vec();
+    next_query.push(("cursor", &cursor));
+    let second_page = get_events(&app, &next_query).await;
+
+    assert_eq!(second_page.events.len(), 1);
+    assert!(second_page.events[0].id < first_page.events[1].id);
+    assert_eq!(second_page.next_cursor, None);
+
+    app.clean_up().await;
+}
@@ -0,0 +1,175 @@
+use auth_service::{
+    domain::{PasswordPolicy, PasswordRule},
+    ErrorResponse,
+};
+
+use crate::helpers::{get_random_email, TestApp};
+
+async fn signup_and_login(app: &TestApp, email: &str, password: &str) {
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": password,
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": email,
+            "password": password,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_return_200_and_replace_the_password() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+    signup_and_login(&app, &email, "password123").await;
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "currentPassword": "password123",
+            "newPassword": "new-password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "new-password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "currentPassword": "password123",
+            "newPassword": "new-password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_current_password_is_incorrect() {
+    let mut app = TestApp::new().await;
+
+    signup_and_login(&app, &get_random_email(), "password123").await;
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "currentPassword": "wrong-password",
+            "newPassword": "new-password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_with_details_if_new_password_breaks_policy() {
+    let mut app = TestApp::with_password_policy(PasswordPolicy::default()).await;
+
+    let email = get_random_email();
+    signup_and_login(&app, &email, "correct horse battery staple").await;
+
+    let local_part = email.split('@').next().unwrap();
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "currentPassword": "correct horse battery staple",
+            "newPassword": format!("{}-x7#Qp2!vL", local_part),
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let body = response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse");
+    let rules: Vec<_> = body
+        .details
+        .iter()
+        .map(|violation| violation.rule)
+        .collect();
+    assert_eq!(rules, vec![PasswordRule::ContainsEmail]);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_new_password_was_used_recently() {
+    let mut app = TestApp::with_password_policy(PasswordPolicy {
+        history_size: 2,
+        ..PasswordPolicy::minimal()
+    })
+    .await;
+
+    signup_and_login(&app, &get_random_email(), "password-1").await;
+
+    let change_password = |current: &str, new: &str| {
+        let body = serde_json::json!({
+            "currentPassword": current,
+            "newPassword": new,
+        });
+        let app = &app;
+        async move { app.post_change_password(&body).await }
+    };
+
+    // The current password and the one before it are off limits
+    let response = change_password("password-1", "password-1").await;
+    assert_eq!(response.status().as_u16(), 400);
+    let error = response.json::<ErrorResponse>().await.unwrap();
+    assert_eq!(error.details.len(), 1);
+    assert_eq!(error.details[0].rule, PasswordRule::Reused);
+
+    assert_eq!(
+        change_password("password-1", "password-2")
+            .await
+            .status()
+            .as_u16(),
+        200
+    );
+    assert_eq!(
+        change_password("password-2", "password-1")
+            .await
+            .status()
+            .as_u16(),
+        400
+    );
+    assert_eq!(
+        change_password("password-2", "password-3")
+            .await
+            .status()
+            .as_u16(),
+        200
+    );
+
+    // By now the first password has dropped out of the history
+    assert_eq!(
+        change_password("password-3", "password-1")
+            .await
+            .status()
+            .as_u16(),
+        200
+    );
+
+    app.clean_up().await;
+}
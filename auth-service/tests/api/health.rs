@@ -0,0 +1,123 @@
+use auth_service::{
+    domain::{RateLimitConfig, RateLimitKey, RateLimitPolicy, RateLimitRule},
+    routes::{HealthResponse, HealthStatus},
+};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::TestApp;
+
+#[tokio::test]
+async fn should_return_200_when_live() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_health_live().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response
+        .json::<HealthResponse>()
+        .await
+        .expect("Could not deserialize response body to HealthResponse");
+    assert_eq!(body.status, HealthStatus::Ok);
+    assert!(body.checks.is_empty());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_200_with_each_dependency_when_ready() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_health_ready().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response
+        .json::<HealthResponse>()
+        .await
+        .expect("Could not deserialize response body to HealthResponse");
+    assert_eq!(body.status, HealthStatus::Ok);
+    assert_eq!(
+        body.checks.keys().collect::<Vec<_>>(),
+        vec!["postgres", "redis"]
+    );
+    for health in body.checks.values() {
+        assert_eq!(health.status, HealthStatus::Ok);
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_503_if_email_provider_rejects_token() {
+    let mut app = TestApp::with_settings(|settings| {
+        settings.health.check_email_provider = true;
+    })
+    .await;
+
+    Mock::given(path("/server"))
+        .and(method("GET"))
+        .respond_with(ResponseTemplate::new(401))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.get_health_ready().await;
+
+    assert_eq!(response.status().as_u16(), 503);
+    let body = response
+        .json::<HealthResponse>()
+        .await
+        .expect("Could not deserialize response body to HealthResponse");
+    assert_eq!(body.status, HealthStatus::Unavailable);
+    assert_eq!(body.checks["postgres"].status, HealthStatus::Ok);
+    assert_eq!(body.checks["redis"].status, HealthStatus::Ok);
+    assert_eq!(body.checks["email"].status, HealthStatus::Unavailable);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_report_dependency_that_times_out() {
+    let mut app = TestApp::with_settings(|settings| {
+        settings.health.check_email_provider = true;
+        settings.health.timeout = std::time::Duration::from_millis(50);
+    })
+    .await;
+
+    Mock::given(path("/server"))
+        .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(1)))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.get_health_ready().await;
+
+    assert_eq!(response.status().as_u16(), 503);
+    let body = response
+        .json::<serde_json::Value>()
+        .await
+        .expect("Could not deserialize response body to JSON");
+    assert_eq!(body["checks"]["email"]["status"], "unavailable");
+    assert!(body["checks"]["email"]["latencyMs"].as_u64().unwrap() >= 50);
+    // Why it's down is only logged
+    assert!(body["checks"]["email"].get("error").is_none());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_not_rate_limit_probes() {
+    let config = RateLimitConfig::disabled().with_global(RateLimitRule::new(
+        RateLimitPolicy::per_minute(1),
+        RateLimitKey::Ip,
+    ));
+    let mut app = TestApp::with_rate_limit_config(config).await;
+
+    for _ in 0..3 {
+        assert_eq!(app.get_health_live().await.status().as_u16(), 200);
+        assert_eq!(app.get_health_ready().await.status().as_u16(), 200);
+    }
+
+    app.clean_up().await;
+}
@@ -1,24 +1,53 @@
 use std::{str::FromStr, sync::Arc};
 use secrecy::{ExposeSecret, Secret};
 use sqlx::{postgres::{PgConnectOptions, PgPoolOptions}, Connection, Executor, PgConnection, PgPool};
-use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
 use uuid::Uuid;
+use redis::aio::ConnectionManager;
 use reqwest::{cookie::Jar, Client};
 
 use auth_service::{
-    app_state::{AppState, BannedTokenStoreType, TwoFACodeStoreType}, domain::Email, get_postgres_pool, get_redis_client, services::{data_stores::{
-        PostgresUserStore, RedisBannedTokenStore, RedisTwoFACodeStore
-    }, postmark_email_client::PostmarkEmailClient}, utils::constants::{test, DATABASE_URL, REDIS_HOST_NAME}, Application
+    app_state::{
+        AppState, BannedTokenStoreType, HealthCheckType, TwoFACodeStoreType, WebhookStoreType,
+    },
+    domain::{BreachedPasswordCheck, PasswordPolicy, RateLimitConfig},
+    get_postgres_pool, get_redis_client,
+    services::{
+        data_stores::{
+            HashmapRateLimitStore, PostgresAuditLogStore, PostgresGroupStore,
+            PostgresKnownDeviceStore, PostgresUserStore, PostgresWebhookStore,
+            RedisBannedTokenStore, RedisTwoFACodeStore,
+        },
+        health_checks::{PostgresHealthCheck, RedisHealthCheck},
+        http_sms_client::HttpSmsClient,
+        password_hasher::PasswordHasher,
+        postmark_email_client::PostmarkEmailClient,
+        webhook_dispatcher::{RetryPolicy, WebhookDispatcher},
+    },
+    settings::{
+        DatabaseSettings, EmailClientSettings, Settings, SmsClientSettings, WebhookSettings,
+    },
+    utils::{rate_limit::RateLimiter, shutdown::ShutdownHandle},
+    Application,
 };
 use wiremock::MockServer;
 
 pub struct TestApp {
     pub address: String,
+    pub redirect_address: Option<String>,
+    pub settings: Arc<Settings>,
     pub cookie_jar: Arc<Jar>,
     pub banned_token_store: BannedTokenStoreType,
     pub two_fa_code_store: TwoFACodeStoreType,
+    pub phone_verification_store: TwoFACodeStoreType,
+    pub webhook_dispatcher: JoinHandle<()>,
+    webhook_dispatcher_shutdown: ShutdownHandle,
+    server: Option<JoinHandle<Result<(), std::io::Error>>>,
+    server_shutdown: ShutdownHandle,
     pub http_client: reqwest::Client,
     pub email_server: MockServer, // New!
+    pub sms_server: MockServer,
+    pub pg_pool: PgPool,
     pub db_name: String,
     pub clean_up_called: bool,
 }
@@ -33,53 +62,233 @@ impl Drop for TestApp {
 
 impl TestApp {
     pub async fn new() -> Self {
+        Self::with_rate_limit_config(RateLimitConfig::disabled()).await
+    }
 
-        let db_name = Uuid::new_v4().to_string();
-        let pg_pool = configure_postgresql(&db_name).await;
+    // Each app gets its own in-memory buckets, so parallel tests can't exhaust each other's limits
+    pub async fn with_rate_limit_config(rate_limit_config: RateLimitConfig) -> Self {
+        Self::build(
+            rate_limit_config,
+            PasswordPolicy::minimal(),
+            None,
+            PasswordHasher::default(),
+            |_| {},
+        )
+        .await
+    }
+
+    // Most tests use short, common passwords, so only tests of the policy itself enforce one
+    pub async fn with_password_policy(password_policy: PasswordPolicy) -> Self {
+        Self::build(
+            RateLimitConfig::disabled(),
+            password_policy,
+            None,
+            PasswordHasher::default(),
+            |_| {},
+        )
+        .await
+    }
+
+    pub async fn with_breached_password_check(check: BreachedPasswordCheck) -> Self {
+        Self::build(
+            RateLimitConfig::disabled(),
+            PasswordPolicy::minimal(),
+            Some(check),
+            PasswordHasher::default(),
+            |_| {},
+        )
+        .await
+    }
+
+    pub async fn with_password_hasher(password_hasher: PasswordHasher) -> Self {
+        Self::build(
+            RateLimitConfig::disabled(),
+            PasswordPolicy::minimal(),
+            None,
+            password_hasher,
+            |_| {},
+        )
+        .await
+    }
 
-        let user_store = Arc::new(RwLock::new(PostgresUserStore::new(pg_pool)));
+    pub async fn with_rate_limit_config_and_password_hasher(
+        rate_limit_config: RateLimitConfig,
+        password_hasher: PasswordHasher,
+    ) -> Self {
+        Self::build(
+            rate_limit_config,
+            PasswordPolicy::minimal(),
+            None,
+            password_hasher,
+            |_| {},
+        )
+        .await
+    }
 
-        let redis_client = Arc::new(RwLock::new(configure_redis()));
-        let banned_token_store = Arc::new(RwLock::new(RedisBannedTokenStore ::new(redis_client.clone())));
-        let two_fa_code_store = Arc::new(RwLock::new(RedisTwoFACodeStore::new(redis_client.clone())));
+    // For tests of behaviour that depends on the deployment, like CORS and cookie attributes
+    pub async fn with_settings(configure: impl FnOnce(&mut Settings)) -> Self {
+        Self::build(
+            RateLimitConfig::disabled(),
+            PasswordPolicy::minimal(),
+            None,
+            PasswordHasher::default(),
+            configure,
+        )
+        .await
+    }
 
-        // Set up a mock email server
+    async fn build(
+        rate_limit_config: RateLimitConfig,
+        password_policy: PasswordPolicy,
+        breached_password_check: Option<BreachedPasswordCheck>,
+        password_hasher: PasswordHasher,
+        configure_settings: impl FnOnce(&mut Settings),
+    ) -> Self {
+        let mut settings =
+            Settings::load_environment(Some("test")).expect("Failed to load settings");
+        configure_settings(&mut settings);
+
+        // Set up mock email and SMS servers
         let email_server = MockServer::start().await; // New!
-        let base_url = email_server.uri(); // New!
-        let email_client = Arc::new(configure_postmark_email_client(base_url)); // Updated!
-        
-        let app_state = AppState::new(user_store, banned_token_store.clone(), two_fa_code_store.clone(), email_client);
+        settings.email_client.base_url = email_server.uri();
+        let sms_server = MockServer::start().await;
+        settings.sms_client.base_url = sms_server.uri();
+        let settings = Arc::new(settings);
+
+        let db_name = Uuid::new_v4().to_string();
+        let pg_pool = configure_postgresql(&settings.database, &db_name).await;
+
+        let user_store = Arc::new(PostgresUserStore::new(pg_pool.clone(), password_hasher));
+        let audit_log_store = Arc::new(PostgresAuditLogStore::new(pg_pool.clone()));
+        let webhook_store: WebhookStoreType = Arc::new(PostgresWebhookStore::new(pg_pool.clone()));
+        let group_store = Arc::new(PostgresGroupStore::new(pg_pool.clone()));
+        let known_device_store = Arc::new(PostgresKnownDeviceStore::new(pg_pool.clone()));
+
+        let redis_client = configure_redis(&settings.redis.host_name).await;
+        let banned_token_store = Arc::new(RedisBannedTokenStore::new(redis_client.clone()));
+        let two_fa_code_store = Arc::new(RedisTwoFACodeStore::new(redis_client.clone()));
+        let phone_verification_store = Arc::new(RedisTwoFACodeStore::with_namespace(
+            redis_client.clone(),
+            "phone_verification",
+        ));
+
+        let email_client = Arc::new(configure_postmark_email_client(&settings.email_client)); // Updated!
+        let sms_client = Arc::new(configure_sms_client(&settings.sms_client));
+
+        let password_policy = Arc::new(password_policy);
+
+        let mut health_checks: Vec<HealthCheckType> = vec![
+            Arc::new(PostgresHealthCheck::new(pg_pool.clone())),
+            Arc::new(RedisHealthCheck::new(redis_client.clone())),
+        ];
+        if settings.health.check_email_provider {
+            health_checks.push(email_client.clone());
+        }
 
-        let app = Application::build(app_state, test::APP_ADDRESS)
+        let rate_limiter = RateLimiter::new(
+            Arc::new(HashmapRateLimitStore::default()),
+            rate_limit_config,
+        );
+
+        let app_state = AppState::new(
+            settings.clone(),
+            user_store,
+            banned_token_store.clone(),
+            two_fa_code_store.clone(),
+            phone_verification_store.clone(),
+            email_client,
+            sms_client,
+            rate_limiter,
+            audit_log_store,
+            webhook_store.clone(),
+            group_store,
+            password_policy,
+            breached_password_check,
+            known_device_store,
+            health_checks,
+        );
+
+        let webhook_dispatcher_shutdown = ShutdownHandle::new();
+        let webhook_dispatcher = tokio::spawn(
+            configure_webhook_dispatcher(&settings.webhooks, webhook_store).run(
+                settings.webhooks.poll_interval,
+                webhook_dispatcher_shutdown.clone(),
+            ),
+        );
+
+        let app = Application::build(app_state, &settings.application)
             .await
             .expect("Failed to build app");
 
-        let address = format!("http://{}", app.address.clone());
+        // Certificates are issued for a name rather than an IP address
+        let address = match &settings.application.tls {
+            Some(_) => app.address.replace("127.0.0.1", "https://localhost"),
+            None => format!("http://{}", app.address.clone()),
+        };
+        let redirect_address = app.redirect_address.clone();
 
-        #[allow(clippy::let_underscore_future)]
-        let _ = tokio::spawn(app.run());
+        let server_shutdown = app.shutdown_handle();
+        let server = Some(tokio::spawn(app.run()));
 
         let cookie_jar = Arc::new(Jar::default());
-        let http_client = reqwest::Client::builder()
-            .cookie_provider(cookie_jar.clone())
-            .build()
-            .unwrap();
+        let mut http_client = reqwest::Client::builder().cookie_provider(cookie_jar.clone());
+        if let Some(tls) = &settings.application.tls {
+            let certificate =
+                std::fs::read(&tls.certificate_path).expect("Failed to read certificate");
+            http_client = http_client.add_root_certificate(
+                reqwest::Certificate::from_pem(&certificate).expect("Invalid certificate"),
+            );
+        }
+        let http_client = http_client.build().unwrap();
 
         Self {
             address,
+            redirect_address,
+            settings,
             cookie_jar,
             banned_token_store,
             two_fa_code_store,
+            phone_verification_store,
+            webhook_dispatcher,
+            webhook_dispatcher_shutdown,
+            server,
+            server_shutdown,
             http_client,
             email_server, // New!
+            sms_server,
+            pg_pool,
             db_name,
             clean_up_called: true,
         }
     }
 
+    pub async fn get_health_live(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/health/live", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_health_ready(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/health/ready", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_metrics(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/metrics", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn get_root(&self) -> reqwest::Response {
         self.http_client
-            .get(&format!("{}/", &self.address))
+            .get(format!("{}/", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -90,7 +299,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.http_client
-            .post(&format!("{}/signup", &self.address))
+            .post(format!("{}/signup", &self.address))
             .json(body)
             .send()
             .await
@@ -102,7 +311,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.http_client
-            .post(&format!("{}/login", &self.address))
+            .post(format!("{}/login", &self.address))
             .json(body)
             .send()
             .await
@@ -117,6 +326,18 @@ impl TestApp {
             .expect("Failed to execute request.")
     } 
 
+    pub async fn post_change_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/change-password", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_verify_2fa<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
@@ -129,6 +350,18 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_resend_2fa_code<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/verify-2fa/resend", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_verify_token<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
@@ -141,8 +374,280 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_phone_number<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/phone-number", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_verify_phone_number<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/phone-number/verify", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_two_fa_channel<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/2fa-channel", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_audit_log(&self, query: &[(&str, &str)]) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/admin/audit", &self.address))
+            .bearer_auth(self.settings.auth.admin_api_token.expose_secret())
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_import_users<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/admin/users/import", &self.address))
+            .bearer_auth(self.settings.auth.admin_api_token.expose_secret())
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_webhook<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/admin/webhooks", &self.address))
+            .bearer_auth(self.settings.auth.admin_api_token.expose_secret())
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_webhooks(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/admin/webhooks", &self.address))
+            .bearer_auth(self.settings.auth.admin_api_token.expose_secret())
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_webhook(&self, id: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/admin/webhooks/{}", &self.address, id))
+            .bearer_auth(self.settings.auth.admin_api_token.expose_secret())
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_webhook(&self, id: &str) -> reqwest::Response {
+        self.http_client
+            .delete(format!("{}/admin/webhooks/{}", &self.address, id))
+            .bearer_auth(self.settings.auth.admin_api_token.expose_secret())
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_webhook_deliveries(&self, id: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!(
+                "{}/admin/webhooks/{}/deliveries",
+                &self.address, id
+            ))
+            .bearer_auth(self.settings.auth.admin_api_token.expose_secret())
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_scim<Body>(&self, path: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/scim/v2/{}", &self.address, path))
+            .bearer_auth(self.settings.auth.scim_api_token.expose_secret())
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_scim(&self, path: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/scim/v2/{}", &self.address, path))
+            .bearer_auth(self.settings.auth.scim_api_token.expose_secret())
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn put_scim<Body>(&self, path: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .put(format!("{}/scim/v2/{}", &self.address, path))
+            .bearer_auth(self.settings.auth.scim_api_token.expose_secret())
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn patch_scim<Body>(&self, path: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .patch(format!("{}/scim/v2/{}", &self.address, path))
+            .bearer_auth(self.settings.auth.scim_api_token.expose_secret())
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_scim(&self, path: &str) -> reqwest::Response {
+        self.http_client
+            .delete(format!("{}/scim/v2/{}", &self.address, path))
+            .bearer_auth(self.settings.auth.scim_api_token.expose_secret())
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_report_login(&self, token: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/report-login", &self.address))
+            .query(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // As submitted by the confirmation page's form
+    pub async fn post_report_login(&self, token: &str) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/report-login", &self.address))
+            .form(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_reset_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/reset-password", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_trusted_devices(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/trusted-devices", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_trusted_device(&self, id: &str) -> reqwest::Response {
+        self.http_client
+            .delete(format!("{}/trusted-devices/{}", &self.address, id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // Logs in from a browser the app hasn't seen, which keeps its own cookies
+    pub async fn post_login_from_new_device<Body>(
+        &self,
+        user_agent: &str,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        Client::builder()
+            .cookie_store(true)
+            .user_agent(user_agent)
+            .build()
+            .unwrap()
+            .post(format!("{}/login", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    /// The token in the link of the latest email sent with `subject`, if there is one
+    pub async fn email_link_token(&self, subject: &str) -> Option<String> {
+        let requests = self
+            .email_server
+            .received_requests()
+            .await
+            .unwrap_or_default();
+        requests.iter().rev().find_map(|request| {
+            let email: serde_json::Value = serde_json::from_slice(&request.body).ok()?;
+            if email["Subject"] != subject {
+                return None;
+            }
+            let token = email["TextBody"].as_str()?.split("token=").nth(1)?;
+            Some(token.split_whitespace().next()?.to_owned())
+        })
+    }
+
+    // Stops the server the way a SIGTERM would, returning once the requests in flight have
+    // finished and the webhooks they queued have been sent
+    pub async fn shutdown(&mut self) {
+        self.server_shutdown.trigger();
+        if let Some(server) = self.server.take() {
+            server
+                .await
+                .expect("Server task panicked")
+                .expect("Server failed");
+        }
+        self.webhook_dispatcher_shutdown.trigger();
+        (&mut self.webhook_dispatcher)
+            .await
+            .expect("Webhook dispatcher panicked");
+    }
+
     pub async fn clean_up(&mut self){
-        delete_database(&self.db_name).await;
+        // Stop serving and polling before the database goes away
+        self.server_shutdown.trigger();
+        self.webhook_dispatcher.abort();
+        delete_database(&self.settings.database.url, &self.db_name).await;
         self.clean_up_called = true;
     }
 }
@@ -153,17 +658,20 @@ pub fn get_random_email() -> String {
     format!("{}@example.com", Uuid::new_v4())
 }
 
-async fn configure_postgresql(db_name: &str) -> PgPool {
-    let postgresql_conn_url = DATABASE_URL.expose_secret().to_owned();
+async fn configure_postgresql(settings: &DatabaseSettings, db_name: &str) -> PgPool {
+    let postgresql_conn_url = settings.url.expose_secret().to_owned();
 
     configure_database(&postgresql_conn_url, db_name).await;
 
     let postgresql_conn_url_with_db = format!("{}/{}", postgresql_conn_url, db_name);
 
     // Create a new connection pool and return it
-    get_postgres_pool(&Secret::new(postgresql_conn_url_with_db))
-        .await
-        .expect("Failed to create Postgres connection pool!")
+    get_postgres_pool(
+        &Secret::new(postgresql_conn_url_with_db),
+        settings.max_connections,
+    )
+    .await
+    .expect("Failed to create Postgres connection pool!")
 }
 
 async fn configure_database(db_conn_string: &str, db_name: &str) {
@@ -195,8 +703,8 @@ async fn configure_database(db_conn_string: &str, db_name: &str) {
         .expect("Failed to migrate the database");
 }
 
-async fn delete_database(db_name: &str) {
-    let postgresql_conn_url: String = DATABASE_URL.expose_secret().to_owned();
+async fn delete_database(database_url: &Secret<String>, db_name: &str) {
+    let postgresql_conn_url: String = database_url.expose_secret().to_owned();
 
     let connection_options = PgConnectOptions::from_str(&postgresql_conn_url)
         .expect("Failed to parse PostgreSQL connection string");
@@ -229,23 +737,59 @@ async fn delete_database(db_name: &str) {
         .expect("Failed to drop the database.");
 }
 
-fn configure_redis() -> redis::Connection {
-    get_redis_client(REDIS_HOST_NAME.to_owned())
-        .expect("Failed to get Redis client")
-        .get_connection()
+async fn configure_redis(host_name: &str) -> ConnectionManager {
+    let client = get_redis_client(host_name.to_owned()).expect("Failed to get Redis client");
+    ConnectionManager::new(client)
+        .await
         .expect("Failed to get Redis connection")
 }
 
 // New!
-fn configure_postmark_email_client(base_url: String) -> PostmarkEmailClient {
-    let postmark_auth_token = Secret::new("auth_token".to_owned());
+fn configure_postmark_email_client(settings: &EmailClientSettings) -> PostmarkEmailClient {
+    let http_client = Client::builder()
+        .timeout(settings.timeout)
+        .build()
+        .expect("Failed to build HTTP client");
 
-    let sender = Email::parse(Secret::new(test::email_client::SENDER.to_owned())).unwrap();
+    PostmarkEmailClient::new(
+        settings.base_url.clone(),
+        settings.sender.clone(),
+        settings.authorization_token.clone(),
+        http_client,
+    )
+}
 
+fn configure_sms_client(settings: &SmsClientSettings) -> HttpSmsClient {
     let http_client = Client::builder()
-        .timeout(test::email_client::TIMEOUT)
+        .timeout(settings.timeout)
         .build()
         .expect("Failed to build HTTP client");
 
-    PostmarkEmailClient::new(base_url, sender, postmark_auth_token, http_client)
-}
\ No newline at end of file
+    HttpSmsClient::new(
+        settings.base_url.clone(),
+        settings.sender.clone(),
+        settings.authorization_token.clone(),
+        http_client,
+    )
+}
+
+fn configure_webhook_dispatcher(
+    settings: &WebhookSettings,
+    webhook_store: WebhookStoreType,
+) -> WebhookDispatcher {
+    let http_client = Client::builder()
+        .timeout(settings.timeout)
+        .build()
+        .expect("Failed to build HTTP client");
+
+    WebhookDispatcher::new(
+        webhook_store,
+        http_client,
+        settings.timeout,
+        RetryPolicy {
+            base_delay: settings.retry_base_delay,
+            max_delay: settings.retry_max_delay,
+            max_attempts: settings.max_attempts,
+        },
+    )
+}
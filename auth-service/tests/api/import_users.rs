@@ -0,0 +1,179 @@
+use argon2::{
+    password_hash::{PasswordHasher, SaltString},
+    Algorithm, Argon2, Params, Version,
+};
+use auth_service::routes::{ImportUsersResponse, SkippedUser};
+use pbkdf2::Pbkdf2;
+use scrypt::Scrypt;
+
+use crate::helpers::{get_random_email, TestApp};
+
+const PASSWORD: &str = "password123";
+
+fn salt() -> SaltString {
+    SaltString::generate(&mut rand::thread_rng())
+}
+
+// A hash of `PASSWORD` in each format the service accepts, all cheaper than the current params
+fn legacy_hashes() -> Vec<String> {
+    let argon2 = Argon2::new(
+        Algorithm::Argon2id,
+        Version::V0x13,
+        Params::new(4096, 1, 1, None).unwrap(),
+    )
+    .hash_password(PASSWORD.as_bytes(), &salt())
+    .unwrap()
+    .to_string();
+    let scrypt = Scrypt
+        .hash_password_customized(
+            PASSWORD.as_bytes(),
+            None,
+            None,
+            scrypt::Params::new(4, 8, 1, 32).unwrap(),
+            &salt(),
+        )
+        .unwrap()
+        .to_string();
+    let pbkdf2 = Pbkdf2
+        .hash_password_customized(
+            PASSWORD.as_bytes(),
+            None,
+            None,
+            pbkdf2::Params {
+                rounds: 1000,
+                output_length: 32,
+            },
+            &salt(),
+        )
+        .unwrap()
+        .to_string();
+
+    vec![bcrypt::hash(PASSWORD, 4).unwrap(), argon2, scrypt, pbkdf2]
+}
+
+async fn stored_password_hash(app: &TestApp, email: &str) -> String {
+    sqlx::query_scalar("SELECT password_hash FROM users WHERE email = $1")
+        .bind(email)
+        .fetch_one(&app.pg_pool)
+        .await
+        .expect("Failed to fetch password hash")
+}
+
+#[tokio::test]
+async fn should_upgrade_imported_hashes_on_login() {
+    let mut app = TestApp::new().await;
+
+    let users: Vec<_> = legacy_hashes()
+        .into_iter()
+        .map(|hash| (get_random_email(), hash))
+        .collect();
+    let body = serde_json::json!({
+        "users": users
+            .iter()
+            .map(|(email, hash)| serde_json::json!({ "email": email, "passwordHash": hash }))
+            .collect::<Vec<_>>(),
+    });
+
+    let response = app.post_import_users(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.json::<ImportUsersResponse>().await.unwrap(),
+        ImportUsersResponse {
+            imported: users.len(),
+            skipped: vec![],
+        }
+    );
+
+    for (email, hash) in &users {
+        let wrong_password = serde_json::json!({ "email": email, "password": "wrong-password" });
+        assert_eq!(app.post_login(&wrong_password).await.status().as_u16(), 401);
+        assert_eq!(&stored_password_hash(&app, email).await, hash);
+
+        let login = serde_json::json!({ "email": email, "password": PASSWORD });
+        assert_eq!(
+            app.post_login(&login).await.status().as_u16(),
+            200,
+            "{}",
+            hash
+        );
+
+        let upgraded = stored_password_hash(&app, email).await;
+        assert!(
+            upgraded.starts_with("$argon2id$v=19$m=15000,t=2,p=1$"),
+            "{} was upgraded to {}",
+            hash,
+            upgraded
+        );
+
+        // The upgraded hash is left alone from then on
+        assert_eq!(app.post_login(&login).await.status().as_u16(), 200);
+        assert_eq!(stored_password_hash(&app, email).await, upgraded);
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_skip_users_that_cannot_be_imported() {
+    let mut app = TestApp::new().await;
+
+    let existing = get_random_email();
+    let signup = serde_json::json!({
+        "email": existing,
+        "password": PASSWORD,
+        "requires2FA": false
+    });
+    assert_eq!(app.post_signup(&signup).await.status().as_u16(), 201);
+
+    let hash = bcrypt::hash(PASSWORD, 4).unwrap();
+    let body = serde_json::json!({
+        "users": [
+            { "email": get_random_email(), "passwordHash": hash, "requires2FA": true },
+            { "email": "not-an-email", "passwordHash": hash },
+            { "email": get_random_email(), "passwordHash": "5f4dcc3b5aa765d61d8327deb882cf99" },
+            { "email": existing, "passwordHash": hash },
+        ],
+    });
+
+    let response = app.post_import_users(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = response.json::<ImportUsersResponse>().await.unwrap();
+
+    assert_eq!(response.imported, 1);
+    let skipped = |index: usize, reason: &str| SkippedUser {
+        index,
+        reason: reason.to_owned(),
+    };
+    assert_eq!(
+        response.skipped,
+        vec![
+            skipped(1, "Invalid email"),
+            skipped(2, "Unsupported password hash"),
+            skipped(3, "User already exists"),
+        ]
+    );
+
+    // The existing user keeps their password
+    let login = serde_json::json!({ "email": existing, "password": PASSWORD });
+    assert_eq!(app.post_login(&login).await.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_admin_token_invalid() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .http_client
+        .post(format!("{}/admin/users/import", &app.address))
+        .bearer_auth("not-the-admin-token")
+        .json(&serde_json::json!({ "users": [] }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
@@ -0,0 +1,141 @@
+use std::time::{Duration, Instant};
+
+use auth_service::{
+    services::password_hasher::{Argon2Params, PasswordHasher},
+    settings::Settings,
+};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{get_random_email, TestApp};
+
+const USERS: usize = 16;
+// Within the email client's timeout in tests
+const EMAIL_DELAY: Duration = Duration::from_millis(100);
+
+fn spawn_post(
+    app: &TestApp,
+    route: &str,
+    client_ip: &str,
+    body: &serde_json::Value,
+) -> tokio::task::JoinHandle<u16> {
+    let request = app
+        .http_client
+        .post(format!("{}{}", &app.address, route))
+        .header("X-Forwarded-For", client_ip)
+        .json(body);
+    tokio::spawn(async move {
+        let response = request.send().await.expect("Failed to execute request.");
+        response.status().as_u16()
+    })
+}
+
+// Every user comes from their own IP, so that the configured limits let the batch through while
+// each request still takes its tokens from the shared rate limit store
+async fn sign_up_users(app: &TestApp, batch: u8) -> Vec<(String, serde_json::Value)> {
+    let mut users = vec![];
+    for i in 0..USERS {
+        let client_ip = format!("10.0.{}.{}", batch, i);
+        let login = serde_json::json!({
+            "email": get_random_email(),
+            "password": "password123",
+            "requires2FA": true
+        });
+        assert_eq!(
+            spawn_post(app, "/signup", &client_ip, &login)
+                .await
+                .unwrap(),
+            201
+        );
+        users.push((client_ip, login));
+    }
+    users
+}
+
+// Each user logs in, which waits on the email provider, and signs up someone else meanwhile
+async fn log_in_and_sign_up(
+    app: &TestApp,
+    client_ip: &str,
+    login: &serde_json::Value,
+) -> (tokio::task::JoinHandle<u16>, tokio::task::JoinHandle<u16>) {
+    let signup = serde_json::json!({
+        "email": get_random_email(),
+        "password": "password123",
+        "requires2FA": false
+    });
+    (
+        spawn_post(app, "/login", client_ip, login),
+        spawn_post(app, "/signup", client_ip, &signup),
+    )
+}
+
+async fn statuses(
+    requests: Vec<(tokio::task::JoinHandle<u16>, tokio::task::JoinHandle<u16>)>,
+) -> Vec<(u16, u16)> {
+    let mut statuses = vec![];
+    for (login, signup) in requests {
+        statuses.push((login.await.unwrap(), signup.await.unwrap()));
+    }
+    statuses
+}
+
+// Logins with 2FA spend most of their time waiting on the email provider. While they do, nothing
+// else a user does should have to wait for them: were any store behind the rate limiter or the
+// routes shared behind one lock, the users would be served one after another, and serving them
+// all at once would take about as long as serving them in turn.
+#[tokio::test]
+async fn should_serve_concurrent_logins_while_users_sign_up() {
+    // Cheap hashes, so that what's measured is time spent waiting rather than hashing
+    let password_hasher = PasswordHasher::new(Argon2Params {
+        memory_kib: 8,
+        iterations: 1,
+        parallelism: 1,
+    })
+    .unwrap();
+    let rate_limit_config = Settings::load_environment(Some("test"))
+        .expect("Failed to load settings")
+        .rate_limit
+        .config()
+        .trust_forwarded_for(true);
+    let mut app =
+        TestApp::with_rate_limit_config_and_password_hasher(rate_limit_config, password_hasher)
+            .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(EMAIL_DELAY))
+        .mount(&app.email_server)
+        .await;
+
+    let sequential_users = sign_up_users(&app, 1).await;
+    let concurrent_users = sign_up_users(&app, 2).await;
+
+    let started = Instant::now();
+    let mut sequential_statuses = vec![];
+    for (client_ip, login) in &sequential_users {
+        let requests = vec![log_in_and_sign_up(&app, client_ip, login).await];
+        sequential_statuses.extend(statuses(requests).await);
+    }
+    let sequential = started.elapsed();
+
+    let started = Instant::now();
+    let mut requests = vec![];
+    for (client_ip, login) in &concurrent_users {
+        requests.push(log_in_and_sign_up(&app, client_ip, login).await);
+    }
+    let concurrent_statuses = statuses(requests).await;
+    let concurrent = started.elapsed();
+
+    assert_eq!(sequential_statuses, vec![(206, 201); USERS]);
+    assert_eq!(concurrent_statuses, vec![(206, 201); USERS]);
+    assert!(
+        concurrent < sequential / 4,
+        "{} users took {:?} at once and {:?} in turn",
+        USERS,
+        concurrent,
+        sequential
+    );
+
+    app.clean_up().await;
+}
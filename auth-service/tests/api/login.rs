@@ -1,11 +1,14 @@
 use crate::helpers::{ get_random_email, TestApp };
-use secrecy::ExposeSecret;
 use auth_service::{
-    domain::Email,
+    domain::{Email, LoginAttemptId, TwoFAChannel},
     routes::TwoFactorAuthResponse,
+    services::password_hasher::{PasswordHasher, Peppers},
     utils::constants::JWT_COOKIE_NAME,
     ErrorResponse,
 };
+use axum_extra::extract::cookie::SameSite;
+use secrecy::{ExposeSecret, Secret};
+use std::time::Duration;
 use wiremock::{matchers::{method, path}, Mock, ResponseTemplate};
 
 #[tokio::test]
@@ -182,6 +185,65 @@ async fn should_return_200_if_valid_credentials_and_2fa_disabled() {
     app.clean_up().await;
 }
 
+#[tokio::test]
+async fn should_set_auth_cookie_with_configured_attributes() {
+    let mut app = TestApp::with_settings(|settings| {
+        let cookie = &mut settings.auth.cookie;
+        cookie.name = "__Secure-session".to_owned();
+        cookie.domain = Some("example.com".to_owned());
+        cookie.path = "/auth".to_owned();
+        cookie.secure = true;
+        cookie.same_site = SameSite::None;
+        cookie.max_age = Some(Duration::from_secs(3600));
+    })
+    .await;
+
+    let random_email = get_random_email();
+
+    let signup = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": false
+    });
+
+    let response = app.post_signup(&signup).await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+
+    let response = app.post_login(&login).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let set_cookie = response
+        .headers()
+        .get_all("set-cookie")
+        .iter()
+        .filter_map(|header| header.to_str().ok())
+        .find(|header| header.starts_with("__Secure-session="))
+        .expect("No auth cookie found")
+        .to_owned();
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == "__Secure-session")
+        .expect("No auth cookie found");
+
+    assert!(!auth_cookie.value().is_empty());
+    assert_eq!(auth_cookie.domain(), Some("example.com"));
+    assert_eq!(auth_cookie.path(), Some("/auth"));
+    assert!(auth_cookie.secure());
+    assert!(auth_cookie.http_only());
+    // reqwest only reports the Lax and Strict values
+    assert!(set_cookie.contains("SameSite=None"));
+    assert_eq!(auth_cookie.max_age(), Some(Duration::from_secs(3600)));
+
+    app.clean_up().await;
+}
+
 #[tokio::test]
 async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
     let mut app = TestApp::new().await;
@@ -223,16 +285,81 @@ async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
         .expect("Could not deserialize response body to TwoFactorAuthResponse");
 
     assert_eq!(json_body.message, "2FA required".to_owned());
+    assert_eq!(json_body.channel, TwoFAChannel::Email);
+    assert_eq!(
+        json_body.destination,
+        Email::parse(random_email.clone().into()).unwrap().masked()
+    );
 
     // assert that `json_body.login_attempt_id` is stored inside `app.two_fa_code_store`
-    let login_attempt_id = app.two_fa_code_store
-        .read()
+    let login_attempt_id = LoginAttemptId::parse(json_body.login_attempt_id.into()).unwrap();
+    let (email, _) = app
+        .two_fa_code_store
+        .get_code(&login_attempt_id)
         .await
-        .get_code(&Email::parse(random_email.into()).unwrap())
+        .unwrap();
+
+    assert_eq!(email, Email::parse(random_email.into()).unwrap());
+
+    app.clean_up().await;
+}
+#[tokio::test]
+async fn should_move_password_hash_to_current_pepper_on_login() {
+    let peppers = |versions: &[i32]| {
+        Peppers::new(
+            versions
+                .iter()
+                .map(|version| (*version, Secret::new(format!("pepper-{}", version)))),
+        )
+        .unwrap()
+    };
+    let mut app =
+        TestApp::with_password_hasher(PasswordHasher::default().with_peppers(peppers(&[1, 2])))
+            .await;
+
+    // Hashed before the pepper was rotated
+    let random_email = get_random_email();
+    let old_hash = PasswordHasher::default()
+        .with_peppers(peppers(&[1]))
+        .hash(Secret::new("password123".to_owned()))
+        .await
+        .unwrap();
+    sqlx::query(
+        "INSERT INTO users (email, password_hash, password_pepper_version, requires_2fa)
+         VALUES ($1, $2, $3, FALSE)",
+    )
+    .bind(&random_email)
+    .bind(old_hash.hash.expose_secret())
+    .bind(old_hash.pepper_version)
+    .execute(&app.pg_pool)
+    .await
+    .unwrap();
+
+    let stored_pepper_version = || async {
+        sqlx::query_scalar::<_, Option<i32>>(
+            "SELECT password_pepper_version FROM users WHERE email = $1",
+        )
+        .bind(&random_email)
+        .fetch_one(&app.pg_pool)
         .await
-        .unwrap().0.expose_secret().to_string();
+        .unwrap()
+    };
 
-    assert_eq!(login_attempt_id, json_body.login_attempt_id);
+    let wrong_password = serde_json::json!({
+        "email": random_email,
+        "password": "wrong-password",
+    });
+    assert_eq!(app.post_login(&wrong_password).await.status().as_u16(), 401);
+    assert_eq!(stored_pepper_version().await, Some(1));
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+    assert_eq!(app.post_login(&login_body).await.status().as_u16(), 200);
+    assert_eq!(stored_pepper_version().await, Some(2));
+
+    assert_eq!(app.post_login(&login_body).await.status().as_u16(), 200);
 
     app.clean_up().await;
-}
\ No newline at end of file
+}
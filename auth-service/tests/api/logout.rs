@@ -115,7 +115,7 @@ async fn should_return_200_if_valid_jwt_cookie() {
 
     app.clean_up().await;
 
-    let banned_token_store = app.banned_token_store.read().await;
+    let banned_token_store = &app.banned_token_store;
     let contains_token = banned_token_store
         .contains_token(&secret_token)
         .await
@@ -1,7 +1,25 @@
+mod audit_log;
+mod change_password;
+mod health;
 mod helpers;
+mod import_users;
+mod load;
 mod login;
 mod logout;
+mod metrics;
+mod phone_number;
+mod rate_limit;
+mod redis;
+mod report_login;
+mod resend_2fa_code;
+mod reset_password;
 mod root;
+mod scim;
+mod shutdown;
 mod signup;
+mod tls;
+mod trusted_devices;
+mod two_fa_channel;
 mod verify_2fa;
-mod verify_token;
\ No newline at end of file
+mod verify_token;
+mod webhooks;
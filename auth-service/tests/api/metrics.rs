@@ -0,0 +1,84 @@
+use crate::helpers::{get_random_email, TestApp};
+
+// Metrics are process-wide and the tests run in parallel, so only the presence of series is
+// checked rather than their values
+#[tokio::test]
+async fn should_expose_request_and_domain_metrics() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+
+    let signup = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": false
+    });
+
+    assert_eq!(app.post_signup(&signup).await.status().as_u16(), 201);
+
+    let login = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+
+    assert_eq!(app.post_login(&login).await.status().as_u16(), 200);
+    assert_eq!(app.post_logout().await.status().as_u16(), 200);
+
+    let response = app.get_metrics().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .headers()
+        .get("content-type")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain"));
+
+    let body = response.text().await.unwrap();
+
+    for series in [
+        r#"auth_http_requests_total{method="POST",route="/login",status="200"}"#,
+        r#"auth_http_request_duration_seconds_bucket{method="POST",route="/signup""#,
+        "auth_signups_total",
+        r#"auth_logins_total{outcome="success"}"#,
+        r#"auth_tokens_banned_total{reason="logout"}"#,
+        r#"auth_token_validation_duration_seconds_count{outcome="valid"}"#,
+        r#"auth_password_hash_duration_seconds_count{operation="hash"}"#,
+        r#"auth_password_hash_duration_seconds_count{operation="verify"}"#,
+    ] {
+        assert!(body.contains(series), "missing {} in\n{}", series, body);
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_label_requests_by_route_rather_than_path() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .http_client
+        .delete(format!(
+            "{}/trusted-devices/{}",
+            &app.address,
+            uuid::Uuid::new_v4()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    let body = app.get_metrics().await.text().await.unwrap();
+
+    assert!(
+        body.contains(
+            r#"auth_http_requests_total{method="DELETE",route="/trusted-devices/:id",status="400"}"#
+        ),
+        "{}",
+        body
+    );
+
+    app.clean_up().await;
+}
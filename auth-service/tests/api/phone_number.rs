@@ -0,0 +1,348 @@
+use auth_service::{
+    domain::{LoginAttemptId, TwoFAChannel},
+    routes::{SetPhoneNumberResponse, TwoFactorAuthResponse},
+    ErrorResponse,
+};
+use secrecy::ExposeSecret;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{get_random_email, TestApp};
+
+// Signs up a user without 2FA and logs them in so the app's cookie jar holds a JWT
+async fn signup_and_login(app: &TestApp, email: &str) {
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    });
+
+    let response = app.post_signup(&signup_body).await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "password123"
+    });
+
+    let response = app.post_login(&login_body).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+async fn mount_sms_gateway(app: &TestApp, expected_messages: u64) {
+    Mock::given(path("/messages"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(expected_messages)
+        .mount(&app.sms_server)
+        .await;
+}
+
+// Sets and verifies a phone number for the logged-in user
+async fn add_verified_phone_number(app: &TestApp, phone_number: &str) {
+    let response = app
+        .post_phone_number(&serde_json::json!({ "phoneNumber": phone_number }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let verification_id = response
+        .json::<SetPhoneNumberResponse>()
+        .await
+        .expect("Could not deserialize response body to SetPhoneNumberResponse")
+        .verification_id;
+
+    let (_, code) = app
+        .phone_verification_store
+        .get_code(&LoginAttemptId::parse(verification_id.clone().into()).unwrap())
+        .await
+        .unwrap();
+
+    let response = app
+        .post_verify_phone_number(&serde_json::json!({
+            "verificationId": verification_id,
+            "2FACode": code.as_ref().expose_secret(),
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .post_phone_number(&serde_json::json!({ "phoneNumber": "+14155551234" }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Missing Token".to_owned()
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_invalid_phone_number() {
+    let mut app = TestApp::new().await;
+
+    signup_and_login(&app, &get_random_email()).await;
+
+    for phone_number in ["", "4155551234", "+1 (415) 555-1234", "+123"] {
+        let response = app
+            .post_phone_number(&serde_json::json!({ "phoneNumber": phone_number }))
+            .await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "Failed for input: {:?}",
+            phone_number
+        );
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_send_masked_verification_code_by_sms() {
+    let mut app = TestApp::new().await;
+
+    signup_and_login(&app, &get_random_email()).await;
+    mount_sms_gateway(&app, 1).await;
+
+    let response = app
+        .post_phone_number(&serde_json::json!({ "phoneNumber": "+14155551234" }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let json_body = response
+        .json::<SetPhoneNumberResponse>()
+        .await
+        .expect("Could not deserialize response body to SetPhoneNumberResponse");
+
+    assert_eq!(json_body.destination, "+1 ***-***-1234".to_owned());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_incorrect_verification_code() {
+    let mut app = TestApp::new().await;
+
+    signup_and_login(&app, &get_random_email()).await;
+    mount_sms_gateway(&app, 1).await;
+
+    let response = app
+        .post_phone_number(&serde_json::json!({ "phoneNumber": "+14155551234" }))
+        .await;
+
+    let verification_id = response
+        .json::<SetPhoneNumberResponse>()
+        .await
+        .expect("Could not deserialize response body to SetPhoneNumberResponse")
+        .verification_id;
+
+    let (_, code) = app
+        .phone_verification_store
+        .get_code(&LoginAttemptId::parse(verification_id.clone().into()).unwrap())
+        .await
+        .unwrap();
+
+    let wrong_code = if code.as_ref().expose_secret() == "123456" {
+        "654321"
+    } else {
+        "123456"
+    };
+
+    let response = app
+        .post_verify_phone_number(&serde_json::json!({
+            "verificationId": verification_id,
+            "2FACode": wrong_code,
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_not_verify_a_number_with_the_code_sent_to_a_previous_one() {
+    let mut app = TestApp::new().await;
+
+    signup_and_login(&app, &get_random_email()).await;
+    mount_sms_gateway(&app, 2).await;
+
+    // A code arrives at a number the user controls...
+    let response = app
+        .post_phone_number(&serde_json::json!({ "phoneNumber": "+14155551234" }))
+        .await;
+
+    let verification_id = response
+        .json::<SetPhoneNumberResponse>()
+        .await
+        .expect("Could not deserialize response body to SetPhoneNumberResponse")
+        .verification_id;
+
+    let (_, code) = app
+        .phone_verification_store
+        .get_code(&LoginAttemptId::parse(verification_id.clone().into()).unwrap())
+        .await
+        .unwrap();
+
+    // ...who then swaps in a number they don't control before confirming it
+    let response = app
+        .post_phone_number(&serde_json::json!({ "phoneNumber": "+14155550000" }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_verify_phone_number(&serde_json::json!({
+            "verificationId": verification_id,
+            "2FACode": code.as_ref().expose_secret(),
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    // The swapped in number stays unverified, so it can't be used for 2FA
+    let response = app
+        .post_two_fa_channel(&serde_json::json!({ "channel": "sms" }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_not_accept_phone_verification_as_login() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+
+    signup_and_login(&app, &random_email).await;
+    mount_sms_gateway(&app, 1).await;
+
+    let response = app
+        .post_phone_number(&serde_json::json!({ "phoneNumber": "+14155551234" }))
+        .await;
+
+    let verification_id = response
+        .json::<SetPhoneNumberResponse>()
+        .await
+        .expect("Could not deserialize response body to SetPhoneNumberResponse")
+        .verification_id;
+
+    let (_, code) = app
+        .phone_verification_store
+        .get_code(&LoginAttemptId::parse(verification_id.clone().into()).unwrap())
+        .await
+        .unwrap();
+
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "email": random_email,
+            "loginAttemptId": verification_id,
+            "2FACode": code.as_ref().expose_secret(),
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_send_2fa_code_by_sms_once_channel_selected() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": true
+    });
+
+    let response = app.post_signup(&signup_body).await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    // The first login still goes out by email, since no phone number is set yet
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // One message verifies the number, the other carries the second login's code
+    mount_sms_gateway(&app, 2).await;
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123"
+    });
+
+    let response = app.post_login(&login_body).await;
+
+    assert_eq!(response.status().as_u16(), 206);
+
+    let json_body = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse");
+
+    let (_, code) = app
+        .two_fa_code_store
+        .get_code(&LoginAttemptId::parse(json_body.login_attempt_id.clone().into()).unwrap())
+        .await
+        .unwrap();
+
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "email": random_email,
+            "loginAttemptId": json_body.login_attempt_id,
+            "2FACode": code.as_ref().expose_secret(),
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    add_verified_phone_number(&app, "+14155551234").await;
+
+    let response = app
+        .post_two_fa_channel(&serde_json::json!({ "channel": "sms" }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_login(&login_body).await;
+
+    assert_eq!(response.status().as_u16(), 206);
+
+    let json_body = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse");
+
+    assert_eq!(json_body.channel, TwoFAChannel::Sms);
+    assert_eq!(json_body.destination, "+1 ***-***-1234".to_owned());
+
+    app.clean_up().await;
+}
@@ -0,0 +1,189 @@
+use auth_service::{
+    domain::{RateLimitConfig, RateLimitKey, RateLimitPolicy, RateLimitRule},
+    ErrorResponse,
+};
+
+use crate::helpers::{get_random_email, TestApp};
+
+fn header(response: &reqwest::Response, name: &str) -> Option<String> {
+    response
+        .headers()
+        .get(name)
+        .map(|value| value.to_str().unwrap().to_owned())
+}
+
+#[tokio::test]
+async fn should_return_429_once_global_limit_is_exhausted() {
+    let config = RateLimitConfig::disabled().with_global(RateLimitRule::new(
+        RateLimitPolicy::per_minute(2),
+        RateLimitKey::Ip,
+    ));
+    let mut app = TestApp::with_rate_limit_config(config).await;
+
+    let body = serde_json::json!({ "token": "invalid" });
+
+    for expected_remaining in ["1", "0"] {
+        let response = app.post_verify_token(&body).await;
+
+        assert_eq!(response.status().as_u16(), 401);
+        assert_eq!(header(&response, "ratelimit-limit").as_deref(), Some("2"));
+        assert_eq!(
+            header(&response, "ratelimit-remaining").as_deref(),
+            Some(expected_remaining)
+        );
+        assert!(header(&response, "ratelimit-reset").is_some());
+    }
+
+    // The global bucket covers every route
+    let response = app.post_logout().await;
+
+    assert_eq!(response.status().as_u16(), 429);
+    assert_eq!(header(&response, "retry-after").as_deref(), Some("30"));
+    assert_eq!(
+        header(&response, "ratelimit-remaining").as_deref(),
+        Some("0")
+    );
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Too Many Requests".to_owned()
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_limit_route_by_email() {
+    let config = RateLimitConfig::disabled().with_route(
+        "/login",
+        RateLimitRule::new(RateLimitPolicy::per_minute(1), RateLimitKey::Email),
+    );
+    let mut app = TestApp::with_rate_limit_config(config).await;
+
+    let first_email = get_random_email();
+    let login = |email: &str| {
+        serde_json::json!({
+            "email": email,
+            "password": "password123",
+        })
+    };
+
+    let response = app.post_login(&login(&first_email)).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    // Emails are compared case-insensitively so the limit can't be dodged
+    let response = app.post_login(&login(&first_email.to_uppercase())).await;
+
+    assert_eq!(response.status().as_u16(), 429);
+
+    let response = app.post_login(&login(&get_random_email())).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    // Routes without a rule aren't limited
+    let response = app.post_logout().await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(header(&response, "ratelimit-limit").is_none());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_limit_route_by_api_key() {
+    let config = RateLimitConfig::disabled().with_route(
+        "/verify-token",
+        RateLimitRule::new(
+            RateLimitPolicy::per_minute(1),
+            RateLimitKey::ApiKey {
+                header: "x-api-key".to_owned(),
+            },
+        ),
+    );
+    let mut app = TestApp::with_rate_limit_config(config).await;
+
+    let verify_token = |api_key: &'static str| {
+        app.http_client
+            .post(format!("{}/verify-token", &app.address))
+            .header("x-api-key", api_key)
+            .json(&serde_json::json!({ "token": "invalid" }))
+            .send()
+    };
+
+    let response = verify_token("first-key")
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = verify_token("first-key")
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 429);
+
+    let response = verify_token("second-key")
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_not_limit_requests_with_an_exempt_api_key() {
+    let config = RateLimitConfig::disabled()
+        .with_global(RateLimitRule::new(
+            RateLimitPolicy::per_minute(1),
+            RateLimitKey::Ip,
+        ))
+        .with_route(
+            "/verify-token",
+            RateLimitRule::new(
+                RateLimitPolicy::per_minute(1),
+                RateLimitKey::ApiKey {
+                    header: "x-api-key".to_owned(),
+                },
+            ),
+        )
+        .exempt_api_key("app-service-key");
+    let mut app = TestApp::with_rate_limit_config(config).await;
+
+    let verify_token = |api_key: &'static str| {
+        app.http_client
+            .post(format!("{}/verify-token", &app.address))
+            .header("x-api-key", api_key)
+            .json(&serde_json::json!({ "token": "invalid" }))
+            .send()
+    };
+
+    // Every page view app-service serves checks a token, whoever's viewing it
+    for _ in 0..3 {
+        let response = verify_token("app-service-key")
+            .await
+            .expect("Failed to execute request.");
+
+        assert_eq!(response.status().as_u16(), 401);
+        assert!(header(&response, "ratelimit-limit").is_none());
+    }
+
+    // Other keys are still limited
+    let response = verify_token("other-key")
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = verify_token("other-key")
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 429);
+
+    app.clean_up().await;
+}
@@ -0,0 +1,317 @@
+use std::{sync::Arc, time::Duration};
+
+use auth_service::{
+    domain::{
+        data_stores::{
+            BannedTokenStore, LoginAttemptId, RateLimitStore, TwoFACode, TwoFACodeStore,
+            TwoFACodeStoreError,
+        },
+        Email, RateLimitPolicy,
+    },
+    get_redis_client,
+    services::data_stores::{RedisBannedTokenStore, RedisRateLimitStore, RedisTwoFACodeStore},
+    settings::Settings,
+    utils::constants::MAX_FAILED_2FA_ATTEMPTS,
+};
+use redis::aio::ConnectionManager;
+use secrecy::{ExposeSecret, Secret};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::Mutex,
+    task::JoinHandle,
+};
+
+use crate::helpers::TestApp;
+
+// Forwards connections to Redis until they're cut, as a Redis restart would
+struct RedisProxy {
+    address: String,
+    connections: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    listener: JoinHandle<()>,
+}
+
+impl RedisProxy {
+    async fn start(redis_host_name: &str) -> Self {
+        let upstream = match redis_host_name.contains(':') {
+            true => redis_host_name.to_owned(),
+            false => format!("{}:6379", redis_host_name),
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let connections = Arc::new(Mutex::new(vec![]));
+
+        let listener = tokio::spawn({
+            let connections = connections.clone();
+            async move {
+                while let Ok((mut client, _)) = listener.accept().await {
+                    let upstream = upstream.clone();
+                    connections.lock().await.push(tokio::spawn(async move {
+                        let mut server = TcpStream::connect(upstream).await.unwrap();
+                        let _ = tokio::io::copy_bidirectional(&mut client, &mut server).await;
+                    }));
+                }
+            }
+        });
+
+        Self {
+            address,
+            connections,
+            listener,
+        }
+    }
+
+    async fn cut_connections(&self) {
+        for connection in self.connections.lock().await.drain(..) {
+            connection.abort();
+        }
+    }
+}
+
+impl Drop for RedisProxy {
+    fn drop(&mut self) {
+        self.listener.abort();
+    }
+}
+
+fn redis_host_name() -> String {
+    Settings::load_environment(Some("test"))
+        .expect("Failed to load settings")
+        .redis
+        .host_name
+}
+
+pub async fn connect() -> ConnectionManager {
+    let client = get_redis_client(redis_host_name()).expect("Failed to get Redis client");
+    ConnectionManager::new(client)
+        .await
+        .expect("Failed to get Redis connection")
+}
+
+#[tokio::test]
+async fn should_reconnect_after_redis_drops_the_connection() {
+    let proxy = RedisProxy::start(&redis_host_name()).await;
+    let mut app = TestApp::with_settings(|settings| {
+        settings.redis.host_name = proxy.address.clone();
+    })
+    .await;
+    assert_eq!(app.get_health_ready().await.status().as_u16(), 200);
+
+    proxy.cut_connections().await;
+
+    // The command that finds the connection gone fails, and the ones after it reconnect
+    let mut recovered = false;
+    for _ in 0..50 {
+        if app.get_health_ready().await.status().is_success() {
+            recovered = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(recovered, "Redis connection was not re-established");
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_not_admit_more_than_the_capacity_when_taking_tokens_concurrently() {
+    let conn = connect().await;
+    let key = uuid::Uuid::new_v4().to_string();
+    let policy = RateLimitPolicy::per_minute(5);
+
+    // Stores sharing a connection, as the service's do, and with one of their own, as other
+    // instances' do
+    let mut stores = vec![];
+    for i in 0..10 {
+        let conn = match i % 2 {
+            0 => conn.clone(),
+            _ => connect().await,
+        };
+        stores.push(RedisRateLimitStore::new(conn));
+    }
+    let takes = stores.into_iter().map(|store| {
+        let key = key.clone();
+        tokio::spawn(async move { store.take_token(&key, &policy).await.unwrap() })
+    });
+
+    let mut allowed = 0;
+    for take in takes {
+        if take.await.unwrap().allowed {
+            allowed += 1;
+        }
+    }
+
+    assert_eq!(allowed, 5);
+}
+
+#[tokio::test]
+async fn should_resend_a_code_only_once_when_resending_concurrently() {
+    let mut conn = connect().await;
+    let login_attempt_id = LoginAttemptId::default();
+
+    // A code sent long enough ago to be resent
+    let pending = serde_json::json!({
+        "email": "user@example.com",
+        "code": "123456",
+        "resend_count": 0,
+        "last_sent_at": chrono::Utc::now().timestamp() - 600,
+    });
+    let _: () = redis::cmd("SET")
+        .arg(format!(
+            "resend_test_code:{}",
+            login_attempt_id.expose_secret()
+        ))
+        .arg(pending.to_string())
+        .arg("EX")
+        .arg(600)
+        .query_async(&mut conn)
+        .await
+        .unwrap();
+
+    let resends = (0..10).map(|_| {
+        let store = RedisTwoFACodeStore::with_namespace(conn.clone(), "resend_test");
+        let login_attempt_id = login_attempt_id.clone();
+        tokio::spawn(async move {
+            store
+                .resend_code(&login_attempt_id, TwoFACode::default())
+                .await
+        })
+    });
+
+    let mut resent = 0;
+    for resend in resends {
+        match resend.await.unwrap() {
+            Ok(email) => {
+                assert_eq!(email.as_ref().expose_secret(), "user@example.com");
+                resent += 1;
+            }
+            Err(TwoFACodeStoreError::ResendCooldown { .. }) => {}
+            Err(e) => panic!("Unexpected error: {:?}", e),
+        }
+    }
+
+    assert_eq!(resent, 1);
+}
+
+#[tokio::test]
+async fn should_not_count_more_guesses_than_the_limit_when_guessing_concurrently() {
+    let conn = connect().await;
+    let store = Arc::new(RedisTwoFACodeStore::with_namespace(conn, "guess_test"));
+    let email = Email::parse(Secret::new("user@example.com".to_owned())).unwrap();
+    let login_attempt_id = LoginAttemptId::default();
+    let code = TwoFACode::parse(Secret::new("123456".to_owned())).unwrap();
+    store
+        .add_code(email.clone(), login_attempt_id.clone(), code.clone())
+        .await
+        .unwrap();
+
+    let guesses = (0..MAX_FAILED_2FA_ATTEMPTS * 4).map(|i| {
+        let store = store.clone();
+        let email = email.clone();
+        let login_attempt_id = login_attempt_id.clone();
+        let guess = TwoFACode::parse(Secret::new((200_000 + i).to_string())).unwrap();
+        tokio::spawn(async move { store.consume_code(&login_attempt_id, &email, &guess).await })
+    });
+
+    let mut incorrect = 0;
+    for guess in guesses {
+        match guess.await.unwrap() {
+            Err(TwoFACodeStoreError::IncorrectCode) => incorrect += 1,
+            Err(TwoFACodeStoreError::LoginAttemptIdNotFound) => {}
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    // Only the guesses up to the limit were checked against the code, which is gone after them
+    assert_eq!(incorrect, MAX_FAILED_2FA_ATTEMPTS);
+    assert_eq!(
+        store
+            .consume_code(&login_attempt_id, &email, &code)
+            .await
+            .unwrap_err(),
+        TwoFACodeStoreError::LoginAttemptIdNotFound
+    );
+}
+
+#[tokio::test]
+async fn should_consume_a_code_only_once_when_consuming_concurrently() {
+    let conn = connect().await;
+    let email = Email::parse(Secret::new("user@example.com".to_owned())).unwrap();
+    let login_attempt_id = LoginAttemptId::default();
+    let code = TwoFACode::parse(Secret::new("123456".to_owned())).unwrap();
+    RedisTwoFACodeStore::with_namespace(conn.clone(), "consume_test")
+        .add_code(email.clone(), login_attempt_id.clone(), code.clone())
+        .await
+        .unwrap();
+
+    // Stores sharing a connection, as the service's do, and with one of their own, as other
+    // instances' do
+    let mut stores = vec![];
+    for i in 0..10 {
+        let conn = match i % 2 {
+            0 => conn.clone(),
+            _ => connect().await,
+        };
+        stores.push(RedisTwoFACodeStore::with_namespace(conn, "consume_test"));
+    }
+    let consumes = stores.into_iter().map(|store| {
+        let email = email.clone();
+        let login_attempt_id = login_attempt_id.clone();
+        let code = code.clone();
+        tokio::spawn(async move { store.consume_code(&login_attempt_id, &email, &code).await })
+    });
+
+    let mut consumed = 0;
+    for consume in consumes {
+        match consume.await.unwrap() {
+            Ok(()) => consumed += 1,
+            Err(TwoFACodeStoreError::LoginAttemptIdNotFound) => {}
+            Err(e) => panic!("Unexpected error: {:?}", e),
+        }
+    }
+
+    assert_eq!(consumed, 1);
+    // Nothing is left to remove either
+    assert_eq!(
+        RedisTwoFACodeStore::with_namespace(conn, "consume_test")
+            .remove_code(&login_attempt_id)
+            .await
+            .unwrap_err(),
+        TwoFACodeStoreError::LoginAttemptIdNotFound
+    );
+}
+
+#[tokio::test]
+async fn should_keep_every_ban_when_banning_tokens_concurrently() {
+    let conn = connect().await;
+    let tokens: Vec<_> = (0..20)
+        .map(|_| Secret::new(uuid::Uuid::new_v4().to_string()))
+        .collect();
+
+    // Each token is banned by one store and checked by another, some of them on other
+    // connections, while the other bans are under way
+    let mut stores = vec![];
+    for i in 0..tokens.len() {
+        let conn = match i % 2 {
+            0 => conn.clone(),
+            _ => connect().await,
+        };
+        stores.push(Arc::new(RedisBannedTokenStore::new(conn)));
+    }
+    let bans = tokens.iter().enumerate().map(|(i, token)| {
+        let store = stores[i].clone();
+        let other_store = stores[(i + 1) % stores.len()].clone();
+        let token = token.clone();
+        tokio::spawn(async move {
+            store.add_token(token.clone(), 600).await.unwrap();
+            other_store.contains_token(&token).await.unwrap()
+        })
+    });
+
+    for ban in bans {
+        assert!(ban.await.unwrap());
+    }
+    let store = RedisBannedTokenStore::new(conn);
+    for token in &tokens {
+        assert!(store.contains_token(token).await.unwrap());
+    }
+}
@@ -0,0 +1,204 @@
+use std::time::Duration;
+
+use auth_service::utils::{
+    auth::TOKEN_TTL_SECONDS,
+    constants::{DEVICE_COOKIE_NAME, JWT_COOKIE_NAME},
+};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::{
+    helpers::{get_random_email, TestApp},
+    redis::connect,
+};
+
+const NEW_SIGN_IN_SUBJECT: &str = "New sign-in to your account";
+const RESET_PASSWORD_SUBJECT: &str = "Reset your password";
+
+async fn mount_email_server(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+async fn signup(app: &TestApp) -> String {
+    let email = get_random_email();
+    let signup = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    });
+    assert_eq!(app.post_signup(&signup).await.status().as_u16(), 201);
+    email
+}
+
+#[tokio::test]
+async fn should_email_user_about_login_from_new_device() {
+    let mut app = TestApp::new().await;
+    mount_email_server(&app).await;
+
+    let email = signup(&app).await;
+    let login = serde_json::json!({ "email": email, "password": "password123" });
+
+    // Nothing to compare the first device against, and the second login is from the same one
+    for _ in 0..2 {
+        let response = app.post_login(&login).await;
+        assert_eq!(response.status().as_u16(), 200);
+        assert!(response
+            .cookies()
+            .any(|cookie| cookie.name() == DEVICE_COOKIE_NAME));
+    }
+    assert_eq!(app.email_link_token(NEW_SIGN_IN_SUBJECT).await, None);
+
+    let response = app
+        .post_login_from_new_device("Other Browser", &login)
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let requests = app.email_server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 1);
+    let notification: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+    assert_eq!(notification["To"], email);
+    assert!(notification["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("Device: Other Browser"));
+    assert!(app.email_link_token(NEW_SIGN_IN_SUBJECT).await.is_some());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_sign_out_everywhere_and_require_password_reset_when_login_reported() {
+    let mut app = TestApp::new().await;
+    mount_email_server(&app).await;
+
+    let email = signup(&app).await;
+    let login = serde_json::json!({ "email": email, "password": "password123" });
+
+    let response = app.post_login(&login).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let session_token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("Auth cookie not found")
+        .value()
+        .to_owned();
+
+    let response = app.post_login_from_new_device("Intruder", &login).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let report_token = app.email_link_token(NEW_SIGN_IN_SUBJECT).await.unwrap();
+
+    // Sessions are revoked by the second they were issued in
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let response = app.post_report_login(&report_token).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Your account is secured"));
+
+    let verify_token = serde_json::json!({ "token": session_token });
+    assert_eq!(
+        app.post_verify_token(&verify_token).await.status().as_u16(),
+        401
+    );
+    assert_eq!(app.post_login(&login).await.status().as_u16(), 401);
+
+    // The link only works once, for as long as it would otherwise work
+    let ban_ttl: i64 = redis::cmd("TTL")
+        .arg(format!("banned_token:{}", report_token))
+        .query_async(&mut connect().await)
+        .await
+        .unwrap();
+    assert!(ban_ttl > TOKEN_TTL_SECONDS);
+    assert_eq!(
+        app.get_report_login(&report_token).await.status().as_u16(),
+        401
+    );
+    assert_eq!(
+        app.post_report_login(&report_token).await.status().as_u16(),
+        401
+    );
+
+    let reset_token = app.email_link_token(RESET_PASSWORD_SUBJECT).await.unwrap();
+    let reset = serde_json::json!({ "token": reset_token, "newPassword": "new-password123" });
+    assert_eq!(app.post_reset_password(&reset).await.status().as_u16(), 200);
+
+    let login = serde_json::json!({ "email": email, "password": "new-password123" });
+    assert_eq!(app.post_login(&login).await.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_only_ask_for_confirmation_when_report_link_opened() {
+    let mut app = TestApp::new().await;
+    mount_email_server(&app).await;
+
+    let email = signup(&app).await;
+    let login = serde_json::json!({ "email": email, "password": "password123" });
+
+    assert_eq!(app.post_login(&login).await.status().as_u16(), 200);
+    let response = app.post_login_from_new_device("Intruder", &login).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let report_token = app.email_link_token(NEW_SIGN_IN_SUBJECT).await.unwrap();
+
+    // Link scanners and previews fetching the link must not lock the user out
+    for _ in 0..2 {
+        let response = app.get_report_login(&report_token).await;
+        assert_eq!(response.status().as_u16(), 200);
+        let page = response.text().await.unwrap();
+        assert!(page.contains("<form method=\"post\" action=\"/report-login\">"));
+        assert!(page.contains(&format!("value=\"{}\"", report_token)));
+    }
+    assert_eq!(app.post_login(&login).await.status().as_u16(), 200);
+    assert_eq!(app.email_link_token(RESET_PASSWORD_SUBJECT).await, None);
+
+    // Confirming still works after the page was shown
+    let response = app.post_report_login(&report_token).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(app.post_login(&login).await.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_invalid_token() {
+    let mut app = TestApp::new().await;
+
+    let email = signup(&app).await;
+    let login = serde_json::json!({ "email": email, "password": "password123" });
+    let response = app.post_login(&login).await;
+    let session_token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("Auth cookie not found")
+        .value()
+        .to_owned();
+
+    for token in ["invalid_token", session_token.as_str()] {
+        for response in [
+            app.get_report_login(token).await,
+            app.post_report_login(token).await,
+        ] {
+            assert_eq!(
+                response.status().as_u16(),
+                401,
+                "Failed for token: {}",
+                token
+            );
+        }
+    }
+
+    // The user can still log in
+    assert_eq!(app.post_login(&login).await.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
@@ -0,0 +1,231 @@
+use auth_service::{
+    domain::LoginAttemptId, routes::TwoFactorAuthResponse,
+    utils::constants::TWO_FA_RESEND_COOLDOWN_SECONDS, ErrorResponse,
+};
+use secrecy::ExposeSecret;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{get_random_email, TestApp};
+
+// Signs up a 2FA user and logs in, returning the login attempt ID
+async fn login_with_2fa(app: &TestApp, email: &str) -> String {
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": true
+    });
+
+    let response = app.post_signup(&signup_body).await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "password123"
+    });
+
+    let response = app.post_login(&login_body).await;
+
+    assert_eq!(response.status().as_u16(), 206);
+
+    response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id
+}
+
+#[tokio::test]
+async fn should_return_429_if_resent_during_cooldown() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+
+    // Only the login sends an email; the resend is rejected
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let login_attempt_id = login_with_2fa(&app, &random_email).await;
+
+    let (_, code_before) = app
+        .two_fa_code_store
+        .get_code(&LoginAttemptId::parse(login_attempt_id.clone().into()).unwrap())
+        .await
+        .unwrap();
+
+    let request_body = serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": login_attempt_id,
+    });
+
+    let response = app.post_resend_2fa_code(&request_body).await;
+
+    assert_eq!(response.status().as_u16(), 429);
+
+    let retry_after: u64 = response
+        .headers()
+        .get("retry-after")
+        .expect("No Retry-After header found")
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+
+    assert!(retry_after > 0 && retry_after <= TWO_FA_RESEND_COOLDOWN_SECONDS);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Too Many Requests".to_owned()
+    );
+
+    // The pending code is left untouched
+    let (_, code_after) = app
+        .two_fa_code_store
+        .get_code(&LoginAttemptId::parse(login_attempt_id.into()).unwrap())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        code_before.as_ref().expose_secret(),
+        code_after.as_ref().expose_secret()
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_incorrect_credentials() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let login_attempt_id = login_with_2fa(&app, &random_email).await;
+
+    let incorrect_email = get_random_email();
+    let incorrect_login_attempt_id = LoginAttemptId::default();
+
+    let test_cases = vec![
+        (incorrect_email.as_str(), login_attempt_id.as_str()),
+        (
+            random_email.as_str(),
+            incorrect_login_attempt_id.expose_secret().as_str(),
+        ),
+    ];
+
+    for (email, login_attempt_id) in test_cases {
+        let request_body = serde_json::json!({
+            "email": email,
+            "loginAttemptId": login_attempt_id,
+        });
+
+        let response = app.post_resend_2fa_code(&request_body).await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            401,
+            "Failed for input: {:?}",
+            request_body
+        );
+
+        assert_eq!(
+            response
+                .json::<ErrorResponse>()
+                .await
+                .expect("Could not deserialize response body to ErrorResponse")
+                .error,
+            "Incorrect Credentials".to_owned()
+        );
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_invalid_input() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    let login_attempt_id = LoginAttemptId::default();
+
+    let test_cases = vec![
+        ("invalid_email", login_attempt_id.expose_secret().as_str()),
+        (random_email.as_str(), "invalid_login_attempt_id"),
+        ("", ""),
+    ];
+
+    for (email, login_attempt_id) in test_cases {
+        let request_body = serde_json::json!({
+            "email": email,
+            "loginAttemptId": login_attempt_id,
+        });
+
+        let response = app.post_resend_2fa_code(&request_body).await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "Failed for input: {:?}",
+            request_body
+        );
+
+        assert_eq!(
+            response
+                .json::<ErrorResponse>()
+                .await
+                .expect("Could not deserialize response body to ErrorResponse")
+                .error,
+            "Invalid Credentials".to_owned()
+        );
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_422_if_malformed_input() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+    let login_attempt_id = LoginAttemptId::default();
+
+    let test_cases = [
+        serde_json::json!({
+            "email": random_email,
+        }),
+        serde_json::json!({
+            "loginAttemptId": login_attempt_id.expose_secret(),
+        }),
+        serde_json::json!({}),
+    ];
+
+    for test_case in test_cases {
+        let response = app.post_resend_2fa_code(&test_case).await;
+
+        assert_eq!(
+            response.status().as_u16(),
+            422,
+            "Failed for input: {:?}",
+            test_case
+        );
+    }
+
+    app.clean_up().await;
+}
@@ -0,0 +1,113 @@
+use auth_service::{
+    domain::{PasswordPolicy, PasswordRule},
+    ErrorResponse,
+};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{get_random_email, TestApp};
+
+// Signs up a user, has someone else log in as them and reports that login, which is how users
+// come by a password reset link
+async fn password_reset_token(app: &TestApp, email: &str) -> String {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let signup = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    });
+    assert_eq!(app.post_signup(&signup).await.status().as_u16(), 201);
+
+    let login = serde_json::json!({ "email": email, "password": "password123" });
+    assert_eq!(app.post_login(&login).await.status().as_u16(), 200);
+    let response = app.post_login_from_new_device("Intruder", &login).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let report_token = app
+        .email_link_token("New sign-in to your account")
+        .await
+        .unwrap();
+    assert_eq!(
+        app.post_report_login(&report_token).await.status().as_u16(),
+        200
+    );
+
+    app.email_link_token("Reset your password").await.unwrap()
+}
+
+#[tokio::test]
+async fn should_return_422_if_malformed_input() {
+    let mut app = TestApp::new().await;
+
+    let test_cases = [
+        serde_json::json!({ "token": "token" }),
+        serde_json::json!({ "newPassword": "new-password123" }),
+        serde_json::json!({}),
+    ];
+
+    for test_case in test_cases {
+        let response = app.post_reset_password(&test_case).await;
+        assert_eq!(
+            response.status().as_u16(),
+            422,
+            "Failed for input: {:?}",
+            test_case
+        );
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_token_invalid_or_used() {
+    let mut app = TestApp::new().await;
+    let email = get_random_email();
+    let token = password_reset_token(&app, &email).await;
+
+    let reset = serde_json::json!({ "token": "invalid_token", "newPassword": "new-password123" });
+    assert_eq!(app.post_reset_password(&reset).await.status().as_u16(), 401);
+
+    let reset = serde_json::json!({ "token": token, "newPassword": "new-password123" });
+    assert_eq!(app.post_reset_password(&reset).await.status().as_u16(), 200);
+
+    let reset = serde_json::json!({ "token": token, "newPassword": "other-password123" });
+    assert_eq!(app.post_reset_password(&reset).await.status().as_u16(), 401);
+
+    let login = serde_json::json!({ "email": email, "password": "new-password123" });
+    assert_eq!(app.post_login(&login).await.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_new_password_was_used_recently() {
+    let mut app = TestApp::with_password_policy(PasswordPolicy {
+        history_size: 5,
+        ..PasswordPolicy::minimal()
+    })
+    .await;
+    let email = get_random_email();
+    let token = password_reset_token(&app, &email).await;
+
+    // Reporting a login doesn't let the password the intruder used back in
+    let reset = serde_json::json!({ "token": token, "newPassword": "password123" });
+    let response = app.post_reset_password(&reset).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let error = response.json::<ErrorResponse>().await.unwrap();
+    assert_eq!(error.details.len(), 1);
+    assert_eq!(error.details[0].rule, PasswordRule::Reused);
+
+    // The token wasn't used up by the rejected attempt
+    let reset = serde_json::json!({ "token": token, "newPassword": "new-password123" });
+    assert_eq!(app.post_reset_password(&reset).await.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
@@ -10,4 +10,74 @@ async fn root_returns_auth_ui() {
     assert_eq!(response.headers().get("content-type").unwrap(), "text/html");
 
     app.clean_up().await;
-}
\ No newline at end of file
+}
+
+#[tokio::test]
+async fn should_allow_only_configured_cors_origins() {
+    let mut app = TestApp::with_settings(|settings| {
+        settings.application.allowed_origins = vec!["https://app.example.com".to_owned()];
+    })
+    .await;
+
+    let response = app
+        .http_client
+        .get(format!("{}/", &app.address))
+        .header("origin", "https://app.example.com")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(
+        response
+            .headers()
+            .get("access-control-allow-origin")
+            .unwrap(),
+        "https://app.example.com"
+    );
+    assert_eq!(
+        response
+            .headers()
+            .get("access-control-allow-credentials")
+            .unwrap(),
+        "true"
+    );
+
+    let response = app
+        .http_client
+        .get(format!("{}/", &app.address))
+        .header("origin", "https://evil.example.com")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert!(response
+        .headers()
+        .get("access-control-allow-origin")
+        .is_none());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_request_id() {
+    let mut app = TestApp::new().await;
+
+    let response = app.get_root().await;
+
+    let request_id = response.headers().get("x-request-id").unwrap();
+    assert!(uuid::Uuid::parse_str(request_id.to_str().unwrap()).is_ok());
+
+    // IDs that would be unsafe to log are replaced
+    let response = app
+        .http_client
+        .get(format!("{}/", &app.address))
+        .header("x-request-id", "bad id\twith spaces")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    let request_id = response.headers().get("x-request-id").unwrap();
+    assert!(uuid::Uuid::parse_str(request_id.to_str().unwrap()).is_ok());
+
+    app.clean_up().await;
+}
@@ -0,0 +1,418 @@
+use std::time::Duration;
+
+use auth_service::{
+    domain::{LoginAttemptId, ERROR_SCHEMA, PATCH_OP_SCHEMA, USER_SCHEMA},
+    routes::TwoFactorAuthResponse,
+    utils::constants::JWT_COOKIE_NAME,
+};
+use secrecy::ExposeSecret;
+use serde_json::Value;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{get_random_email, TestApp};
+
+async fn create_user(app: &TestApp, email: &str) -> Value {
+    let response = app
+        .post_scim(
+            "Users",
+            &serde_json::json!({
+                "schemas": [USER_SCHEMA],
+                "userName": email,
+                "externalId": format!("ext-{}", email),
+                "password": "password123",
+            }),
+        )
+        .await;
+
+    assert_eq!(response.status().as_u16(), 201);
+    assert_eq!(response.headers()["content-type"], "application/scim+json");
+
+    response
+        .json()
+        .await
+        .expect("Could not deserialize SCIM user")
+}
+
+async fn login(app: &TestApp, email: &str) -> u16 {
+    app.post_login(&serde_json::json!({
+        "email": email,
+        "password": "password123",
+    }))
+    .await
+    .status()
+    .as_u16()
+}
+
+// Logs the user in and returns their session token
+async fn login_token(app: &TestApp, email: &str) -> String {
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("Auth cookie not found")
+        .value()
+        .to_owned();
+    token
+}
+
+async fn verify_token(app: &TestApp, token: &str) -> u16 {
+    app.post_verify_token(&serde_json::json!({ "token": token }))
+        .await
+        .status()
+        .as_u16()
+}
+
+#[tokio::test]
+async fn should_return_401_without_scim_token() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .http_client
+        .get(format!("{}/scim/v2/Users", &app.address))
+        .bearer_auth("wrong-token")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 401);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["schemas"][0], ERROR_SCHEMA);
+    assert_eq!(body["status"], "401");
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_create_get_and_filter_users() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+    let user = create_user(&app, &email).await;
+    let id = user["id"].as_str().unwrap();
+    assert_eq!(user["userName"], email);
+    assert_eq!(user["active"], true);
+    assert_eq!(user["meta"]["location"], format!("/scim/v2/Users/{}", id));
+
+    create_user(&app, &get_random_email()).await;
+
+    let response = app.get_scim(&format!("Users/{}", id)).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.json::<Value>().await.unwrap(), user);
+
+    let response = app
+        .get_scim(&format!("Users?filter=userName%20eq%20%22{}%22", email))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let list: Value = response.json().await.unwrap();
+    assert_eq!(list["totalResults"], 1);
+    assert_eq!(list["Resources"][0]["id"], id);
+
+    let response = app.get_scim("Users?startIndex=2&count=1").await;
+    let list: Value = response.json().await.unwrap();
+    assert_eq!(list["totalResults"], 2);
+    assert_eq!(list["startIndex"], 2);
+    assert_eq!(list["itemsPerPage"], 1);
+
+    let response = app.get_scim("Users?filter=name.givenName%20pr").await;
+    assert_eq!(response.status().as_u16(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["scimType"], "invalidFilter");
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_409_if_user_name_is_taken() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+    create_user(&app, &email).await;
+
+    let response = app
+        .post_scim(
+            "Users",
+            &serde_json::json!({ "schemas": [USER_SCHEMA], "userName": email }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 409);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["scimType"], "uniqueness");
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn deactivated_users_cannot_log_in() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+    let user = create_user(&app, &email).await;
+    let path = format!("Users/{}", user["id"].as_str().unwrap());
+    assert_eq!(login(&app, &email).await, 200);
+
+    let response = app
+        .patch_scim(
+            &path,
+            &serde_json::json!({
+                "schemas": [PATCH_OP_SCHEMA],
+                "Operations": [{ "op": "Replace", "path": "active", "value": "False" }],
+            }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.json::<Value>().await.unwrap()["active"], false);
+    assert_eq!(login(&app, &email).await, 401);
+
+    let response = app
+        .patch_scim(
+            &path,
+            &serde_json::json!({
+                "schemas": [PATCH_OP_SCHEMA],
+                "Operations": [{ "op": "replace", "value": { "active": true } }],
+            }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(login(&app, &email).await, 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_revoke_sessions_of_deactivated_users() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+    let user = create_user(&app, &email).await;
+    let path = format!("Users/{}", user["id"].as_str().unwrap());
+    let token = login_token(&app, &email).await;
+    assert_eq!(verify_token(&app, &token).await, 200);
+
+    // Sessions are revoked by the second they were issued in
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let response = app
+        .put_scim(
+            &path,
+            &serde_json::json!({
+                "schemas": [USER_SCHEMA],
+                "userName": email,
+                "active": false,
+            }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(verify_token(&app, &token).await, 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_not_complete_2fa_login_of_user_deactivated_meanwhile() {
+    let mut app = TestApp::new().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let email = get_random_email();
+    let signup = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": true
+    });
+    assert_eq!(app.post_signup(&signup).await.status().as_u16(), 201);
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+    let (_, code) = app
+        .two_fa_code_store
+        .get_code(&LoginAttemptId::parse(login_attempt_id.clone().into()).unwrap())
+        .await
+        .unwrap();
+
+    let response = app
+        .get_scim(&format!("Users?filter=userName%20eq%20%22{}%22", email))
+        .await;
+    let list: Value = response.json().await.unwrap();
+    let path = format!("Users/{}", list["Resources"][0]["id"].as_str().unwrap());
+    let response = app
+        .patch_scim(
+            &path,
+            &serde_json::json!({
+                "schemas": [PATCH_OP_SCHEMA],
+                "Operations": [{ "op": "replace", "path": "active", "value": false }],
+            }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "email": email,
+            "loginAttemptId": login_attempt_id,
+            "2FACode": code.as_ref().expose_secret(),
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_reject_user_name_changes() {
+    let mut app = TestApp::new().await;
+
+    let user = create_user(&app, &get_random_email()).await;
+    let path = format!("Users/{}", user["id"].as_str().unwrap());
+
+    let response = app
+        .put_scim(
+            &path,
+            &serde_json::json!({
+                "schemas": [USER_SCHEMA],
+                "userName": get_random_email(),
+                "active": true,
+            }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["scimType"], "mutability");
+
+    let response = app
+        .put_scim(
+            &path,
+            &serde_json::json!({
+                "schemas": [USER_SCHEMA],
+                "userName": user["userName"],
+                "externalId": "replaced",
+                "active": true,
+            }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.json::<Value>().await.unwrap()["externalId"],
+        "replaced"
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_delete_users() {
+    let mut app = TestApp::new().await;
+
+    let email = get_random_email();
+    let user = create_user(&app, &email).await;
+    let path = format!("Users/{}", user["id"].as_str().unwrap());
+    let token = login_token(&app, &email).await;
+
+    // Sessions are revoked by the second they were issued in
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    assert_eq!(app.delete_scim(&path).await.status().as_u16(), 204);
+    assert_eq!(app.get_scim(&path).await.status().as_u16(), 404);
+    assert_eq!(app.delete_scim(&path).await.status().as_u16(), 404);
+    assert_eq!(login(&app, &email).await, 401);
+    assert_eq!(verify_token(&app, &token).await, 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_manage_group_members() {
+    let mut app = TestApp::new().await;
+
+    let alice = create_user(&app, &get_random_email()).await;
+    let bob = create_user(&app, &get_random_email()).await;
+
+    let response = app
+        .post_scim(
+            "Groups",
+            &serde_json::json!({
+                "displayName": "Engineering",
+                "members": [{ "value": alice["id"] }],
+            }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    let group: Value = response.json().await.unwrap();
+    assert_eq!(group["members"][0]["display"], alice["userName"]);
+    let path = format!("Groups/{}", group["id"].as_str().unwrap());
+
+    let response = app
+        .patch_scim(
+            &path,
+            &serde_json::json!({
+                "schemas": [PATCH_OP_SCHEMA],
+                "Operations": [
+                    { "op": "add", "path": "members", "value": [{ "value": bob["id"] }] },
+                    {
+                        "op": "remove",
+                        "path": format!("members[value eq \"{}\"]", alice["id"].as_str().unwrap()),
+                    },
+                ],
+            }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let group: Value = response.json().await.unwrap();
+    assert_eq!(group["members"].as_array().unwrap().len(), 1);
+    assert_eq!(group["members"][0]["value"], bob["id"]);
+
+    // Members must be existing users
+    let response = app
+        .patch_scim(
+            &path,
+            &serde_json::json!({
+                "schemas": [PATCH_OP_SCHEMA],
+                "Operations": [{
+                    "op": "add",
+                    "path": "members",
+                    "value": [{ "value": uuid::Uuid::new_v4() }],
+                }],
+            }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app
+        .get_scim("Groups?filter=displayName%20eq%20%22Engineering%22")
+        .await;
+    let list: Value = response.json().await.unwrap();
+    assert_eq!(list["totalResults"], 1);
+
+    let response = app
+        .post_scim(
+            "Groups",
+            &serde_json::json!({ "displayName": "Engineering" }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 409);
+
+    assert_eq!(app.delete_scim(&path).await.status().as_u16(), 204);
+    assert_eq!(app.get_scim(&path).await.status().as_u16(), 404);
+
+    app.clean_up().await;
+}
@@ -0,0 +1,141 @@
+use std::time::{Duration, Instant};
+
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use auth_service::settings::Settings;
+
+use crate::helpers::{get_random_email, TestApp};
+
+// An app whose 2FA emails take `delay` to send, so that logins stay in flight for that long
+async fn app_with_slow_email(
+    delay: Duration,
+    configure: impl FnOnce(&mut Settings),
+) -> (TestApp, serde_json::Value) {
+    let app = TestApp::with_settings(|settings| {
+        settings.email_client.timeout = Duration::from_secs(10);
+        configure(settings);
+    })
+    .await;
+
+    let email = get_random_email();
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+            "requires2FA": true
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(delay))
+        .mount(&app.email_server)
+        .await;
+
+    let login = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+    (app, login)
+}
+
+fn spawn_login(
+    app: &TestApp,
+    login: serde_json::Value,
+) -> tokio::task::JoinHandle<reqwest::Result<reqwest::Response>> {
+    let request = app
+        .http_client
+        .post(format!("{}/login", &app.address))
+        .json(&login);
+    tokio::spawn(request.send())
+}
+
+#[tokio::test]
+async fn should_finish_requests_in_flight_before_stopping() {
+    let (mut app, login) = app_with_slow_email(Duration::from_millis(500), |_| {}).await;
+
+    let login = spawn_login(&app, login);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    app.shutdown().await;
+
+    let response = login.await.unwrap().expect("Request in flight was dropped");
+    assert_eq!(response.status().as_u16(), 206);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_refuse_connections_after_stopping() {
+    let mut app = TestApp::new().await;
+
+    app.shutdown().await;
+
+    let result = app
+        .http_client
+        .get(format!("{}/health/live", &app.address))
+        .send()
+        .await;
+    assert!(result.is_err(), "{:?}", result);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_stop_waiting_for_requests_after_the_timeout() {
+    let (mut app, login) = app_with_slow_email(Duration::from_secs(5), |settings| {
+        settings.application.shutdown_timeout = Duration::from_millis(200);
+    })
+    .await;
+
+    let login = spawn_login(&app, login);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let started = Instant::now();
+    app.shutdown().await;
+
+    assert!(
+        started.elapsed() < Duration::from_secs(2),
+        "{:?}",
+        started.elapsed()
+    );
+    assert!(login.await.unwrap().is_err());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_send_queued_webhooks_before_stopping() {
+    let mut app = TestApp::new().await;
+    let receiver = wiremock::MockServer::start().await;
+    Mock::given(path("/hooks"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&receiver)
+        .await;
+
+    let response = app
+        .post_webhook(&serde_json::json!({
+            "url": format!("{}/hooks", receiver.uri()),
+            "eventTypes": ["user.created"],
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": get_random_email(),
+            "password": "password123",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    app.shutdown().await;
+
+    receiver.verify().await;
+    app.clean_up().await;
+}
@@ -1,5 +1,13 @@
 use crate::helpers::{get_random_email, TestApp};
-use auth_service::{routes::SignupResponse, ErrorResponse};
+use std::sync::Arc;
+
+use auth_service::{
+    domain::{BreachedPasswordCheck, BreachedPasswordMode, PasswordPolicy, PasswordRule},
+    routes::SignupResponse,
+    services::hibp_breached_passwords::HashList,
+    ErrorResponse,
+};
+use sha1::{Digest, Sha1};
 
 #[tokio::test]
 async fn should_return_422_if_malformed_input() {
@@ -142,4 +150,98 @@ async fn should_return_409_if_email_already_exists() {
     );
 
     app.clean_up().await;
-}
\ No newline at end of file
+}
+
+#[tokio::test]
+async fn should_return_400_with_details_if_password_breaks_policy() {
+    let mut app = TestApp::with_password_policy(PasswordPolicy::default()).await;
+
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": get_random_email(),
+            "password": "password123",
+            "requires2FA": false
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    let body = response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse");
+    assert_eq!(body.error, "Password does not meet the password policy");
+    let rules: Vec<_> = body
+        .details
+        .iter()
+        .map(|violation| violation.rule)
+        .collect();
+    assert_eq!(rules, vec![PasswordRule::Common, PasswordRule::Strength]);
+
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": get_random_email(),
+            "password": "correct horse battery staple",
+            "requires2FA": false
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    app.clean_up().await;
+}
+
+fn breached_password_check(mode: BreachedPasswordMode) -> BreachedPasswordCheck {
+    let breached = Sha1::digest(b"password123").into();
+    BreachedPasswordCheck::new(Arc::new(HashList::new(vec![breached])), mode)
+}
+
+#[tokio::test]
+async fn should_return_400_if_password_is_breached() {
+    let mut app = TestApp::with_breached_password_check(breached_password_check(
+        BreachedPasswordMode::Reject,
+    ))
+    .await;
+
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": get_random_email(),
+            "password": "password123",
+            "requires2FA": false
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    let body = response
+        .json::<ErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse");
+    let rules: Vec<_> = body
+        .details
+        .iter()
+        .map(|violation| violation.rule)
+        .collect();
+    assert_eq!(rules, vec![PasswordRule::Breached]);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_allow_breached_password_in_warn_mode() {
+    let mut app =
+        TestApp::with_breached_password_check(breached_password_check(BreachedPasswordMode::Warn))
+            .await;
+
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": get_random_email(),
+            "password": "password123",
+            "requires2FA": false
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    app.clean_up().await;
+}
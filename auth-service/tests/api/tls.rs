@@ -0,0 +1,108 @@
+use std::{path::PathBuf, time::Duration};
+
+use auth_service::settings::TlsSettings;
+use rcgen::{generate_simple_self_signed, CertifiedKey};
+use reqwest::redirect::Policy;
+
+use crate::helpers::TestApp;
+
+// Writes a new self-signed certificate for localhost to the paths in `tls`, returning it in PEM
+fn write_certificate(tls: &TlsSettings) -> Vec<u8> {
+    let CertifiedKey { cert, key_pair } =
+        generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+    std::fs::write(&tls.certificate_path, cert.pem()).unwrap();
+    std::fs::write(&tls.private_key_path, key_pair.serialize_pem()).unwrap();
+    cert.pem().into_bytes()
+}
+
+async fn app_with_tls(redirect_address: Option<&str>) -> (TestApp, PathBuf) {
+    let directory = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+    std::fs::create_dir_all(&directory).unwrap();
+    let path = |name: &str| directory.join(name).to_string_lossy().into_owned();
+    let tls = TlsSettings {
+        certificate_path: path("cert.pem"),
+        private_key_path: path("key.pem"),
+        redirect_address: redirect_address.map(str::to_owned),
+    };
+    write_certificate(&tls);
+
+    let app = TestApp::with_settings(|settings| {
+        settings.application.public_url = "https://auth.example.com".to_owned();
+        settings.application.tls = Some(tls);
+    })
+    .await;
+    (app, directory)
+}
+
+#[tokio::test]
+async fn should_serve_https_with_the_configured_certificate() {
+    let (mut app, directory) = app_with_tls(None).await;
+
+    let response = app.get_health_live().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let plain_address = app.address.replace("https://", "http://");
+    let result = reqwest::get(format!("{}/health/live", plain_address)).await;
+    assert!(result.is_err(), "{:?}", result);
+
+    app.clean_up().await;
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[tokio::test]
+async fn should_redirect_plain_http_to_https() {
+    let (mut app, directory) = app_with_tls(Some("127.0.0.1:0")).await;
+    let redirect_address = app.redirect_address.clone().unwrap();
+    let http_client = reqwest::Client::builder()
+        .redirect(Policy::none())
+        .build()
+        .unwrap();
+
+    let response = http_client
+        .post(format!(
+            "http://{}/report-login?token=abc",
+            redirect_address
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Permanent redirects keep the method and body
+    assert_eq!(response.status().as_u16(), 308);
+    assert_eq!(
+        response.headers()["location"],
+        "https://auth.example.com/report-login?token=abc"
+    );
+
+    app.clean_up().await;
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[tokio::test]
+async fn should_serve_a_renewed_certificate_without_restarting() {
+    let (mut app, directory) = app_with_tls(None).await;
+
+    let renewed = write_certificate(app.settings.application.tls.as_ref().unwrap());
+    // Only trusts the renewed certificate
+    let http_client = reqwest::Client::builder()
+        .add_root_certificate(reqwest::Certificate::from_pem(&renewed).unwrap())
+        .build()
+        .unwrap();
+
+    let mut served = false;
+    for _ in 0..100 {
+        let result = http_client
+            .get(format!("{}/health/live", &app.address))
+            .send()
+            .await;
+        if result.is_ok_and(|response| response.status().is_success()) {
+            served = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert!(served, "Renewed certificate was not served");
+
+    app.clean_up().await;
+    std::fs::remove_dir_all(directory).unwrap();
+}
@@ -0,0 +1,176 @@
+use auth_service::{
+    domain::LoginAttemptId,
+    routes::{ListTrustedDevicesResponse, TwoFactorAuthResponse},
+    utils::constants::{JWT_COOKIE_NAME, TRUSTED_DEVICE_COOKIE_NAME},
+};
+use secrecy::{ExposeSecret, Secret};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{get_random_email, TestApp};
+
+async fn signup_with_2fa(app: &TestApp) -> String {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let email = get_random_email();
+    let signup = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": true
+    });
+    assert_eq!(app.post_signup(&signup).await.status().as_u16(), 201);
+    email
+}
+
+async fn login_with_2fa(app: &TestApp, email: &str, remember_device: bool) -> reqwest::Response {
+    let login = serde_json::json!({ "email": email, "password": "password123" });
+    let response = app.post_login(&login).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .unwrap()
+        .login_attempt_id;
+    let (_, code) = app
+        .two_fa_code_store
+        .get_code(&LoginAttemptId::parse(Secret::new(login_attempt_id.clone())).unwrap())
+        .await
+        .unwrap();
+
+    let verify_2fa = serde_json::json!({
+        "email": email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": code.as_ref().expose_secret(),
+        "rememberDevice": remember_device,
+    });
+    app.post_verify_2fa(&verify_2fa).await
+}
+
+#[tokio::test]
+async fn should_skip_2fa_on_remembered_device() {
+    let mut app = TestApp::new().await;
+    let email = signup_with_2fa(&app).await;
+
+    let response = login_with_2fa(&app, &email, true).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .cookies()
+        .any(|cookie| cookie.name() == TRUSTED_DEVICE_COOKIE_NAME));
+
+    assert_eq!(app.post_logout().await.status().as_u16(), 200);
+
+    let login = serde_json::json!({ "email": email, "password": "password123" });
+    let response = app.post_login(&login).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .cookies()
+        .any(|cookie| cookie.name() == JWT_COOKIE_NAME));
+
+    // The device doesn't stand in for the password
+    let login = serde_json::json!({ "email": email, "password": "wrong-password" });
+    assert_eq!(app.post_login(&login).await.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_require_2fa_if_device_not_remembered() {
+    let mut app = TestApp::new().await;
+    let email = signup_with_2fa(&app).await;
+
+    let response = login_with_2fa(&app, &email, false).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(!response
+        .cookies()
+        .any(|cookie| cookie.name() == TRUSTED_DEVICE_COOKIE_NAME));
+
+    let login = serde_json::json!({ "email": email, "password": "password123" });
+    assert_eq!(app.post_login(&login).await.status().as_u16(), 206);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_require_2fa_if_trusted_device_cookie_is_used_on_another_device() {
+    let mut app = TestApp::new().await;
+    let email = signup_with_2fa(&app).await;
+
+    let response = login_with_2fa(&app, &email, true).await;
+    let trusted_device_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == TRUSTED_DEVICE_COOKIE_NAME)
+        .expect("Trusted device cookie not found")
+        .value()
+        .to_owned();
+
+    let login = serde_json::json!({ "email": email, "password": "password123" });
+    let response = reqwest::Client::new()
+        .post(format!("{}/login", &app.address))
+        .header(
+            "Cookie",
+            format!("{}={}", TRUSTED_DEVICE_COOKIE_NAME, trusted_device_cookie),
+        )
+        .json(&login)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 206);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_list_and_revoke_trusted_devices() {
+    let mut app = TestApp::new().await;
+    let email = signup_with_2fa(&app).await;
+
+    assert_eq!(
+        login_with_2fa(&app, &email, true).await.status().as_u16(),
+        200
+    );
+
+    let response = app.get_trusted_devices().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let devices = response
+        .json::<ListTrustedDevicesResponse>()
+        .await
+        .unwrap()
+        .devices;
+    assert_eq!(devices.len(), 1);
+    assert!(devices[0].current);
+    assert!(devices[0].expires_at > devices[0].created_at);
+
+    let id = devices[0].id.to_string();
+    assert_eq!(app.delete_trusted_device(&id).await.status().as_u16(), 204);
+    assert_eq!(app.delete_trusted_device(&id).await.status().as_u16(), 404);
+    assert_eq!(
+        app.delete_trusted_device("not-an-id")
+            .await
+            .status()
+            .as_u16(),
+        404
+    );
+
+    let response = app.get_trusted_devices().await;
+    let devices = response
+        .json::<ListTrustedDevicesResponse>()
+        .await
+        .unwrap()
+        .devices;
+    assert!(devices.is_empty());
+
+    let login = serde_json::json!({ "email": email, "password": "password123" });
+    assert_eq!(app.post_login(&login).await.status().as_u16(), 206);
+
+    assert_eq!(app.post_logout().await.status().as_u16(), 200);
+    assert_eq!(app.get_trusted_devices().await.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
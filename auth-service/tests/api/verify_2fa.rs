@@ -1,7 +1,9 @@
 use auth_service::{
     domain::{Email, LoginAttemptId, TwoFACode},
     routes::TwoFactorAuthResponse,
-    utils::constants::JWT_COOKIE_NAME,
+    utils::constants::{
+        JWT_COOKIE_NAME, MAX_FAILED_2FA_ATTEMPTS, MAX_PENDING_2FA_ATTEMPTS_PER_USER,
+    },
     ErrorResponse,
 };
 use secrecy::{ExposeSecret, Secret};
@@ -54,9 +56,7 @@ async fn should_return_200_if_correct_code() {
 
     let code_tuple = app
         .two_fa_code_store
-        .read()
-        .await
-        .get_code(&Email::parse(random_email.clone().into()).unwrap())
+        .get_code(&LoginAttemptId::parse(login_attempt_id.clone().into()).unwrap())
         .await
         .unwrap();
 
@@ -188,9 +188,7 @@ async fn should_return_401_if_incorrect_credentials() {
 
     let code_tuple = app
         .two_fa_code_store
-        .read()
-        .await
-        .get_code(&Email::parse(random_email.clone().into()).unwrap())
+        .get_code(&LoginAttemptId::parse(login_attempt_id.clone().into()).unwrap())
         .await
         .unwrap();
 
@@ -250,7 +248,7 @@ async fn should_return_401_if_incorrect_credentials() {
 }
 
 #[tokio::test]
-async fn should_return_401_if_old_code() {
+async fn should_return_200_for_each_concurrent_login_attempt() {
     let mut app = TestApp::new().await;
 
     let random_email = get_random_email();
@@ -272,44 +270,109 @@ async fn should_return_401_if_old_code() {
         .mount(&app.email_server)
         .await;
 
-    // First login call
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123"
+    });
+
+    // Log in from two devices before either completes 2FA
+
+    let mut login_attempt_ids = vec![];
+    for _ in 0..2 {
+        let response = app.post_login(&login_body).await;
+
+        assert_eq!(response.status().as_u16(), 206);
+
+        let response_body = response
+            .json::<TwoFactorAuthResponse>()
+            .await
+            .expect("Could not deserialize response body to TwoFactorAuthResponse");
+
+        login_attempt_ids.push(response_body.login_attempt_id);
+    }
+
+    // The first attempt's code must not have been overwritten by the second
+
+    for login_attempt_id in login_attempt_ids {
+        let (email, code) = app
+            .two_fa_code_store
+            .get_code(&LoginAttemptId::parse(login_attempt_id.clone().into()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(email, Email::parse(random_email.clone().into()).unwrap());
+
+        let request_body = serde_json::json!({
+            "email": random_email,
+            "loginAttemptId": login_attempt_id,
+            "2FACode": code.as_ref().expose_secret()
+        });
+
+        let response = app.post_verify_2fa(&request_body).await;
+
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_login_attempt_evicted() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": true
+    });
+
+    let response = app.post_signup(&signup_body).await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(MAX_PENDING_2FA_ATTEMPTS_PER_USER as u64 + 1)
+        .mount(&app.email_server)
+        .await;
 
     let login_body = serde_json::json!({
         "email": random_email,
         "password": "password123"
     });
 
+    // First login call
+
     let response = app.post_login(&login_body).await;
 
     assert_eq!(response.status().as_u16(), 206);
 
-    let response_body = response
+    let login_attempt_id = response
         .json::<TwoFactorAuthResponse>()
         .await
-        .expect("Could not deserialize response body to TwoFactorAuthResponse");
-
-    assert_eq!(response_body.message, "2FA required".to_owned());
-    assert!(!response_body.login_attempt_id.is_empty());
-
-    let login_attempt_id = response_body.login_attempt_id;
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
 
     let code_tuple = app
         .two_fa_code_store
-        .read()
-        .await
-        .get_code(&Email::parse(random_email.clone().into()).unwrap())
+        .get_code(&LoginAttemptId::parse(login_attempt_id.clone().into()).unwrap())
         .await
         .unwrap();
 
     let code = code_tuple.1.as_ref();
 
-    // Second login call
+    // Enough further logins to push the first attempt past the per-user cap
 
-    let response = app.post_login(&login_body).await;
+    for _ in 0..MAX_PENDING_2FA_ATTEMPTS_PER_USER {
+        let response = app.post_login(&login_body).await;
 
-    assert_eq!(response.status().as_u16(), 206);
+        assert_eq!(response.status().as_u16(), 206);
+    }
 
-    // 2FA attempt with old login_attempt_id and code
+    // 2FA attempt with the evicted login_attempt_id and code
 
     let request_body = serde_json::json!({
         "email": random_email,
@@ -368,9 +431,7 @@ async fn should_return_401_if_same_code_twice() {
 
     let code_tuple = app
         .two_fa_code_store
-        .read()
-        .await
-        .get_code(&Email::parse(random_email.clone().into()).unwrap())
+        .get_code(&LoginAttemptId::parse(login_attempt_id.clone().into()).unwrap())
         .await
         .unwrap();
 
@@ -400,6 +461,173 @@ async fn should_return_401_if_same_code_twice() {
     app.clean_up().await;
 }
 
+#[tokio::test]
+async fn should_return_401_if_too_many_failed_attempts() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": true
+    });
+
+    let response = app.post_signup(&signup_body).await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123"
+    });
+
+    let response = app.post_login(&login_body).await;
+
+    assert_eq!(response.status().as_u16(), 206);
+
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+
+    let code_tuple = app
+        .two_fa_code_store
+        .get_code(&LoginAttemptId::parse(login_attempt_id.clone().into()).unwrap())
+        .await
+        .unwrap();
+
+    let code = code_tuple.1.as_ref();
+
+    // Pick a guess that is guaranteed to be wrong
+    let wrong_code = if code.expose_secret() == "123456" {
+        "654321"
+    } else {
+        "123456"
+    };
+
+    for _ in 0..MAX_FAILED_2FA_ATTEMPTS {
+        let request_body = serde_json::json!({
+            "email": random_email,
+            "loginAttemptId": login_attempt_id,
+            "2FACode": wrong_code
+        });
+
+        let response = app.post_verify_2fa(&request_body).await;
+
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    // The correct code no longer works once the attempt has been invalidated
+    let request_body = serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": code.expose_secret()
+    });
+
+    let response = app.post_verify_2fa(&request_body).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Incorrect Credentials".to_owned()
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_accept_a_code_only_once_when_verifying_concurrently() {
+    let mut app = TestApp::new().await;
+
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": true
+    });
+
+    let response = app.post_signup(&signup_body).await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123"
+    });
+
+    let response = app.post_login(&login_body).await;
+
+    assert_eq!(response.status().as_u16(), 206);
+
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+
+    let code_tuple = app
+        .two_fa_code_store
+        .get_code(&LoginAttemptId::parse(login_attempt_id.clone().into()).unwrap())
+        .await
+        .unwrap();
+
+    let request_body = serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": code_tuple.1.as_ref().expose_secret()
+    });
+
+    let verifications = (0..10).map(|_| {
+        let request = app
+            .http_client
+            .post(format!("{}/verify-2fa", &app.address))
+            .json(&request_body);
+        tokio::spawn(async move {
+            request
+                .send()
+                .await
+                .expect("Failed to execute request.")
+                .status()
+                .as_u16()
+        })
+    });
+
+    let mut verified = 0;
+    for verification in verifications {
+        match verification.await.unwrap() {
+            200 => verified += 1,
+            401 => {}
+            status => panic!("Unexpected status: {}", status),
+        }
+    }
+
+    assert_eq!(verified, 1);
+
+    app.clean_up().await;
+}
+
 #[tokio::test]
 async fn should_return_422_if_malformed_input() {
     let mut app = TestApp::new().await;
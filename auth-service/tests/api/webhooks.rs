@@ -0,0 +1,290 @@
+use std::time::Duration;
+
+use auth_service::{
+    domain::{WebhookDeliveryStatus, WebhookEventType},
+    routes::{
+        CreateWebhookResponse, ListWebhooksResponse, WebhookDeliveriesResponse,
+        WebhookSubscriptionResponse,
+    },
+    services::webhook_dispatcher::{
+        sign, WEBHOOK_ID_HEADER, WEBHOOK_SIGNATURE_HEADER, WEBHOOK_TIMESTAMP_HEADER,
+    },
+};
+use secrecy::Secret;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use crate::helpers::{get_random_email, TestApp};
+
+async fn create_webhook(app: &TestApp, receiver: &MockServer) -> CreateWebhookResponse {
+    let response = app
+        .post_webhook(&serde_json::json!({
+            "url": format!("{}/hooks", receiver.uri()),
+            "eventTypes": ["user.created"],
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 201);
+
+    response
+        .json::<CreateWebhookResponse>()
+        .await
+        .expect("Could not deserialize response body to CreateWebhookResponse")
+}
+
+async fn signup(app: &TestApp, email: &str) {
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+            "requires2FA": false
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+// Waits for the dispatcher to settle every delivery made to the subscription
+async fn wait_for_deliveries(app: &TestApp, id: &str) -> WebhookDeliveriesResponse {
+    for _ in 0..100 {
+        let response = app.get_webhook_deliveries(id).await;
+        assert_eq!(response.status().as_u16(), 200);
+
+        let deliveries = response
+            .json::<WebhookDeliveriesResponse>()
+            .await
+            .expect("Could not deserialize response body to WebhookDeliveriesResponse");
+
+        if !deliveries.deliveries.is_empty()
+            && deliveries
+                .deliveries
+                .iter()
+                .all(|delivery| delivery.status != WebhookDeliveryStatus::Pending)
+        {
+            return deliveries;
+        }
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    panic!("Webhook deliveries were not settled in time");
+}
+
+#[tokio::test]
+async fn should_return_400_if_admin_token_missing() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .http_client
+        .get(format!("{}/admin/webhooks", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_if_admin_token_invalid() {
+    let mut app = TestApp::new().await;
+
+    let response = app
+        .http_client
+        .post(format!("{}/admin/webhooks", &app.address))
+        .bearer_auth("not-the-admin-token")
+        .json(&serde_json::json!({
+            "url": "https://example.com/hooks",
+            "eventTypes": ["user.created"],
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_if_invalid_input() {
+    let mut app = TestApp::new().await;
+
+    let test_cases = [
+        serde_json::json!({ "url": "not-a-url", "eventTypes": ["user.created"] }),
+        serde_json::json!({ "url": "ftp://example.com/hooks", "eventTypes": ["user.created"] }),
+        serde_json::json!({ "url": "https://example.com/hooks", "eventTypes": ["user.renamed"] }),
+        serde_json::json!({ "url": "https://example.com/hooks", "eventTypes": [] }),
+        serde_json::json!({
+            "url": "https://example.com/hooks",
+            "eventTypes": ["user.created"],
+            "secret": ""
+        }),
+    ];
+
+    for test_case in test_cases.iter() {
+        let response = app.post_webhook(test_case).await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "Failed for input: {:?}",
+            test_case
+        );
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_manage_subscriptions() {
+    let mut app = TestApp::new().await;
+    let receiver = MockServer::start().await;
+
+    let created = create_webhook(&app, &receiver).await;
+    assert!(created.secret.starts_with("whsec_"));
+    assert_eq!(
+        created.subscription.event_types,
+        vec![WebhookEventType::UserCreated]
+    );
+
+    let id = created.subscription.id.to_string();
+
+    // The secret is only handed out on creation
+    let response = app.get_webhook(&id).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert!(body.get("secret").is_none());
+    let subscription: WebhookSubscriptionResponse = serde_json::from_value(body).unwrap();
+    assert_eq!(subscription, created.subscription);
+
+    let response = app.get_webhooks().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let subscriptions = response.json::<ListWebhooksResponse>().await.unwrap();
+    assert_eq!(subscriptions.subscriptions, vec![created.subscription]);
+
+    let response = app.delete_webhook(&id).await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    assert_eq!(app.get_webhook(&id).await.status().as_u16(), 404);
+    assert_eq!(app.delete_webhook(&id).await.status().as_u16(), 404);
+    assert_eq!(app.get_webhook_deliveries(&id).await.status().as_u16(), 404);
+    assert_eq!(app.get_webhook("not-a-uuid").await.status().as_u16(), 404);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_deliver_signed_user_created_event() {
+    let mut app = TestApp::new().await;
+    let receiver = MockServer::start().await;
+
+    Mock::given(path("/hooks"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&receiver)
+        .await;
+
+    let created = create_webhook(&app, &receiver).await;
+    let email = get_random_email();
+    signup(&app, &email).await;
+
+    let deliveries = wait_for_deliveries(&app, &created.subscription.id.to_string()).await;
+    assert_eq!(deliveries.deliveries.len(), 1);
+    assert_eq!(
+        deliveries.deliveries[0].status,
+        WebhookDeliveryStatus::Succeeded
+    );
+
+    let request = &receiver.received_requests().await.unwrap()[0];
+    let body = String::from_utf8(request.body.clone()).unwrap();
+    let timestamp: i64 = request.headers[WEBHOOK_TIMESTAMP_HEADER]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+
+    // Receivers verify deliveries with the secret they got on creation
+    assert_eq!(
+        request.headers[WEBHOOK_SIGNATURE_HEADER].to_str().unwrap(),
+        sign(&Secret::new(created.secret), timestamp, &body)
+    );
+
+    let event: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(event["type"], "user.created");
+    assert_eq!(event["data"]["email"], email);
+    assert_eq!(event["data"]["requires2FA"], false);
+    assert_eq!(
+        request.headers[WEBHOOK_ID_HEADER].to_str().unwrap(),
+        event["id"].as_str().unwrap()
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_retry_failed_deliveries() {
+    let mut app = TestApp::new().await;
+    let receiver = MockServer::start().await;
+
+    Mock::given(path("/hooks"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&receiver)
+        .await;
+    Mock::given(path("/hooks"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&receiver)
+        .await;
+
+    let created = create_webhook(&app, &receiver).await;
+    signup(&app, &get_random_email()).await;
+
+    let deliveries = wait_for_deliveries(&app, &created.subscription.id.to_string()).await;
+    let delivery = &deliveries.deliveries[0];
+
+    assert_eq!(delivery.status, WebhookDeliveryStatus::Succeeded);
+    let statuses: Vec<_> = delivery
+        .attempts
+        .iter()
+        .map(|attempt| attempt.response_status)
+        .collect();
+    assert_eq!(statuses, vec![Some(500), Some(200)]);
+
+    // Every attempt carries the same event ID so receivers can deduplicate
+    let requests = receiver.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(
+        requests[0].headers[WEBHOOK_ID_HEADER],
+        requests[1].headers[WEBHOOK_ID_HEADER]
+    );
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_give_up_after_max_attempts() {
+    let mut app = TestApp::new().await;
+    let receiver = MockServer::start().await;
+
+    Mock::given(path("/hooks"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&receiver)
+        .await;
+
+    let created = create_webhook(&app, &receiver).await;
+    signup(&app, &get_random_email()).await;
+
+    let deliveries = wait_for_deliveries(&app, &created.subscription.id.to_string()).await;
+    let delivery = &deliveries.deliveries[0];
+
+    assert_eq!(delivery.status, WebhookDeliveryStatus::Failed);
+    assert_eq!(delivery.attempts.len(), 3);
+    assert_eq!(delivery.next_attempt_at, None);
+
+    app.clean_up().await;
+}